@@ -0,0 +1,103 @@
+use axum::{
+    Router,
+    extract::{ConnectInfo, Request, State},
+    http::HeaderValue,
+    middleware::{self, Next},
+    response::Response,
+};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::Deserialize;
+use std::net::SocketAddr;
+use std::time::Instant;
+use tracing::Instrument;
+
+use crate::proxy::ClientInfo;
+
+/// Header carrying the ID of a request, kept when a proxy already set it
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Which requests the server logs
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AccessLog {
+    /// None of them
+    Off,
+    /// Those answered with a 4xx or 5xx status
+    Errors,
+    /// Every request
+    #[default]
+    All,
+}
+
+/// Gives each request `router` answers an ID, sent back in
+/// `X-Request-Id` and attached to every message logged while answering it,
+/// and logs the requests as `AccessLog` asks: method, path, status, latency
+/// and client address. Server errors are logged as warnings, the rest at
+/// info level.
+pub fn with_access_log(router: Router, access_log: AccessLog) -> Router {
+    router.layer(middleware::from_fn_with_state(access_log, log_request))
+}
+
+async fn log_request(
+    State(access_log): State<AccessLog>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| {
+            !id.is_empty()
+                && id.len() <= 64
+                && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+        .map(str::to_string)
+        .unwrap_or_else(new_request_id);
+    let span = tracing::info_span!("request", id = %request_id);
+    let mut response = log_response(access_log, request, next)
+        .instrument(span)
+        .await;
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
+
+/// Random 16 hex digit request ID
+fn new_request_id() -> String {
+    let mut bytes = [0u8; 8];
+    let _ = SystemRandom::new().fill(&mut bytes);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+async fn log_response(access_log: AccessLog, request: Request, next: Next) -> Response {
+    let started = Instant::now();
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    // The proxy's view of the client when trusted, see `proxy::with_client_info`
+    let client = match request.extensions().get::<ClientInfo>() {
+        Some(client) => client.ip,
+        None => request
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip()),
+    }
+    .map_or_else(|| "-".to_string(), |ip| ip.to_string());
+
+    let response = next.run(request).await;
+    let status = response.status();
+    if access_log == AccessLog::Off
+        || access_log == AccessLog::Errors && !status.is_client_error() && !status.is_server_error()
+    {
+        return response;
+    }
+    let status = status.as_u16();
+    let latency_ms = started.elapsed().as_secs_f64() * 1000.0;
+    if status >= 500 {
+        tracing::warn!(%client, %method, %path, status, latency_ms, "Request failed");
+    } else {
+        tracing::info!(%client, %method, %path, status, latency_ms, "Request");
+    }
+    response
+}
@@ -0,0 +1,200 @@
+use pulldown_cmark::{Event, Options, Parser as MarkdownParser, Tag, TagEnd};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::{BufRead, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::frontmatter;
+use crate::store::{ContentStore, LocalStore, write_atomic};
+
+/// An image of a page that has no alt text
+#[derive(Serialize)]
+pub struct MissingAlt {
+    pub page: String,
+    /// 1-based line of the image in the page's file
+    pub line: usize,
+    pub url: String,
+    /// Where `![...](...)` is in the page's file
+    #[serde(skip)]
+    range: Range<usize>,
+}
+
+/// Finds the images of a page, given its whole file, whose alt text is empty.
+pub fn find_missing_alt(filename: &str, content: &str) -> Vec<MissingAlt> {
+    let (_, body) = frontmatter::parse(content);
+    let body_start = content.len() - body.len();
+    let parser = MarkdownParser::new_ext(body, Options::ENABLE_TABLES | Options::ENABLE_FOOTNOTES);
+
+    let mut missing = Vec::new();
+    let mut image: Option<(String, Range<usize>, String)> = None;
+    for (event, range) in parser.into_offset_iter() {
+        match event {
+            Event::Start(Tag::Image { dest_url, .. }) => {
+                image = Some((dest_url.to_string(), range, String::new()));
+            }
+            Event::Text(text) | Event::Code(text) => {
+                if let Some((_, _, alt)) = &mut image {
+                    alt.push_str(&text);
+                }
+            }
+            Event::End(TagEnd::Image) => {
+                let Some((url, range, alt)) = image.take() else {
+                    continue;
+                };
+                if alt.trim().is_empty() {
+                    let start = body_start + range.start;
+                    missing.push(MissingAlt {
+                        page: filename.to_string(),
+                        line: content[..start].matches('\n').count() + 1,
+                        url,
+                        range: start..body_start + range.end,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    missing
+}
+
+/// Finds the images without alt text of every page of `docs_dir`.
+pub async fn missing_alt(docs_dir: &Path) -> anyhow::Result<Vec<MissingAlt>> {
+    let store = LocalStore::new(docs_dir.to_path_buf());
+    let mut missing = Vec::new();
+    for filename in store.list_pages().await? {
+        let content = store.read_page(&filename).await?;
+        missing.extend(find_missing_alt(&filename, &content));
+    }
+    missing.sort_by(|a, b| (&a.page, a.line).cmp(&(&b.page, b.line)));
+    Ok(missing)
+}
+
+/// File of the docs directory an image URL of `page` points at, `None` for
+/// web images.
+fn image_path(docs_dir: &Path, page: &str, url: &str) -> Option<PathBuf> {
+    if url.contains("://") || url.starts_with("data:") {
+        return None;
+    }
+    let path = url.split(['#', '?']).next()?;
+    let file = match path.strip_prefix('/') {
+        Some(path) => docs_dir.join(path),
+        None => docs_dir
+            .join(Path::new(page).parent().unwrap_or(Path::new("")))
+            .join(path),
+    };
+    file.is_file().then_some(file)
+}
+
+/// Asks `command` for the alt text of `image`: it runs through `sh` with the
+/// image's path as last argument, and what it prints is the alt text.
+pub fn suggest_alt(command: &str, image: &Path) -> anyhow::Result<String> {
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$1\"", command))
+        .arg("sh")
+        .arg(image)
+        .output()
+        .map_err(|e| anyhow::anyhow!("Failed to run {}: {}", command, e))?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "{} failed on {}: {}",
+            command,
+            image.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    let alt = String::from_utf8_lossy(&output.stdout);
+    Ok(alt.split_whitespace().collect::<Vec<_>>().join(" "))
+}
+
+/// Goes through the images of `docs_dir` without alt text, asking for each
+/// one on the terminal and writing the answer into the page. With `command`,
+/// its suggestion for the image is offered first, see `suggest_alt`. Returns
+/// the number of images given an alt text.
+pub async fn fill_missing_alt(docs_dir: &Path, command: Option<&str>) -> anyhow::Result<usize> {
+    let mut by_page: BTreeMap<String, Vec<MissingAlt>> = BTreeMap::new();
+    for image in missing_alt(docs_dir).await? {
+        by_page.entry(image.page.clone()).or_default().push(image);
+    }
+
+    let mut stdin = std::io::stdin().lock();
+    let mut filled = 0;
+    'pages: for (page, images) in by_page {
+        let mut edits = Vec::new();
+        for image in images {
+            println!("{}:{}: {}", image.page, image.line, image.url);
+            let suggestion = match (command, image_path(docs_dir, &page, &image.url)) {
+                (Some(command), Some(path)) => match suggest_alt(command, &path) {
+                    Ok(alt) if !alt.is_empty() => Some(alt),
+                    Ok(_) => None,
+                    Err(e) => {
+                        tracing::warn!("{}", e);
+                        None
+                    }
+                },
+                _ => None,
+            };
+            match &suggestion {
+                Some(alt) => {
+                    println!("  suggested: {}", alt);
+                    print!("  alt text (Enter to accept, - to skip): ");
+                }
+                None => print!("  alt text (Enter to skip): "),
+            }
+            std::io::stdout().flush()?;
+
+            let mut answer = String::new();
+            let end_of_input = stdin.read_line(&mut answer)? == 0;
+            let alt = match answer.trim() {
+                "" => suggestion.filter(|_| !end_of_input),
+                "-" => None,
+                answer => Some(answer.to_string()),
+            };
+            if let Some(alt) = alt {
+                edits.push((image.range, alt));
+            }
+            if end_of_input {
+                println!();
+                filled += write_alt(docs_dir, &page, edits)?;
+                break 'pages;
+            }
+        }
+        filled += write_alt(docs_dir, &page, edits)?;
+    }
+    Ok(filled)
+}
+
+/// Writes alt texts into the images at the given ranges of `page`, returning
+/// how many were written.
+fn write_alt(
+    docs_dir: &Path,
+    page: &str,
+    edits: Vec<(Range<usize>, String)>,
+) -> anyhow::Result<usize> {
+    if edits.is_empty() {
+        return Ok(0);
+    }
+    let path = docs_dir.join(page);
+    let mut content = std::fs::read_to_string(&path)?;
+    let count = edits.len();
+    // From the end, so the ranges before stay valid
+    for (range, alt) in edits.into_iter().rev() {
+        let image = &content[range.clone()];
+        let Some(close) = image.find(']') else {
+            continue;
+        };
+        let mut escaped = String::new();
+        for c in alt.chars() {
+            if matches!(c, '[' | ']' | '\\') {
+                escaped.push('\\');
+            }
+            escaped.push(c);
+        }
+        content.replace_range(range.start + 2..range.start + close, &escaped);
+    }
+    write_atomic(&path, content.as_bytes())?;
+    tracing::info!("Wrote alt text into {}", page);
+    Ok(count)
+}
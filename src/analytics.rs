@@ -0,0 +1,80 @@
+use serde::{Deserialize, Serialize};
+
+use crate::RenderOptions;
+
+/// Privacy-friendly analytics services whose script pages can load
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AnalyticsProvider {
+    /// https://plausible.io, the site being its domain
+    Plausible,
+    /// https://www.goatcounter.com, the site being its code or the URL it
+    /// counts at
+    Goatcounter,
+    /// https://umami.is, the site being its website ID
+    Umami,
+}
+
+/// Settings of the analytics script, see `analytics` in `blog.toml`
+#[derive(Clone, Debug)]
+pub struct AnalyticsOptions {
+    pub provider: AnalyticsProvider,
+    pub site: String,
+    /// URL of the script of a self-hosted instance, instead of the
+    /// provider's
+    pub script_url: Option<String>,
+}
+
+/// An attribute of the analytics script
+#[derive(Serialize)]
+pub struct Attribute {
+    name: &'static str,
+    value: String,
+}
+
+/// The `<script>` tag `_base.html` adds for the analytics
+#[derive(Serialize)]
+pub struct AnalyticsScript {
+    src: String,
+    attributes: Vec<Attribute>,
+}
+
+impl AnalyticsOptions {
+    pub fn script(&self) -> AnalyticsScript {
+        let attribute = |name, value: &str| Attribute {
+            name,
+            value: value.to_string(),
+        };
+        let (src, attributes) = match self.provider {
+            AnalyticsProvider::Plausible => (
+                "https://plausible.io/js/script.js",
+                vec![attribute("data-domain", &self.site)],
+            ),
+            AnalyticsProvider::Goatcounter => {
+                let endpoint = if self.site.contains("://") {
+                    self.site.clone()
+                } else {
+                    format!("https://{}.goatcounter.com/count", self.site)
+                };
+                (
+                    "https://gc.zgo.at/count.js",
+                    vec![attribute("data-goatcounter", &endpoint)],
+                )
+            }
+            AnalyticsProvider::Umami => (
+                "https://cloud.umami.is/script.js",
+                vec![attribute("data-website-id", &self.site)],
+            ),
+        };
+        AnalyticsScript {
+            src: self.script_url.as_deref().unwrap_or(src).to_string(),
+            attributes,
+        }
+    }
+}
+
+/// Sets `analytics` in the template context of a page of the site.
+pub fn insert_into(options: &RenderOptions, context: &mut tera::Context) {
+    let script = options.analytics.as_ref().map(AnalyticsOptions::script);
+    context.insert("analytics", &script);
+}
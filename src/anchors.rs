@@ -0,0 +1,120 @@
+use pulldown_cmark::{CowStr, Event, Options, Parser as MarkdownParser, Tag, TagEnd};
+use std::collections::{BTreeSet, HashSet, VecDeque};
+
+use crate::glossary::{self, GLOSSARY_FILE};
+use crate::scaffold::slugify;
+
+/// Gives each heading an `id` from its text, "Getting started" becoming
+/// `getting-started`, so sections can be linked to. Repeated headings get
+/// `-1`, `-2`... suffixes.
+pub struct HeadingAnchorRenderer<'a, I> {
+    inner: I,
+    used: HashSet<String>,
+    queue: VecDeque<Event<'a>>,
+}
+
+impl<'a, I: Iterator<Item = Event<'a>>> HeadingAnchorRenderer<'a, I> {
+    pub fn new(inner: I) -> Self {
+        Self {
+            inner,
+            used: HashSet::new(),
+            queue: VecDeque::new(),
+        }
+    }
+
+    fn anchor(&mut self, text: &str) -> Option<String> {
+        let slug = slugify(text);
+        if slug.is_empty() {
+            return None;
+        }
+        let mut anchor = slug.clone();
+        let mut n = 0;
+        while self.used.contains(&anchor) {
+            n += 1;
+            anchor = format!("{}-{}", slug, n);
+        }
+        self.used.insert(anchor.clone());
+        Some(anchor)
+    }
+}
+
+impl<'a, I: Iterator<Item = Event<'a>>> Iterator for HeadingAnchorRenderer<'a, I> {
+    type Item = Event<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(event) = self.queue.pop_front() {
+            return Some(event);
+        }
+
+        let event = self.inner.next()?;
+        let Event::Start(Tag::Heading {
+            level,
+            id: None,
+            classes,
+            attrs,
+        }) = event
+        else {
+            return Some(event);
+        };
+
+        // The anchor is made from the heading's text
+        let mut text = String::new();
+        for inner_event in self.inner.by_ref() {
+            let end = matches!(inner_event, Event::End(TagEnd::Heading(_)));
+            if let Event::Text(t) | Event::Code(t) = &inner_event {
+                text.push_str(t);
+            }
+            self.queue.push_back(inner_event);
+            if end {
+                break;
+            }
+        }
+
+        Some(Event::Start(Tag::Heading {
+            level,
+            id: self.anchor(&text).map(CowStr::from),
+            classes,
+            attrs,
+        }))
+    }
+}
+
+/// Anchors a page can be linked to: its headings and the `id`s of its raw
+/// HTML, and the terms of the glossary page.
+pub fn page_anchors(filename: &str, body: &str) -> BTreeSet<String> {
+    let body = if filename == GLOSSARY_FILE {
+        glossary::anchor_entries(body)
+    } else {
+        body.to_string()
+    };
+
+    let mut anchors = BTreeSet::new();
+    let parser = MarkdownParser::new_ext(&body, Options::ENABLE_TABLES | Options::ENABLE_FOOTNOTES);
+    for event in HeadingAnchorRenderer::new(parser) {
+        match event {
+            Event::Start(Tag::Heading { id: Some(id), .. }) => {
+                anchors.insert(id.to_string());
+            }
+            Event::Html(html) | Event::InlineHtml(html) => anchors.extend(html_ids(&html)),
+            _ => {}
+        }
+    }
+    anchors
+}
+
+/// Values of the `id` and `name` attributes of an HTML fragment.
+fn html_ids(html: &str) -> Vec<String> {
+    let mut ids = Vec::new();
+    for attribute in [" id=\"", " name=\""] {
+        let mut rest = html;
+        while let Some(start) = rest.find(attribute) {
+            rest = &rest[start + attribute.len()..];
+            let Some(end) = rest.find('"') else {
+                break;
+            };
+            ids.push(rest[..end].to_string());
+            rest = &rest[end..];
+        }
+    }
+    ids
+}
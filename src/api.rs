@@ -0,0 +1,191 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
+use serde::Serialize;
+use std::sync::Arc;
+
+use crate::check::backlinks;
+use crate::dates::{DateWindow, WindowQuery};
+use crate::menu::{Crumb, CurrentPage, Location, SiteNavigation};
+use crate::metadata::image_url;
+use crate::{AppState, display_date, frontmatter, get_nav_links, get_summary_data, page_url};
+
+/// A page as linked to from another one
+#[derive(Serialize)]
+struct PageLink {
+    filename: String,
+    url: String,
+    title: String,
+}
+
+#[derive(Serialize)]
+struct Navigation {
+    prev: Option<PageLink>,
+    next: Option<PageLink>,
+    /// Title of the `[[menu]]` section holding the page, if any
+    section: Option<String>,
+    /// From the home page down to the page itself, through its section
+    breadcrumbs: Vec<Crumb>,
+    backlinks: Vec<PageLink>,
+}
+
+/// A page as listed by `GET /api/pages`
+#[derive(Serialize)]
+struct PageEntry {
+    filename: String,
+    url: String,
+    title: String,
+    /// Publication date as a Unix timestamp
+    datetime: Option<i64>,
+    /// `datetime` formatted with the configured date display, if any
+    date: Option<String>,
+    tags: Vec<String>,
+    excerpt: Option<String>,
+    /// Social image, absolute with a base URL, else from the site root
+    image: Option<String>,
+    /// Only known when serving with a database
+    views: Option<i64>,
+}
+
+#[derive(Serialize)]
+struct PageDetails {
+    filename: String,
+    url: String,
+    title: String,
+    /// Publication date as a Unix timestamp
+    datetime: Option<i64>,
+    tags: Vec<String>,
+    excerpt: Option<String>,
+    /// Social image, absolute with a base URL, else from the site root
+    image: Option<String>,
+    navigation: Navigation,
+}
+
+/// `GET /api/pages`: the pages of the home page, in its order, with their
+/// metadata, for widgets and other frontends. Takes the home page's
+/// `?since=&before=&months=` window.
+pub async fn pages_handler(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<WindowQuery>,
+) -> Response {
+    let window = match query.window(DateWindow::default()) {
+        Ok(window) => window,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+    let store = state.store.as_ref();
+    let pages = match &state.db {
+        Some(db) => match db.summary_data(store).await {
+            Ok(pages) => pages,
+            Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        },
+        None => get_summary_data(store).await,
+    };
+
+    let mut entries = Vec::new();
+    for page in window.filter(pages, |page| page.datetime) {
+        let content = store.read_page(&page.filename).await.unwrap_or_default();
+        let (meta, _) = frontmatter::parse(&content);
+        entries.push(PageEntry {
+            url: page_url(&page.filename, false, state.options.pretty_urls),
+            date: display_date(&state.options, page.datetime),
+            tags: meta.tags,
+            image: page
+                .image
+                .as_deref()
+                .map(|image| image_url(image, state.options.base_url.as_deref(), "")),
+            filename: page.filename,
+            title: page.title,
+            datetime: page.datetime,
+            excerpt: page.excerpt,
+            views: page.views,
+        });
+    }
+    ([("cache-control", "no-store")], Json(entries)).into_response()
+}
+
+/// `GET /api/pages/{page}`: a page's metadata along with the navigation
+/// computed for it, so other frontends don't need to derive it.
+pub async fn page_details_handler(
+    State(state): State<Arc<AppState>>,
+    Path(page): Path<String>,
+) -> Response {
+    let filename = if page.ends_with(".md") {
+        page
+    } else {
+        format!("{}.md", page)
+    };
+    let store = state.store.as_ref();
+    let Ok(content) = store.read_page(&filename).await else {
+        return (StatusCode::NOT_FOUND, "Page not found").into_response();
+    };
+    let (meta, _) = frontmatter::parse(&content);
+
+    let pages = get_summary_data(store).await;
+    let pretty_urls = state.options.pretty_urls;
+    let link = |filename: &str| {
+        pages
+            .iter()
+            .find(|p| p.filename == filename)
+            .map(|p| PageLink {
+                filename: p.filename.clone(),
+                url: page_url(&p.filename, false, pretty_urls),
+                title: p.title.clone(),
+            })
+    };
+    let (Some(page), Some(current)) = (
+        pages.iter().find(|p| p.filename == filename),
+        link(&filename),
+    ) else {
+        return (StatusCode::NOT_FOUND, "Page not found").into_response();
+    };
+
+    // The first page's previous link is the home page, already in breadcrumbs
+    let (prev, next) = if state.options.no_navigation {
+        (None, None)
+    } else {
+        get_nav_links(
+            store,
+            &filename,
+            state.options.nav_order,
+            state.options.language.as_deref(),
+        )
+        .await
+    };
+    let mut sources = backlinks(store).await.remove(&filename).unwrap_or_default();
+    sources.sort();
+
+    let site = SiteNavigation::new(
+        &state.options.menu,
+        &Location::Page(CurrentPage {
+            filename: &filename,
+            title: &current.title,
+            tags: &meta.tags,
+        }),
+        "",
+        "./",
+        false,
+        pretty_urls,
+    );
+    let details = PageDetails {
+        filename: filename.clone(),
+        url: current.url.clone(),
+        title: current.title.clone(),
+        datetime: page.datetime,
+        tags: meta.tags.clone(),
+        excerpt: page.excerpt.clone(),
+        image: page
+            .image
+            .as_deref()
+            .map(|image| image_url(image, state.options.base_url.as_deref(), "")),
+        navigation: Navigation {
+            prev: prev.as_deref().and_then(link),
+            next: next.as_deref().and_then(link),
+            section: site.section,
+            breadcrumbs: site.breadcrumbs,
+            backlinks: sources.iter().filter_map(|s| link(s)).collect(),
+        },
+    };
+    ([("cache-control", "no-store")], Json(details)).into_response()
+}
@@ -0,0 +1,79 @@
+use std::path::{Path, PathBuf};
+
+use crate::TEMPLATES_DIR;
+use crate::config::CONFIG_FILE;
+use crate::images::{copy_without_metadata, has_metadata_format};
+use crate::themes::THEMES_DIR;
+use crate::translations::LOCALES_DIR;
+
+/// Lists the files of `docs_dir` that are not pages, config, templates or themes,
+/// relative to it. Hidden entries and `out_dir` (when nested) are skipped.
+pub fn list_assets(docs_dir: &Path, out_dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut assets = Vec::new();
+    walk(docs_dir, docs_dir, out_dir, &mut assets)?;
+    assets.sort();
+    Ok(assets)
+}
+
+fn walk(root: &Path, dir: &Path, out_dir: &Path, assets: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let relative = path.strip_prefix(root).unwrap().to_path_buf();
+        let name = relative.to_string_lossy();
+
+        if name.starts_with('.') || path == out_dir {
+            continue;
+        }
+        if path.is_dir() {
+            if dir == root && (name == TEMPLATES_DIR || name == THEMES_DIR || name == LOCALES_DIR) {
+                continue;
+            }
+            walk(root, &path, out_dir, assets)?;
+        } else if dir == root && (name.ends_with(".md") || name == CONFIG_FILE) {
+            continue;
+        } else {
+            assets.push(relative);
+        }
+    }
+    Ok(())
+}
+
+/// Copies the assets of `docs_dir` into `out_dir`, skipping those rejected
+/// by `keep`, and with `strip_metadata`, leaving out the metadata of images.
+/// Nothing is copied when building in place.
+pub async fn copy_assets(
+    docs_dir: &Path,
+    out_dir: &Path,
+    keep: impl Fn(&Path) -> bool,
+    strip_metadata: bool,
+) -> anyhow::Result<()> {
+    if docs_dir == out_dir {
+        return Ok(());
+    }
+    for asset in list_assets(docs_dir, out_dir)? {
+        if !keep(&asset) {
+            continue;
+        }
+        let target = out_dir.join(&asset);
+        if let Some(parent) = target.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let source = docs_dir.join(&asset);
+        if strip_metadata && has_metadata_format(&asset) {
+            let (from, to) = (source.clone(), target.clone());
+            let stripped =
+                tokio::task::spawn_blocking(move || copy_without_metadata(&from, &to)).await?;
+            match stripped {
+                Ok(true) => tracing::info!("Removed the metadata of {}", asset.display()),
+                Ok(false) => {}
+                Err(e) => {
+                    tracing::warn!("Copying {} as is: {}", asset.display(), e);
+                    tokio::fs::copy(source, &target).await?;
+                }
+            }
+            continue;
+        }
+        tokio::fs::copy(source, &target).await?;
+    }
+    Ok(())
+}
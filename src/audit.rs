@@ -0,0 +1,379 @@
+use serde::Serialize;
+use serde_json::{Value, json};
+use std::fmt;
+use std::path::Path;
+use tera::Context;
+
+use crate::translations;
+use crate::{EMBEDDED_TEMPLATES, RenderOptions, TEMPLATES, read_template_overrides};
+
+/// Injected in place of untrusted strings; it must never come out unescaped
+const CANARY: &str = "\"'><audit-canary>";
+const CANARY_TAG: &str = "<audit-canary>";
+
+/// Variables that hold trusted HTML and are expected to go through `| safe`
+const TRUSTED_HTML: &[&str] = &["content", "column.content", "page.content"];
+
+#[derive(Serialize)]
+pub struct Finding {
+    pub template: String,
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.template, self.message)
+    }
+}
+
+/// Audits the active templates for injection issues: every `| safe` applied
+/// to something other than rendered markdown is flagged, then each template
+/// is rendered with untrusted strings (titles, frontmatter values) replaced
+/// by markup that must come out escaped.
+pub fn audit_templates(docs_dir: &Path, theme: Option<&str>) -> anyhow::Result<Vec<Finding>> {
+    let mut findings = Vec::new();
+
+    let overrides = read_template_overrides(docs_dir, theme)?;
+    for (name, embedded) in EMBEDDED_TEMPLATES {
+        let source = overrides
+            .iter()
+            .find(|(n, _)| n == name)
+            .map_or(*embedded, |(_, s)| s.as_str());
+        findings.extend(scan_safe_filters(name, source));
+    }
+
+    for (template, variable, context) in canary_contexts() {
+        let mut context = Context::from_value(context)?;
+        translations::insert_into(&RenderOptions::default(), &mut context);
+        let rendered = TEMPLATES.read().unwrap().render(template, &context)?;
+        if rendered.contains(CANARY_TAG) {
+            findings.push(Finding {
+                template: template.to_string(),
+                message: format!("`{}` is rendered without escaping", variable),
+            });
+        }
+    }
+    Ok(findings)
+}
+
+/// Finds `{{ ... | safe }}` expressions on values not known to be trusted.
+fn scan_safe_filters(template: &str, source: &str) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    for (number, line) in source.lines().enumerate() {
+        let mut rest = line;
+        while let Some(start) = rest.find("{{") {
+            let Some(end) = rest[start..].find("}}") else {
+                break;
+            };
+            let expression = rest[start + 2..start + end].trim();
+            rest = &rest[start + end + 2..];
+
+            let mut parts = expression.split('|').map(str::trim);
+            let variable = parts.next().unwrap_or_default();
+            if parts.any(|filter| filter == "safe") && !TRUSTED_HTML.contains(&variable) {
+                findings.push(Finding {
+                    template: template.to_string(),
+                    message: format!(
+                        "line {}: `{{{{ {} }}}}` marks a possibly untrusted value as safe",
+                        number + 1,
+                        expression
+                    ),
+                });
+            }
+        }
+    }
+    findings
+}
+
+/// One render context per (template, untrusted variable), where only that
+/// variable holds the canary. Keep in sync with the contexts built for
+/// `home.html`, `page.html`, `languages.html`, `compare.html`,
+/// `series.html`, `not_found.html`, `reader.html`, `changes.html`,
+/// `print.html` and `all.html`.
+fn canary_contexts() -> Vec<(&'static str, &'static str, Value)> {
+    let page = |title: &str, filename: &str, excerpt: &str| {
+        json!({
+            "filename": filename,
+            "title": title,
+            "datetime": 0,
+            "weight": null,
+            "excerpt": excerpt,
+            "views": null,
+            "date": null,
+        })
+    };
+    let home = |files: Value| {
+        json!({
+            "title": "Pages",
+            "files": [files],
+            "is_static": true,
+            "canonical": null,
+            "root": "",
+            "home_url": "./",
+        })
+    };
+    let page_context = |title: &str, canonical: &str| {
+        json!({
+            "title": title,
+            "content": "",
+            "prev_page": null,
+            "next_page": null,
+            "no_navigation": false,
+            "is_static": true,
+            "canonical": canonical,
+            "root": "",
+            "home_url": "./",
+        })
+    };
+
+    let reader = |title: &str, date: &str| {
+        json!({
+            "title": title,
+            "content": "",
+            "page_url": "/a.md",
+            "date": date,
+            "root": "/",
+        })
+    };
+
+    vec![
+        ("reader.html", "title", reader(CANARY, "")),
+        (
+            "all.html",
+            "pages[].title",
+            json!({
+                "title": "",
+                "pages": [{ "id": "page-a", "title": CANARY, "content": "" }],
+            }),
+        ),
+        (
+            "print.html",
+            "title",
+            json!({
+                "title": CANARY,
+                "base_url": "file:///blog/",
+                "pages": [{ "title": CANARY, "content": "" }],
+            }),
+        ),
+        ("reader.html", "date", reader("a", CANARY)),
+        (
+            "home.html",
+            "files[].title",
+            home(page(CANARY, "a.html", "")),
+        ),
+        ("home.html", "files[].filename", home(page("a", CANARY, ""))),
+        (
+            "home.html",
+            "files[].excerpt",
+            home(page("a", "a.html", CANARY)),
+        ),
+        (
+            "home.html",
+            "canonical",
+            json!({
+                "title": "Pages",
+                "files": [],
+                "is_static": true,
+                "canonical": CANARY,
+                "root": "",
+                "home_url": "./",
+            }),
+        ),
+        ("page.html", "title", page_context(CANARY, "")),
+        ("page.html", "canonical", page_context("a", CANARY)),
+        (
+            "page.html",
+            "prev_page",
+            json!({
+                "title": "a",
+                "content": "",
+                "is_static": true,
+                "root": "",
+                "home_url": "./",
+                "prev_page": CANARY,
+                "next_page": CANARY,
+            }),
+        ),
+        (
+            "page.html",
+            "related_pages[].title",
+            json!({
+                "title": "a",
+                "content": "",
+                "is_static": true,
+                "root": "",
+                "home_url": "./",
+                "related_pages": [{ "url": "b.html", "title": CANARY }],
+            }),
+        ),
+        (
+            "page.html",
+            "alternates[].url",
+            json!({
+                "title": "a",
+                "content": "",
+                "is_static": true,
+                "root": "",
+                "home_url": "./",
+                "alternates": [
+                    { "lang": "en", "url": "a.html" },
+                    { "lang": "fr", "url": CANARY },
+                ],
+            }),
+        ),
+        (
+            "page.html",
+            "webmentions[]",
+            json!({
+                "title": "a",
+                "content": "",
+                "is_static": false,
+                "root": "",
+                "home_url": "./",
+                "webmentions": [CANARY],
+            }),
+        ),
+        (
+            "page.html",
+            "comments.attributes",
+            json!({
+                "title": "a",
+                "content": "",
+                "is_static": true,
+                "root": "",
+                "home_url": "./",
+                "comments": {
+                    "provider": "giscus",
+                    "src": "https://giscus.app/client.js",
+                    "attributes": CANARY,
+                    "theme_attribute": "data-theme",
+                    "theme_light": CANARY,
+                    "theme_dark": CANARY,
+                },
+            }),
+        ),
+        (
+            "home.html",
+            "analytics.attributes[].value",
+            json!({
+                "title": "Pages",
+                "files": [],
+                "is_static": true,
+                "canonical": null,
+                "root": "",
+                "home_url": "./",
+                "analytics": {
+                    "src": "https://plausible.io/js/script.js",
+                    "attributes": [{ "name": "data-domain", "value": CANARY }],
+                },
+            }),
+        ),
+        (
+            "languages.html",
+            "languages[].url",
+            json!({
+                "title": "Languages",
+                "root": "",
+                "home_url": "./",
+                "languages": [{ "lang": "fr", "url": CANARY }],
+            }),
+        ),
+        (
+            "page.html",
+            "series.parts[].title",
+            json!({
+                "title": "a",
+                "content": "",
+                "is_static": true,
+                "root": "",
+                "home_url": "./",
+                "series": {
+                    "name": "s",
+                    "anchor": "series-s",
+                    "position": 1,
+                    "parts": [
+                        { "url": "a.html", "title": CANARY, "current": true },
+                        { "url": "b.html", "title": CANARY, "current": false },
+                    ],
+                },
+                "series_url": "series.html#series-s",
+            }),
+        ),
+        (
+            "page.html",
+            "series.name",
+            json!({
+                "title": "a",
+                "content": "",
+                "is_static": true,
+                "root": "",
+                "home_url": "./",
+                "series": { "name": CANARY, "anchor": "series-s", "position": 1, "parts": [] },
+                "series_url": "series.html#series-s",
+            }),
+        ),
+        (
+            "series.html",
+            "series[].name",
+            json!({
+                "title": "Series",
+                "root": "",
+                "home_url": "./",
+                "series": [{ "name": CANARY, "anchor": "series-s", "position": 0, "parts": [] }],
+            }),
+        ),
+        (
+            "series.html",
+            "series[].parts[].title",
+            json!({
+                "title": "Series",
+                "root": "",
+                "home_url": "./",
+                "series": [{
+                    "name": "s",
+                    "anchor": "series-s",
+                    "position": 0,
+                    "parts": [{ "url": "a.html", "title": CANARY, "current": false }],
+                }],
+            }),
+        ),
+        (
+            "changes.html",
+            "changes[].title",
+            json!({
+                "title": "What's new",
+                "root": "",
+                "home_url": "./",
+                "feed_url": null,
+                "changes": [{
+                    "url": "a.html",
+                    "title": CANARY,
+                    "kind": "added",
+                    "datetime": 0,
+                    "date": "1970-01-01",
+                }],
+            }),
+        ),
+        (
+            "not_found.html",
+            "suggestions[].title",
+            json!({
+                "title": "Page not found",
+                "root": "/",
+                "home_url": "/",
+                "search_root": "/",
+                "suggestions": [{ "url": "/a.md", "title": CANARY }],
+            }),
+        ),
+        (
+            "compare.html",
+            "columns[].filename",
+            json!({
+                "title": "a",
+                "root": "../",
+                "home_url": "../",
+                "columns": [{ "lang": "fr", "filename": CANARY, "content": "" }],
+            }),
+        ),
+    ]
+}
@@ -0,0 +1,162 @@
+use axum::{
+    Router,
+    extract::{Request, State},
+    http::{HeaderMap, HeaderValue, Method, StatusCode, header},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::post,
+};
+use ring::hmac;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Route of the webhook purging the CDN's cache
+pub const PURGE_HOOK_PATH: &str = "/hooks/purge";
+
+/// Header telling CDNs, and only them, how long to cache a response
+const SURROGATE_CONTROL: &str = "surrogate-control";
+
+/// How the server helps a CDN in front of it, see the `cache_*`,
+/// `surrogate_*` and `cdn_*` settings
+#[derive(Clone, Debug, Default)]
+pub struct CdnOptions {
+    /// `max-age` of `Cache-Control`, in seconds
+    pub max_age: Option<u64>,
+    /// `max-age` of `Surrogate-Control`, in seconds
+    pub surrogate_max_age: Option<u64>,
+    /// Seconds a stale response may be served while it is fetched again
+    pub stale_while_revalidate: Option<u64>,
+    /// URL `POST /hooks/purge` sends a POST request to
+    pub purge_url: Option<String>,
+    /// JSON body of the purge request, such as `{"purge_everything":true}`
+    pub purge_body: Option<String>,
+    /// Bearer token of the purge request
+    pub purge_token: Option<String>,
+    /// Bearer token callers of the webhook must send, which enables it
+    pub hook_secret: Option<String>,
+}
+
+impl CdnOptions {
+    fn cache_control(&self) -> Option<String> {
+        let max_age = self.max_age?;
+        let mut value = format!("public, max-age={}", max_age);
+        if let Some(stale) = self.stale_while_revalidate {
+            value.push_str(&format!(", stale-while-revalidate={}", stale));
+        }
+        Some(value)
+    }
+
+    fn surrogate_control(&self) -> Option<String> {
+        let max_age = self.surrogate_max_age?;
+        let mut value = format!("max-age={}", max_age);
+        if let Some(stale) = self.stale_while_revalidate {
+            value.push_str(&format!(", stale-while-revalidate={}", stale));
+        }
+        Some(value)
+    }
+}
+
+/// Adds the caching headers of `options` to the successful GET responses of
+/// `router` that don't set `Cache-Control` themselves, and mounts the purge
+/// webhook when it has a secret and a URL to call.
+pub fn with_cdn(router: Router, options: CdnOptions) -> Router {
+    let options = Arc::new(options);
+    let mut router = router;
+    if options.hook_secret.is_some() && options.purge_url.is_some() {
+        router = router.route(
+            PURGE_HOOK_PATH,
+            post(purge_handler).with_state(options.clone()),
+        );
+    }
+    if options.max_age.is_none() && options.surrogate_max_age.is_none() {
+        return router;
+    }
+    router.layer(middleware::from_fn_with_state(options, add_cache_headers))
+}
+
+async fn add_cache_headers(
+    State(options): State<Arc<CdnOptions>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let cacheable = matches!(*request.method(), Method::GET | Method::HEAD);
+    let mut response = next.run(request).await;
+    if !cacheable
+        || !response.status().is_success()
+        || response.headers().contains_key(header::CACHE_CONTROL)
+    {
+        return response;
+    }
+
+    let headers = response.headers_mut();
+    if let Some(value) = options.cache_control()
+        && let Ok(value) = HeaderValue::from_str(&value)
+    {
+        headers.insert(header::CACHE_CONTROL, value);
+    }
+    if let Some(value) = options.surrogate_control()
+        && let Ok(value) = HeaderValue::from_str(&value)
+    {
+        headers.insert(SURROGATE_CONTROL, value);
+    }
+    response
+}
+
+/// `POST /hooks/purge`: asks the CDN to drop its cache, for publishing
+/// scripts to call once the content changed.
+async fn purge_handler(State(options): State<Arc<CdnOptions>>, headers: HeaderMap) -> Response {
+    let authorized = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .zip(options.hook_secret.as_deref())
+        .is_some_and(|(token, secret)| is_secret(token, secret));
+    if !authorized {
+        return (StatusCode::UNAUTHORIZED, "Invalid purge secret").into_response();
+    }
+
+    match purge(&options).await {
+        Ok(()) => {
+            tracing::info!("Purged the CDN cache");
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(e) => {
+            tracing::warn!("Failed to purge the CDN cache: {}", e);
+            (StatusCode::BAD_GATEWAY, e.to_string()).into_response()
+        }
+    }
+}
+
+/// Whether `token` is `secret`, compared in constant time through HMACs
+/// keyed with each, not to leak how much of the secret a caller guessed
+fn is_secret(token: &str, secret: &str) -> bool {
+    let expected = hmac::sign(
+        &hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes()),
+        PURGE_HOOK_PATH.as_bytes(),
+    );
+    let key = hmac::Key::new(hmac::HMAC_SHA256, token.as_bytes());
+    hmac::verify(&key, PURGE_HOOK_PATH.as_bytes(), expected.as_ref()).is_ok()
+}
+
+async fn purge(options: &CdnOptions) -> anyhow::Result<()> {
+    let Some(url) = &options.purge_url else {
+        anyhow::bail!("No purge URL configured");
+    };
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(30))
+        .build()?;
+    let mut request = client.post(url);
+    if let Some(token) = &options.purge_token {
+        request = request.bearer_auth(token);
+    }
+    if let Some(body) = &options.purge_body {
+        request = request
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body.clone());
+    }
+    let response = request.send().await?;
+    if !response.status().is_success() {
+        anyhow::bail!("{} answered {}", url, response.status());
+    }
+    Ok(())
+}
@@ -0,0 +1,141 @@
+use chrono::DateTime;
+use serde::Serialize;
+use std::time::{Duration, SystemTime};
+use tera::Context;
+
+use crate::analytics;
+use crate::menu::{Location, SiteNavigation};
+use crate::store::ContentStore;
+use crate::translations::{self, ui_string};
+use crate::{RenderOptions, TEMPLATES, display_date, get_summary_data, link_roots, page_url};
+
+/// Name of the generated "What's new" page, as if it were a page
+pub const CHANGES_FILE: &str = "changes.md";
+
+/// Atom feed of the changes, next to the main `FEED_FILE`
+pub const CHANGES_FEED_FILE: &str = "changes.xml";
+
+/// Most changes listed on the page and in the feed
+const MAX_CHANGES: usize = 50;
+
+/// Edits this soon after a page was added still count as adding it
+const ADDED_GRACE: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Added,
+    Updated,
+}
+
+/// The latest change of a page
+#[derive(Serialize, Debug, Clone)]
+pub struct Change {
+    pub filename: String,
+    pub title: String,
+    pub kind: ChangeKind,
+    /// When the change was made, as a Unix timestamp
+    pub datetime: i64,
+}
+
+/// The latest change of each page, newest first. Pages are added when first
+/// committed, or created when git doesn't track them; stores that don't
+/// know fall back on the page's date.
+pub async fn collect_changes(store: &dyn ContentStore) -> Vec<Change> {
+    let mut changes = Vec::new();
+    for page in get_summary_data(store).await {
+        let Some(history) = store.history(&page.filename).await else {
+            continue;
+        };
+        let added = history.added.or_else(|| {
+            let secs = u64::try_from(page.datetime?).ok()?;
+            Some(SystemTime::UNIX_EPOCH + Duration::from_secs(secs))
+        });
+        let kind = match added {
+            Some(added) if history.updated <= added + ADDED_GRACE => ChangeKind::Added,
+            _ => ChangeKind::Updated,
+        };
+        let Ok(since_epoch) = history.updated.duration_since(SystemTime::UNIX_EPOCH) else {
+            continue;
+        };
+        changes.push(Change {
+            filename: page.filename,
+            title: page.title,
+            kind,
+            datetime: since_epoch.as_secs() as i64,
+        });
+    }
+    changes.sort_by_key(|change| std::cmp::Reverse(change.datetime));
+    changes.truncate(MAX_CHANGES);
+    changes
+}
+
+/// A change as listed on the "What's new" page
+#[derive(Serialize)]
+struct ListedChange {
+    url: String,
+    title: String,
+    kind: ChangeKind,
+    datetime: i64,
+    date: String,
+}
+
+/// Renders the "What's new" page listing the latest changes.
+pub async fn render_changes_page(
+    store: &dyn ContentStore,
+    options: &RenderOptions,
+    is_static: bool,
+) -> anyhow::Result<String> {
+    let (root, home_url) = link_roots(options, is_static, true);
+    let changes: Vec<ListedChange> = collect_changes(store)
+        .await
+        .into_iter()
+        .map(|change| ListedChange {
+            url: format!(
+                "{}{}",
+                root,
+                page_url(&change.filename, is_static, options.pretty_urls)
+            ),
+            date: display_date(options, Some(change.datetime)).unwrap_or_else(|| {
+                DateTime::from_timestamp(change.datetime, 0)
+                    .unwrap_or_default()
+                    .format("%Y-%m-%d")
+                    .to_string()
+            }),
+            title: change.title,
+            kind: change.kind,
+            datetime: change.datetime,
+        })
+        .collect();
+
+    let mut context = Context::new();
+    context.insert("root", &root);
+    context.insert("home_url", &home_url);
+    SiteNavigation::new(
+        &options.menu,
+        &Location::Elsewhere,
+        &root,
+        &home_url,
+        is_static,
+        options.pretty_urls,
+    )
+    .insert_into(&mut context);
+    analytics::insert_into(options, &mut context);
+    translations::insert_into(options, &mut context);
+    context.insert("title", ui_string(options, "whats_new"));
+    context.insert("changes", &changes);
+    context.insert(
+        "feed_url",
+        &options
+            .base_url
+            .as_ref()
+            .map(|_| format!("{}{}", root, CHANGES_FEED_FILE)),
+    );
+    context.insert("is_static", &is_static);
+    context.insert("no_navigation", &options.no_navigation);
+    context.insert("canonical", &None::<String>);
+    if let Some(language) = &options.language {
+        context.insert("lang", language);
+    }
+    Ok(TEMPLATES.read().unwrap().render("changes.html", &context)?)
+}
@@ -0,0 +1,338 @@
+use pulldown_cmark::{Event, Options, Parser as MarkdownParser, Tag};
+use reqwest::StatusCode;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::alt_text::{MissingAlt, missing_alt};
+use crate::anchors::page_anchors;
+use crate::assets::list_assets;
+use crate::config::Config;
+use crate::languages::is_subtree_page;
+use crate::store::{ContentStore, LocalStore};
+use crate::{build_artifacts, dates, frontmatter, get_summary_data, nav_files};
+
+/// Pages the orphan walk starts from
+const ENTRY_PAGES: &[&str] = &["SUMMARY.md", "index.md"];
+
+/// Problems found by `blog check`
+#[derive(Default, Serialize)]
+pub struct CheckReport {
+    /// Pages no link or previous/next link leads to from the entry pages
+    pub orphans: Vec<String>,
+    /// Files of the docs directory no page references
+    pub unused_assets: Vec<PathBuf>,
+    /// Pages whose date can't be parsed, with the reason
+    pub invalid_dates: Vec<(String, String)>,
+    /// Images without alt text
+    pub missing_alt: Vec<MissingAlt>,
+}
+
+impl CheckReport {
+    pub fn is_empty(&self) -> bool {
+        self.orphans.is_empty()
+            && self.unused_assets.is_empty()
+            && self.invalid_dates.is_empty()
+            && self.missing_alt.is_empty()
+    }
+}
+
+pub async fn run_check(docs_dir: &Path, no_navigation: bool) -> anyhow::Result<CheckReport> {
+    let store = LocalStore::new(docs_dir.to_path_buf());
+    let links = store_links(&store).await;
+    let artifacts = build_artifacts(docs_dir, docs_dir).await?;
+    let config = Config::load(docs_dir)?;
+
+    // Previous and next links are edges too, unless they aren't rendered
+    let mut edges = links.clone();
+    if !no_navigation {
+        let order = nav_files(
+            &store,
+            config.nav_order.unwrap_or_default(),
+            config.locale.as_deref(),
+        )
+        .await;
+        for pair in order.windows(2) {
+            edges
+                .entry(pair[0].clone())
+                .or_default()
+                .push(pair[1].clone());
+            edges
+                .entry(pair[1].clone())
+                .or_default()
+                .push(pair[0].clone());
+        }
+    }
+
+    // Walk the link graph from the entry points
+    let mut reachable = BTreeSet::new();
+    let mut queue: VecDeque<&str> = ENTRY_PAGES
+        .iter()
+        .copied()
+        .filter(|page| links.contains_key(*page))
+        .collect();
+    while let Some(page) = queue.pop_front() {
+        if !reachable.insert(page) {
+            continue;
+        }
+        for target in edges.get(page).into_iter().flatten() {
+            if links.contains_key(target) {
+                queue.push_back(target);
+            }
+        }
+    }
+
+    let referenced = referenced_files(&links);
+    let languages = config.languages;
+    let unused_assets = list_assets(docs_dir, docs_dir)?
+        .into_iter()
+        .filter(|asset| {
+            let name = asset.to_string_lossy();
+            !referenced.contains(name.as_ref())
+                && !artifacts.iter().any(|a| *a == name)
+                && !is_subtree_page(asset, &languages)
+        })
+        .collect();
+
+    let mut orphans: Vec<String> = links
+        .keys()
+        .filter(|page| !reachable.contains(page.as_str()))
+        .cloned()
+        .collect();
+    orphans.sort();
+
+    Ok(CheckReport {
+        orphans,
+        unused_assets,
+        invalid_dates: invalid_dates(docs_dir).await?,
+        missing_alt: missing_alt(docs_dir).await?,
+    })
+}
+
+async fn invalid_dates(docs_dir: &Path) -> anyhow::Result<Vec<(String, String)>> {
+    let store = LocalStore::new(docs_dir.to_path_buf());
+    let mut invalid = Vec::new();
+    for filename in store.list_pages().await? {
+        let content = store.read_page(&filename).await?;
+        let (meta, _) = frontmatter::parse(&content);
+        if let Err(e) = dates::page_date(&filename, &meta) {
+            invalid.push((filename, e.to_string()));
+        }
+    }
+    invalid.sort();
+    Ok(invalid)
+}
+
+/// Returns every file referenced by a page of `docs_dir`, relative to it.
+pub async fn referenced_assets(docs_dir: &Path) -> BTreeSet<String> {
+    referenced_files(&collect_links(docs_dir).await)
+}
+
+fn referenced_files(links: &HashMap<String, Vec<String>>) -> BTreeSet<String> {
+    links.values().flatten().cloned().collect()
+}
+
+async fn collect_links(docs_dir: &Path) -> HashMap<String, Vec<String>> {
+    store_links(&LocalStore::new(docs_dir.to_path_buf())).await
+}
+
+/// Maps each page to the local files its links and images point at, as
+/// paths relative to the docs directory. Links to pages are normalized to
+/// `name.md`.
+pub async fn store_links(store: &dyn ContentStore) -> HashMap<String, Vec<String>> {
+    let mut links = HashMap::new();
+    for page in get_summary_data(store).await {
+        let content = store.read_page(&page.filename).await.unwrap_or_default();
+        let (_, body) = frontmatter::parse(&content);
+        links.insert(page.filename, page_links(body));
+    }
+    links
+}
+
+/// Maps each page to the pages linking to it, in no particular order.
+pub async fn backlinks(store: &dyn ContentStore) -> HashMap<String, Vec<String>> {
+    let mut backlinks: HashMap<String, Vec<String>> = HashMap::new();
+    for (source, targets) in store_links(store).await {
+        for target in targets {
+            let sources = backlinks.entry(target).or_default();
+            if !sources.contains(&source) {
+                sources.push(source.clone());
+            }
+        }
+    }
+    backlinks
+}
+
+/// The local files the links and images of `markdown` point at, see
+/// `local_target`
+pub fn page_links(markdown: &str) -> Vec<String> {
+    MarkdownParser::new_ext(markdown, Options::ENABLE_TABLES | Options::ENABLE_FOOTNOTES)
+        .filter_map(|event| match event {
+            Event::Start(Tag::Link { dest_url, .. } | Tag::Image { dest_url, .. }) => {
+                local_target(&dest_url)
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Resolves a link destination to a file of the docs directory, or `None`
+/// for external URLs and same-page anchors.
+pub fn local_target(url: &str) -> Option<String> {
+    if url.contains("://") || url.starts_with("mailto:") || url.starts_with('#') {
+        return None;
+    }
+    let path = url.split(['#', '?']).next()?;
+    let path = path.trim_start_matches("./").trim_start_matches('/');
+    if path.is_empty() {
+        return None;
+    }
+
+    let file = Path::new(path);
+    match file.extension().and_then(|e| e.to_str()) {
+        Some("md") => Some(path.to_string()),
+        Some("html") => Some(file.with_extension("md").to_string_lossy().into_owned()),
+        None => Some(format!("{}.md", path)),
+        Some(_) => Some(path.to_string()),
+    }
+}
+
+/// A link of a page that leads nowhere
+#[derive(Serialize)]
+pub struct BrokenLink {
+    pub page: String,
+    /// 1-based line of the link in the page's file
+    pub line: usize,
+    pub url: String,
+    pub reason: String,
+}
+
+/// Finds the links and images of the pages of `docs_dir` pointing at
+/// missing files or headings. With `external`, web URLs are requested too,
+/// and those that don't answer successfully are reported.
+pub async fn check_links(docs_dir: &Path, external: bool) -> anyhow::Result<Vec<BrokenLink>> {
+    let store = LocalStore::new(docs_dir.to_path_buf());
+    let artifacts = build_artifacts(docs_dir, docs_dir).await?;
+    let mut pages = HashMap::new();
+    for filename in store.list_pages().await? {
+        let content = store.read_page(&filename).await?;
+        pages.insert(filename, content);
+    }
+    let anchors: HashMap<&str, BTreeSet<String>> = pages
+        .iter()
+        .map(|(filename, content)| {
+            let (_, body) = frontmatter::parse(content);
+            (filename.as_str(), page_anchors(filename, body))
+        })
+        .collect();
+
+    let mut broken = Vec::new();
+    let mut web_links = Vec::new();
+    for (filename, content) in &pages {
+        let (_, body) = frontmatter::parse(content);
+        let body_start = content.len() - body.len();
+        let parser =
+            MarkdownParser::new_ext(body, Options::ENABLE_TABLES | Options::ENABLE_FOOTNOTES);
+        for (event, range) in parser.into_offset_iter() {
+            let Event::Start(Tag::Link { dest_url, .. } | Tag::Image { dest_url, .. }) = event
+            else {
+                continue;
+            };
+            let line = content[..body_start + range.start].matches('\n').count() + 1;
+            let link = |reason: String| BrokenLink {
+                page: filename.clone(),
+                line,
+                url: dest_url.to_string(),
+                reason,
+            };
+
+            if let Ok(url) = reqwest::Url::parse(&dest_url) {
+                if matches!(url.scheme(), "http" | "https") {
+                    web_links.push(link(String::new()));
+                }
+                continue;
+            }
+            let reason = local_link_error(filename, &dest_url, docs_dir, &anchors, &artifacts);
+            if let Some(reason) = reason {
+                broken.push(link(reason));
+            }
+        }
+    }
+
+    if external {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()?;
+        let mut checked: HashMap<String, Option<String>> = HashMap::new();
+        for mut link in web_links {
+            if !checked.contains_key(&link.url) {
+                let error = web_link_error(&client, &link.url).await;
+                checked.insert(link.url.clone(), error);
+            }
+            if let Some(reason) = &checked[&link.url] {
+                link.reason = reason.clone();
+                broken.push(link);
+            }
+        }
+    }
+
+    broken.sort_by(|a, b| (&a.page, a.line).cmp(&(&b.page, b.line)));
+    Ok(broken)
+}
+
+/// Why a link to the docs directory leads nowhere, if it does.
+fn local_link_error(
+    page: &str,
+    url: &str,
+    docs_dir: &Path,
+    anchors: &HashMap<&str, BTreeSet<String>>,
+    artifacts: &[String],
+) -> Option<String> {
+    let (path, fragment) = url.split_once('#').unwrap_or((url, ""));
+    let path = path.split('?').next().unwrap_or_default();
+    let path = path.trim_start_matches("./").trim_start_matches('/');
+
+    // Same-page anchors and the home page
+    let target = if path.is_empty() {
+        if url.starts_with('#') {
+            page.to_string()
+        } else {
+            return None;
+        }
+    } else if let Some(dir) = path.strip_suffix('/') {
+        format!("{}.md", dir)
+    } else {
+        local_target(path)?
+    };
+
+    let Some(targets) = anchors.get(target.as_str()) else {
+        let generated = artifacts
+            .iter()
+            .any(|a| a == path || *a == format!("{}index.html", path));
+        if generated || docs_dir.join(&target).is_file() {
+            return None;
+        }
+        return Some(format!("no file {}", target));
+    };
+    if fragment.is_empty() || targets.contains(fragment) {
+        return None;
+    }
+    Some(format!("no heading #{} in {}", fragment, target))
+}
+
+/// Why a web URL doesn't answer successfully, if it doesn't. Servers that
+/// refuse HEAD requests are asked with GET.
+async fn web_link_error(client: &reqwest::Client, url: &str) -> Option<String> {
+    let response = match client.head(url).send().await {
+        Ok(response) if response.status() == StatusCode::METHOD_NOT_ALLOWED => {
+            client.get(url).send().await
+        }
+        response => response,
+    };
+    match response {
+        Ok(response) if response.status().is_success() => None,
+        Ok(response) => Some(response.status().to_string()),
+        Err(e) => Some(e.to_string()),
+    }
+}
@@ -0,0 +1,490 @@
+use pulldown_cmark::{CowStr, Event, Tag, TagEnd};
+use pulldown_cmark_escape::{escape_href, escape_html};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+use crate::store::ContentStore;
+
+/// An entry of the bibliography, as listed in a page's references
+#[derive(Debug, Clone, Default)]
+pub struct Reference {
+    pub authors: Vec<String>,
+    pub title: Option<String>,
+    pub year: Option<String>,
+    /// Journal, book or proceedings the work appeared in, else its publisher
+    pub container: Option<String>,
+    /// The DOI as a URL, else the entry's URL
+    pub url: Option<String>,
+}
+
+/// Works pages cite with `[@key]`, read from a BibTeX or CSL-JSON file
+#[derive(Default)]
+pub struct Bibliography {
+    references: HashMap<String, Reference>,
+}
+
+impl Bibliography {
+    /// Reads the bibliography at `path` in `store`: CSL-JSON when it ends
+    /// with `.json`, BibTeX otherwise. Empty, with a warning, when it can't
+    /// be read.
+    pub async fn load(store: &dyn ContentStore, path: &str) -> Self {
+        let content = match store.read_file(path).await {
+            Ok(content) => content,
+            Err(e) => {
+                tracing::warn!("Failed to read the bibliography {}: {}", path, e);
+                return Self::default();
+            }
+        };
+        let references = if path.ends_with(".json") {
+            match parse_csl_json(&content) {
+                Ok(references) => references,
+                Err(e) => {
+                    tracing::warn!("Invalid bibliography {}: {}", path, e);
+                    return Self::default();
+                }
+            }
+        } else {
+            parse_bibtex(&content)
+        };
+        Self {
+            references: references.into_iter().collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.references.is_empty()
+    }
+}
+
+/// Entries of a BibTeX file, by key. `@string`, `@preamble` and `@comment`
+/// are skipped, and the LaTeX of values is reduced to plain text.
+pub fn parse_bibtex(content: &str) -> Vec<(String, Reference)> {
+    let mut references = Vec::new();
+    let mut rest = content;
+    while let Some(at) = rest.find('@') {
+        rest = &rest[at + 1..];
+        let Some(open) = rest.find(['{', '(']) else {
+            break;
+        };
+        let kind = rest[..open].trim().to_ascii_lowercase();
+        let Some(length) = balanced(&rest[open..]) else {
+            break;
+        };
+        let entry = &rest[open + 1..open + length - 1];
+        rest = &rest[open + length..];
+        if matches!(kind.as_str(), "string" | "preamble" | "comment") {
+            continue;
+        }
+
+        let Some((key, fields)) = entry.split_once(',') else {
+            continue;
+        };
+        let fields = bibtex_fields(fields);
+        let field = |name: &str| fields.get(name).filter(|value| !value.is_empty()).cloned();
+        let reference = Reference {
+            authors: field("author")
+                .or_else(|| field("editor"))
+                .map(|names| names.split(" and ").map(bibtex_name).collect())
+                .unwrap_or_default(),
+            title: field("title"),
+            year: field("year").or_else(|| {
+                field("date").map(|date| date.chars().take_while(|c| *c != '-').collect())
+            }),
+            container: field("journal")
+                .or_else(|| field("journaltitle"))
+                .or_else(|| field("booktitle"))
+                .or_else(|| field("publisher"))
+                .or_else(|| field("institution")),
+            url: field("doi")
+                .map(|doi| doi_url(&doi))
+                .or_else(|| field("url")),
+        };
+        references.push((key.trim().to_string(), reference));
+    }
+    references
+}
+
+/// Length of the `{...}` or `(...)` group `text` starts with, brackets
+/// included
+fn balanced(text: &str) -> Option<usize> {
+    let (open, close) = match text.chars().next()? {
+        '(' => ('(', ')'),
+        _ => ('{', '}'),
+    };
+    let mut depth = 0;
+    for (i, c) in text.char_indices() {
+        if c == open {
+            depth += 1;
+        } else if c == close {
+            depth -= 1;
+            if depth == 0 {
+                return Some(i + 1);
+            }
+        }
+    }
+    None
+}
+
+/// `name = {value}`, `name = "value"` and `name = 1984` fields, by
+/// lowercase name
+fn bibtex_fields(fields: &str) -> HashMap<String, String> {
+    let mut values = HashMap::new();
+    let mut rest = fields;
+    while let Some(equals) = rest.find('=') {
+        let name = rest[..equals].trim().trim_start_matches(',').trim();
+        let value = rest[equals + 1..].trim_start();
+        let (raw, length) = match value.chars().next() {
+            Some('{') => match balanced(value) {
+                Some(length) => (&value[1..length - 1], length),
+                None => (&value[1..], value.len()),
+            },
+            Some('"') => match value[1..].find('"') {
+                Some(end) => (&value[1..end + 1], end + 2),
+                None => (&value[1..], value.len()),
+            },
+            _ => {
+                let end = value.find(',').unwrap_or(value.len());
+                (value[..end].trim(), end)
+            }
+        };
+        values.insert(name.to_ascii_lowercase(), plain_text(raw));
+        rest = &value[length..];
+    }
+    values
+}
+
+/// The text of a BibTeX value, without its braces, commands and line breaks
+fn plain_text(latex: &str) -> String {
+    let text = latex
+        .replace("---", "—")
+        .replace("--", "–")
+        .replace("\\&", "&")
+        .replace("\\%", "%")
+        .replace("\\_", "_")
+        .replace('~', " ");
+    let text: String = text
+        .chars()
+        .filter(|c| !matches!(c, '{' | '}' | '\\'))
+        .collect();
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// `Knuth, Donald E.` or `Donald E. Knuth` as `Knuth, D. E.`
+fn bibtex_name(name: &str) -> String {
+    let name = name.trim();
+    let (family, given) = match name.split_once(',') {
+        Some((family, given)) => (family.trim(), given.trim()),
+        None => match name.rsplit_once(' ') {
+            Some((given, family)) => (family, given.trim()),
+            None => (name, ""),
+        },
+    };
+    format_name(family, given)
+}
+
+fn format_name(family: &str, given: &str) -> String {
+    let initials: Vec<String> = given
+        .split([' ', '-'])
+        .filter_map(|part| part.chars().find(|c| c.is_alphabetic()))
+        .map(|initial| format!("{}.", initial))
+        .collect();
+    if initials.is_empty() {
+        family.to_string()
+    } else {
+        format!("{}, {}", family, initials.join(" "))
+    }
+}
+
+fn doi_url(doi: &str) -> String {
+    if doi.starts_with("http") {
+        doi.to_string()
+    } else {
+        format!("https://doi.org/{}", doi)
+    }
+}
+
+/// An item of a CSL-JSON bibliography, as exported by Zotero and others
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct CslItem {
+    id: CslId,
+    title: Option<String>,
+    #[serde(default)]
+    author: Vec<CslName>,
+    #[serde(default)]
+    editor: Vec<CslName>,
+    issued: Option<CslDate>,
+    container_title: Option<String>,
+    publisher: Option<String>,
+    #[serde(rename = "DOI")]
+    doi: Option<String>,
+    #[serde(rename = "URL")]
+    url: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum CslId {
+    Text(String),
+    Number(i64),
+}
+
+#[derive(Deserialize)]
+struct CslName {
+    family: Option<String>,
+    given: Option<String>,
+    literal: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct CslDate {
+    date_parts: Option<Vec<Vec<serde_json::Value>>>,
+    literal: Option<String>,
+}
+
+/// Entries of a CSL-JSON file, by `id`
+pub fn parse_csl_json(content: &str) -> anyhow::Result<Vec<(String, Reference)>> {
+    let items: Vec<CslItem> = serde_json::from_str(content)?;
+    Ok(items
+        .into_iter()
+        .map(|item| {
+            let key = match item.id {
+                CslId::Text(id) => id,
+                CslId::Number(id) => id.to_string(),
+            };
+            let names = if item.author.is_empty() {
+                item.editor
+            } else {
+                item.author
+            };
+            let reference = Reference {
+                authors: names
+                    .into_iter()
+                    .filter_map(|name| match (name.family, name.literal) {
+                        (Some(family), _) => {
+                            Some(format_name(&family, name.given.as_deref().unwrap_or("")))
+                        }
+                        (None, literal) => literal,
+                    })
+                    .collect(),
+                title: item.title,
+                year: item.issued.and_then(|date| {
+                    let year = date
+                        .date_parts
+                        .and_then(|parts| parts.into_iter().next()?.into_iter().next());
+                    match year {
+                        Some(serde_json::Value::String(year)) => Some(year),
+                        Some(year) => Some(year.to_string()),
+                        None => date.literal,
+                    }
+                }),
+                container: item.container_title.or(item.publisher),
+                url: item.doi.map(|doi| doi_url(&doi)).or(item.url),
+            };
+            (key, reference)
+        })
+        .collect())
+}
+
+/// Replaces the `[@key]` citations of a page with the number of the cited
+/// work, and lists the works in a references section at the end. Several
+/// works are cited as `[@one; @two]`, and a locator follows the key, as in
+/// `[@knuth84, p. 12]`. Like `FootnoteRenderer`, this needs the whole
+/// document up front.
+pub struct CitationRenderer<'a> {
+    events: std::vec::IntoIter<Event<'a>>,
+}
+
+impl<'a> CitationRenderer<'a> {
+    pub fn new<I: Iterator<Item = Event<'a>>>(inner: I, bibliography: &Bibliography) -> Self {
+        let events = if bibliography.is_empty() {
+            inner.collect()
+        } else {
+            cite(inner, bibliography)
+        };
+        Self {
+            events: events.into_iter(),
+        }
+    }
+}
+
+impl<'a> Iterator for CitationRenderer<'a> {
+    type Item = Event<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.events.next()
+    }
+}
+
+fn cite<'a>(inner: impl Iterator<Item = Event<'a>>, bibliography: &Bibliography) -> Vec<Event<'a>> {
+    let mut citations = Citations {
+        bibliography,
+        cited: Vec::new(),
+        unknown: Vec::new(),
+    };
+    let mut output = Vec::new();
+    // Text is split where brackets don't make links, so it is joined first
+    let mut text = String::new();
+    // Depth of the links and images, whose text isn't cited in
+    let mut skip_depth = 0usize;
+
+    for event in inner {
+        match event {
+            Event::Text(t) => text.push_str(&t),
+            event => {
+                citations.push_text(&mut text, skip_depth > 0, &mut output);
+                match &event {
+                    Event::Start(Tag::Link { .. } | Tag::Image { .. }) => skip_depth += 1,
+                    Event::End(TagEnd::Link | TagEnd::Image) => {
+                        skip_depth = skip_depth.saturating_sub(1)
+                    }
+                    _ => {}
+                }
+                output.push(event);
+            }
+        }
+    }
+    citations.push_text(&mut text, skip_depth > 0, &mut output);
+
+    if !citations.cited.is_empty() {
+        output.push(Event::Html(CowStr::from(references_html(
+            &citations.cited,
+            bibliography,
+        ))));
+    }
+    output
+}
+
+struct Citations<'b> {
+    bibliography: &'b Bibliography,
+    /// Keys in the order they are first cited, which numbers them
+    cited: Vec<&'b str>,
+    /// Keys missing from the bibliography, warned about once
+    unknown: Vec<String>,
+}
+
+impl<'b> Citations<'b> {
+    /// Pushes `text` to `output` with its citations replaced, then clears it
+    fn push_text<'a>(&mut self, text: &mut String, skipped: bool, output: &mut Vec<Event<'a>>) {
+        if text.is_empty() {
+            return;
+        }
+        if skipped || !text.contains("[@") {
+            output.push(Event::Text(CowStr::from(std::mem::take(text))));
+            return;
+        }
+        let mut rest = text.as_str();
+        while let Some(start) = rest.find("[@") {
+            let Some(length) = rest[start..].find(']') else {
+                break;
+            };
+            let Some(html) = self.render(&rest[start + 1..start + length]) else {
+                output.push(Event::Text(CowStr::from(rest[..start + 1].to_string())));
+                rest = &rest[start + 1..];
+                continue;
+            };
+            if start > 0 {
+                output.push(Event::Text(CowStr::from(rest[..start].to_string())));
+            }
+            output.push(Event::InlineHtml(CowStr::from(html)));
+            rest = &rest[start + length + 1..];
+        }
+        if !rest.is_empty() {
+            output.push(Event::Text(CowStr::from(rest.to_string())));
+        }
+        text.clear();
+    }
+
+    /// The links of `citation`, the text between the brackets, or `None` if
+    /// it isn't one
+    fn render(&mut self, citation: &str) -> Option<String> {
+        let mut parts = Vec::new();
+        for part in citation.split(';') {
+            let part = part.trim().strip_prefix('@')?;
+            let (key, locator) = match part.split_once(',') {
+                Some((key, locator)) => (key.trim(), Some(locator.trim())),
+                None => (part.trim(), None),
+            };
+            let Some((key, _)) = self.bibliography.references.get_key_value(key) else {
+                if !self.unknown.iter().any(|k| k == key) {
+                    tracing::warn!("No reference {} in the bibliography", key);
+                    self.unknown.push(key.to_string());
+                }
+                return None;
+            };
+            parts.push((key.as_str(), locator));
+        }
+
+        let mut html = String::from("<span class=\"citation\">[");
+        for (i, (key, locator)) in parts.into_iter().enumerate() {
+            if i > 0 {
+                html.push_str("; ");
+            }
+            let number = match self.cited.iter().position(|k| *k == key) {
+                Some(position) => position + 1,
+                None => {
+                    self.cited.push(key);
+                    self.cited.len()
+                }
+            };
+            html.push_str("<a href=\"#ref-");
+            let _ = escape_href(&mut html, key);
+            html.push_str(&format!("\">{}</a>", number));
+            if let Some(locator) = locator.filter(|locator| !locator.is_empty()) {
+                html.push_str(", ");
+                let _ = escape_html(&mut html, locator);
+            }
+        }
+        html.push_str("]</span>");
+        Some(html)
+    }
+}
+
+/// The references section listing the `cited` works, in citation order
+fn references_html(cited: &[&str], bibliography: &Bibliography) -> String {
+    let mut html = String::from(
+        "<section class=\"references\" id=\"references\">\n<h2>References</h2>\n<ol>\n",
+    );
+    for key in cited {
+        let reference = &bibliography.references[*key];
+        html.push_str("<li id=\"ref-");
+        let _ = escape_html(&mut html, key);
+        html.push_str("\">");
+        let authors = match reference.authors.as_slice() {
+            [] => String::new(),
+            [author] => author.clone(),
+            [first @ .., last] => format!("{} & {}", first.join(", "), last),
+        };
+        if !authors.is_empty() {
+            let _ = escape_html(&mut html, &authors);
+            html.push(' ');
+        }
+        if let Some(year) = &reference.year {
+            html.push('(');
+            let _ = escape_html(&mut html, year);
+            html.push_str("). ");
+        }
+        if let Some(title) = &reference.title {
+            html.push_str("<cite>");
+            let _ = escape_html(&mut html, title);
+            html.push_str("</cite>. ");
+        }
+        if let Some(container) = &reference.container {
+            let _ = escape_html(&mut html, container);
+            html.push_str(". ");
+        }
+        if let Some(url) = &reference.url {
+            html.push_str("<a href=\"");
+            let _ = escape_href(&mut html, url);
+            html.push_str("\">");
+            let _ = escape_html(&mut html, url);
+            html.push_str("</a>");
+        }
+        let trimmed = html.trim_end().len();
+        html.truncate(trimmed);
+        html.push_str("</li>\n");
+    }
+    html.push_str("</ol>\n</section>\n");
+    html
+}
@@ -1,22 +1,198 @@
-use pulldown_cmark::{CodeBlockKind, CowStr, Event, Parser as MarkdownParser, Tag, TagEnd};
+use lazy_static::lazy_static;
+use pulldown_cmark::{CodeBlockKind, CowStr, Event, Tag, TagEnd};
 use pulldown_cmark_escape::escape_html;
-use syntect::html::highlighted_html_for_string;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use syntect::easy::HighlightLines;
+use syntect::highlighting::Theme;
+use syntect::html::{
+    ClassStyle, IncludeBackground, append_highlighted_html_for_styled_line,
+    css_for_theme_with_class_style, line_tokens_to_classed_spans, start_highlighted_html_snippet,
+};
+use syntect::parsing::{ParseState, ScopeStack, SyntaxReference};
+use syntect::util::LinesWithEndings;
 
-use crate::{SYNTAX_SET, THEME_SET};
+use crate::includes::{parse_args, parse_range};
+use crate::{DEFAULT_THEME, SYNTAX_SET, THEME_SET};
+
+/// Number of highlighted snippets kept before the cache is flushed
+const HIGHLIGHT_CACHE_SIZE: usize = 512;
+
+/// Stylesheet of the code themes, for code highlighted with classes
+pub const CODE_THEME_CSS: &str = "code-theme.css";
+
+/// Prefix of the highlighting classes, so they don't clash with the site's
+const CLASS_PREFIX: &str = "syn-";
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed {
+    prefix: CLASS_PREFIX,
+};
+
+thread_local! {
+    static HIGHLIGHT_TIME: Cell<Duration> = const { Cell::new(Duration::ZERO) };
+    static HIGHLIGHT_KEYS: RefCell<HashSet<u64>> = RefCell::new(HashSet::new());
+}
+
+/// Time this thread spent highlighting code since the last call
+pub fn take_highlight_time() -> Duration {
+    HIGHLIGHT_TIME.take()
+}
+
+/// Cache keys of the snippets this thread highlighted since the last call
+pub fn take_highlight_keys() -> HashSet<u64> {
+    HIGHLIGHT_KEYS.take()
+}
+
+#[derive(Default)]
+struct HighlightCache {
+    /// Highlighted HTML keyed by a hash of (code, language, theme)
+    html: HashMap<u64, String>,
+    /// Keys of the snippets of each page, to drop them when it changes
+    pages: HashMap<String, HashSet<u64>>,
+}
+
+lazy_static! {
+    /// Repeated snippets are only highlighted once per process
+    static ref HIGHLIGHT_CACHE: Mutex<HighlightCache> = Mutex::new(HighlightCache::default());
+}
+
+/// Records `keys`, from `take_highlight_keys`, as the snippets of the page
+/// `filename`.
+pub fn record_page_highlights(filename: &str, keys: HashSet<u64>) {
+    let mut cache = HIGHLIGHT_CACHE.lock().unwrap();
+    cache
+        .pages
+        .entry(filename.to_string())
+        .or_default()
+        .extend(keys);
+}
+
+/// Drops the highlighted snippets of the page `filename` that no other page
+/// uses, once it changed.
+pub fn invalidate_page_highlights(filename: &str) {
+    let mut cache = HIGHLIGHT_CACHE.lock().unwrap();
+    let Some(keys) = cache.pages.remove(filename) else {
+        return;
+    };
+    let HighlightCache { html, pages } = &mut *cache;
+    for key in keys {
+        if !pages.values().any(|keys| keys.contains(&key)) {
+            html.remove(&key);
+        }
+    }
+}
 
 // I found this at <https://github.com/pulldown-cmark/pulldown-cmark/issues/167#issuecomment-3700787117>
 
-pub struct CodeblockRenderer<'a> {
-    inner: MarkdownParser<'a>,
+pub struct CodeblockRenderer<'a, I> {
+    inner: I,
+    theme: &'a str,
+    light_theme: Option<&'a str>,
+    line_numbers: bool,
+    classes: bool,
+    max_lines: Option<usize>,
+    expand_label: &'a str,
+}
+
+impl<'a, I: Iterator<Item = Event<'a>>> CodeblockRenderer<'a, I> {
+    /// When `light_theme` is set, every block is rendered twice and the
+    /// stylesheet shows the variant matching the current color scheme.
+    /// `line_numbers` is the default for blocks that don't ask otherwise.
+    /// With `classes`, blocks are highlighted with the classes styled by
+    /// `CODE_THEME_CSS` rather than inline styles, and rendered only once.
+    /// Blocks longer than `max_lines` are shown that tall until expanded,
+    /// by a button labelled `expand_label` with `{lines}` as the line count.
+    pub fn new(
+        inner: I,
+        theme: &'a str,
+        light_theme: Option<&'a str>,
+        line_numbers: bool,
+        classes: bool,
+        max_lines: Option<usize>,
+        expand_label: &'a str,
+    ) -> Self {
+        Self {
+            inner,
+            theme,
+            light_theme,
+            line_numbers,
+            classes,
+            max_lines,
+            expand_label,
+        }
+    }
+}
+
+/// Language of a code block with the fence info `info`, like `rust` for
+/// `rust,linenos hl_lines=2`, or `text` when it has none
+pub fn fence_language(info: &str) -> &str {
+    let head = info.split_whitespace().next().unwrap_or_default();
+    let lang = head.split(',').next().unwrap_or_default().trim();
+    if lang.is_empty() { "text" } else { lang }
+}
+
+/// Fence info string of a code block, like `rust,linenos,hl_lines=3-5
+/// title="main.rs"`: the language followed by comma-separated flags, then
+/// attributes.
+struct FenceInfo<'a> {
+    lang: &'a str,
+    /// `linenos` or `nolinenos`, when given
+    line_numbers: Option<bool>,
+    /// Lines to highlight, like `3-5` or `1 3-5` as an attribute
+    highlighted_lines: Option<String>,
+    /// Shown in a header above the block, usually a file name
+    title: Option<String>,
 }
 
-impl<'a> CodeblockRenderer<'a> {
-    pub fn new(inner: MarkdownParser<'a>) -> Self {
-        Self { inner }
+impl<'a> FenceInfo<'a> {
+    fn parse(info: &'a str) -> Self {
+        let info = info.trim();
+        let (head, attributes) = info.split_once(char::is_whitespace).unwrap_or((info, ""));
+        let mut attributes = parse_args(attributes).unwrap_or_else(|e| {
+            tracing::warn!("Invalid code block attributes '{}': {}", attributes, e);
+            Default::default()
+        });
+
+        let mut fence = Self {
+            lang: fence_language(head),
+            line_numbers: None,
+            highlighted_lines: attributes.remove("hl_lines"),
+            title: attributes.remove("title"),
+        };
+        for flag in head.split(',').map(str::trim).skip(1) {
+            match flag {
+                "linenos" => fence.line_numbers = Some(true),
+                "nolinenos" => fence.line_numbers = Some(false),
+                _ => {
+                    if let Some(lines) = flag.strip_prefix("hl_lines=") {
+                        fence.highlighted_lines = Some(lines.to_string());
+                    }
+                }
+            }
+        }
+        fence
+    }
+
+    /// Ranges of lines to highlight in a block of `count` lines. Invalid
+    /// ranges are left out, with a warning.
+    fn highlighted_ranges(&self, count: usize) -> Vec<(usize, usize)> {
+        let Some(lines) = &self.highlighted_lines else {
+            return Vec::new();
+        };
+        lines
+            .split_whitespace()
+            .filter_map(|range| {
+                parse_range(range, count)
+                    .inspect_err(|e| tracing::warn!("Invalid hl_lines: {}", e))
+                    .ok()
+            })
+            .collect()
     }
 }
 
-impl<'a> Iterator for CodeblockRenderer<'a> {
+impl<'a, I: Iterator<Item = Event<'a>>> Iterator for CodeblockRenderer<'a, I> {
     type Item = Event<'a>;
 
     fn next(&mut self) -> Option<Self::Item> {
@@ -29,7 +205,7 @@ impl<'a> Iterator for CodeblockRenderer<'a> {
 
         let mut code_content = String::new();
 
-        while let Some(inner_event) = self.inner.next() {
+        for inner_event in self.inner.by_ref() {
             match inner_event {
                 Event::End(TagEnd::CodeBlock) => break,
                 Event::Text(code) => code_content.push_str(&code),
@@ -37,30 +213,233 @@ impl<'a> Iterator for CodeblockRenderer<'a> {
             }
         }
 
-        let lang = match kind {
-            CodeBlockKind::Indented => "text",
-            CodeBlockKind::Fenced(ref language) => language.as_ref(),
+        let fence = match kind {
+            CodeBlockKind::Indented => FenceInfo::parse("text"),
+            CodeBlockKind::Fenced(ref info) => FenceInfo::parse(info),
         };
-
-        let rendered_html = render_code_to_html(&code_content, lang);
+        let line_numbers = fence.line_numbers.unwrap_or(self.line_numbers);
+        let line_count = LinesWithEndings::from(&code_content).count();
+        let highlighted = fence.highlighted_ranges(line_count);
 
         let mut escaped_code = String::new();
         let _ = escape_html(&mut escaped_code, &code_content);
 
-        let rendered_html =
-            rendered_html.replace("<pre", &format!("<pre data-code=\"{}\"", escaped_code));
+        let render = |theme| {
+            render_code_to_html(&code_content, fence.lang, theme, line_numbers, &highlighted)
+                .replace("<pre", &format!("<pre data-code=\"{}\"", escaped_code))
+        };
+
+        let started = Instant::now();
+        let blocks = match self.light_theme {
+            _ if self.classes => render(None),
+            None => render(Some(self.theme)),
+            Some(light_theme) => format!(
+                "<div class=\"code-theme-dark\">{}</div><div class=\"code-theme-light\">{}</div>",
+                render(Some(self.theme)),
+                render(Some(light_theme))
+            ),
+        };
+        HIGHLIGHT_TIME.set(HIGHLIGHT_TIME.get() + started.elapsed());
+
+        // Header, copy and expand buttons, wired up by the page template
+        let collapsed = self.max_lines.filter(|max| line_count > *max);
+        let mut rendered_html = match collapsed {
+            Some(max) => format!(
+                "<div class=\"code-wrapper code-collapsed\" style=\"--code-max-lines: {}\">",
+                max
+            ),
+            None => String::from("<div class=\"code-wrapper\">"),
+        };
+        if let Some(title) = &fence.title {
+            rendered_html.push_str("<div class=\"code-title\">");
+            let _ = escape_html(&mut rendered_html, title);
+            rendered_html.push_str("</div>");
+        }
+        rendered_html.push_str(&blocks);
+        if collapsed.is_some() {
+            rendered_html.push_str("<button class=\"code-expand\" type=\"button\">");
+            let label = self
+                .expand_label
+                .replace("{lines}", &line_count.to_string());
+            let _ = escape_html(&mut rendered_html, &label);
+            rendered_html.push_str("</button>");
+        }
+        rendered_html.push_str(
+            "<button class=\"copy-button\" type=\"button\" title=\"Copy\">\
+             <i class=\"fa-regular fa-copy\"></i></button></div>",
+        );
 
         Some(Event::Html(CowStr::Boxed(rendered_html.into_boxed_str())))
     }
 }
 
-pub fn render_code_to_html(code: &str, lang: &str) -> String {
+/// `highlighted` holds 1-based inclusive ranges of lines given a highlighted
+/// background. Without a `theme`, the code is highlighted with classes.
+pub fn render_code_to_html(
+    code: &str,
+    lang: &str,
+    theme: Option<&str>,
+    line_numbers: bool,
+    highlighted: &[(usize, usize)],
+) -> String {
+    let mut hasher = DefaultHasher::new();
+    (code, lang, theme, line_numbers, highlighted).hash(&mut hasher);
+    let key = hasher.finish();
+    HIGHLIGHT_KEYS.with_borrow_mut(|keys| keys.insert(key));
+
+    if let Some(html) = HIGHLIGHT_CACHE.lock().unwrap().html.get(&key) {
+        return html.clone();
+    }
+
+    let html = highlight(code, lang, theme, line_numbers, highlighted);
+
+    let mut cache = HIGHLIGHT_CACHE.lock().unwrap();
+    if cache.html.len() >= HIGHLIGHT_CACHE_SIZE {
+        *cache = HighlightCache::default();
+    }
+    cache.html.insert(key, html.clone());
+    html
+}
+
+fn highlight(
+    code: &str,
+    lang: &str,
+    theme: Option<&str>,
+    line_numbers: bool,
+    highlighted: &[(usize, usize)],
+) -> String {
     let syntax = SYNTAX_SET
         .find_syntax_by_token(lang)
         .unwrap_or_else(|| SYNTAX_SET.find_syntax_plain_text());
 
-    let theme = &THEME_SET.themes["Catppuccin Macchiato"];
+    let Some(theme) = theme else {
+        return classed_lines(code, syntax, line_numbers, highlighted)
+            .unwrap_or_else(|_| format!("<pre><code>{}</code></pre>", code));
+    };
+
+    let themes = THEME_SET.read().unwrap();
+    let theme = themes
+        .themes
+        .get(theme)
+        .unwrap_or_else(|| &themes.themes[DEFAULT_THEME]);
 
-    highlighted_html_for_string(code, &SYNTAX_SET, syntax, theme)
+    highlight_lines(code, syntax, theme, line_numbers, highlighted)
         .unwrap_or_else(|_| format!("<pre><code>{}</code></pre>", code))
 }
+
+/// Same output as syntect's `highlighted_html_for_string`, with each line
+/// optionally prefixed by its number, and highlighted lines wrapped in a
+/// `hl-line` span.
+fn highlight_lines(
+    code: &str,
+    syntax: &SyntaxReference,
+    theme: &Theme,
+    line_numbers: bool,
+    highlighted: &[(usize, usize)],
+) -> Result<String, syntect::Error> {
+    let mut highlighter = HighlightLines::new(syntax, theme);
+    let (mut output, background) = start_highlighted_html_snippet(theme);
+    for (number, line) in LinesWithEndings::from(code).enumerate() {
+        let number = number + 1;
+        let is_highlighted = highlighted
+            .iter()
+            .any(|(start, end)| (*start..=*end).contains(&number));
+        if is_highlighted {
+            output.push_str("<span class=\"hl-line\">");
+        }
+        if line_numbers {
+            output.push_str(&format!("<span class=\"line-number\">{}</span>", number));
+        }
+        let mut regions = highlighter.highlight_line(line, &SYNTAX_SET)?;
+        // The line break goes after the span, or it would take a line of its own
+        if is_highlighted && let Some((_, text)) = regions.last_mut() {
+            *text = text.trim_end_matches('\n');
+        }
+        append_highlighted_html_for_styled_line(
+            &regions,
+            IncludeBackground::IfDifferent(background),
+            &mut output,
+        )?;
+        if is_highlighted {
+            output.push_str("</span>");
+            if line.ends_with('\n') {
+                output.push('\n');
+            }
+        }
+    }
+    output.push_str("</pre>\n");
+    Ok(output)
+}
+
+/// Like `highlight_lines`, with classes instead of inline styles. Each line
+/// closes the spans it leaves open and the next one reopens them, so lines
+/// can be wrapped.
+fn classed_lines(
+    code: &str,
+    syntax: &SyntaxReference,
+    line_numbers: bool,
+    highlighted: &[(usize, usize)],
+) -> Result<String, syntect::Error> {
+    let mut parse_state = ParseState::new(syntax);
+    let mut scopes = ScopeStack::new();
+    let mut output = format!("<pre class=\"{}code\">\n", CLASS_PREFIX);
+    for (number, line) in LinesWithEndings::from(code).enumerate() {
+        let number = number + 1;
+        let is_highlighted = highlighted
+            .iter()
+            .any(|(start, end)| (*start..=*end).contains(&number));
+        if is_highlighted {
+            output.push_str("<span class=\"hl-line\">");
+        }
+        if line_numbers {
+            output.push_str(&format!("<span class=\"line-number\">{}</span>", number));
+        }
+
+        // Reopen the scopes of the previous line
+        for scope in scopes.as_slice() {
+            let classes: Vec<String> = scope
+                .build_string()
+                .split('.')
+                .map(|atom| format!("{}{}", CLASS_PREFIX, atom))
+                .collect();
+            output.push_str(&format!("<span class=\"{}\">", classes.join(" ")));
+        }
+        let ops = parse_state.parse_line(line, &SYNTAX_SET)?;
+        let (html, _) = line_tokens_to_classed_spans(line, &ops, CLASS_STYLE, &mut scopes)?;
+        // The line break is the only one, and goes after the closed spans
+        output.push_str(&html.replacen('\n', "", 1));
+        output.push_str(&"</span>".repeat(scopes.len()));
+        if is_highlighted {
+            output.push_str("</span>");
+        }
+        if line.ends_with('\n') {
+            output.push('\n');
+        }
+    }
+    output.push_str("</pre>\n");
+    Ok(output)
+}
+
+/// Stylesheet for code highlighted with classes: `theme`, and `light_theme`
+/// when the reader prefers a light color scheme or picked the light theme.
+pub fn code_theme_css(theme: &str, light_theme: Option<&str>) -> anyhow::Result<String> {
+    let themes = THEME_SET.read().unwrap();
+    let css = |name: &str| {
+        let theme = themes
+            .themes
+            .get(name)
+            .unwrap_or_else(|| &themes.themes[DEFAULT_THEME]);
+        css_for_theme_with_class_style(theme, CLASS_STYLE)
+    };
+
+    let mut output = css(theme)?;
+    if let Some(light_theme) = light_theme {
+        let light = css(light_theme)?;
+        output.push_str(&format!(
+            "\n@media (prefers-color-scheme: light) {{\n:root:not([data-theme=\"dark\"]) {{\n{}}}\n}}\n",
+            light
+        ));
+        output.push_str(&format!("\n:root[data-theme=\"light\"] {{\n{}}}\n", light));
+    }
+    Ok(output)
+}
@@ -0,0 +1,24 @@
+use icu_collator::{Collator, CollatorOptions};
+use icu_locid::Locale;
+use std::cmp::Ordering;
+
+/// Compares text the way readers of a language expect, so "Été" sorts
+/// between "Eau" and "Fin" rather than after "Zèbre".
+pub struct TitleCollator(Collator);
+
+impl TitleCollator {
+    /// Collation of `language`, or the language-neutral one when unknown
+    pub fn new(language: Option<&str>) -> Self {
+        let locale = language
+            .and_then(|language| language.parse::<Locale>().ok())
+            .unwrap_or_default();
+        let collator = Collator::try_new(&(&locale).into(), CollatorOptions::new())
+            .or_else(|_| Collator::try_new(&Default::default(), CollatorOptions::new()))
+            .expect("the root collation is compiled in");
+        Self(collator)
+    }
+
+    pub fn compare(&self, a: &str, b: &str) -> Ordering {
+        self.0.compare(a, b)
+    }
+}
@@ -0,0 +1,58 @@
+use serde::Serialize;
+use tera::Context;
+
+use crate::pdf::{print_options, print_page};
+use crate::store::ContentStore;
+use crate::{RenderOptions, TEMPLATES, get_summary_data, scaffold, translations};
+
+/// File the build writes every page to with `all_html`
+pub const COMBINED_FILE: &str = "all.html";
+
+/// Whether a page of `pages` is built to `all.html`, which then isn't
+/// written
+pub fn is_taken(pages: &[String], pretty_urls: bool) -> bool {
+    !pretty_urls
+        && pages
+            .iter()
+            .any(|page| page.strip_suffix(".md") == COMBINED_FILE.strip_suffix(".html"))
+}
+
+/// A page of `all.html`
+#[derive(Serialize)]
+struct CombinedPage {
+    /// Anchor the table of contents links to
+    id: String,
+    title: String,
+    content: String,
+}
+
+/// `all.html`: every page of `store` in the home page's order, after a table
+/// of contents, with the site's stylesheet inlined so that the file can be
+/// printed or archived on its own.
+pub async fn render_combined(
+    store: &dyn ContentStore,
+    options: &RenderOptions,
+    title: &str,
+) -> anyhow::Result<String> {
+    let options = print_options(options);
+    let mut pages = Vec::new();
+    for page in get_summary_data(store).await {
+        let stem = page.filename.strip_suffix(".md").unwrap_or(&page.filename);
+        let id = format!("page-{}", scaffold::slugify(stem));
+        let printed = print_page(store, &options, &page.filename).await?;
+        pages.push(CombinedPage {
+            id,
+            title: printed.title,
+            content: printed.content,
+        });
+    }
+
+    let mut context = Context::new();
+    context.insert("title", title);
+    context.insert("pages", &pages);
+    if let Some(language) = &options.language {
+        context.insert("lang", language);
+    }
+    translations::insert_into(&options, &mut context);
+    Ok(TEMPLATES.read().unwrap().render(COMBINED_FILE, &context)?)
+}
@@ -0,0 +1,121 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// Service whose widget holds the comments of pages, backed by the
+/// discussions or issues of a GitHub repository
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CommentsProvider {
+    /// https://giscus.app, on GitHub Discussions
+    Giscus,
+    /// https://utteranc.es, on GitHub issues
+    Utterances,
+}
+
+/// Settings of the comments widget under pages, see `comments` in
+/// `blog.toml`
+#[derive(Clone, Debug)]
+pub struct CommentsOptions {
+    pub provider: CommentsProvider,
+    /// Repository holding the discussions, like `me/blog`
+    pub repo: String,
+    /// Giscus' IDs of the repository and category, from its setup page
+    pub repo_id: Option<String>,
+    pub category: Option<String>,
+    pub category_id: Option<String>,
+    /// How pages map to discussions: `pathname` by default, `url`,
+    /// `title`, ...
+    pub mapping: Option<String>,
+    /// Label of the issues utterances opens
+    pub label: Option<String>,
+    /// Widget themes for the site's light and dark color schemes, the
+    /// provider's light and dark ones by default
+    pub theme_light: Option<String>,
+    pub theme_dark: Option<String>,
+}
+
+impl CommentsOptions {
+    /// Checks that the provider has what it needs.
+    pub fn check(&self) -> anyhow::Result<()> {
+        if !self.repo.contains('/') {
+            anyhow::bail!("Comments need `comments_repo`, like \"me/blog\"");
+        }
+        if self.provider == CommentsProvider::Giscus
+            && (self.repo_id.is_none() || self.category.is_none() || self.category_id.is_none())
+        {
+            anyhow::bail!(
+                "Giscus comments need `comments_repo_id`, `comments_category` and \
+                 `comments_category_id`, see https://giscus.app"
+            );
+        }
+        Ok(())
+    }
+
+    /// What `page.html` needs to load the widget, in `language`
+    pub fn embed(&self, language: Option<&str>) -> CommentsEmbed {
+        let mut attributes = Map::new();
+        let mut set = |name: &str, value: &str| {
+            attributes.insert(name.to_string(), Value::from(value));
+        };
+        let mapping = self.mapping.as_deref().unwrap_or("pathname");
+        let (src, theme_attribute, light, dark) = match self.provider {
+            CommentsProvider::Giscus => {
+                set("data-repo", &self.repo);
+                set("data-repo-id", self.repo_id.as_deref().unwrap_or_default());
+                set(
+                    "data-category",
+                    self.category.as_deref().unwrap_or_default(),
+                );
+                set(
+                    "data-category-id",
+                    self.category_id.as_deref().unwrap_or_default(),
+                );
+                set("data-mapping", mapping);
+                set("data-reactions-enabled", "1");
+                set("data-input-position", "bottom");
+                set("data-lang", language.unwrap_or("en"));
+                set("data-loading", "lazy");
+                (
+                    "https://giscus.app/client.js",
+                    "data-theme",
+                    "light",
+                    "dark",
+                )
+            }
+            CommentsProvider::Utterances => {
+                set("repo", &self.repo);
+                set("issue-term", mapping);
+                if let Some(label) = &self.label {
+                    set("label", label);
+                }
+                (
+                    "https://utteranc.es/client.js",
+                    "theme",
+                    "github-light",
+                    "github-dark",
+                )
+            }
+        };
+        CommentsEmbed {
+            provider: self.provider,
+            src,
+            attributes: Value::Object(attributes).to_string(),
+            theme_attribute,
+            theme_light: self.theme_light.as_deref().unwrap_or(light).to_string(),
+            theme_dark: self.theme_dark.as_deref().unwrap_or(dark).to_string(),
+        }
+    }
+}
+
+/// The comments widget of a page. Its script is added by the page, with the
+/// theme of the current color scheme.
+#[derive(Serialize)]
+pub struct CommentsEmbed {
+    provider: CommentsProvider,
+    src: &'static str,
+    /// Attributes of the script, as a JSON object
+    attributes: String,
+    theme_attribute: &'static str,
+    theme_light: String,
+    theme_dark: String,
+}
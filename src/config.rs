@@ -0,0 +1,445 @@
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::NavOrder;
+use crate::access_log::AccessLog;
+use crate::analytics::AnalyticsProvider;
+use crate::comments::CommentsProvider;
+use crate::exit::Failure;
+use crate::footnotes::FootnotePosition;
+use crate::images::ImageFormat;
+use crate::menu::MenuEntry;
+use crate::preprocess::Preprocessor;
+use crate::translations::Translations;
+use crate::typography::TypographyLocale;
+
+pub const CONFIG_FILE: &str = "blog.toml";
+
+/// Site settings read from `blog.toml` in the docs directory. Every field is
+/// optional, and command line flags take precedence over it.
+#[derive(Deserialize, Default, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub no_navigation: bool,
+    pub code_theme: Option<String>,
+    pub code_theme_light: Option<String>,
+    pub line_numbers: bool,
+    pub highlight_classes: bool,
+    pub code_max_lines: Option<usize>,
+    /// Relative paths are resolved from the docs directory
+    pub theme_file: Option<PathBuf>,
+    pub footnotes: Option<FootnotePosition>,
+    pub footnote_title: Option<String>,
+    pub footnote_separator: bool,
+    pub bibliography: Option<String>,
+    pub external_link_target: Option<String>,
+    pub external_link_rel: Option<String>,
+    pub typography: Option<TypographyLocale>,
+    pub base_url: Option<String>,
+    pub pretty_urls: bool,
+    pub theme: Option<String>,
+    /// Builds one tree per language when set, the first being the default
+    pub languages: Vec<String>,
+    pub locale: Option<String>,
+    /// `strftime` format for dates, like "%d/%m/%Y"
+    pub date_format: Option<String>,
+    /// IANA timezone dates are shown in, like "Europe/Paris"
+    pub timezone: Option<String>,
+    /// Where `blog sync` sends the docs directory, see `sync::Remote`
+    pub sync_remote: Option<String>,
+    pub nav_order: Option<NavOrder>,
+    pub numbering: bool,
+    pub related_pages: Option<usize>,
+    pub age_warning_years: Option<u32>,
+    /// Accent color and icon of the pages with a tag, by tag
+    pub tag_styles: BTreeMap<String, TagStyle>,
+    /// Canonical tag of each alias, see `tags::set_tag_aliases`
+    pub tag_aliases: BTreeMap<String, String>,
+    /// Entries of the navbar, in order
+    pub menu: Vec<MenuEntry>,
+    /// Steps rewriting the markdown of pages, `["includes"]` when unset
+    pub preprocessors: Option<Vec<Preprocessor>>,
+    /// Values of the `{{ vars.name }}` of pages
+    pub variables: BTreeMap<String, String>,
+    /// UI strings by language, see `translations::UI_STRINGS`
+    pub translations: Translations,
+    pub fallback_page: Option<String>,
+    pub optimize_images: bool,
+    pub image_widths: Option<Vec<u32>>,
+    pub image_formats: Vec<ImageFormat>,
+    pub lazy_images: bool,
+    pub keep_image_metadata: bool,
+    /// Command suggesting the alt text of an image, see `alt_text::suggest_alt`
+    pub alt_text_command: Option<String>,
+    /// Command turning HTML into PDF, see `pdf::html_to_pdf`
+    pub pdf_command: Option<String>,
+    pub changelog: bool,
+    pub all_html: bool,
+    pub page_json: bool,
+    pub feed_months: Option<u32>,
+    pub minify: bool,
+    /// Size in bytes, see `inline_assets::inline_tree`
+    pub inline_assets: Option<u64>,
+    pub fingerprint: bool,
+    pub precompress: bool,
+    /// URLs requested after builds and when pages are published, see `pings::Pings`
+    pub ping_urls: Vec<String>,
+    pub websub_hub: Option<String>,
+    pub webmentions: bool,
+    pub comments: Option<CommentsProvider>,
+    pub comments_repo: Option<String>,
+    pub comments_repo_id: Option<String>,
+    pub comments_category: Option<String>,
+    pub comments_category_id: Option<String>,
+    pub comments_mapping: Option<String>,
+    pub comments_label: Option<String>,
+    pub comments_theme_light: Option<String>,
+    pub comments_theme_dark: Option<String>,
+    pub analytics: Option<AnalyticsProvider>,
+    pub analytics_site: Option<String>,
+    pub analytics_script: Option<String>,
+    /// Relative paths are resolved from the docs directory
+    pub signing_key: Option<PathBuf>,
+    /// Addresses `blog serve` listens on, like "0.0.0.0" or "[::]:8080"
+    pub bind: Vec<String>,
+    /// Prefix `blog serve` mounts the site under, like "/blog"
+    pub base_path: Option<String>,
+    pub trust_proxy: bool,
+    pub access_log: Option<AccessLog>,
+    /// Seconds browsers and CDNs may cache served pages
+    pub cache_max_age: Option<u64>,
+    /// Seconds CDNs may cache served pages, through `Surrogate-Control`
+    pub surrogate_max_age: Option<u64>,
+    pub stale_while_revalidate: Option<u64>,
+    pub cdn_purge_url: Option<String>,
+    pub cdn_purge_body: Option<String>,
+    pub worker_threads: Option<usize>,
+    pub blocking_threads: Option<usize>,
+}
+
+/// Look of the pages with a given tag, see `[tag_styles]`
+#[derive(Deserialize, Serialize, Default, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct TagStyle {
+    /// CSS color, such as "#dea584", used as the accent and `theme-color`
+    pub color: Option<String>,
+    /// Font Awesome classes, such as "fa-brands fa-rust"
+    pub icon: Option<String>,
+}
+
+impl TagStyle {
+    /// Checks that the color is a hex color or a color name, so it can go in
+    /// a stylesheet as is.
+    pub fn check(&self, tag: &str) -> anyhow::Result<()> {
+        if let Some(color) = &self.color {
+            let valid = match color.strip_prefix('#') {
+                Some(hex) => {
+                    [3, 4, 6, 8].contains(&hex.len()) && hex.chars().all(|c| c.is_ascii_hexdigit())
+                }
+                None => !color.is_empty() && color.chars().all(|c| c.is_ascii_alphabetic()),
+            };
+            if !valid {
+                anyhow::bail!("Invalid color '{}' for tag '{}'", color, tag);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Config {
+    /// Loads `blog.toml` from `docs_dir`, falling back to the defaults when
+    /// there is none.
+    pub fn load(docs_dir: &Path) -> anyhow::Result<Self> {
+        let path = docs_dir.join(CONFIG_FILE);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = std::fs::read_to_string(&path).context(Failure::Config)?;
+        let mut config: Config = toml::from_str(&content)
+            .map_err(|e| anyhow::anyhow!("{:?}: {}", path, e))
+            .context(Failure::Config)?;
+        config.theme_file = config.theme_file.map(|f| docs_dir.join(f));
+        config.signing_key = config.signing_key.map(|f| docs_dir.join(f));
+        Ok(config)
+    }
+}
+
+/// Starter config written by `blog init`
+pub const STARTER_CONFIG: &str = r##"# Site settings for blog. Command line flags take precedence over these.
+
+# Remove the home page and navbar
+# no_navigation = false
+
+# Syntax highlighting themes for code blocks
+# code_theme = "Catppuccin Macchiato"
+# code_theme_light = "InspiredGitHub"
+# theme_file = "themes/custom.tmTheme"
+
+# Number the lines of code blocks. A block can ask otherwise with
+# ```rust,linenos or ```rust,nolinenos
+# line_numbers = false
+
+# Highlight code with classes styled by code-theme.css rather than inline
+# styles, for smaller pages
+# highlight_classes = false
+
+# Show longer code blocks this many lines tall, with a button to expand them
+# code_max_lines = 40
+
+# Footnote placement: "inline", "page" or "section"
+# footnotes = "inline"
+# footnote_title = "Notes"
+# footnote_separator = true
+
+# BibTeX or CSL-JSON (.json) file of the works pages cite as [@key], or
+# [@key, p. 12] and [@one; @two], numbered and listed in a references
+# section at the end of the page. A page's frontmatter can name another
+# bibliography = "references.bib"
+
+# Attributes of links leaving the site, an empty value leaving it out
+# external_link_target = "_blank"
+# external_link_rel = "noopener noreferrer"
+
+# Non-breaking space rules: "en" or "fr"
+# typography = "en"
+
+# Number section headings, like "2.3 Installation"
+# numbering = false
+
+# Number of pages sharing tags listed under each page, 0 to list none
+# related_pages = 3
+
+# Warn readers of pages published this many years ago or more that they may
+# be out of date. Built sites tell the age as of the build
+# age_warning_years = 3
+
+# Page with a search for unknown URLs, named for the static host: usually
+# "404.html", or "200.html" for hosts serving it for every unknown URL
+# fallback_page = "404.html"
+
+# Have builds write resized copies of the images pages show, offered to
+# browsers through srcset, and encode them in more formats: "webp", "avif"
+# optimize_images = false
+# image_widths = [480, 960, 1600]
+# image_formats = ["webp"]
+
+# Copy images with their EXIF and XMP metadata, which builds otherwise leave
+# out since it can tell where and when a photo was taken
+# keep_image_metadata = false
+
+# Command `blog alt-text` runs on each image without alt text, with the
+# image's path as last argument, offering what it prints as the alt text.
+# Any captioning tool works, such as a local vision model
+# alt_text_command = "caption --short"
+
+# Command `blog export pdf` turns its print-ready HTML into PDF with, given
+# the HTML and PDF files as "$1" and "$2", appended unless used. Defaults to
+# weasyprint; a headless browser works too
+# pdf_command = "chromium --headless --no-pdf-header-footer --print-to-pdf=\"$2\" \"$1\""
+
+# Load images only when scrolled to, setting their size from the image files
+# so the page doesn't shift as they load
+# lazy_images = false
+
+# Add a "What's new" page (changes.html) listing the latest added and updated
+# pages, dated from git when it tracks them, and its feed (changes.xml)
+# changelog = false
+
+# Also build all.html: every page in one self-contained file, after a table
+# of contents, for printing or archiving the whole site
+# all_html = false
+
+# Write a .json file next to each built page, like post.json, holding its
+# frontmatter, excerpt, word count, anchors and links, for search services
+# and other tools to read instead of the HTML
+# page_json = false
+
+# Keep feed.xml to the pages of the last months before the newest one, and
+# write every page to archive.xml. Served feeds and the home page also take
+# ?since=2024-01-01, ?before=2025-01-01 and ?months=6
+# feed_months = 12
+
+# Minify the built HTML and CSS files, keeping the text of code blocks as is
+# minify = false
+
+# Inline the built assets smaller than this many bytes into the pages using
+# them, saving a request each: images, icons and fonts as data URIs, and
+# stylesheets as <style> blocks
+# inline_assets = 2048
+
+# Also write the built stylesheets, scripts, images and fonts under names
+# holding a hash of their content, like style.1a2b3c4d.css, and link pages to
+# them, so they can be cached forever
+# fingerprint = false
+
+# Write .gz and .br copies of the built HTML, CSS, JavaScript, SVG, XML and
+# JSON files, for nginx (gzip_static, brotli_static) or Caddy (precompressed)
+# to send instead. .br files need the brotli command
+# precompress = false
+
+# Sign builds with a key made by `blog keygen`: they write the SHA-256 of
+# every file to content-manifest.json, signed in content-manifest.json.sig,
+# for `blog verify` to check. Keep the key out of this directory
+# signing_key = "../blog.key"
+
+# Endpoints told when the site changes, after `blog build` (unless run with
+# --no-ping) and when `blog serve` finds newly published pages. Ping URLs get
+# {sitemap} and {feed} replaced with the URL of the sitemap and of the feed.
+# The feeds advertise the WebSub hub, which is sent a publish request for
+# them, also when serving finds they changed. Both need base_url
+# ping_urls = ["https://www.google.com/ping?sitemap={sitemap}"]
+# websub_hub = "https://pubsubhubbub.appspot.com/"
+
+# Send a webmention to the sites pages link to after each build (unless
+# --no-ping), for the links added or removed since, as remembered in
+# .webmentions.json. With --database, `blog serve` also accepts them at
+# /webmention once it checked the source links to the page, and lists them
+# under it. Needs base_url
+# webmentions = false
+
+# Comments under pages, held by GitHub Discussions with "giscus" or GitHub
+# issues with "utterances". Giscus also needs the IDs its setup page at
+# https://giscus.app gives. Pages are mapped to discussions by "pathname"
+# unless set otherwise, and the widget follows the site's color scheme with
+# the given themes. A page's frontmatter can say `comments: false`
+# comments = "giscus"
+# comments_repo = "me/blog"
+# comments_repo_id = "R_kgDO..."
+# comments_category = "Comments"
+# comments_category_id = "DIC_kwDO..."
+# comments_mapping = "pathname"
+# comments_label = "comments"
+# comments_theme_light = "light"
+# comments_theme_dark = "dark"
+
+# Privacy-friendly analytics, with "plausible", "goatcounter" or "umami".
+# The site is the domain registered with Plausible, the GoatCounter code or
+# the website ID Umami gives. analytics_script points at the script of a
+# self-hosted instance; self-hosted GoatCounter takes its count URL as site
+# analytics = "plausible"
+# analytics_site = "example.com"
+# analytics_script = "https://stats.example.com/js/script.js"
+
+# Public URL of the site, used for canonical links and the sitemap
+# base_url = "https://example.com/blog/"
+
+# Write pages to `slug/index.html` so URLs have no extension
+# pretty_urls = false
+
+# Order of the prev/next links: "date", like the home page, "alphabetical"
+# by file name, or "title", following the sorting rules of the language
+# nav_order = "date"
+
+# Site theme installed in themes/ with `blog theme install`
+# theme = "my-theme"
+
+# Build one tree per language into out/<lang>/. Pages are in the first
+# language unless named with a suffix, like `post.fr@1700000000.md`, or
+# kept in the language's directory, like `fr/post@1700000000.md`. Pages
+# link to their translations from the navbar
+# languages = ["en", "fr"]
+
+# Language of a single-language site, showing the UI strings of its
+# templates in it. blog ships "de", "es" and "fr"; others, or changes, go in
+# locales/<lang>.toml files of `key = "text"` lines, or [translations]
+# locale = "fr"
+
+# Format dates when rendering instead of in the reader's locale, with a
+# strftime format and a timezone (defaults: "%B %-d, %Y" and "UTC")
+# date_format = "%d/%m/%Y"
+# timezone = "Europe/Paris"
+
+# Addresses `blog serve` listens on, IPv4 or IPv6, on --port unless given
+# one (default: 127.0.0.1). "0.0.0.0" and "::" listen on every interface
+# bind = ["0.0.0.0", "[::]:8080"]
+
+# Serve the site under a prefix, for a reverse proxy forwarding a subpath
+# of its domain. Routes and links start with it
+# base_path = "/blog"
+
+# Behind a reverse proxy such as nginx, take the client address from
+# X-Forwarded-For, and the site URL of canonical links from
+# X-Forwarded-Proto and X-Forwarded-Host when base_url isn't set. Only
+# enable it when every request goes through the proxy, clients can send
+# these headers too
+# trust_proxy = true
+
+# Requests `blog serve` logs with their status, latency and client address:
+# "all", "errors" (4xx and 5xx) or "off"
+# access_log = "all"
+
+# Caching of served pages behind a CDN, in seconds: `Cache-Control` for
+# browsers and CDNs, `Surrogate-Control` for CDNs only, and how long a stale
+# page may be served while it is fetched again
+# cache_max_age = 60
+# surrogate_max_age = 86400
+# stale_while_revalidate = 300
+
+# Request `POST /hooks/purge` makes to drop the CDN's cache, authenticated
+# with --cdn-purge-token. The hook itself needs --purge-hook-secret
+# cdn_purge_url = "https://api.cloudflare.com/client/v4/zones/ZONE_ID/purge_cache"
+# cdn_purge_body = '{"purge_everything":true}'
+
+# Threads serving requests and building pages, and most threads for blocking
+# work such as encoding images (defaults: one per core and 8 per worker).
+# Fewer suit a single-core VPS, more a large build machine
+# worker_threads = 4
+# blocking_threads = 32
+
+# Where `blog sync` sends this directory: an rsync destination or a
+# directory. In a git repository, a git remote, "origin" by default
+# sync_remote = "me@example.com:/srv/blog"
+
+# Steps rewriting the markdown of pages before it is parsed, in this order.
+# "includes" expands {{ include_code(path="src/main.rs", lines="1-10") }},
+# "shortcodes" renders {{ name(arg="value") }} with the Tera template
+# templates/shortcodes/name.md, "variables" replaces {{ site.base_url }},
+# {{ site.base_path }}, {{ site.language }}, {{ page.title }},
+# {{ page.filename }} and the {{ vars.name }} of [variables]. A command gets
+# the markdown on its standard input and the page in $BLOG_PAGE, and prints
+# the new markdown. Code blocks are left alone by the built-in steps
+# preprocessors = ["includes", "shortcodes", "variables", { command = "./filter.sh" }]
+
+# Entries of the navbar after Home: a page, "" for the home page, or a web
+# URL. Pages with one of `tags` count as being in the entry's section, which
+# the navbar highlights and breadcrumbs go through
+# [[menu]]
+# title = "About"
+# link = "about.md"
+#
+# [[menu]]
+# title = "Rust"
+# link = "rust.md"
+# tags = ["rust"]
+
+# Values of {{ vars.name }}, with the "variables" preprocessor
+# [variables]
+# repo = "https://github.com/me/blog"
+
+# UI strings of the templates per language, over those of locales/, English
+# for those left out: home, toggle_theme, languages, pages, search,
+# no_results, view, views, previous, next, related, mentions, series,
+# series_part, whats_new, follow_changes, added, updated, show_all_lines,
+# not_found and not_found_text. Custom templates can add their own and find
+# them all under `strings`
+# [translations.fr]
+# home = "Accueil"
+# search = "Rechercher..."
+# series_part = "Partie {position} de"
+
+# Accent color (also the browser's theme-color) and icon of the pages with a
+# tag, the first of a page's tags with a style winning. Tables go last
+# [tag_styles.rust]
+# color = "#dea584"
+# icon = "fa-brands fa-rust"
+
+# Tags merged into another, so that pages tagged either way share one tag
+# feed and listing. Tags are matched ignoring case, and take the casing of
+# the canonical tag they match, like "Rust" here
+# [tag_aliases]
+# rustlang = "rust"
+# "rust-lang" = "rust"
+"##;
@@ -0,0 +1,190 @@
+use chrono::format::StrftimeItems;
+use chrono::{DateTime, Months, NaiveDate, NaiveDateTime, Utc};
+use chrono_tz::Tz;
+use serde::Deserialize;
+
+use crate::frontmatter::{Frontmatter, RawDate};
+
+/// Formats accepted for frontmatter dates besides RFC 3339, read as UTC
+const DATETIME_FORMATS: &[&str] = &["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M:%S"];
+
+/// Publication date of a page: the frontmatter `date`, else the timestamp
+/// of a `slug@timestamp.md` file name. Pages with neither have no date.
+pub fn page_date(filename: &str, meta: &Frontmatter) -> anyhow::Result<Option<DateTime<Utc>>> {
+    if let Some(date) = &meta.date {
+        return parse_raw(date).map(Some);
+    }
+
+    let Some((_, ts_with_ext)) = filename.split_once('@') else {
+        return Ok(None);
+    };
+    let ts = ts_with_ext.split('.').next().unwrap_or_default();
+    let date = ts
+        .parse()
+        .ok()
+        .and_then(|ts| DateTime::from_timestamp(ts, 0))
+        .ok_or_else(|| anyhow::anyhow!("invalid timestamp '{}' in file name", ts))?;
+    Ok(Some(date))
+}
+
+/// Full years elapsed from `date` to now
+pub fn years_since(date: DateTime<Utc>) -> u32 {
+    Utc::now().years_since(date).unwrap_or_default()
+}
+
+fn parse_raw(date: &RawDate) -> anyhow::Result<DateTime<Utc>> {
+    match date {
+        RawDate::Timestamp(ts) => DateTime::from_timestamp(*ts, 0)
+            .ok_or_else(|| anyhow::anyhow!("invalid timestamp {} in frontmatter", ts)),
+        RawDate::Text(text) => parse_date(text),
+    }
+}
+
+/// Parses an RFC 3339 date, a `YYYY-MM-DD` day or one of `DATETIME_FORMATS`.
+pub fn parse_date(text: &str) -> anyhow::Result<DateTime<Utc>> {
+    let text = text.trim();
+    if let Ok(date) = DateTime::parse_from_rfc3339(text) {
+        return Ok(date.to_utc());
+    }
+    for format in DATETIME_FORMATS {
+        if let Ok(date) = NaiveDateTime::parse_from_str(text, format) {
+            return Ok(date.and_utc());
+        }
+    }
+    if let Ok(day) = NaiveDate::parse_from_str(text, "%Y-%m-%d") {
+        return Ok(day.and_time(Default::default()).and_utc());
+    }
+    anyhow::bail!("invalid date '{}' in frontmatter", text)
+}
+
+/// Display format used when only a timezone is configured: "January 3, 2025"
+pub const DEFAULT_DATE_FORMAT: &str = "%B %-d, %Y";
+
+/// How publication dates are shown when formatted at render time rather
+/// than in the reader's locale by the browser.
+#[derive(Clone, Debug)]
+pub struct DateDisplay {
+    /// A `strftime` format, checked by `DateDisplay::new`
+    pub format: String,
+    pub timezone: Tz,
+}
+
+impl DateDisplay {
+    pub fn new(format: Option<String>, timezone: Option<&str>) -> anyhow::Result<Self> {
+        let format = format.unwrap_or_else(|| DEFAULT_DATE_FORMAT.to_string());
+        StrftimeItems::new(&format)
+            .parse()
+            .map_err(|_| anyhow::anyhow!("Invalid date format '{}'", format))?;
+        let timezone = match timezone {
+            Some(name) => name
+                .parse()
+                .map_err(|_| anyhow::anyhow!("Unknown timezone '{}'", name))?,
+            None => Tz::UTC,
+        };
+        Ok(Self { format, timezone })
+    }
+
+    pub fn format(&self, timestamp: i64) -> Option<String> {
+        let date = DateTime::from_timestamp(timestamp, 0)?;
+        Some(
+            date.with_timezone(&self.timezone)
+                .format(&self.format)
+                .to_string(),
+        )
+    }
+}
+
+/// Publication dates a feed or listing is limited to. Pages without a date
+/// are only in unlimited windows.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DateWindow {
+    /// Pages published at or after this timestamp
+    pub since: Option<i64>,
+    /// Pages published before this timestamp
+    pub before: Option<i64>,
+    /// Pages published in the months up to the newest page of the window,
+    /// rather than up to now, so that builds are reproducible and a quiet
+    /// blog's feed never empties
+    pub months: Option<u32>,
+}
+
+impl DateWindow {
+    /// The last `months` months, or everything without a limit
+    pub fn recent(months: Option<u32>) -> Self {
+        Self {
+            months,
+            ..Self::default()
+        }
+    }
+
+    pub fn is_unlimited(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Keeps the items of `items` published in the window, by their
+    /// `datetime`.
+    pub fn filter<T>(&self, items: Vec<T>, datetime: impl Fn(&T) -> Option<i64>) -> Vec<T> {
+        if self.is_unlimited() {
+            return items;
+        }
+        let in_bounds = |ts: i64| {
+            self.since.is_none_or(|since| ts >= since)
+                && self.before.is_none_or(|before| ts < before)
+        };
+        let items: Vec<T> = items
+            .into_iter()
+            .filter(|item| datetime(item).is_some_and(in_bounds))
+            .collect();
+        let Some(months) = self.months else {
+            return items;
+        };
+        let Some(start) = items
+            .iter()
+            .filter_map(&datetime)
+            .max()
+            .and_then(|newest| DateTime::from_timestamp(newest, 0))
+            .and_then(|newest| newest.checked_sub_months(Months::new(months)))
+        else {
+            return items;
+        };
+        items
+            .into_iter()
+            .filter(|item| datetime(item).is_some_and(|ts| ts > start.timestamp()))
+            .collect()
+    }
+}
+
+/// `?since=2024-01-01&before=2025-01-01&months=12`, limiting a served feed
+/// or listing to a `DateWindow`. Dates are those of frontmatter.
+#[derive(Deserialize, Debug, Default)]
+pub struct WindowQuery {
+    pub since: Option<String>,
+    pub before: Option<String>,
+    pub months: Option<u32>,
+}
+
+impl WindowQuery {
+    /// The window asked for, or `default` when nothing is
+    pub fn window(&self, default: DateWindow) -> anyhow::Result<DateWindow> {
+        if self.since.is_none() && self.before.is_none() && self.months.is_none() {
+            return Ok(default);
+        }
+        if self.months == Some(0) {
+            anyhow::bail!("months must be at least 1");
+        }
+        let timestamp = |date: &Option<String>| {
+            date.as_deref()
+                .map(|date| {
+                    parse_date(date)
+                        .map(|date| date.timestamp())
+                        .map_err(|_| anyhow::anyhow!("Invalid date '{}', use YYYY-MM-DD", date))
+                })
+                .transpose()
+        };
+        Ok(DateWindow {
+            since: timestamp(&self.since)?,
+            before: timestamp(&self.before)?,
+            months: self.months,
+        })
+    }
+}
@@ -0,0 +1,244 @@
+use rusqlite::{Connection, OptionalExtension, params};
+use std::collections::HashSet;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::ax_models::Page;
+use crate::store::ContentStore;
+use crate::{frontmatter, sort_pages, summarize_page};
+
+/// Schema changes, applied in order. `PRAGMA user_version` records how many
+/// already ran, so only append to this list.
+const MIGRATIONS: &[&str] = &[INITIAL_SCHEMA, TIMESTAMP_DATES, PAGE_IMAGES, NO_COMMENTS];
+
+const INITIAL_SCHEMA: &str = "
+    CREATE TABLE pages (
+        filename TEXT PRIMARY KEY,
+        hash TEXT NOT NULL,
+        title TEXT NOT NULL,
+        datetime TEXT NOT NULL,
+        weight INTEGER,
+        excerpt TEXT
+    );
+    CREATE TABLE views (
+        filename TEXT PRIMARY KEY,
+        count INTEGER NOT NULL
+    );
+    CREATE TABLE comments (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        filename TEXT NOT NULL,
+        author TEXT NOT NULL,
+        body TEXT NOT NULL,
+        created_at TEXT NOT NULL
+    );
+    CREATE TABLE webmentions (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        filename TEXT NOT NULL,
+        source TEXT NOT NULL,
+        created_at TEXT NOT NULL,
+        UNIQUE (filename, source)
+    );";
+
+/// SQLite file keeping the page index, view counts and webmentions across
+/// restarts
+pub struct Database {
+    conn: Mutex<Connection>,
+}
+
+impl Database {
+    /// Opens or creates the database at `path`, migrating it to the latest
+    /// schema.
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let mut conn = Connection::open(path)?;
+        migrate(&mut conn)?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// Same as `get_summary_data`, but only re-derives the entries of pages
+    /// whose content changed since they were indexed, and fills in view counts.
+    pub async fn summary_data(&self, store: &dyn ContentStore) -> anyhow::Result<Vec<Page>> {
+        let mut contents = Vec::new();
+        for filename in store.list_pages().await? {
+            let content = store.read_page(&filename).await.unwrap_or_default();
+            if !frontmatter::parse(&content).0.is_draft() {
+                contents.push((filename, content));
+            }
+        }
+
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let mut pages = Vec::new();
+        for (filename, content) in contents {
+            let hash = content_hash(&content);
+            let indexed = tx
+                .query_row(
+                    "SELECT title, datetime, weight, excerpt, image FROM pages
+                     WHERE filename = ?1 AND hash = ?2",
+                    params![filename, hash],
+                    |row| {
+                        Ok(Page {
+                            filename: filename.clone(),
+                            title: row.get(0)?,
+                            datetime: row.get(1)?,
+                            weight: row.get(2)?,
+                            excerpt: row.get(3)?,
+                            image: row.get(4)?,
+                            views: None,
+                            date: None,
+                        })
+                    },
+                )
+                .optional()?;
+
+            let mut page = match indexed {
+                Some(page) => page,
+                None => {
+                    let page = summarize_page(filename, &content);
+                    tx.execute(
+                        "INSERT OR REPLACE INTO pages
+                         (filename, hash, title, datetime, weight, excerpt, image)
+                         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                        params![
+                            page.filename,
+                            hash,
+                            page.title,
+                            page.datetime,
+                            page.weight,
+                            page.excerpt,
+                            page.image
+                        ],
+                    )?;
+                    page
+                }
+            };
+            page.views = Some(views(&tx, &page.filename)?);
+            pages.push(page);
+        }
+
+        // Forget pages that were removed
+        let listed: HashSet<&str> = pages.iter().map(|p| p.filename.as_str()).collect();
+        let indexed: Vec<String> = tx
+            .prepare("SELECT filename FROM pages")?
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<_, _>>()?;
+        for filename in indexed {
+            if !listed.contains(filename.as_str()) {
+                tx.execute("DELETE FROM pages WHERE filename = ?1", [&filename])?;
+            }
+        }
+        tx.commit()?;
+
+        sort_pages(&mut pages);
+        Ok(pages)
+    }
+
+    /// Counts one more view of `filename`.
+    pub fn record_view(&self, filename: &str) -> anyhow::Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO views (filename, count) VALUES (?1, 1)
+             ON CONFLICT (filename) DO UPDATE SET count = count + 1",
+            [filename],
+        )?;
+        Ok(())
+    }
+
+    /// Records that `source` links to `filename`. Mentions already known are
+    /// ignored.
+    pub fn add_webmention(&self, filename: &str, source: &str) -> anyhow::Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT OR IGNORE INTO webmentions (filename, source, created_at)
+             VALUES (?1, ?2, ?3)",
+            params![filename, source, now()],
+        )?;
+        Ok(())
+    }
+
+    /// Forgets that `source` links to `filename`. Returns whether it was
+    /// known.
+    pub fn remove_webmention(&self, filename: &str, source: &str) -> anyhow::Result<bool> {
+        let removed = self.conn.lock().unwrap().execute(
+            "DELETE FROM webmentions WHERE filename = ?1 AND source = ?2",
+            params![filename, source],
+        )?;
+        Ok(removed > 0)
+    }
+
+    /// URLs of the pages mentioning `filename`, oldest first
+    pub fn webmentions(&self, filename: &str) -> anyhow::Result<Vec<String>> {
+        let conn = self.conn.lock().unwrap();
+        let sources = conn
+            .prepare("SELECT source FROM webmentions WHERE filename = ?1 ORDER BY id")?
+            .query_map([filename], |row| row.get(0))?
+            .collect::<Result<_, _>>()?;
+        Ok(sources)
+    }
+}
+
+/// Page dates became optional timestamps. The index is rebuilt from the
+/// pages anyway, so it is simply recreated.
+const TIMESTAMP_DATES: &str = "
+    DROP TABLE pages;
+    CREATE TABLE pages (
+        filename TEXT PRIMARY KEY,
+        hash TEXT NOT NULL,
+        title TEXT NOT NULL,
+        datetime INTEGER,
+        weight INTEGER,
+        excerpt TEXT
+    );";
+
+/// Pages gained a social image. Indexed pages are forgotten so they are
+/// summarized again with it.
+const PAGE_IMAGES: &str = "
+    ALTER TABLE pages ADD COLUMN image TEXT;
+    DELETE FROM pages;";
+
+/// Comments are left to the widget of `comments`, the table was never
+/// written to.
+const NO_COMMENTS: &str = "DROP TABLE comments;";
+
+fn migrate(conn: &mut Connection) -> anyhow::Result<()> {
+    let version: u32 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    let version = version as usize;
+    if version > MIGRATIONS.len() {
+        anyhow::bail!(
+            "Database schema version {} is newer than this build supports ({})",
+            version,
+            MIGRATIONS.len()
+        );
+    }
+
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration)?;
+        tx.pragma_update(None, "user_version", (i + 1) as u32)?;
+        tx.commit()?;
+        tracing::info!("Migrated database to schema version {}", i + 1);
+    }
+    Ok(())
+}
+
+fn views(conn: &Connection, filename: &str) -> rusqlite::Result<i64> {
+    conn.query_row(
+        "SELECT count FROM views WHERE filename = ?1",
+        [filename],
+        |row| row.get(0),
+    )
+    .optional()
+    .map(|count| count.unwrap_or(0))
+}
+
+/// Detects content changes only: the value may differ between builds of
+/// the crate, which just causes the index to be rebuilt once.
+fn content_hash(content: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    content.hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+fn now() -> String {
+    chrono::Utc::now().to_rfc3339()
+}
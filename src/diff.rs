@@ -0,0 +1,118 @@
+use serde::Serialize;
+use similar::TextDiff;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::assets::list_assets;
+use crate::{RenderOptions, build_artifacts, run_build};
+
+/// Output files that would change if the site was rebuilt
+#[derive(Default, Serialize)]
+pub struct DiffReport {
+    pub added: Vec<PathBuf>,
+    pub removed: Vec<PathBuf>,
+    /// With a unified diff of the content, when asked for and both sides are text
+    pub modified: Vec<(PathBuf, Option<String>)>,
+}
+
+impl DiffReport {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty()
+    }
+}
+
+impl fmt::Display for DiffReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for path in &self.added {
+            writeln!(f, "A {}", path.display())?;
+        }
+        for path in &self.removed {
+            writeln!(f, "D {}", path.display())?;
+        }
+        for (path, diff) in &self.modified {
+            writeln!(f, "M {}", path.display())?;
+            if let Some(diff) = diff {
+                write!(f, "{}", diff)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Builds the site into a temporary directory and compares the result with
+/// what is currently in `out_dir`.
+pub async fn run_diff(
+    docs_dir: PathBuf,
+    out_dir: PathBuf,
+    options: &RenderOptions,
+    prune_unused_assets: bool,
+    show_content: bool,
+) -> anyhow::Result<DiffReport> {
+    let temp_dir = tempfile::tempdir()?;
+    let temp_path = temp_dir.path().to_path_buf();
+    run_build(
+        docs_dir.clone(),
+        temp_path.clone(),
+        options,
+        prune_unused_assets,
+    )
+    .await?;
+
+    let mut new_files: BTreeSet<PathBuf> =
+        list_assets(&temp_path, &temp_path)?.into_iter().collect();
+    let mut old_files: BTreeSet<PathBuf> = if out_dir.exists() {
+        list_assets(&out_dir, &temp_path)?.into_iter().collect()
+    } else {
+        BTreeSet::new()
+    };
+
+    // When building in place, only generated files are comparable: assets are
+    // not copied there, and the rest of the directory is the sources
+    if docs_dir == out_dir {
+        let artifacts: BTreeSet<PathBuf> = build_artifacts(&docs_dir, &out_dir)
+            .await?
+            .into_iter()
+            .map(PathBuf::from)
+            .collect();
+        let generated = |path: &PathBuf| {
+            artifacts.contains(path) || path.extension().is_some_and(|e| e == "html")
+        };
+        old_files.retain(|path| generated(path));
+        new_files.retain(|path| generated(path));
+    }
+
+    let mut report = DiffReport::default();
+    for path in new_files.difference(&old_files) {
+        report.added.push(path.clone());
+    }
+    for path in old_files.difference(&new_files) {
+        report.removed.push(path.clone());
+    }
+    for path in new_files.intersection(&old_files) {
+        let old = tokio::fs::read(out_dir.join(path)).await?;
+        let new = tokio::fs::read(temp_path.join(path)).await?;
+        if old == new {
+            continue;
+        }
+        let diff = if show_content {
+            text_diff(path, &old, &new)
+        } else {
+            None
+        };
+        report.modified.push((path.clone(), diff));
+    }
+    Ok(report)
+}
+
+fn text_diff(path: &Path, old: &[u8], new: &[u8]) -> Option<String> {
+    let old = std::str::from_utf8(old).ok()?;
+    let new = std::str::from_utf8(new).ok()?;
+    let name = path.display().to_string();
+    Some(
+        TextDiff::from_lines(old, new)
+            .unified_diff()
+            .header(&format!("a/{}", name), &format!("b/{}", name))
+            .to_string(),
+    )
+}
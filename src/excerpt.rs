@@ -0,0 +1,46 @@
+use pulldown_cmark::{Event, Parser as MarkdownParser, Tag, TagEnd};
+
+use crate::frontmatter::Frontmatter;
+
+/// Marks the end of the excerpt in a page
+pub const MORE_MARKER: &str = "<!-- more -->";
+
+/// Plain text summary of a page: the frontmatter `description`, else what
+/// comes before `<!-- more -->`, else the first paragraph.
+pub fn excerpt(meta: &Frontmatter, body: &str) -> Option<String> {
+    if let Some(description) = &meta.description {
+        return Some(description.clone());
+    }
+
+    match body.find(MORE_MARKER) {
+        Some(pos) => paragraphs_text(&body[..pos], usize::MAX),
+        None => paragraphs_text(body, 1),
+    }
+}
+
+/// Joins the text of the first `limit` paragraphs of `markdown`.
+fn paragraphs_text(markdown: &str, limit: usize) -> Option<String> {
+    let mut text = String::new();
+    let mut in_paragraph = false;
+    let mut count = 0;
+
+    for event in MarkdownParser::new(markdown) {
+        match event {
+            Event::Start(Tag::Paragraph) => in_paragraph = true,
+            Event::End(TagEnd::Paragraph) => {
+                in_paragraph = false;
+                text.push(' ');
+                count += 1;
+                if count >= limit {
+                    break;
+                }
+            }
+            Event::Text(t) | Event::Code(t) if in_paragraph => text.push_str(&t),
+            Event::SoftBreak | Event::HardBreak if in_paragraph => text.push(' '),
+            _ => {}
+        }
+    }
+
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    (!text.is_empty()).then_some(text)
+}
@@ -0,0 +1,33 @@
+use std::fmt;
+
+/// Classes of failures commands exit with, each with its own exit code so
+/// scripts can tell them apart. Other errors exit with 1, and command line
+/// mistakes with 2.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Failure {
+    /// `blog.toml` or the flags are invalid
+    Config = 3,
+    /// Rendering or writing the site failed
+    Build = 4,
+    /// A check ran and found problems: `check`, `check-links`, `audit` and
+    /// `verify`
+    Problems = 5,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Failure::Config => "Invalid configuration",
+            Failure::Build => "Build failed",
+            Failure::Problems => "Problems found",
+        })
+    }
+}
+
+/// Exit code of a command that failed with `error`, marked with a `Failure`
+/// as context or not.
+pub fn exit_code(error: &anyhow::Error) -> u8 {
+    error
+        .downcast_ref::<Failure>()
+        .map_or(1, |failure| *failure as u8)
+}
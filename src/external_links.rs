@@ -0,0 +1,81 @@
+use pulldown_cmark::{CowStr, Event, Tag, TagEnd};
+use pulldown_cmark_escape::{escape_href, escape_html};
+
+/// Default `target` of links leaving the site
+pub const DEFAULT_TARGET: &str = "_blank";
+
+/// Default `rel` of links leaving the site
+pub const DEFAULT_REL: &str = "noopener noreferrer";
+
+/// Attributes given to links leaving the site, none when both are `None`
+#[derive(Clone, Debug, Default)]
+pub struct ExternalLinkOptions {
+    pub target: Option<String>,
+    pub rel: Option<String>,
+}
+
+/// Adds the `ExternalLinkOptions` attributes to links pointing outside the
+/// site: absolute URLs not under `base_url`.
+pub struct ExternalLinkRenderer<'o, I> {
+    inner: I,
+    options: &'o ExternalLinkOptions,
+    base_url: Option<&'o str>,
+    /// Whether the link being rendered was rewritten, so its end must be too
+    in_external: bool,
+}
+
+impl<'o, I> ExternalLinkRenderer<'o, I> {
+    pub fn new(inner: I, options: &'o ExternalLinkOptions, base_url: Option<&'o str>) -> Self {
+        Self {
+            inner,
+            options,
+            base_url,
+            in_external: false,
+        }
+    }
+
+    fn is_external(&self, url: &str) -> bool {
+        let absolute =
+            url.starts_with("http://") || url.starts_with("https://") || url.starts_with("//");
+        absolute && self.base_url.is_none_or(|base| !url.starts_with(base))
+    }
+}
+
+impl<'a, I: Iterator<Item = Event<'a>>> Iterator for ExternalLinkRenderer<'_, I> {
+    type Item = Event<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let event = self.inner.next()?;
+        if self.options.target.is_none() && self.options.rel.is_none() {
+            return Some(event);
+        }
+        match &event {
+            Event::Start(Tag::Link {
+                dest_url, title, ..
+            }) if self.is_external(dest_url) => {
+                self.in_external = true;
+                let mut html = String::from("<a href=\"");
+                let _ = escape_href(&mut html, dest_url);
+                if !title.is_empty() {
+                    html.push_str("\" title=\"");
+                    let _ = escape_html(&mut html, title);
+                }
+                if let Some(target) = &self.options.target {
+                    html.push_str("\" target=\"");
+                    let _ = escape_html(&mut html, target);
+                }
+                if let Some(rel) = &self.options.rel {
+                    html.push_str("\" rel=\"");
+                    let _ = escape_html(&mut html, rel);
+                }
+                html.push_str("\">");
+                Some(Event::InlineHtml(CowStr::Boxed(html.into_boxed_str())))
+            }
+            Event::End(TagEnd::Link) if self.in_external => {
+                self.in_external = false;
+                Some(Event::InlineHtml(CowStr::Borrowed("</a>")))
+            }
+            _ => Some(event),
+        }
+    }
+}
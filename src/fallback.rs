@@ -0,0 +1,147 @@
+use axum::{
+    extract::State,
+    http::{StatusCode, Uri},
+    response::{Html, IntoResponse, Response},
+};
+use serde::Serialize;
+use std::sync::Arc;
+use tera::Context;
+
+use crate::analytics;
+use crate::menu::{Location, SiteNavigation};
+use crate::translations::{self, ui_string};
+use crate::{AppState, RenderOptions, TEMPLATES, get_summary_data, page_url, served_root};
+
+/// Most pages suggested on the not found page
+const MAX_SUGGESTIONS: usize = 5;
+
+/// A page whose name or title is close to the requested one
+#[derive(Serialize)]
+pub struct Suggestion {
+    pub url: String,
+    pub title: String,
+}
+
+/// Page shown for unknown URLs, with a search prefilled from the URL. Since
+/// it can be served at any depth, links start from the site's base URL, or
+/// from the served root without one or when serving.
+pub fn render_fallback(
+    options: &RenderOptions,
+    is_static: bool,
+    suggestions: &[Suggestion],
+) -> anyhow::Result<String> {
+    let root = match &options.base_url {
+        Some(base_url) if is_static => base_url.clone(),
+        _ => served_root(options),
+    };
+    // Each language tree has its own search index
+    let search_root = match options.languages.first() {
+        Some(language) if is_static => format!("{}{}/", root, language),
+        _ => root.to_string(),
+    };
+
+    let mut context = Context::new();
+    context.insert("root", &root);
+    context.insert("home_url", &root);
+    SiteNavigation::new(
+        &options.menu,
+        &Location::Elsewhere,
+        &root,
+        &root,
+        is_static,
+        options.pretty_urls,
+    )
+    .insert_into(&mut context);
+    analytics::insert_into(options, &mut context);
+    translations::insert_into(options, &mut context);
+    context.insert("search_root", &search_root);
+    context.insert("suggestions", suggestions);
+    context.insert("title", ui_string(options, "not_found"));
+    context.insert("is_static", &is_static);
+    context.insert("no_navigation", &options.no_navigation);
+    context.insert("canonical", &None::<String>);
+    Ok(TEMPLATES
+        .read()
+        .unwrap()
+        .render("not_found.html", &context)?)
+}
+
+/// Checks that the fallback page is a plain `.html` file name.
+pub fn check_fallback_page(name: &str) -> anyhow::Result<()> {
+    if !name.ends_with(".html") || name.contains(['/', '\\']) || name.starts_with('.') {
+        anyhow::bail!("Invalid fallback page '{}', use a name like 404.html", name);
+    }
+    Ok(())
+}
+
+/// Answers unknown URLs with the fallback page when serving with one.
+pub async fn fallback_handler(State(state): State<Arc<AppState>>, uri: Uri) -> Response {
+    let requested = uri.path().trim_end_matches('/').rsplit('/').next();
+    not_found(&state, requested.unwrap_or_default()).await
+}
+
+/// The not found page for the page `requested`, suggesting the pages with
+/// the closest names and titles.
+pub async fn not_found(state: &AppState, requested: &str) -> Response {
+    let suggestions = suggest(state, requested).await;
+    match render_fallback(&state.options, false, &suggestions) {
+        Ok(rendered) => (StatusCode::NOT_FOUND, Html(rendered)).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+async fn suggest(state: &AppState, requested: &str) -> Vec<Suggestion> {
+    let requested = slug(requested);
+    if requested.is_empty() {
+        return Vec::new();
+    }
+    // Tolerate about one typo every three letters
+    let max_distance = (requested.chars().count() / 3).max(2);
+
+    let mut close: Vec<_> = get_summary_data(state.store.as_ref())
+        .await
+        .into_iter()
+        .filter_map(|page| {
+            let distance = levenshtein(&requested, &slug(&page.filename))
+                .min(levenshtein(&requested, &page.title.to_lowercase()));
+            (distance <= max_distance).then_some((distance, page))
+        })
+        .collect();
+    close.sort_by_key(|(distance, _)| *distance);
+    close
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, page)| Suggestion {
+            url: format!(
+                "{}{}",
+                served_root(&state.options),
+                page_url(&page.filename, false, state.options.pretty_urls)
+            ),
+            title: page.title,
+        })
+        .collect()
+}
+
+/// `post@1700000000.md` and `post.html` both become `post`.
+fn slug(name: &str) -> String {
+    let name = percent_encoding::percent_decode_str(name).decode_utf8_lossy();
+    let name = name.trim_end_matches(".md").trim_end_matches(".html");
+    let name = name.split_once('@').map_or(name, |(name, _)| name);
+    name.to_lowercase()
+}
+
+/// Number of single character edits turning `a` into `b`.
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
@@ -0,0 +1,297 @@
+use chrono::{DateTime, Utc};
+use pulldown_cmark_escape::escape_html;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+
+use crate::changes::{CHANGES_FEED_FILE, ChangeKind, collect_changes};
+use crate::dates::DateWindow;
+use crate::metadata::image_url;
+use crate::scaffold::slugify;
+use crate::store::ContentStore;
+use crate::{Page, absolute_url, frontmatter, get_summary_data};
+
+pub const FEED_FILE: &str = "feed.xml";
+
+/// Feed of every dated page, for readers catching up on a blog whose main
+/// feed only holds the last `feed_months` months
+pub const ARCHIVE_FEED_FILE: &str = "archive.xml";
+
+/// Directory of the feeds of each tag, see `tag_feed_file`
+pub const TAGS_DIR: &str = "tags";
+
+/// Atom feed of the dated pages under `base_url` published in `window`,
+/// newest first. With a WebSub `hub`, the feed tells readers to subscribe
+/// through it. A feed limited to recent months links to `ARCHIVE_FEED_FILE`.
+pub async fn build_feed(
+    store: &dyn ContentStore,
+    base_url: &str,
+    is_static: bool,
+    pretty_urls: bool,
+    hub: Option<&str>,
+    window: &DateWindow,
+) -> String {
+    let entries = dated_pages(store, window).await;
+    let archive = window
+        .months
+        .is_some()
+        .then(|| format!("{}{}", base_url, ARCHIVE_FEED_FILE));
+    let url = |filename: &str| absolute_url(base_url, filename, is_static, pretty_urls);
+    feed_xml(
+        entries,
+        base_url,
+        base_url,
+        FEED_FILE,
+        hub,
+        archive.as_deref(),
+        url,
+    )
+}
+
+/// Feed of every dated page under `base_url`, see `ARCHIVE_FEED_FILE`
+pub async fn build_archive_feed(
+    store: &dyn ContentStore,
+    base_url: &str,
+    is_static: bool,
+    pretty_urls: bool,
+) -> String {
+    let entries = dated_pages(store, &DateWindow::default()).await;
+    let url = |filename: &str| absolute_url(base_url, filename, is_static, pretty_urls);
+    feed_xml(
+        entries,
+        base_url,
+        base_url,
+        ARCHIVE_FEED_FILE,
+        None,
+        None,
+        url,
+    )
+}
+
+/// Where the feed of `tag` is published, like `tags/rust/feed.xml`. Tags
+/// with the same slug, such as "Rust" and "rust", share it.
+pub fn tag_feed_file(tag: &str) -> String {
+    format!("{}/{}/{}", TAGS_DIR, slugify(tag), FEED_FILE)
+}
+
+/// Dated pages of each tag, newest first, by the slug of the tag, along
+/// with the tag as first written
+async fn tagged_pages(
+    store: &dyn ContentStore,
+) -> BTreeMap<String, (String, Vec<(DateTime<Utc>, Page)>)> {
+    let mut tags: BTreeMap<String, (String, Vec<_>)> = BTreeMap::new();
+    for (published, page) in dated_pages(store, &DateWindow::default()).await {
+        let content = store.read_page(&page.filename).await.unwrap_or_default();
+        let mut slugs: Vec<(String, String)> = frontmatter::parse(&content)
+            .0
+            .tags
+            .into_iter()
+            .map(|tag| (slugify(&tag), tag))
+            .filter(|(slug, _)| !slug.is_empty())
+            .collect();
+        // Stable, keeping the first of the tags sharing a slug
+        slugs.sort_by(|a, b| a.0.cmp(&b.0));
+        slugs.dedup_by(|a, b| a.0 == b.0);
+        for (slug, tag) in slugs {
+            tags.entry(slug)
+                .or_insert_with(|| (tag, Vec::new()))
+                .1
+                .push((published, page.clone()));
+        }
+    }
+    tags
+}
+
+/// Slugs of the tags with a feed, see `tag_feed_file`
+pub async fn feed_tags(store: &dyn ContentStore) -> Vec<String> {
+    tagged_pages(store).await.into_keys().collect()
+}
+
+/// Atom feed of every dated page of each tag, by the path it is published
+/// at, see `tag_feed_file`.
+pub async fn build_tag_feeds(
+    store: &dyn ContentStore,
+    base_url: &str,
+    is_static: bool,
+    pretty_urls: bool,
+    hub: Option<&str>,
+) -> Vec<(String, String)> {
+    let url = |filename: &str| absolute_url(base_url, filename, is_static, pretty_urls);
+    tagged_pages(store)
+        .await
+        .into_values()
+        .map(|(tag, entries)| {
+            let file = tag_feed_file(&tag);
+            let title = format!("{} on {}", tag, base_url);
+            let xml = feed_xml(entries, &title, base_url, &file, hub, None, url);
+            (file, xml)
+        })
+        .collect()
+}
+
+/// The feed of the tag with this slug, `None` if no dated page has it
+pub async fn build_tag_feed(
+    store: &dyn ContentStore,
+    base_url: &str,
+    is_static: bool,
+    pretty_urls: bool,
+    hub: Option<&str>,
+    slug: &str,
+) -> Option<String> {
+    let (tag, entries) = tagged_pages(store).await.remove(slug)?;
+    let url = |filename: &str| absolute_url(base_url, filename, is_static, pretty_urls);
+    let title = format!("{} on {}", tag, base_url);
+    Some(feed_xml(
+        entries,
+        &title,
+        base_url,
+        &tag_feed_file(&tag),
+        hub,
+        None,
+        url,
+    ))
+}
+
+/// Pages published in `window` with their date, newest first
+async fn dated_pages(store: &dyn ContentStore, window: &DateWindow) -> Vec<(DateTime<Utc>, Page)> {
+    let mut entries: Vec<_> = window
+        .filter(get_summary_data(store).await, |page| page.datetime)
+        .into_iter()
+        .filter_map(|page| {
+            let published = DateTime::from_timestamp(page.datetime?, 0)?;
+            Some((published, page))
+        })
+        .collect();
+    entries.sort_by_key(|(published, _)| Reverse(*published));
+    entries
+}
+
+/// Atom document of `entries`, published at `file` under `base_url`
+fn feed_xml(
+    entries: Vec<(DateTime<Utc>, Page)>,
+    title: &str,
+    base_url: &str,
+    file: &str,
+    hub: Option<&str>,
+    archive: Option<&str>,
+    page_url: impl Fn(&str) -> String,
+) -> String {
+    // The newest entry, rather than the build time, keeps builds reproducible
+    let updated = entries
+        .first()
+        .map_or(DateTime::UNIX_EPOCH, |(published, _)| *published);
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    push_element(&mut xml, 1, "title", title);
+    // The main feed keeps the ID it always had
+    if file == FEED_FILE {
+        push_element(&mut xml, 1, "id", base_url);
+    } else {
+        push_element(&mut xml, 1, "id", &format!("{}{}", base_url, file));
+    }
+    push_element(&mut xml, 1, "updated", &updated.to_rfc3339());
+    push_links(&mut xml, &format!("{}{}", base_url, file), hub);
+    if let Some(archive) = archive {
+        // RFC 5005, the rest of the history is in the archive
+        xml.push_str("  <link rel=\"prev-archive\" href=\"");
+        let _ = escape_html(&mut xml, archive);
+        xml.push_str("\"/>\n");
+    }
+
+    for (published, page) in entries {
+        let url = page_url(&page.filename);
+        xml.push_str("  <entry>\n");
+        push_element(&mut xml, 2, "title", &page.title);
+        push_element(&mut xml, 2, "id", &url);
+        xml.push_str("    <link href=\"");
+        let _ = escape_html(&mut xml, &url);
+        xml.push_str("\"/>\n");
+        push_element(&mut xml, 2, "updated", &published.to_rfc3339());
+        if let Some(excerpt) = &page.excerpt {
+            push_element(&mut xml, 2, "summary", excerpt);
+        }
+        if let Some(image) = &page.image {
+            xml.push_str("    <link rel=\"enclosure\" href=\"");
+            let _ = escape_html(&mut xml, &image_url(image, Some(base_url), base_url));
+            xml.push_str("\"/>\n");
+        }
+        xml.push_str("  </entry>\n");
+    }
+    xml.push_str("</feed>\n");
+    xml
+}
+
+/// Atom feed of the latest changes to pages, separate from the main feed
+/// since pages show up again each time they are updated.
+pub async fn build_changes_feed(
+    store: &dyn ContentStore,
+    base_url: &str,
+    is_static: bool,
+    pretty_urls: bool,
+    hub: Option<&str>,
+) -> String {
+    let changes = collect_changes(store).await;
+    let updated = changes
+        .first()
+        .and_then(|change| DateTime::from_timestamp(change.datetime, 0))
+        .unwrap_or(DateTime::UNIX_EPOCH);
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    push_element(&mut xml, 1, "title", &format!("What's new on {}", base_url));
+    push_element(
+        &mut xml,
+        1,
+        "id",
+        &format!("{}{}", base_url, CHANGES_FEED_FILE),
+    );
+    push_element(&mut xml, 1, "updated", &updated.to_rfc3339());
+    push_links(&mut xml, &format!("{}{}", base_url, CHANGES_FEED_FILE), hub);
+
+    for change in changes {
+        let Some(changed) = DateTime::from_timestamp(change.datetime, 0) else {
+            continue;
+        };
+        let url = absolute_url(base_url, &change.filename, is_static, pretty_urls);
+        let verb = match change.kind {
+            ChangeKind::Added => "Added",
+            ChangeKind::Updated => "Updated",
+        };
+        xml.push_str("  <entry>\n");
+        push_element(&mut xml, 2, "title", &format!("{}: {}", verb, change.title));
+        // Each change is its own entry, the page's URL alone would merge them
+        push_element(&mut xml, 2, "id", &format!("{}#{}", url, change.datetime));
+        xml.push_str("    <link href=\"");
+        let _ = escape_html(&mut xml, &url);
+        xml.push_str("\"/>\n");
+        push_element(&mut xml, 2, "updated", &changed.to_rfc3339());
+        xml.push_str("  </entry>\n");
+    }
+    xml.push_str("</feed>\n");
+    xml
+}
+
+/// The feed's own URL and, if any, the WebSub hub to subscribe to it through
+fn push_links(xml: &mut String, self_url: &str, hub: Option<&str>) {
+    xml.push_str("  <link rel=\"self\" href=\"");
+    let _ = escape_html(&mut *xml, self_url);
+    xml.push_str("\"/>\n");
+    if let Some(hub) = hub {
+        xml.push_str("  <link rel=\"hub\" href=\"");
+        let _ = escape_html(&mut *xml, hub);
+        xml.push_str("\"/>\n");
+    }
+}
+
+/// `Link` header of a served feed advertising its WebSub hub, which
+/// subscribers find there without parsing the feed.
+pub fn hub_link_header(self_url: &str, hub: &str) -> String {
+    format!("<{}>; rel=\"hub\", <{}>; rel=\"self\"", hub, self_url)
+}
+
+fn push_element(xml: &mut String, depth: usize, name: &str, text: &str) {
+    xml.push_str(&"  ".repeat(depth));
+    xml.push_str(&format!("<{}>", name));
+    let _ = escape_html(&mut *xml, text);
+    xml.push_str(&format!("</{}>\n", name));
+}
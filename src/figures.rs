@@ -0,0 +1,115 @@
+use pulldown_cmark::{CowStr, Event, Tag, TagEnd};
+use pulldown_cmark_escape::escape_html;
+use std::collections::VecDeque;
+
+/// Turns paragraphs holding nothing but an image with a title,
+/// `![alt](src "caption")`, into a `<figure>` captioned by the title. With
+/// `numbered`, captions start with "Figure 1.", "Figure 2." and so on.
+pub struct FigureRenderer<'a, I> {
+    inner: I,
+    numbered: bool,
+    /// Figures rendered so far
+    count: usize,
+    queue: VecDeque<Event<'a>>,
+}
+
+impl<'a, I: Iterator<Item = Event<'a>>> FigureRenderer<'a, I> {
+    pub fn new(inner: I, numbered: bool) -> Self {
+        Self {
+            inner,
+            numbered,
+            count: 0,
+            queue: VecDeque::new(),
+        }
+    }
+}
+
+impl<'a, I: Iterator<Item = Event<'a>>> Iterator for FigureRenderer<'a, I> {
+    type Item = Event<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(event) = self.queue.pop_front() {
+            return Some(event);
+        }
+
+        let event = self.inner.next()?;
+        if !matches!(event, Event::Start(Tag::Paragraph)) {
+            return Some(event);
+        }
+
+        // Paragraphs don't nest, so this is the whole paragraph
+        let mut paragraph = vec![event];
+        for inner_event in self.inner.by_ref() {
+            let end = matches!(inner_event, Event::End(TagEnd::Paragraph));
+            paragraph.push(inner_event);
+            if end {
+                break;
+            }
+        }
+
+        // Leave out the whitespace around the image
+        let content: Vec<&Event> = paragraph[1..paragraph.len() - 1]
+            .iter()
+            .filter(|e| match e {
+                Event::SoftBreak => false,
+                Event::Text(text) => !text.trim().is_empty(),
+                _ => true,
+            })
+            .collect();
+        let caption = match (content.first(), content.last()) {
+            (Some(Event::Start(Tag::Image { title, .. })), Some(Event::End(TagEnd::Image)))
+                if !title.is_empty()
+                    && content
+                        .iter()
+                        .filter(|e| matches!(e, Event::Start(Tag::Image { .. })))
+                        .count()
+                        == 1 =>
+            {
+                title.to_string()
+            }
+            _ => {
+                self.queue.extend(paragraph);
+                return self.queue.pop_front();
+            }
+        };
+
+        self.count += 1;
+        self.queue.push_back(Event::Html(CowStr::from("<figure>")));
+        for event in paragraph.into_iter().filter(|e| {
+            !matches!(
+                e,
+                Event::Start(Tag::Paragraph) | Event::End(TagEnd::Paragraph)
+            )
+        }) {
+            // The caption takes the place of the title
+            let event = match event {
+                Event::Start(Tag::Image {
+                    link_type,
+                    dest_url,
+                    id,
+                    ..
+                }) => Event::Start(Tag::Image {
+                    link_type,
+                    dest_url,
+                    title: CowStr::from(""),
+                    id,
+                }),
+                event => event,
+            };
+            self.queue.push_back(event);
+        }
+
+        let mut figcaption = String::from("<figcaption>");
+        if self.numbered {
+            figcaption.push_str(&format!(
+                "<span class=\"figure-number\">Figure {}.</span> ",
+                self.count
+            ));
+        }
+        let _ = escape_html(&mut figcaption, &caption);
+        figcaption.push_str("</figcaption></figure>");
+        self.queue
+            .push_back(Event::Html(CowStr::Boxed(figcaption.into_boxed_str())));
+        self.queue.pop_front()
+    }
+}
@@ -0,0 +1,387 @@
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use crate::sync::hash_tree;
+
+/// Extensions of the assets given a fingerprinted copy
+const FINGERPRINTED: &[&str] = &[
+    "css", "js", "mjs", "png", "jpg", "jpeg", "gif", "webp", "avif", "svg", "ico", "woff", "woff2",
+    "ttf", "otf", "mp3", "mp4", "webm", "pdf",
+];
+
+/// Files browsers and crawlers request by name
+const KEPT_NAMES: &[&str] = &["favicon.ico", "apple-touch-icon.png"];
+
+/// Hex digits of the content hash in fingerprinted names
+const HASH_LENGTH: usize = 8;
+
+/// HTML attributes whose values are rewritten
+const URL_ATTRIBUTES: &[&str] = &["href", "src", "srcset", "poster", "content"];
+
+/// Where the built site is deployed, to resolve the URLs pointing into it
+pub struct SiteLocation<'a> {
+    /// `base_url`, which social images and feeds link with
+    pub base_url: Option<&'a str>,
+    /// `base_path`, which absolute URLs start with
+    pub base_path: &'a str,
+}
+
+/// Writes a copy of every asset of `out_dir` named after its content, like
+/// `style.1a2b3c4d.css`, and points the HTML and CSS files at the copies so
+/// they can be cached for good. The originals stay for feeds, links from
+/// other sites and pages cached from a previous deploy. Returns how many
+/// copies were written.
+pub fn fingerprint_tree(out_dir: &Path, site: &SiteLocation) -> anyhow::Result<usize> {
+    let files = hash_tree(out_dir)?;
+    let assets: BTreeMap<&String, &String> = files
+        .iter()
+        .filter(|(path, _)| is_fingerprintable(path))
+        .collect();
+
+    // Hash of each asset, by path relative to `out_dir`
+    let mut hashes: HashMap<String, String> = HashMap::new();
+    for (path, hash) in &assets {
+        if !path.ends_with(".css") {
+            hashes.insert(path.to_string(), hash[..HASH_LENGTH].to_string());
+        }
+    }
+
+    // Stylesheets link to assets, and to each other with `@import`, so
+    // their hash is the one of the rewritten content, the imported first
+    let mut stylesheets: Vec<(&String, String)> = assets
+        .keys()
+        .filter(|path| path.ends_with(".css"))
+        .map(|path| Ok((*path, std::fs::read_to_string(out_dir.join(path))?)))
+        .collect::<anyhow::Result<_>>()?;
+    while !stylesheets.is_empty() {
+        let imports_pending = |(path, css): &(&String, String)| {
+            css_urls(css).iter().any(|url| {
+                resolve(url, directory(path), site).is_some_and(|target| {
+                    target != **path && stylesheets.iter().any(|(other, _)| **other == target)
+                })
+            })
+        };
+        // Any of them if they import each other
+        let ready = stylesheets
+            .iter()
+            .position(|stylesheet| !imports_pending(stylesheet))
+            .unwrap_or(0);
+        let (path, css) = stylesheets.remove(ready);
+        let rewritten = rewrite_css(&css, directory(path), site, &hashes);
+        if rewritten != css {
+            std::fs::write(out_dir.join(path), &rewritten)?;
+        }
+        let digest = Sha256::digest(rewritten.as_bytes());
+        let hash: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+        hashes.insert(path.to_string(), hash[..HASH_LENGTH].to_string());
+    }
+
+    for path in files.keys().filter(|path| path.ends_with(".html")) {
+        let content = std::fs::read_to_string(out_dir.join(path))?;
+        let rewritten = rewrite_html(&content, directory(path), site, &hashes);
+        if rewritten != content {
+            std::fs::write(out_dir.join(path), rewritten)?;
+        }
+    }
+
+    for (path, hash) in &hashes {
+        std::fs::copy(
+            out_dir.join(path),
+            out_dir.join(fingerprinted_name(path, hash)),
+        )?;
+    }
+    tracing::info!("Fingerprinted {} assets", hashes.len());
+    Ok(hashes.len())
+}
+
+fn is_fingerprintable(path: &str) -> bool {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    let extension = name.rsplit_once('.').map(|(_, extension)| extension);
+    extension.is_some_and(|extension| FINGERPRINTED.contains(&extension))
+        && !KEPT_NAMES.contains(&path)
+        && !is_fingerprinted(name)
+}
+
+/// Whether `name` is that of a copy written by `fingerprint_tree`, which
+/// never changes
+pub fn is_fingerprinted(name: &str) -> bool {
+    let mut parts = name.rsplit('.');
+    parts.next();
+    parts.next().is_some_and(is_hash) && parts.next().is_some()
+}
+
+fn is_hash(s: &str) -> bool {
+    s.len() == HASH_LENGTH
+        && s.bytes()
+            .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+}
+
+/// Whether `name` is a fingerprinted copy of the file named `original`, or
+/// a compressed sibling of one
+pub fn is_copy_of(name: &str, original: &str) -> bool {
+    let Some((stem, extension)) = original.rsplit_once('.') else {
+        return false;
+    };
+    let Some(rest) = name
+        .strip_prefix(stem)
+        .and_then(|rest| rest.strip_prefix('.'))
+    else {
+        return false;
+    };
+    let Some((hash, rest)) = rest.split_at_checked(HASH_LENGTH) else {
+        return false;
+    };
+    let Some(suffix) = rest
+        .strip_prefix('.')
+        .and_then(|rest| rest.strip_prefix(extension))
+    else {
+        return false;
+    };
+    is_hash(hash)
+        && (suffix.is_empty()
+            || crate::precompress::ENCODINGS
+                .iter()
+                .any(|(_, compressed)| suffix.strip_prefix('.') == Some(compressed)))
+}
+
+/// `images/photo.png` becomes `images/photo.1a2b3c4d.png`
+fn fingerprinted_name(path: &str, hash: &str) -> String {
+    match path.rsplit_once('.') {
+        Some((stem, extension)) => format!("{}.{}.{}", stem, hash, extension),
+        None => format!("{}.{}", path, hash),
+    }
+}
+
+pub fn directory(path: &str) -> &str {
+    path.rsplit_once('/').map_or("", |(dir, _)| dir)
+}
+
+/// Path relative to the output directory of what `url`, found in a file of
+/// `dir`, links to. `None` for URLs leaving the site.
+pub fn resolve(url: &str, dir: &str, site: &SiteLocation) -> Option<String> {
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    let absolute = site
+        .base_url
+        .and_then(|base| path.strip_prefix(base.trim_end_matches('/')))
+        .or_else(|| (!path.starts_with("//")).then_some(path))
+        .filter(|path| path.starts_with('/'));
+    if let Some(path) = absolute {
+        let path = path
+            .strip_prefix(site.base_path)
+            .filter(|path| path.starts_with('/'))
+            .unwrap_or(path);
+        return normalize("", path);
+    }
+    if path.starts_with('/') || path.contains(':') {
+        return None;
+    }
+    normalize(dir, path)
+}
+
+fn normalize(dir: &str, path: &str) -> Option<String> {
+    let path = percent_encoding::percent_decode_str(path)
+        .decode_utf8()
+        .ok()?;
+    let mut segments: Vec<&str> = dir.split('/').filter(|s| !s.is_empty()).collect();
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop()?;
+            }
+            segment => segments.push(segment),
+        }
+    }
+    (!segments.is_empty()).then(|| segments.join("/"))
+}
+
+/// `url` pointing at the fingerprinted copy of what it links to, if any
+fn rewrite_url(
+    url: &str,
+    dir: &str,
+    site: &SiteLocation,
+    hashes: &HashMap<String, String>,
+) -> Option<String> {
+    let hash = hashes.get(&resolve(url, dir, site)?)?;
+    let end = url.find(['?', '#']).unwrap_or(url.len());
+    let (path, suffix) = url.split_at(end);
+    let name_start = path.rfind('/').map_or(0, |i| i + 1);
+    let dot = path[name_start..].rfind('.')? + name_start;
+    Some(format!(
+        "{}.{}{}{}",
+        &path[..dot],
+        hash,
+        &path[dot..],
+        suffix
+    ))
+}
+
+/// URLs of the `url()` and `@import` rules of a stylesheet
+fn css_urls(css: &str) -> Vec<&str> {
+    css_url_spans(css)
+        .into_iter()
+        .map(|(start, end)| &css[start..end])
+        .collect()
+}
+
+/// Byte ranges of the URLs of a stylesheet
+pub fn css_url_spans(css: &str) -> Vec<(usize, usize)> {
+    let mut spans = Vec::new();
+    for (keyword, _) in css
+        .match_indices("url(")
+        .chain(css.match_indices("@import"))
+    {
+        let start = keyword
+            + if css[keyword..].starts_with("url(") {
+                "url(".len()
+            } else {
+                "@import".len()
+            };
+        let value = &css[start..];
+        let trimmed = value.trim_start();
+        let start = start + value.len() - trimmed.len();
+        let span = match trimmed.chars().next() {
+            Some(quote @ ('"' | '\'')) => trimmed[1..]
+                .find(quote)
+                .map(|end| (start + 1, start + 1 + end)),
+            // `@import url(...)` is found as `url(`
+            Some(_) if css[keyword..].starts_with("@import") => None,
+            Some(_) => trimmed.find([')', ' ']).map(|end| (start, start + end)),
+            None => None,
+        };
+        spans.extend(span);
+    }
+    spans.sort();
+    spans.dedup();
+    spans
+}
+
+fn rewrite_css(
+    css: &str,
+    dir: &str,
+    site: &SiteLocation,
+    hashes: &HashMap<String, String>,
+) -> String {
+    let mut out = String::with_capacity(css.len());
+    let mut copied = 0;
+    for (start, end) in css_url_spans(css) {
+        if let Some(url) = rewrite_url(&css[start..end], dir, site, hashes) {
+            out.push_str(&css[copied..start]);
+            out.push_str(&url);
+            copied = end;
+        }
+    }
+    out.push_str(&css[copied..]);
+    out
+}
+
+fn rewrite_html(
+    html: &str,
+    dir: &str,
+    site: &SiteLocation,
+    hashes: &HashMap<String, String>,
+) -> String {
+    rewrite_html_urls(html, |_, url| rewrite_url(url, dir, site, hashes))
+}
+
+/// Rewrites the URLs of the double-quoted `URL_ATTRIBUTES` of `html`, the
+/// only quotes generated pages leave unescaped outside of tags. `rewrite`
+/// is given the attribute's name and a decoded URL, each one of a `srcset`,
+/// and returns the URL replacing it, if any.
+pub fn rewrite_html_urls(
+    html: &str,
+    mut rewrite: impl FnMut(&str, &str) -> Option<String>,
+) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(equals) = rest.find("=\"") {
+        let value_start = equals + "=\"".len();
+        let Some(length) = rest[value_start..].find('"') else {
+            break;
+        };
+        let value = &rest[value_start..value_start + length];
+        let before = &rest[..equals];
+        let name_start = before
+            .rfind(|c: char| !c.is_ascii_alphanumeric() && c != '-')
+            .map_or(0, |i| i + 1);
+        let is_url = URL_ATTRIBUTES.contains(&&before[name_start..])
+            && before[..name_start].ends_with(char::is_whitespace);
+        out.push_str(&rest[..value_start]);
+        match is_url.then(|| rewrite_attribute(&before[name_start..], value, &mut rewrite)) {
+            Some(Some(rewritten)) => out.push_str(&rewritten),
+            _ => out.push_str(value),
+        }
+        rest = &rest[value_start + length..];
+    }
+    out.push_str(rest);
+    out
+}
+
+fn rewrite_attribute(
+    name: &str,
+    value: &str,
+    rewrite: &mut impl FnMut(&str, &str) -> Option<String>,
+) -> Option<String> {
+    // Templates escape attributes, `/` included
+    let value = unescape(value);
+    if name != "srcset" {
+        return rewrite(name, &value).map(|url| url.replace('&', "&amp;"));
+    }
+    let mut changed = false;
+    let candidates: Vec<String> = value
+        .split(',')
+        .map(|candidate| {
+            let candidate = candidate.trim();
+            let (url, descriptor) = candidate.split_once(' ').unwrap_or((candidate, ""));
+            match rewrite(name, url) {
+                Some(url) => {
+                    changed = true;
+                    format!("{} {}", url, descriptor).trim_end().to_string()
+                }
+                None => candidate.to_string(),
+            }
+        })
+        .collect();
+    changed.then(|| candidates.join(", ").replace('&', "&amp;"))
+}
+
+/// `value` with the character references templates write decoded
+pub fn unescape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let decoded = rest.find(';').and_then(|end| {
+            let c = match &rest[1..end] {
+                "amp" => '&',
+                "lt" => '<',
+                "gt" => '>',
+                "quot" => '"',
+                "apos" => '\'',
+                reference => {
+                    let number = reference.strip_prefix('#')?;
+                    let code = match number.strip_prefix(['x', 'X']) {
+                        Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                        None => number.parse().ok()?,
+                    };
+                    char::from_u32(code)?
+                }
+            };
+            Some((c, end + 1))
+        });
+        match decoded {
+            Some((c, length)) => {
+                out.push(c);
+                rest = &rest[length..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+
+use pulldown_cmark::{CowStr, Event, Tag, TagEnd};
+use pulldown_cmark_escape::escape_html;
+use serde::Deserialize;
+
+/// Where footnote definitions end up in the rendered page.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FootnotePosition {
+    /// Leave definitions where they are written in the markdown
+    #[default]
+    Inline,
+    /// Gather every definition at the end of the page
+    Page,
+    /// Gather definitions at the end of the section that references them
+    Section,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct FootnoteOptions {
+    pub position: FootnotePosition,
+    /// Heading shown above a gathered footnote block
+    pub title: Option<String>,
+    /// Whether to draw a `<hr>` above a gathered footnote block
+    pub separator: bool,
+}
+
+/// Moves footnote definitions according to `FootnoteOptions`. This needs the
+/// whole document up front, since definitions usually come after their uses.
+pub struct FootnoteRenderer<'a> {
+    events: std::vec::IntoIter<Event<'a>>,
+}
+
+impl<'a> FootnoteRenderer<'a> {
+    pub fn new<I: Iterator<Item = Event<'a>>>(inner: I, options: &FootnoteOptions) -> Self {
+        let events = match options.position {
+            FootnotePosition::Inline => inner.collect(),
+            _ => place_footnotes(inner, options),
+        };
+        Self {
+            events: events.into_iter(),
+        }
+    }
+}
+
+impl<'a> Iterator for FootnoteRenderer<'a> {
+    type Item = Event<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.events.next()
+    }
+}
+
+fn place_footnotes<'a>(
+    inner: impl Iterator<Item = Event<'a>>,
+    options: &FootnoteOptions,
+) -> Vec<Event<'a>> {
+    // Pull every definition out of the stream
+    let mut body = Vec::new();
+    let mut definitions: HashMap<String, Vec<Event<'a>>> = HashMap::new();
+    let mut order = Vec::new();
+    let mut current: Option<(String, Vec<Event<'a>>)> = None;
+
+    for event in inner {
+        match (&mut current, event) {
+            (None, Event::Start(Tag::FootnoteDefinition(label))) => {
+                let start = Event::Start(Tag::FootnoteDefinition(label.clone()));
+                current = Some((label.to_string(), vec![start]));
+            }
+            (Some(_), Event::End(TagEnd::FootnoteDefinition)) => {
+                let (label, mut events) = current.take().unwrap();
+                events.push(Event::End(TagEnd::FootnoteDefinition));
+                order.push(label.clone());
+                definitions.insert(label, events);
+            }
+            (Some((_, events)), event) => events.push(event),
+            (None, event) => body.push(event),
+        }
+    }
+
+    let mut output = Vec::with_capacity(body.len());
+    let mut pending = Vec::new();
+
+    if options.position == FootnotePosition::Section {
+        for event in body {
+            if let Event::FootnoteReference(label) = &event
+                && definitions.contains_key(label.as_ref())
+                && !pending.contains(&label.to_string())
+            {
+                pending.push(label.to_string());
+            }
+            if let Event::Start(Tag::Heading { .. }) = event {
+                flush(&mut output, &mut pending, &mut definitions, options);
+            }
+            output.push(event);
+        }
+    } else {
+        output = body;
+    }
+
+    // Whatever was not placed yet goes at the end, in definition order. Labels
+    // already flushed are skipped since they are no longer in `definitions`
+    pending.extend(order);
+    flush(&mut output, &mut pending, &mut definitions, options);
+    output
+}
+
+fn flush<'a>(
+    output: &mut Vec<Event<'a>>,
+    pending: &mut Vec<String>,
+    definitions: &mut HashMap<String, Vec<Event<'a>>>,
+    options: &FootnoteOptions,
+) {
+    let blocks: Vec<_> = pending
+        .drain(..)
+        .filter_map(|label| definitions.remove(&label))
+        .collect();
+    if blocks.is_empty() {
+        return;
+    }
+
+    let mut header = String::from("<section class=\"footnotes\">");
+    if options.separator {
+        header.push_str("<hr />");
+    }
+    if let Some(title) = &options.title {
+        header.push_str("<p class=\"footnotes-title\">");
+        let _ = escape_html(&mut header, title);
+        header.push_str("</p>");
+    }
+
+    output.push(Event::Html(CowStr::from(header)));
+    output.extend(blocks.into_iter().flatten());
+    output.push(Event::Html(CowStr::Borrowed("</section>")));
+}
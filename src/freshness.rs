@@ -0,0 +1,393 @@
+use axum::http::{HeaderValue, header};
+use axum::response::Response;
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use crate::glossary::GLOSSARY_FILE;
+use crate::includes::included_files;
+use crate::preprocess::{Preprocessor, shortcode_files};
+use crate::scaffold::slugify;
+use crate::store::ContentStore;
+use crate::{RenderOptions, codeblocks, frontmatter, nav_files};
+
+/// Header carrying the generation of the site index a page was rendered from
+pub const CONTENT_VERSION_HEADER: &str = "x-content-version";
+
+/// What served responses render pages into
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Artifact {
+    /// A page, by file name
+    Page(String),
+    /// The home listing
+    Home,
+    /// The feeds
+    Feed,
+    /// The feed of a tag, by slug
+    TagFeed(String),
+}
+
+/// Counts changes to the sources pages are rendered from: the pages, and the
+/// glossary, bibliographies, shortcodes and included files they read. The
+/// generation of the index goes up by one each time a request sees a source
+/// changed, and only the artifacts depending on it take it, while the
+/// highlighted snippets of the pages reading it are dropped. Requests only
+/// compare the versions the store reports, the pages are read again to
+/// find what depends on what only once something changed.
+#[derive(Default)]
+pub struct IndexGeneration {
+    index: Mutex<SiteIndex>,
+}
+
+#[derive(Default)]
+struct SiteIndex {
+    /// 0 until the first request
+    generation: u64,
+    /// Version of each source, by page name or file path, when it exists
+    versions: HashMap<String, String>,
+    /// Artifacts depending on each source
+    dependents: HashMap<String, HashSet<Artifact>>,
+    /// Sources that aren't pages, whose versions are asked for one by one
+    files: BTreeSet<String>,
+    /// Generation of the index each artifact last changed at, when after 1
+    changed: HashMap<Artifact, u64>,
+}
+
+impl IndexGeneration {
+    /// Generation of `artifact` with the current content of `store`: that of
+    /// the index when a source it depends on last changed, starting at 1.
+    pub async fn current(
+        &self,
+        store: &dyn ContentStore,
+        options: &RenderOptions,
+        artifact: &Artifact,
+    ) -> u64 {
+        let pages = store.page_versions().await.unwrap_or_default();
+        let mut versions = pages.clone();
+        let files = self.index.lock().unwrap().files.clone();
+        add_file_versions(store, &files, &mut versions).await;
+
+        if !self.index.lock().unwrap().is_current(&versions) {
+            let mut contents: BTreeMap<String, String> = BTreeMap::new();
+            for filename in pages.keys() {
+                if let Ok(content) = store.read_page(filename).await {
+                    contents.insert(filename.clone(), content);
+                }
+            }
+            let order = nav_files(store, options.nav_order, options.language.as_deref()).await;
+            let dependents = dependents(&contents, &order, options);
+
+            // Sources no page reads anymore are forgotten
+            let files: BTreeSet<String> = dependents
+                .keys()
+                .filter(|source| !contents.contains_key(*source))
+                .cloned()
+                .collect();
+            versions.retain(|source, _| pages.contains_key(source) || files.contains(source));
+            add_file_versions(store, &files, &mut versions).await;
+            self.index
+                .lock()
+                .unwrap()
+                .update(versions, dependents, files);
+        }
+        let index = self.index.lock().unwrap();
+        index.changed.get(artifact).copied().unwrap_or(1)
+    }
+}
+
+/// Adds the versions of `files` missing from `versions`
+async fn add_file_versions(
+    store: &dyn ContentStore,
+    files: &BTreeSet<String>,
+    versions: &mut HashMap<String, String>,
+) {
+    for path in files {
+        if !versions.contains_key(path)
+            && let Some(version) = store.file_version(path).await
+        {
+            versions.insert(path.clone(), version);
+        }
+    }
+}
+
+impl SiteIndex {
+    fn is_current(&self, versions: &HashMap<String, String>) -> bool {
+        self.generation > 0 && self.versions == *versions
+    }
+
+    /// Replaces the sources with `versions` and `dependents`, invalidating
+    /// what depended on the changed ones before and after the change.
+    fn update(
+        &mut self,
+        versions: HashMap<String, String>,
+        dependents: HashMap<String, HashSet<Artifact>>,
+        files: BTreeSet<String>,
+    ) {
+        let changed: HashSet<&String> = self
+            .versions
+            .keys()
+            .chain(versions.keys())
+            .filter(|source| self.versions.get(*source) != versions.get(*source))
+            .collect();
+        if changed.is_empty() && self.generation > 0 {
+            self.dependents = dependents;
+            self.files = files;
+            return;
+        }
+
+        self.generation += 1;
+        if self.generation > 1 {
+            for source in changed {
+                let before = self.dependents.get(source);
+                let after = dependents.get(source);
+                for artifact in before.into_iter().chain(after).flatten() {
+                    self.changed.insert(artifact.clone(), self.generation);
+                }
+                if self.files.contains(source) || files.contains(source) {
+                    for artifact in before.into_iter().chain(after).flatten() {
+                        if let Artifact::Page(page) = artifact {
+                            codeblocks::invalidate_page_highlights(page);
+                        }
+                    }
+                } else {
+                    codeblocks::invalidate_page_highlights(source);
+                }
+            }
+        }
+        self.versions = versions;
+        self.dependents = dependents;
+        self.files = files;
+    }
+}
+
+/// Artifacts depending on each source of the pages of `contents`. A page is
+/// rendered into its own page, the home listing, the feeds and those of its
+/// tags, its neighbors in `nav_order`, and the pages listing it as related
+/// or in their series, which share a tag or its series. The glossary, its
+/// bibliography, the shortcodes it calls and the files it includes are
+/// rendered into its own page.
+fn dependents(
+    contents: &BTreeMap<String, String>,
+    nav_order: &[String],
+    options: &RenderOptions,
+) -> HashMap<String, HashSet<Artifact>> {
+    let metas: Vec<(&String, frontmatter::Frontmatter, &str)> = contents
+        .iter()
+        .map(|(filename, content)| {
+            let (meta, body) = frontmatter::parse(content);
+            (filename, meta, body)
+        })
+        .collect();
+    let mut groups: HashMap<(&str, &str), Vec<&String>> = HashMap::new();
+    for (filename, meta, _) in &metas {
+        for tag in &meta.tags {
+            groups.entry(("tag", tag)).or_default().push(filename);
+        }
+        if let Some(series) = &meta.series {
+            groups.entry(("series", series)).or_default().push(filename);
+        }
+    }
+
+    let mut dependents: HashMap<String, HashSet<Artifact>> = HashMap::new();
+    for (filename, meta, body) in &metas {
+        let page = Artifact::Page(filename.to_string());
+        let mut artifacts = HashSet::from([page.clone(), Artifact::Home, Artifact::Feed]);
+        artifacts.extend(meta.tags.iter().map(|tag| Artifact::TagFeed(slugify(tag))));
+        if let Some(i) = nav_order.iter().position(|f| f == *filename) {
+            let neighbors = [i.checked_sub(1), Some(i + 1)];
+            for neighbor in neighbors.into_iter().flatten() {
+                if let Some(neighbor) = nav_order.get(neighbor) {
+                    artifacts.insert(Artifact::Page(neighbor.clone()));
+                }
+            }
+        }
+        let tags = meta.tags.iter().map(|tag| ("tag", tag.as_str()));
+        let series = meta.series.iter().map(|series| ("series", series.as_str()));
+        for group in tags.chain(series) {
+            for other in &groups[&group] {
+                artifacts.insert(Artifact::Page(other.to_string()));
+            }
+        }
+        dependents
+            .entry(filename.to_string())
+            .or_default()
+            .extend(artifacts);
+
+        let mut reads = Vec::new();
+        if filename.as_str() != GLOSSARY_FILE && meta.glossary != Some(false) {
+            reads.push(GLOSSARY_FILE.to_string());
+        }
+        reads.extend(meta.bibliography.clone().or(options.bibliography.clone()));
+        if options.preprocessors.contains(&Preprocessor::Shortcodes) {
+            reads.extend(shortcode_files(body));
+        }
+        if options.preprocessors.contains(&Preprocessor::Includes) {
+            reads.extend(included_files(body));
+        }
+        for source in reads {
+            dependents.entry(source).or_default().insert(page.clone());
+        }
+    }
+    dependents
+}
+
+/// Adds `X-Content-Version` and, when known, `Last-Modified` to a page
+/// response.
+pub fn add_headers(response: &mut Response, generation: u64, modified: Option<SystemTime>) {
+    let headers = response.headers_mut();
+    headers.insert(CONTENT_VERSION_HEADER, HeaderValue::from(generation));
+    if let Some(modified) = modified
+        && let Ok(value) = HeaderValue::from_str(&http_date(modified))
+    {
+        headers.insert(header::LAST_MODIFIED, value);
+    }
+}
+
+/// Formats `time` like `Wed, 21 Oct 2015 07:28:00 GMT`.
+fn http_date(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time)
+        .format("%a, %d %b %Y %H:%M:%S GMT")
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pages(pages: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pages
+            .iter()
+            .map(|(name, content)| (name.to_string(), content.to_string()))
+            .collect()
+    }
+
+    /// Updates `index` to `contents`, versioned by their content
+    fn update(index: &mut SiteIndex, contents: &BTreeMap<String, String>, nav_order: &[&str]) {
+        let nav_order: Vec<String> = nav_order.iter().map(|name| name.to_string()).collect();
+        let dependents = dependents(contents, &nav_order, &RenderOptions::default());
+        let files = dependents
+            .keys()
+            .filter(|source| !contents.contains_key(*source))
+            .cloned()
+            .collect();
+        index.update(contents.clone().into_iter().collect(), dependents, files);
+    }
+
+    fn generation(index: &SiteIndex, artifact: Artifact) -> u64 {
+        index.changed.get(&artifact).copied().unwrap_or(1)
+    }
+
+    fn page(name: &str) -> Artifact {
+        Artifact::Page(name.to_string())
+    }
+
+    #[test]
+    fn page_depends_on_its_listings_and_neighbors() {
+        let contents = pages(&[
+            ("a.md", "---\ntags: [rust]\n---\n# A"),
+            ("b.md", "# B"),
+            ("c.md", "---\ntags: [rust]\n---\n# C"),
+            ("d.md", "# D"),
+        ]);
+        let order = ["a.md", "b.md", "c.md", "d.md"].map(String::from);
+        let dependents = dependents(&contents, &order, &RenderOptions::default());
+
+        let b = &dependents["b.md"];
+        for artifact in [page("a.md"), page("b.md"), page("c.md")] {
+            assert!(b.contains(&artifact), "{:?}", artifact);
+        }
+        assert!(b.contains(&Artifact::Home) && b.contains(&Artifact::Feed));
+        assert!(!b.contains(&page("d.md")));
+
+        // Sharing a tag, even though not neighbors
+        let a = &dependents["a.md"];
+        assert!(a.contains(&page("c.md")));
+        assert!(a.contains(&Artifact::TagFeed("rust".to_string())));
+        assert!(!a.contains(&page("d.md")));
+    }
+
+    #[test]
+    fn pages_depend_on_the_glossary_unless_disabled() {
+        let contents = pages(&[
+            ("a.md", "# A"),
+            ("b.md", "---\nglossary: false\n---\n# B"),
+            (GLOSSARY_FILE, "# Glossary"),
+        ]);
+        let dependents = dependents(&contents, &[], &RenderOptions::default());
+        let glossary = &dependents[GLOSSARY_FILE];
+        assert!(glossary.contains(&page("a.md")));
+        assert!(!glossary.contains(&page("b.md")));
+    }
+
+    #[test]
+    fn pages_depend_on_included_files_and_shortcodes() {
+        let contents = pages(&[(
+            "a.md",
+            "# A\n\n{{ include_code(path=\"src/main.rs\") }}\n\n{{ note(text=\"hi\") }}\n",
+        )]);
+        let options = RenderOptions {
+            preprocessors: vec![Preprocessor::Includes, Preprocessor::Shortcodes],
+            ..Default::default()
+        };
+        let dependents = dependents(&contents, &[], &options);
+        assert_eq!(dependents["src/main.rs"], HashSet::from([page("a.md")]));
+        let shortcode = format!("{}/note.md", crate::preprocess::SHORTCODES_DIR);
+        assert_eq!(dependents[&shortcode], HashSet::from([page("a.md")]));
+    }
+
+    #[test]
+    fn tag_change_invalidates_old_and_new_tag_feeds() {
+        let mut index = SiteIndex::default();
+        let before = pages(&[
+            ("a.md", "---\ntags: [old]\n---\n# A"),
+            ("b.md", "---\ntags: [other]\n---\n# B"),
+        ]);
+        update(&mut index, &before, &[]);
+        assert_eq!(index.generation, 1);
+
+        let after = pages(&[
+            ("a.md", "---\ntags: [new]\n---\n# A"),
+            ("b.md", "---\ntags: [other]\n---\n# B"),
+        ]);
+        update(&mut index, &after, &[]);
+        assert_eq!(index.generation, 2);
+        assert_eq!(generation(&index, Artifact::TagFeed("old".to_string())), 2);
+        assert_eq!(generation(&index, Artifact::TagFeed("new".to_string())), 2);
+        assert_eq!(
+            generation(&index, Artifact::TagFeed("other".to_string())),
+            1
+        );
+        assert_eq!(generation(&index, page("b.md")), 1);
+    }
+
+    #[test]
+    fn neighbor_edit_bumps_adjacent_pages() {
+        let order = ["a.md", "b.md", "c.md", "d.md"];
+        let mut index = SiteIndex::default();
+        let mut contents = pages(&[
+            ("a.md", "# A"),
+            ("b.md", "# B"),
+            ("c.md", "# C"),
+            ("d.md", "# D"),
+        ]);
+        update(&mut index, &contents, &order);
+
+        contents.insert("c.md".to_string(), "# C, edited".to_string());
+        update(&mut index, &contents, &order);
+        for name in ["b.md", "c.md", "d.md"] {
+            assert_eq!(generation(&index, page(name)), 2, "{}", name);
+        }
+        assert_eq!(generation(&index, page("a.md")), 1);
+    }
+
+    #[test]
+    fn unchanged_sources_keep_the_generation() {
+        let mut index = SiteIndex::default();
+        let contents = pages(&[("a.md", "# A")]);
+        update(&mut index, &contents, &[]);
+        update(&mut index, &contents, &[]);
+        assert_eq!(index.generation, 1);
+        assert!(index.is_current(&contents.clone().into_iter().collect()));
+    }
+}
@@ -0,0 +1,89 @@
+use serde::Deserialize;
+
+use crate::tags;
+
+/// Metadata block at the top of a page, delimited by `---` lines.
+#[derive(Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct Frontmatter {
+    /// Explicit position in listings and navigation, lower comes first
+    pub weight: Option<i64>,
+    /// URL of the original publication, for cross-posted content
+    pub canonical: Option<String>,
+    /// In their canonical spelling, see `tags::set_tag_aliases`
+    pub tags: Vec<String>,
+    /// Summary shown on the home page instead of the first paragraph
+    pub description: Option<String>,
+    /// Image representing the page, in the docs directory or on the web
+    pub cover: Option<String>,
+    /// Link preview overrides, see `metadata::PageMetadata`
+    pub og_title: Option<String>,
+    pub og_description: Option<String>,
+    pub og_image: Option<String>,
+    /// Publication date, taking precedence over the file name's timestamp
+    pub date: Option<RawDate>,
+    /// Set to false to leave glossary terms unlinked on this page
+    pub glossary: Option<bool>,
+    /// Set to false to leave the comments widget out of this page
+    pub comments: Option<bool>,
+    /// Bibliography of this page's citations, instead of `bibliography`
+    pub bibliography: Option<String>,
+    /// Name of the series this page is a part of
+    pub series: Option<String>,
+    /// Position in the series, parts without one come last
+    pub part: Option<i64>,
+    /// Set to false for a draft, left out of listings and builds until
+    /// `blog publish`
+    pub published: Option<bool>,
+}
+
+impl Frontmatter {
+    pub fn is_draft(&self) -> bool {
+        self.published == Some(false)
+    }
+}
+
+/// A date as written in the frontmatter, parsed by `dates::page_date`
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum RawDate {
+    Timestamp(i64),
+    Text(String),
+}
+
+/// Splits a page into its frontmatter and markdown body. Pages without a
+/// frontmatter block, or with one that fails to parse, get the defaults.
+pub fn parse(content: &str) -> (Frontmatter, &str) {
+    let Some((yaml, body)) = split(content) else {
+        return (Frontmatter::default(), content);
+    };
+    // An empty or comment-only block deserializes to `None`
+    match serde_yaml::from_str::<Option<Frontmatter>>(yaml) {
+        Ok(frontmatter) => {
+            let mut frontmatter: Frontmatter = frontmatter.unwrap_or_default();
+            frontmatter.tags = tags::canonical_tags(frontmatter.tags);
+            (frontmatter, body)
+        }
+        Err(e) => {
+            tracing::warn!("Invalid frontmatter: {}", e);
+            (Frontmatter::default(), body)
+        }
+    }
+}
+
+/// The frontmatter block and the body of a page, if it has a block
+pub fn split(content: &str) -> Option<(&str, &str)> {
+    let rest = content.strip_prefix("---")?;
+    let rest = rest
+        .strip_prefix('\n')
+        .or_else(|| rest.strip_prefix("\r\n"))?;
+
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end() == "---" {
+            return Some((&rest[..offset], &rest[offset + line.len()..]));
+        }
+        offset += line.len();
+    }
+    None
+}
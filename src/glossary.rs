@@ -0,0 +1,188 @@
+use pulldown_cmark::{CowStr, Event, LinkType, Tag, TagEnd};
+use std::collections::VecDeque;
+
+use crate::frontmatter;
+use crate::scaffold::slugify;
+use crate::store::ContentStore;
+
+/// Page whose `term: definition` lines make up the glossary
+pub const GLOSSARY_FILE: &str = "glossary.md";
+
+/// Terms of the glossary and the URL of their entry
+#[derive(Default)]
+pub struct Glossary {
+    terms: Vec<(String, String)>,
+}
+
+impl Glossary {
+    /// Reads the glossary page from `store`, if there is one. `page_url` is
+    /// the glossary page's URL as linked from the page being rendered.
+    pub async fn load(store: &dyn ContentStore, page_url: &str) -> Self {
+        let Ok(content) = store.read_page(GLOSSARY_FILE).await else {
+            return Self::default();
+        };
+        let (_, body) = frontmatter::parse(&content);
+        let mut terms: Vec<(String, String)> = entries(body)
+            .map(|(term, _)| (term.to_string(), format!("{}#{}", page_url, anchor(term))))
+            .collect();
+        // Longer terms first, so "static site" wins over "site"
+        terms.sort_by_key(|(term, _)| std::cmp::Reverse(term.len()));
+        Self { terms }
+    }
+}
+
+/// Glossary entries of a page body: `term: definition` lines outside of
+/// code blocks, headings and lists.
+fn entries(body: &str) -> impl Iterator<Item = (&str, &str)> {
+    let mut in_code = false;
+    body.lines().filter_map(move |line| {
+        if line.trim_start().starts_with("```") {
+            in_code = !in_code;
+            return None;
+        }
+        if in_code || line.starts_with([' ', '\t', '#', '-', '*', '>', '<', '|']) {
+            return None;
+        }
+        let (term, definition) = line.split_once(": ")?;
+        let term = term.trim();
+        (!term.is_empty()).then_some((term, definition.trim()))
+    })
+}
+
+fn anchor(term: &str) -> String {
+    format!("term-{}", slugify(term))
+}
+
+/// Rewrites the entries of the glossary page with an anchor on each term,
+/// for the links to point at.
+pub fn anchor_entries(body: &str) -> String {
+    let mut output = String::with_capacity(body.len());
+    let mut in_code = false;
+    for line in body.lines() {
+        if line.trim_start().starts_with("```") {
+            in_code = !in_code;
+        }
+        match entries(line).next().filter(|_| !in_code) {
+            Some((term, definition)) => output.push_str(&format!(
+                "<a id=\"{}\"></a>**{}**: {}\n\n",
+                anchor(term),
+                term,
+                definition
+            )),
+            None => {
+                output.push_str(line);
+                output.push('\n');
+            }
+        }
+    }
+    output
+}
+
+/// Links the first occurrence of each glossary term in a page to its entry.
+/// Text in links, headings, images and code is left alone.
+pub struct GlossaryRenderer<'a, 'g, I> {
+    inner: I,
+    glossary: &'g Glossary,
+    /// Whether each term of the glossary has been linked already
+    linked: Vec<bool>,
+    /// Depth of the elements whose text is not linked
+    skip_depth: usize,
+    queue: VecDeque<Event<'a>>,
+}
+
+impl<'a, 'g, I: Iterator<Item = Event<'a>>> GlossaryRenderer<'a, 'g, I> {
+    pub fn new(inner: I, glossary: &'g Glossary) -> Self {
+        Self {
+            inner,
+            glossary,
+            linked: vec![false; glossary.terms.len()],
+            skip_depth: 0,
+            queue: VecDeque::new(),
+        }
+    }
+
+    fn link_terms(&mut self, text: &str) {
+        let mut rest = text;
+        loop {
+            let earliest = self
+                .glossary
+                .terms
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| !self.linked[*i])
+                .filter_map(|(i, (term, _))| find_term(rest, term).map(|pos| (pos, i)))
+                .min_by_key(|(pos, _)| *pos);
+            let Some((pos, i)) = earliest else {
+                break;
+            };
+            self.linked[i] = true;
+
+            let (term, url) = &self.glossary.terms[i];
+            let end = pos + term.len();
+            if pos > 0 {
+                self.queue
+                    .push_back(Event::Text(CowStr::from(rest[..pos].to_string())));
+            }
+            self.queue.push_back(Event::Start(Tag::Link {
+                link_type: LinkType::Inline,
+                dest_url: CowStr::from(url.clone()),
+                title: CowStr::from("glossary"),
+                id: CowStr::from(""),
+            }));
+            self.queue
+                .push_back(Event::Text(CowStr::from(rest[pos..end].to_string())));
+            self.queue.push_back(Event::End(TagEnd::Link));
+            rest = &rest[end..];
+        }
+        if !rest.is_empty() {
+            self.queue
+                .push_back(Event::Text(CowStr::from(rest.to_string())));
+        }
+    }
+}
+
+impl<'a, I: Iterator<Item = Event<'a>>> Iterator for GlossaryRenderer<'a, '_, I> {
+    type Item = Event<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(event) = self.queue.pop_front() {
+            return Some(event);
+        }
+
+        let event = self.inner.next()?;
+        match &event {
+            Event::Start(
+                Tag::Link { .. } | Tag::Image { .. } | Tag::Heading { .. } | Tag::CodeBlock(_),
+            ) => self.skip_depth += 1,
+            Event::End(TagEnd::Link | TagEnd::Image | TagEnd::Heading(_) | TagEnd::CodeBlock) => {
+                self.skip_depth -= 1
+            }
+            Event::Text(text) if self.skip_depth == 0 && !self.glossary.terms.is_empty() => {
+                let text = text.to_string();
+                self.link_terms(&text);
+                return self.queue.pop_front();
+            }
+            _ => {}
+        }
+        Some(event)
+    }
+}
+
+/// Byte offset of the first whole-word occurrence of `term`, ignoring ASCII
+/// case.
+fn find_term(text: &str, term: &str) -> Option<usize> {
+    text.char_indices().map(|(i, _)| i).find(|&i| {
+        let end = i + term.len();
+        end <= text.len()
+            && text.is_char_boundary(end)
+            && text[i..end].eq_ignore_ascii_case(term)
+            && !text[..i]
+                .chars()
+                .next_back()
+                .is_some_and(char::is_alphanumeric)
+            && !text[end..]
+                .chars()
+                .next()
+                .is_some_and(char::is_alphanumeric)
+    })
+}
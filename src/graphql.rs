@@ -0,0 +1,147 @@
+use async_graphql::{Context, EmptyMutation, EmptySubscription, Object, Schema, SimpleObject};
+use axum::{Json, Router, extract::State, response::IntoResponse, routing::post};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::check::backlinks;
+use crate::metadata::image_url;
+use crate::search::build_search_index;
+use crate::{AppState, frontmatter, get_summary_data, page_url};
+
+type BlogSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+/// Read-only GraphQL endpoint over the site index, served at `/graphql`.
+pub fn graphql_router(state: Arc<AppState>) -> Router {
+    let schema = Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+        .data(state)
+        .finish();
+    Router::new()
+        .route("/graphql", post(graphql_handler))
+        .with_state(schema)
+}
+
+async fn graphql_handler(
+    State(schema): State<BlogSchema>,
+    Json(request): Json<async_graphql::Request>,
+) -> impl IntoResponse {
+    (
+        [("cache-control", "no-store")],
+        Json(schema.execute(request).await),
+    )
+}
+
+#[derive(SimpleObject, Clone)]
+#[graphql(name = "Page")]
+struct PageObject {
+    filename: String,
+    url: String,
+    title: String,
+    /// Publication date as a Unix timestamp
+    datetime: Option<i64>,
+    weight: Option<i64>,
+    excerpt: Option<String>,
+    /// Social image, absolute with a base URL, else from the site root
+    image: Option<String>,
+    tags: Vec<String>,
+    /// Pages linking to this one
+    backlinks: Vec<String>,
+}
+
+#[derive(SimpleObject)]
+struct Tag {
+    name: String,
+    pages: Vec<String>,
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    /// Pages in home page order, optionally only those with `tag`
+    async fn pages(&self, ctx: &Context<'_>, tag: Option<String>) -> Vec<PageObject> {
+        let pages = load_pages(ctx.data_unchecked()).await;
+        match tag {
+            Some(tag) => pages
+                .into_iter()
+                .filter(|p| p.tags.contains(&tag))
+                .collect(),
+            None => pages,
+        }
+    }
+
+    async fn page(&self, ctx: &Context<'_>, filename: String) -> Option<PageObject> {
+        load_pages(ctx.data_unchecked())
+            .await
+            .into_iter()
+            .find(|p| p.filename == filename)
+    }
+
+    /// Every tag, sorted by name
+    async fn tags(&self, ctx: &Context<'_>) -> Vec<Tag> {
+        let mut tags: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for page in load_pages(ctx.data_unchecked()).await {
+            for tag in page.tags {
+                tags.entry(tag).or_default().push(page.filename.clone());
+            }
+        }
+        tags.into_iter()
+            .map(|(name, pages)| Tag { name, pages })
+            .collect()
+    }
+
+    /// Pages whose title, headings or text contain `query`, ignoring case
+    async fn search(&self, ctx: &Context<'_>, query: String) -> Vec<PageObject> {
+        let state: &Arc<AppState> = ctx.data_unchecked();
+        let query = query.to_lowercase();
+        let matches: Vec<String> =
+            build_search_index(state.store.as_ref(), false, state.options.pretty_urls)
+                .await
+                .into_iter()
+                .filter(|entry| {
+                    entry.title.to_lowercase().contains(&query)
+                        || entry
+                            .headings
+                            .iter()
+                            .any(|h| h.to_lowercase().contains(&query))
+                        || entry.text.to_lowercase().contains(&query)
+                })
+                .map(|entry| entry.url)
+                .collect();
+
+        load_pages(state)
+            .await
+            .into_iter()
+            .filter(|p| matches.contains(&p.url))
+            .collect()
+    }
+}
+
+async fn load_pages(state: &Arc<AppState>) -> Vec<PageObject> {
+    let store = state.store.as_ref();
+    let mut backlinks = backlinks(store).await;
+    let mut pages = Vec::new();
+    for page in get_summary_data(store).await {
+        let content = store.read_page(&page.filename).await.unwrap_or_default();
+        let (meta, _) = frontmatter::parse(&content);
+
+        pages.push(PageObject {
+            url: page_url(&page.filename, false, state.options.pretty_urls),
+            filename: page.filename,
+            title: page.title,
+            datetime: page.datetime,
+            weight: page.weight,
+            excerpt: page.excerpt,
+            image: page
+                .image
+                .map(|image| image_url(&image, state.options.base_url.as_deref(), "")),
+            tags: meta.tags,
+            backlinks: Vec::new(),
+        });
+    }
+
+    for page in &mut pages {
+        page.backlinks = backlinks.remove(&page.filename).unwrap_or_default();
+        page.backlinks.sort();
+    }
+    pages
+}
@@ -0,0 +1,489 @@
+use image::codecs::avif::AvifEncoder;
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::{CompressionType, FilterType as PngFilter, PngEncoder};
+use image::codecs::webp::WebPEncoder;
+use image::imageops::FilterType;
+use image::metadata::Orientation;
+use image::{DynamicImage, ImageDecoder, ImageEncoder, ImageReader};
+use pulldown_cmark::{CowStr, Event, Parser as MarkdownParser, Tag, TagEnd};
+use pulldown_cmark_escape::{escape_href, escape_html};
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::{BufWriter, Cursor};
+use std::path::Path;
+
+use crate::links::UrlResolver;
+use crate::store::ContentStore;
+
+/// Widths of the resized copies when `image_widths` is not set
+pub const DEFAULT_IMAGE_WIDTHS: &[u32] = &[480, 960, 1600];
+
+/// Quality of the lossy encodings, out of 100
+const QUALITY: u8 = 80;
+
+/// Widest an image is shown, the width of the page's content
+const SIZES: &str = "(max-width: 850px) 100vw, 850px";
+
+/// Extra format images are also encoded in, for browsers that support it
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageFormat {
+    Webp,
+    Avif,
+}
+
+impl ImageFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            ImageFormat::Webp => "webp",
+            ImageFormat::Avif => "avif",
+        }
+    }
+}
+
+/// How `build` optimizes the images pages show
+#[derive(Debug, Clone)]
+pub struct ImageOptions {
+    /// Widths of the resized copies, larger ones are left out for each image
+    pub widths: Vec<u32>,
+    pub formats: Vec<ImageFormat>,
+}
+
+/// Copies written for an image, used to fill in `srcset`
+#[derive(Debug, Clone)]
+pub struct ImageSet {
+    pub width: u32,
+    pub height: u32,
+    /// Widths of the resized copies, smaller than `width`
+    pub widths: Vec<u32>,
+    pub formats: Vec<ImageFormat>,
+}
+
+/// Optimized images by path relative to the docs directory
+pub type ImageSets = BTreeMap<String, ImageSet>;
+
+fn is_raster(path: &str) -> bool {
+    let path = path.to_ascii_lowercase();
+    path.ends_with(".png") || path.ends_with(".jpg") || path.ends_with(".jpeg")
+}
+
+/// `img/photo.jpg` at 480 pixels wide in webp is `img/photo-480w.webp`.
+/// Without a width, the copy is full size.
+fn variant_path(path: &str, width: Option<u32>, extension: &str) -> String {
+    let stem = path.rsplit_once('.').map_or(path, |(stem, _)| stem);
+    match width {
+        Some(width) => format!("{}-{}w.{}", stem, width, extension),
+        None => format!("{}.{}", stem, extension),
+    }
+}
+
+/// Writes resized and re-encoded copies of the `images` of `docs_dir` next
+/// to their originals in `out_dir`. Images that fail to decode are left
+/// as is, with a warning.
+pub async fn optimize_images(
+    docs_dir: &Path,
+    out_dir: &Path,
+    images: BTreeSet<String>,
+    options: &ImageOptions,
+) -> anyhow::Result<ImageSets> {
+    let mut sets = ImageSets::new();
+    for path in images.into_iter().filter(|path| is_raster(path)) {
+        let source = docs_dir.join(&path);
+        if !source.is_file() {
+            continue;
+        }
+        let out_dir = out_dir.to_path_buf();
+        let options = options.clone();
+        let relative = path.clone();
+        let set = tokio::task::spawn_blocking(move || {
+            optimize_image(&source, &out_dir, &relative, &options)
+        })
+        .await?;
+        match set {
+            Ok(set) => {
+                tracing::info!("Optimized {}", path);
+                sets.insert(path, set);
+            }
+            Err(e) => tracing::warn!("Failed to optimize {}: {}", path, e),
+        }
+    }
+    Ok(sets)
+}
+
+fn optimize_image(
+    source: &Path,
+    out_dir: &Path,
+    path: &str,
+    options: &ImageOptions,
+) -> anyhow::Result<ImageSet> {
+    let image = image::open(source)?;
+    let (width, height) = (image.width(), image.height());
+    let extension = path.rsplit_once('.').map_or("", |(_, ext)| ext);
+    if let Some(parent) = out_dir.join(path).parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let widths: Vec<u32> = options
+        .widths
+        .iter()
+        .copied()
+        .filter(|w| *w < width)
+        .collect();
+    for &w in &widths {
+        let resized = image.resize(w, u32::MAX, FilterType::Lanczos3);
+        encode(
+            &resized,
+            &out_dir.join(variant_path(path, Some(w), extension)),
+        )?;
+        for format in &options.formats {
+            let target = variant_path(path, Some(w), format.extension());
+            encode(&resized, &out_dir.join(target))?;
+        }
+    }
+    for format in &options.formats {
+        let target = variant_path(path, None, format.extension());
+        encode(&image, &out_dir.join(target))?;
+    }
+
+    Ok(ImageSet {
+        width,
+        height,
+        widths,
+        formats: options.formats.clone(),
+    })
+}
+
+/// Encodes `image` in the format of `target`'s extension.
+fn encode(image: &DynamicImage, target: &Path) -> anyhow::Result<()> {
+    let extension = target.extension().and_then(|e| e.to_str()).unwrap_or("");
+    // Encoders take 8-bit pixels, and JPEG has no alpha channel
+    let image = match extension {
+        "jpg" | "jpeg" => DynamicImage::ImageRgb8(image.to_rgb8()),
+        _ if image.color().has_alpha() => DynamicImage::ImageRgba8(image.to_rgba8()),
+        _ => DynamicImage::ImageRgb8(image.to_rgb8()),
+    };
+    let (pixels, width, height, color) = (
+        image.as_bytes(),
+        image.width(),
+        image.height(),
+        image.color().into(),
+    );
+
+    let writer = BufWriter::new(File::create(target)?);
+    match extension.to_ascii_lowercase().as_str() {
+        "jpg" | "jpeg" => JpegEncoder::new_with_quality(writer, QUALITY)
+            .write_image(pixels, width, height, color)?,
+        "png" => PngEncoder::new_with_quality(writer, CompressionType::Best, PngFilter::Adaptive)
+            .write_image(pixels, width, height, color)?,
+        "webp" => WebPEncoder::new_lossless(writer).write_image(pixels, width, height, color)?,
+        "avif" => AvifEncoder::new_with_speed_quality(writer, 8, QUALITY)
+            .write_image(pixels, width, height, color)?,
+        _ => anyhow::bail!("Unsupported image format '{}'", extension),
+    }
+    Ok(())
+}
+
+/// Width and height of images by path relative to the docs directory
+pub type ImageSizes = BTreeMap<String, (u32, u32)>;
+
+/// Reads the size of the local images of a page body from their headers.
+/// Images that can't be read are left out.
+pub async fn image_sizes(store: &dyn ContentStore, body: &str, urls: &UrlResolver) -> ImageSizes {
+    let mut sizes = ImageSizes::new();
+    for event in MarkdownParser::new(body) {
+        let Event::Start(Tag::Image { dest_url, .. }) = event else {
+            continue;
+        };
+        let Some(path) = urls.local_path(&dest_url) else {
+            continue;
+        };
+        if sizes.contains_key(&path) {
+            continue;
+        }
+        let size = match store.read_bytes(&path).await {
+            Ok(bytes) => ImageReader::new(Cursor::new(bytes))
+                .with_guessed_format()
+                .ok()
+                .and_then(|reader| reader.into_dimensions().ok()),
+            Err(_) => None,
+        };
+        if let Some(size) = size {
+            sizes.insert(path, size);
+        }
+    }
+    sizes
+}
+
+/// Rewrites the images of a page: those `build` optimized become a
+/// `<picture>` offering the resized copies and extra formats, and with
+/// `lazy`, images load when scrolled to, with their size set beforehand so
+/// the page doesn't shift.
+pub struct ImageRenderer<'s, I> {
+    inner: I,
+    sets: &'s ImageSets,
+    sizes: &'s ImageSizes,
+    lazy: bool,
+    urls: &'s UrlResolver,
+}
+
+impl<'s, I> ImageRenderer<'s, I> {
+    pub fn new(
+        inner: I,
+        sets: &'s ImageSets,
+        sizes: &'s ImageSizes,
+        lazy: bool,
+        urls: &'s UrlResolver,
+    ) -> Self {
+        Self {
+            inner,
+            sets,
+            sizes,
+            lazy,
+            urls,
+        }
+    }
+}
+
+impl<'a, I: Iterator<Item = Event<'a>>> Iterator for ImageRenderer<'_, I> {
+    type Item = Event<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let event = self.inner.next()?;
+        let Event::Start(Tag::Image {
+            dest_url, title, ..
+        }) = &event
+        else {
+            return Some(event);
+        };
+        let path = self.urls.local_path(dest_url);
+        let set = path.as_ref().and_then(|path| self.sets.get(path));
+        if set.is_none() && !self.lazy {
+            return Some(event);
+        }
+        let dest_url = self
+            .urls
+            .resolve(dest_url)
+            .unwrap_or_else(|| dest_url.to_string());
+
+        // The alt text is the image's inline content
+        let mut alt = String::new();
+        let mut depth = 0;
+        for inner_event in self.inner.by_ref() {
+            match inner_event {
+                Event::Start(_) => depth += 1,
+                Event::End(TagEnd::Image) if depth == 0 => break,
+                Event::End(_) => depth -= 1,
+                Event::Text(text) | Event::Code(text) => alt.push_str(&text),
+                _ => {}
+            }
+        }
+
+        let srcset = |set: &ImageSet, extension: &str| {
+            let full = if dest_url.ends_with(extension) {
+                dest_url.to_string()
+            } else {
+                variant_path(&dest_url, None, extension)
+            };
+            let candidates = set
+                .widths
+                .iter()
+                .map(|w| (variant_path(&dest_url, Some(*w), extension), *w))
+                .chain([(full, set.width)]);
+
+            let mut srcset = String::new();
+            for (url, width) in candidates {
+                if !srcset.is_empty() {
+                    srcset.push_str(", ");
+                }
+                let _ = escape_href(&mut srcset, &url);
+                srcset.push_str(&format!(" {}w", width));
+            }
+            srcset
+        };
+
+        let mut html = String::new();
+        if let Some(set) = set {
+            html.push_str("<picture>");
+            for format in set.formats.iter().rev() {
+                html.push_str(&format!(
+                    "<source type=\"image/{}\" srcset=\"{}\" sizes=\"{}\">",
+                    format.extension(),
+                    srcset(set, format.extension()),
+                    SIZES
+                ));
+            }
+        }
+        html.push_str("<img src=\"");
+        let _ = escape_href(&mut html, &dest_url);
+        html.push('"');
+        if let Some(set) = set {
+            let extension = dest_url.rsplit_once('.').map_or("", |(_, ext)| ext);
+            html.push_str(&format!(
+                " srcset=\"{}\" sizes=\"{}\"",
+                srcset(set, extension),
+                SIZES
+            ));
+        }
+        let size = match set {
+            Some(set) => Some((set.width, set.height)),
+            None => path.and_then(|path| self.sizes.get(&path).copied()),
+        };
+        if let Some((width, height)) = size {
+            html.push_str(&format!(" width=\"{}\" height=\"{}\"", width, height));
+        }
+        if self.lazy {
+            html.push_str(" loading=\"lazy\" decoding=\"async\"");
+        }
+        html.push_str(" alt=\"");
+        let _ = escape_html(&mut html, &alt);
+        if !title.is_empty() {
+            html.push_str("\" title=\"");
+            let _ = escape_html(&mut html, title);
+        }
+        html.push_str("\">");
+        if set.is_some() {
+            html.push_str("</picture>");
+        }
+        Some(Event::InlineHtml(CowStr::Boxed(html.into_boxed_str())))
+    }
+}
+
+/// Copies the image `source` to `target` without its EXIF and XMP metadata,
+/// which can hold where and when a photo was taken. Returns whether there
+/// was any. Photos the metadata turns upright are re-encoded turned, since
+/// the orientation goes with the rest.
+pub fn copy_without_metadata(source: &Path, target: &Path) -> anyhow::Result<bool> {
+    let bytes = std::fs::read(source)?;
+    let mut decoder = ImageReader::new(Cursor::new(&bytes))
+        .with_guessed_format()?
+        .into_decoder()?;
+    let orientation = decoder.orientation()?;
+    if orientation != Orientation::NoTransforms {
+        let mut image = DynamicImage::from_decoder(decoder)?;
+        image.apply_orientation(orientation);
+        encode(&image, target)?;
+        return Ok(true);
+    }
+
+    let stripped = strip_jpeg(&bytes)
+        .or_else(|| strip_png(&bytes))
+        .or_else(|| strip_webp(&bytes))
+        .filter(|stripped| stripped.len() < bytes.len());
+    std::fs::write(target, stripped.as_deref().unwrap_or(&bytes))?;
+    Ok(stripped.is_some())
+}
+
+/// Whether the image at `path` is of a format `copy_without_metadata` handles
+pub fn has_metadata_format(path: &Path) -> bool {
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    matches!(
+        extension.to_ascii_lowercase().as_str(),
+        "jpg" | "jpeg" | "png" | "webp"
+    )
+}
+
+/// JPEG without its EXIF, XMP and Photoshop (IPTC) segments, `None` when the
+/// bytes are not a well-formed JPEG.
+fn strip_jpeg(bytes: &[u8]) -> Option<Vec<u8>> {
+    if !bytes.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+    let mut stripped = vec![0xFF, 0xD8];
+    let mut pos = 2;
+    loop {
+        if pos + 2 > bytes.len() || bytes[pos] != 0xFF {
+            return None;
+        }
+        let marker = bytes[pos + 1];
+        match marker {
+            // Fill byte
+            0xFF => {
+                pos += 1;
+                continue;
+            }
+            // Start of scan and end of image, the rest is image data
+            0xDA | 0xD9 => {
+                stripped.extend_from_slice(&bytes[pos..]);
+                return Some(stripped);
+            }
+            // Markers without a length
+            0x01 | 0xD0..=0xD7 => {
+                stripped.extend_from_slice(&bytes[pos..pos + 2]);
+                pos += 2;
+                continue;
+            }
+            _ => {}
+        }
+
+        let length = u16::from_be_bytes([*bytes.get(pos + 2)?, *bytes.get(pos + 3)?]) as usize;
+        let end = pos + 2 + length;
+        let data = bytes.get(pos + 4..end)?;
+        let metadata = match marker {
+            0xE1 => {
+                data.starts_with(b"Exif\0") || data.starts_with(b"http://ns.adobe.com/xap/1.0/\0")
+            }
+            0xED => data.starts_with(b"Photoshop 3.0\0"),
+            _ => false,
+        };
+        if !metadata {
+            stripped.extend_from_slice(&bytes[pos..end]);
+        }
+        pos = end;
+    }
+}
+
+/// PNG without its `eXIf` chunk and XMP or EXIF text chunks, `None` when the
+/// bytes are not a well-formed PNG.
+fn strip_png(bytes: &[u8]) -> Option<Vec<u8>> {
+    const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+    if !bytes.starts_with(SIGNATURE) {
+        return None;
+    }
+    let mut stripped = SIGNATURE.to_vec();
+    let mut pos = SIGNATURE.len();
+    while pos < bytes.len() {
+        let length = u32::from_be_bytes(bytes.get(pos..pos + 4)?.try_into().ok()?) as usize;
+        let kind = bytes.get(pos + 4..pos + 8)?;
+        // Length, type, data and CRC
+        let end = pos + 12 + length;
+        let data = bytes.get(pos + 8..pos + 8 + length)?;
+        let metadata = kind == b"eXIf"
+            || (matches!(kind, b"tEXt" | b"zTXt" | b"iTXt")
+                && (data.starts_with(b"XML:com.adobe.xmp\0")
+                    || data.starts_with(b"Raw profile type")));
+        if !metadata {
+            stripped.extend_from_slice(bytes.get(pos..end)?);
+        }
+        pos = end;
+    }
+    Some(stripped)
+}
+
+/// WebP without its `EXIF` and `XMP ` chunks, `None` when the bytes are not
+/// a well-formed WebP.
+fn strip_webp(bytes: &[u8]) -> Option<Vec<u8>> {
+    if bytes.len() < 12 || &bytes[..4] != b"RIFF" || &bytes[8..12] != b"WEBP" {
+        return None;
+    }
+    let mut stripped = bytes[..12].to_vec();
+    let mut pos = 12;
+    while pos < bytes.len() {
+        let kind = bytes.get(pos..pos + 4)?;
+        let length = u32::from_le_bytes(bytes.get(pos + 4..pos + 8)?.try_into().ok()?) as usize;
+        // Chunks are padded to an even length
+        let end = (pos + 8 + length + (length & 1)).min(bytes.len());
+        if kind != b"EXIF" && kind != b"XMP " {
+            let start = stripped.len();
+            stripped.extend_from_slice(bytes.get(pos..end)?);
+            // The extended header flags which metadata chunks follow
+            if kind == b"VP8X" && length > 0 {
+                stripped[start + 8] &= !(0x08 | 0x04);
+            }
+        }
+        pos = end;
+    }
+    let size = u32::try_from(stripped.len() - 8).ok()?;
+    stripped[4..8].copy_from_slice(&size.to_le_bytes());
+    Some(stripped)
+}
@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+
+use crate::store::ContentStore;
+
+/// Replaces `{{ include_code(path="src/main.rs", lang="rust", lines="10-40") }}`
+/// lines with a fenced code block holding those lines of the file, read from
+/// the content store, so the block is highlighted like any other. `lang`
+/// defaults to the file extension and `lines` to the whole file. Shortcodes
+/// that fail are left as is, with a warning.
+pub async fn expand_includes(body: &str, store: &dyn ContentStore) -> String {
+    let mut output = String::with_capacity(body.len());
+    for (line, call) in include_calls(body) {
+        let Some(args) = call else {
+            output.push_str(line);
+            continue;
+        };
+
+        match include_code(args, store).await {
+            Ok(block) => output.push_str(&block),
+            Err(e) => {
+                tracing::warn!("Failed to expand {}: {}", line.trim(), e);
+                output.push_str(line);
+            }
+        }
+    }
+    output
+}
+
+/// Files the `include_code` shortcodes of `body` read
+pub fn included_files(body: &str) -> Vec<String> {
+    include_calls(body)
+        .filter_map(|(_, call)| parse_args(call?).ok()?.remove("path"))
+        .collect()
+}
+
+/// Lines of `body`, with the arguments of those calling `include_code`
+/// outside of code blocks
+fn include_calls(body: &str) -> impl Iterator<Item = (&str, Option<&str>)> {
+    let mut fence: Option<&str> = None;
+    body.split_inclusive('\n').map(move |line| {
+        let trimmed = line.trim();
+
+        // Leave code blocks alone, they may be documenting the shortcode
+        let marker = trimmed.trim_start_matches('`');
+        let ticks = &trimmed[..trimmed.len() - marker.len()];
+        if ticks.len() >= 3 {
+            match fence {
+                Some(open) if ticks.len() >= open.len() && marker.is_empty() => fence = None,
+                None => fence = Some(ticks),
+                _ => {}
+            }
+        }
+
+        let call = trimmed
+            .strip_prefix("{{")
+            .and_then(|s| s.strip_suffix("}}"))
+            .map(str::trim)
+            .and_then(|s| s.strip_prefix("include_code("))
+            .and_then(|s| s.strip_suffix(')'));
+        (line, call.filter(|_| fence.is_none()))
+    })
+}
+
+async fn include_code(args: &str, store: &dyn ContentStore) -> anyhow::Result<String> {
+    let args = parse_args(args)?;
+    let Some(path) = args.get("path") else {
+        anyhow::bail!("missing path argument");
+    };
+    let lang = args
+        .get("lang")
+        .map(String::as_str)
+        .or_else(|| path.rsplit_once('.').map(|(_, ext)| ext))
+        .unwrap_or("text");
+
+    let content = store.read_file(path).await?;
+    let lines: Vec<&str> = content.lines().collect();
+    let (start, end) = match args.get("lines") {
+        Some(range) => parse_range(range, lines.len())?,
+        None => (1, lines.len()),
+    };
+    let code = lines[start - 1..end].join("\n");
+
+    // A fence longer than any backtick run in the code
+    let longest = code
+        .split(|c| c != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or_default();
+    let fence = "`".repeat(longest.max(2) + 1);
+    Ok(format!("{}{}\n{}\n{}\n", fence, lang, code, fence))
+}
+
+/// Parses `key="value"` pairs separated by commas or spaces. Values without
+/// spaces or commas can go unquoted, like `hl_lines=3-5`.
+pub fn parse_args(args: &str) -> anyhow::Result<HashMap<String, String>> {
+    let mut parsed = HashMap::new();
+    let mut rest = args.trim();
+    while !rest.is_empty() {
+        let (key, value) = rest
+            .split_once('=')
+            .ok_or_else(|| anyhow::anyhow!("expected key=\"value\" in '{}'", rest))?;
+        let value = value.trim_start();
+        let (value, after) = match value.strip_prefix('"') {
+            Some(value) => value
+                .split_once('"')
+                .ok_or_else(|| anyhow::anyhow!("unterminated value for {}", key.trim()))?,
+            None => value.split_at(value.find([' ', '\t', ',']).unwrap_or(value.len())),
+        };
+        parsed.insert(key.trim().to_string(), value.to_string());
+        rest = after.trim_start().trim_start_matches(',').trim_start();
+    }
+    Ok(parsed)
+}
+
+/// Parses `10-40`, `10-` or `12` into a 1-based inclusive range within
+/// `count` lines.
+pub fn parse_range(range: &str, count: usize) -> anyhow::Result<(usize, usize)> {
+    let invalid = || anyhow::anyhow!("invalid line range '{}' for {} lines", range, count);
+    let (start, end) = match range.split_once('-') {
+        Some((start, "")) => (start.trim().parse().ok(), Some(count)),
+        Some((start, end)) => (start.trim().parse().ok(), end.trim().parse().ok()),
+        None => {
+            let line = range.trim().parse().ok();
+            (line, line)
+        }
+    };
+    match (start, end) {
+        (Some(start), Some(end)) if 1 <= start && start <= end && end <= count => Ok((start, end)),
+        _ => Err(invalid()),
+    }
+}
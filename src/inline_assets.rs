@@ -0,0 +1,153 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::fingerprint::{SiteLocation, css_url_spans, directory, resolve, unescape};
+use crate::static_site::content_type;
+use crate::sync::hash_tree;
+
+/// Extensions of the assets inlined as data URIs
+const DATA_URI_ASSETS: &[&str] = &[
+    "png", "jpg", "jpeg", "gif", "webp", "avif", "svg", "ico", "woff", "woff2",
+];
+
+/// Inlines the assets of `out_dir` smaller than `max_size` bytes into the
+/// files referencing them: images and fonts become data URIs in `<img src>`,
+/// icon links and stylesheets, and stylesheets linked by pages become
+/// `<style>` blocks, saving a request each. Stylesheets still linking to
+/// files of the site once their assets are inlined stay linked, since their
+/// URLs would break. The assets stay for the feeds and other sites. Returns
+/// how many references were inlined.
+pub fn inline_tree(out_dir: &Path, site: &SiteLocation, max_size: u64) -> anyhow::Result<usize> {
+    let files = hash_tree(out_dir)?;
+    let is_small = |path: &str| {
+        std::fs::metadata(out_dir.join(path)).is_ok_and(|metadata| metadata.len() < max_size)
+    };
+
+    // Data URI of each small image and font, by path relative to `out_dir`
+    let mut data_uris: HashMap<String, String> = HashMap::new();
+    for path in files.keys() {
+        let extension = path.rsplit_once('.').map(|(_, extension)| extension);
+        if extension.is_some_and(|extension| DATA_URI_ASSETS.contains(&extension)) && is_small(path)
+        {
+            let data = BASE64.encode(std::fs::read(out_dir.join(path))?);
+            let uri = format!("data:{};base64,{}", content_type(Path::new(path)), data);
+            data_uris.insert(path.clone(), uri);
+        }
+    }
+
+    // Stylesheets first, so that those inlined into pages carry data URIs
+    let mut inlined = 0;
+    let mut stylesheets: HashMap<String, String> = HashMap::new();
+    for path in files.keys().filter(|path| path.ends_with(".css")) {
+        let css = std::fs::read_to_string(out_dir.join(path))?;
+        let mut rewritten = String::with_capacity(css.len());
+        let mut copied = 0;
+        let mut links_site = false;
+        for (start, end) in css_url_spans(&css) {
+            let Some(target) = resolve(&css[start..end], directory(path), site) else {
+                continue;
+            };
+            match data_uris.get(&target) {
+                Some(uri) => {
+                    rewritten.push_str(&css[copied..start]);
+                    rewritten.push_str(uri);
+                    copied = end;
+                    inlined += 1;
+                }
+                None => links_site = true,
+            }
+        }
+        rewritten.push_str(&css[copied..]);
+        if rewritten != css {
+            std::fs::write(out_dir.join(path), &rewritten)?;
+        }
+        if !links_site && (rewritten.len() as u64) < max_size {
+            stylesheets.insert(path.clone(), rewritten);
+        }
+    }
+
+    for path in files.keys().filter(|path| path.ends_with(".html")) {
+        let html = std::fs::read_to_string(out_dir.join(path))?;
+        let (rewritten, count) =
+            inline_html(&html, directory(path), site, &data_uris, &stylesheets);
+        if count > 0 {
+            std::fs::write(out_dir.join(path), rewritten)?;
+            inlined += count;
+        }
+    }
+    tracing::info!("Inlined {} asset references", inlined);
+    Ok(inlined)
+}
+
+/// `html` with its stylesheet links, `<img>` sources and icon links
+/// replaced by `stylesheets` and `data_uris`, with the number replaced
+fn inline_html(
+    html: &str,
+    dir: &str,
+    site: &SiteLocation,
+    data_uris: &HashMap<String, String>,
+    stylesheets: &HashMap<String, String>,
+) -> (String, usize) {
+    let mut out = String::with_capacity(html.len());
+    let mut count = 0;
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(end) = rest.find('>') else {
+            break;
+        };
+        let tag = &rest[..=end];
+        rest = &rest[end + 1..];
+        let target =
+            |name: &str| attribute(tag, name).and_then(|url| resolve(&unescape(url), dir, site));
+
+        let replacement = if tag.starts_with("<link ") {
+            let rel = attribute(tag, "rel").unwrap_or_default();
+            let href = target("href");
+            if rel.split_whitespace().any(|rel| rel == "stylesheet") {
+                href.and_then(|href| stylesheets.get(&href))
+                    .map(|css| format!("<style>{}</style>", css))
+            } else if rel.split_whitespace().any(|rel| rel == "icon") {
+                href.and_then(|href| data_uris.get(&href))
+                    .and_then(|uri| replace_attribute(tag, "href", uri))
+            } else {
+                None
+            }
+        } else if tag.starts_with("<img ") {
+            target("src")
+                .and_then(|src| data_uris.get(&src))
+                .and_then(|uri| replace_attribute(tag, "src", uri))
+        } else {
+            None
+        };
+        match replacement {
+            Some(replacement) => {
+                out.push_str(&replacement);
+                count += 1;
+            }
+            None => out.push_str(tag),
+        }
+    }
+    out.push_str(rest);
+    (out, count)
+}
+
+/// Byte range of the double-quoted value of the attribute `name` of `tag`
+fn attribute_span(tag: &str, name: &str) -> Option<(usize, usize)> {
+    let pattern = format!(" {}=\"", name);
+    let start = tag.find(&pattern)? + pattern.len();
+    let length = tag[start..].find('"')?;
+    Some((start, start + length))
+}
+
+fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    attribute_span(tag, name).map(|(start, end)| &tag[start..end])
+}
+
+fn replace_attribute(tag: &str, name: &str, value: &str) -> Option<String> {
+    let (start, end) = attribute_span(tag, name)?;
+    Some(format!("{}{}{}", &tag[..start], value, &tag[end..]))
+}
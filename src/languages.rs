@@ -0,0 +1,431 @@
+use async_trait::async_trait;
+use axum::{
+    extract::{Path as UrlPath, Query, State},
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
+};
+use percent_encoding::utf8_percent_encode;
+use pulldown_cmark_escape::escape_html;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::SystemTime;
+use tera::Context;
+
+use crate::analytics;
+use crate::citations::Bibliography;
+use crate::glossary::Glossary;
+use crate::images::{ImageSizes, image_sizes};
+use crate::links::UrlResolver;
+use crate::metadata::PageMetadata;
+use crate::preprocess::{PageInfo, preprocess};
+use crate::store::{ContentStore, LocalStore, PageHistory};
+use crate::translations::{self, ui_string};
+use crate::{
+    AppState, PATH_ESCAPES, RenderOptions, SITEMAP_FILE, TEMPLATES, build_tree, frontmatter,
+    markdown_to_html, write_stylesheets,
+};
+
+/// Language of a page and its file name without the language suffix:
+/// `post.fr@1700000000.md` is the French version of `post@1700000000.md`.
+/// Pages without a known suffix are in the default, first, language.
+pub fn page_language<'a>(filename: &str, languages: &'a [String]) -> (&'a str, String) {
+    let stem = filename.strip_suffix(".md").unwrap_or(filename);
+    let (name, timestamp) = match stem.split_once('@') {
+        Some((name, ts)) => (name, format!("@{}", ts)),
+        None => (stem, String::new()),
+    };
+    if let Some((base, suffix)) = name.rsplit_once('.')
+        && let Some(language) = languages.iter().find(|l| *l == suffix)
+    {
+        return (language, format!("{}{}.md", base, timestamp));
+    }
+    (&languages[0], filename.to_string())
+}
+
+/// Where the file of a page of a language is
+#[derive(Clone)]
+enum PageFile {
+    /// In the docs directory, with this name
+    Root(String),
+    /// In the language's directory, like `fr/`, under the page's name
+    Subtree,
+}
+
+/// The pages of one language, named without their language suffix so that
+/// translations share the same URL in each language's tree
+pub struct LanguageStore {
+    inner: LocalStore,
+    /// The language's directory of the docs directory
+    subtree: LocalStore,
+    /// Page name to its file
+    files: HashMap<String, PageFile>,
+    /// Page name to the languages it exists in
+    translations: HashMap<String, Vec<String>>,
+}
+
+impl LanguageStore {
+    /// The store holding the file of the page `name`, and its name there
+    fn locate<'a>(&'a self, name: &'a str) -> Option<(&'a LocalStore, &'a str)> {
+        match self.files.get(name)? {
+            PageFile::Root(file) => Some((&self.inner, file)),
+            PageFile::Subtree => Some((&self.subtree, name)),
+        }
+    }
+}
+
+#[async_trait]
+impl ContentStore for LanguageStore {
+    async fn list_pages(&self) -> anyhow::Result<Vec<String>> {
+        Ok(self.files.keys().cloned().collect())
+    }
+
+    async fn read_page(&self, name: &str) -> anyhow::Result<String> {
+        match self.locate(name) {
+            Some((store, file)) => store.read_page(file).await,
+            None => anyhow::bail!("No page '{}' in this language", name),
+        }
+    }
+
+    async fn read_file(&self, path: &str) -> anyhow::Result<String> {
+        self.inner.read_file(path).await
+    }
+
+    async fn read_bytes(&self, path: &str) -> anyhow::Result<Vec<u8>> {
+        self.inner.read_bytes(path).await
+    }
+
+    async fn modified(&self, name: &str) -> Option<SystemTime> {
+        let (store, file) = self.locate(name)?;
+        store.modified(file).await
+    }
+
+    async fn page_versions(&self) -> anyhow::Result<HashMap<String, String>> {
+        let mut versions = HashMap::new();
+        for name in self.files.keys() {
+            if let Some((store, file)) = self.locate(name)
+                && let Some(version) = store.file_version(file).await
+            {
+                versions.insert(name.clone(), version);
+            }
+        }
+        Ok(versions)
+    }
+
+    async fn file_version(&self, path: &str) -> Option<String> {
+        self.inner.file_version(path).await
+    }
+
+    async fn history(&self, name: &str) -> Option<PageHistory> {
+        let (store, file) = self.locate(name)?;
+        store.history(file).await
+    }
+
+    async fn translations(&self, name: &str) -> Vec<String> {
+        self.translations.get(name).cloned().unwrap_or_default()
+    }
+}
+
+/// Splits the pages of `docs_dir` into one store per language, in the order
+/// of `languages`. The pages of a language's directory, like `fr/`, are in
+/// that language, and win over same-named pages with a language suffix.
+pub async fn language_stores(
+    docs_dir: &Path,
+    languages: &[String],
+) -> anyhow::Result<Vec<(String, LanguageStore)>> {
+    let local = LocalStore::new(docs_dir.to_path_buf());
+    let mut files: HashMap<&str, HashMap<String, PageFile>> = HashMap::new();
+    for filename in local.list_pages().await? {
+        let (language, name) = page_language(&filename, languages);
+        files
+            .entry(language)
+            .or_default()
+            .insert(name, PageFile::Root(filename));
+    }
+    for language in languages {
+        let dir = docs_dir.join(language);
+        if !dir.is_dir() {
+            continue;
+        }
+        for name in LocalStore::new(dir).list_pages().await? {
+            files
+                .entry(language.as_str())
+                .or_default()
+                .insert(name, PageFile::Subtree);
+        }
+    }
+
+    let mut translations: HashMap<String, Vec<String>> = HashMap::new();
+    for (language, pages) in &files {
+        for name in pages.keys() {
+            translations
+                .entry(name.clone())
+                .or_default()
+                .push(language.to_string());
+        }
+    }
+    for langs in translations.values_mut() {
+        langs.sort_by_key(|l| languages.iter().position(|x| x == l));
+    }
+
+    Ok(languages
+        .iter()
+        .map(|language| {
+            let store = LanguageStore {
+                inner: LocalStore::new(docs_dir.to_path_buf()),
+                subtree: LocalStore::new(docs_dir.join(language)),
+                files: files.remove(language.as_str()).unwrap_or_default(),
+                translations: translations.clone(),
+            };
+            (language.clone(), store)
+        })
+        .collect())
+}
+
+/// A version of the current page in another language, for hreflang links
+#[derive(Serialize)]
+pub struct Alternate {
+    pub lang: String,
+    pub url: String,
+}
+
+/// Links to the versions of `path` in `languages`, `path` being relative to
+/// a language tree's root. Absolute when the site has a base URL.
+pub fn alternates(
+    options: &RenderOptions,
+    languages: &[String],
+    root: &str,
+    path: &str,
+) -> Vec<Alternate> {
+    let Some(current) = &options.language else {
+        return Vec::new();
+    };
+    let site_url = options
+        .base_url
+        .as_deref()
+        .and_then(|url| url.strip_suffix(&format!("{}/", current)));
+    let path = utf8_percent_encode(path, PATH_ESCAPES);
+
+    languages
+        .iter()
+        .map(|lang| Alternate {
+            lang: lang.clone(),
+            url: match site_url {
+                Some(site_url) => format!("{}{}/{}", site_url, lang, path),
+                None => format!("{}../{}/{}", root, lang, path),
+            },
+        })
+        .collect()
+}
+
+/// Whether `path`, relative to the docs directory, is a page of a
+/// language's directory rather than an asset
+pub fn is_subtree_page(path: &Path, languages: &[String]) -> bool {
+    let mut components = path.iter();
+    let in_language = components
+        .next()
+        .is_some_and(|dir| languages.iter().any(|l| dir == l.as_str()));
+    in_language && components.count() == 1 && path.extension().is_some_and(|e| e == "md")
+}
+
+/// Path of a language tree's home page, relative to the tree
+pub fn home_path(options: &RenderOptions) -> &'static str {
+    if options.pretty_urls {
+        ""
+    } else {
+        "index.html"
+    }
+}
+
+/// Builds one tree per language into `out_dir/<lang>`, with a language
+/// selector page at the root.
+pub async fn build_languages(
+    docs_dir: &Path,
+    out_dir: &Path,
+    options: &RenderOptions,
+    prune_unused_assets: bool,
+    only: Option<&str>,
+) -> anyhow::Result<()> {
+    if docs_dir == out_dir {
+        anyhow::bail!("Building one tree per language needs an output directory, see --out-dir");
+    }
+    let only = only.map(|page| page_language(page, &options.languages));
+
+    for (language, store) in language_stores(docs_dir, &options.languages).await? {
+        // The page, named as in the language's tree
+        let only_here = match &only {
+            Some((page_language, name)) if *page_language == language => Some(name.as_str()),
+            Some(_) => continue,
+            None => None,
+        };
+        let mut language_options = options.clone();
+        language_options.language = Some(language.clone());
+        language_options.base_url = options
+            .base_url
+            .as_ref()
+            .map(|url| format!("{}{}/", url, language));
+
+        let language_dir = out_dir.join(&language);
+        tokio::fs::create_dir_all(&language_dir).await?;
+        build_tree(
+            &store,
+            docs_dir,
+            &language_dir,
+            &language_options,
+            prune_unused_assets,
+            out_dir,
+            only_here,
+        )
+        .await?;
+    }
+
+    // Build language selector
+    let languages: Vec<Alternate> = options
+        .languages
+        .iter()
+        .map(|lang| Alternate {
+            lang: lang.clone(),
+            url: format!("{}/{}", lang, home_path(options)),
+        })
+        .collect();
+    let mut context = Context::new();
+    context.insert("root", "");
+    context.insert("home_url", "./");
+    context.insert("title", ui_string(options, "languages"));
+    context.insert("languages", &languages);
+    context.insert("is_static", &true);
+    context.insert("no_navigation", &true);
+    context.insert("canonical", &options.base_url);
+    analytics::insert_into(options, &mut context);
+    translations::insert_into(options, &mut context);
+    let rendered = TEMPLATES
+        .read()
+        .unwrap()
+        .render("languages.html", &context)?;
+    tokio::fs::write(out_dir.join("index.html"), rendered).await?;
+
+    write_stylesheets(out_dir, options).await?;
+
+    // Build sitemap index
+    if let Some(base_url) = &options.base_url {
+        let mut xml = String::from(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <sitemapindex xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n",
+        );
+        for language in &options.languages {
+            xml.push_str("  <sitemap><loc>");
+            let _ = escape_html(
+                &mut xml,
+                &format!("{}{}/{}", base_url, language, SITEMAP_FILE),
+            );
+            xml.push_str("</loc></sitemap>\n");
+        }
+        xml.push_str("</sitemapindex>\n");
+        tokio::fs::write(out_dir.join(SITEMAP_FILE), xml).await?;
+    }
+    Ok(())
+}
+
+#[derive(Deserialize)]
+pub struct CompareQuery {
+    left: Option<String>,
+    right: Option<String>,
+}
+
+/// One side of the compare view
+#[derive(Serialize)]
+struct CompareColumn {
+    lang: String,
+    filename: String,
+    content: String,
+}
+
+/// `GET /compare/{page}`: two translations of `page`, named without its
+/// language suffix, side by side. `?left=` and `?right=` pick the languages,
+/// by default the first two the page exists in.
+pub async fn compare_handler(
+    State(state): State<Arc<AppState>>,
+    UrlPath(page): UrlPath<String>,
+    Query(query): Query<CompareQuery>,
+) -> Response {
+    let languages = &state.options.languages;
+    if languages.is_empty() {
+        return (StatusCode::NOT_FOUND, "No languages configured").into_response();
+    }
+    let name = if page.ends_with(".md") {
+        page
+    } else {
+        format!("{}.md", page)
+    };
+
+    // Find the translations of the page
+    let mut translations: Vec<(&str, String)> = state
+        .store
+        .list_pages()
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|filename| {
+            let (language, base) = page_language(&filename, languages);
+            (base == name).then_some((language, filename))
+        })
+        .collect();
+    translations.sort_by_key(|(language, _)| languages.iter().position(|l| l == language));
+
+    let pick = |wanted: Option<&String>, fallback: usize| match wanted {
+        Some(wanted) => translations.iter().find(|(l, _)| *l == wanted.as_str()),
+        None => translations.get(fallback),
+    };
+    let (Some(left), Some(right)) = (pick(query.left.as_ref(), 0), pick(query.right.as_ref(), 1))
+    else {
+        return (StatusCode::NOT_FOUND, "Translation not found").into_response();
+    };
+
+    // The view is served at `/compare/{page}`
+    let urls = UrlResolver::new("../", &state.options, false);
+    let mut columns = Vec::new();
+    for (language, filename) in [left, right] {
+        let Ok(content) = state.store.read_page(filename).await else {
+            return (StatusCode::NOT_FOUND, "Translation not found").into_response();
+        };
+        let (meta, body) = frontmatter::parse(&content);
+        let metadata = PageMetadata::resolve(filename, &meta, body);
+        let page = PageInfo {
+            filename,
+            title: &metadata.title,
+        };
+        let body = preprocess(body, &page, state.store.as_ref(), &state.options).await;
+        let image_sizes = if state.options.lazy_images {
+            image_sizes(state.store.as_ref(), &body, &urls).await
+        } else {
+            ImageSizes::new()
+        };
+        columns.push(CompareColumn {
+            lang: language.to_string(),
+            filename: filename.clone(),
+            content: markdown_to_html(
+                &body,
+                &state.options,
+                &Glossary::default(),
+                &Bibliography::default(),
+                &image_sizes,
+                &urls,
+            ),
+        });
+    }
+
+    let mut context = Context::new();
+    context.insert("root", "../");
+    context.insert("home_url", "../");
+    context.insert("title", &name);
+    context.insert("columns", &columns);
+    context.insert("is_static", &false);
+    context.insert("no_navigation", &true);
+    context.insert("canonical", &None::<String>);
+    translations::insert_into(&state.options, &mut context);
+    match TEMPLATES.read().unwrap().render("compare.html", &context) {
+        Ok(rendered) => Html(rendered).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
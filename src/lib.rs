@@ -0,0 +1,2067 @@
+//! Markdown blog renderer, usable on its own or through the `blog` command.
+//!
+//! A site is a directory of markdown pages, read through a [`ContentStore`].
+//! [`RenderOptions`] holds the settings rendering follows; its `Default` is
+//! what `blog` does without flags or `blog.toml`.
+//!
+//! - [`run_build`] renders a whole site to static files, like `blog build`.
+//! - [`blog_router`] serves it, like `blog serve`, and can be nested into an
+//!   existing axum application.
+//! - [`render_markdown_to_html`] renders a single page, and
+//!   [`markdown_to_html`] a bare markdown body, through the same pipeline of
+//!   event adapters, such as [`CodeblockRenderer`] for highlighting.
+//! - [`get_summary_data`] lists the pages as the home page does.
+//!
+//! ```no_run
+//! use blog::{RenderOptions, run_build};
+//!
+//! # async fn build() -> anyhow::Result<()> {
+//! let options = RenderOptions {
+//!     pretty_urls: true,
+//!     ..Default::default()
+//! };
+//! run_build("docs".into(), "public".into(), &options, false).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+pub use ax_models::Page;
+use axum::{
+    Router,
+    extract::{Path, Query, Request, State},
+    http::StatusCode,
+    middleware::{self, Next},
+    response::{Extension, Html, IntoResponse, Json, Redirect, Response},
+    routing::{get, post},
+};
+use lazy_static::lazy_static;
+use percent_encoding::{AsciiSet, CONTROLS, utf8_percent_encode};
+use pulldown_cmark::{Options, Parser as MarkdownParser, html};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, RwLock};
+use std::time::Instant;
+use std::{
+    io::Cursor,
+    path::{Path as FsPath, PathBuf},
+};
+use syntect::{highlighting::ThemeSet, parsing::SyntaxSet};
+use tera::{Context, Tera};
+
+pub mod access_log;
+pub mod alt_text;
+pub mod analytics;
+pub mod anchors;
+pub mod api;
+pub mod assets;
+pub mod audit;
+pub mod cdn;
+pub mod changes;
+pub mod check;
+pub mod citations;
+pub mod codeblocks;
+pub mod collation;
+pub mod combined;
+pub mod comments;
+pub mod config;
+pub mod dates;
+pub mod db;
+pub mod diff;
+pub mod excerpt;
+pub mod exit;
+pub mod external_links;
+pub mod fallback;
+pub mod feed;
+pub mod figures;
+pub mod fingerprint;
+pub mod footnotes;
+pub mod freshness;
+pub mod frontmatter;
+pub mod glossary;
+pub mod graphql;
+pub mod images;
+pub mod includes;
+pub mod inline_assets;
+pub mod languages;
+pub mod links;
+pub mod live_preview;
+pub mod menu;
+pub mod metadata;
+pub mod migrate;
+pub mod minify;
+pub mod numbering;
+pub mod pdf;
+pub mod pings;
+pub mod precompress;
+pub mod preprocess;
+pub mod proxy;
+pub mod publish;
+pub mod reader;
+pub mod related;
+pub mod scaffold;
+pub mod search;
+pub mod series;
+pub mod sidecar;
+pub mod signing;
+pub mod sitemap;
+pub mod snapshot;
+pub mod static_site;
+pub mod stats;
+pub mod store;
+pub mod sync;
+pub mod tags;
+pub mod themes;
+pub mod timings;
+pub mod translations;
+pub mod typography;
+pub mod webmentions;
+use analytics::AnalyticsOptions;
+use anchors::HeadingAnchorRenderer;
+use changes::{CHANGES_FEED_FILE, CHANGES_FILE};
+use citations::{Bibliography, CitationRenderer};
+use codeblocks::*;
+use collation::TitleCollator;
+use comments::CommentsOptions;
+use config::TagStyle;
+use dates::{DateWindow, WindowQuery};
+use external_links::{ExternalLinkOptions, ExternalLinkRenderer};
+use feed::*;
+use figures::FigureRenderer;
+use footnotes::*;
+use glossary::*;
+use images::{ImageOptions, ImageRenderer, ImageSets, ImageSizes};
+use links::{UrlRenderer, UrlResolver};
+use menu::{CurrentPage, Location, MenuEntry, SiteNavigation};
+use metadata::PageMetadata;
+use numbering::*;
+use preprocess::{PageInfo, Preprocessor, preprocess};
+use proxy::ClientInfo;
+use search::*;
+use sitemap::*;
+use store::{ContentStore, LocalStore};
+use timings::PageTimings;
+use translations::{Translations, ui_string};
+use typography::*;
+
+pub const DEFAULT_THEME: &str = "Catppuccin Macchiato";
+
+/// Related pages listed under a page unless configured otherwise
+pub const DEFAULT_RELATED_PAGES: usize = 3;
+
+/// Templates compiled into the binary, by name
+pub const EMBEDDED_TEMPLATES: &[(&str, &str)] = &[
+    ("_base.html", include_str!("../templates/_base.html")),
+    ("all.html", include_str!("../templates/all.html")),
+    ("changes.html", include_str!("../templates/changes.html")),
+    ("compare.html", include_str!("../templates/compare.html")),
+    ("home.html", include_str!("../templates/home.html")),
+    (
+        "languages.html",
+        include_str!("../templates/languages.html"),
+    ),
+    (
+        "not_found.html",
+        include_str!("../templates/not_found.html"),
+    ),
+    ("page.html", include_str!("../templates/page.html")),
+    ("print.html", include_str!("../templates/print.html")),
+    ("reader.html", include_str!("../templates/reader.html")),
+    ("series.html", include_str!("../templates/series.html")),
+    ("style.css", include_str!("../templates/style.css")),
+];
+
+/// Directory, inside the docs directory, whose files override the embedded templates
+pub const TEMPLATES_DIR: &str = "templates";
+
+lazy_static! {
+    pub static ref TEMPLATES: RwLock<Tera> = {
+        let mut tera = Tera::default();
+        tera.add_raw_templates(EMBEDDED_TEMPLATES.to_vec()).unwrap();
+        RwLock::new(tera)
+    };
+    pub static ref SYNTAX_SET: SyntaxSet = SyntaxSet::load_defaults_newlines();
+    pub static ref THEME_SET: RwLock<ThemeSet> = {
+        let mut set = ThemeSet::load_defaults();
+        let theme_bytes = include_bytes!(env!("THEME_FILE_PATH"));
+        let mut cursor = Cursor::new(theme_bytes);
+        match syntect::highlighting::ThemeSet::load_from_reader(&mut cursor) {
+            Ok(theme) => {
+                set.themes.insert(DEFAULT_THEME.to_string(), theme);
+            }
+            Err(e) => {
+                tracing::error!("Failed to load embedded theme: {}", e);
+            }
+        }
+        RwLock::new(set)
+    };
+}
+
+/// Settings shared by every rendered page, in both serve and build modes
+#[derive(Clone, Debug)]
+pub struct RenderOptions {
+    pub no_navigation: bool,
+    pub code_theme: String,
+    /// Code theme used when the reader prefers a light color scheme
+    pub code_theme_light: Option<String>,
+    /// Number the lines of code blocks, unless they ask otherwise
+    pub line_numbers: bool,
+    /// Highlight code with the classes of `CODE_THEME_CSS` instead of
+    /// inline styles
+    pub highlight_classes: bool,
+    /// Lines of code blocks shown before they need expanding
+    pub code_max_lines: Option<usize>,
+    pub footnotes: FootnoteOptions,
+    /// BibTeX or CSL-JSON file of the works pages cite, see `citations`
+    pub bibliography: Option<String>,
+    /// Attributes of the links leaving the site
+    pub external_links: ExternalLinkOptions,
+    /// Locale for the non-breaking space pass, disabled when `None`
+    pub typography: Option<TypographyLocale>,
+    /// Public URL of the site, always ending with `/`
+    pub base_url: Option<String>,
+    /// Whether pages live at `slug/` rather than `slug.html`
+    pub pretty_urls: bool,
+    /// Name of the installed site theme
+    pub theme: Option<String>,
+    /// Languages to build one tree each for, the first being the default
+    pub languages: Vec<String>,
+    /// Language of the tree being built
+    pub language: Option<String>,
+    /// Formats dates when rendering, the browser does when `None`
+    pub date_display: Option<dates::DateDisplay>,
+    pub nav_order: NavOrder,
+    /// Whether section headings are numbered
+    pub numbering: bool,
+    /// Most related pages listed under a page, 0 to list none
+    pub related_pages: usize,
+    /// Age in years from which pages show a banner saying how old they are
+    pub age_warning_years: Option<u32>,
+    pub tag_styles: BTreeMap<String, TagStyle>,
+    /// File the build writes the page for unknown URLs to, which serving
+    /// also answers them with
+    pub fallback_page: Option<String>,
+    /// Resize and re-encode the images pages show when building
+    pub image_optimization: Option<ImageOptions>,
+    /// Images optimized by the build in progress
+    pub image_sets: Arc<ImageSets>,
+    /// Leave the EXIF and XMP metadata out of the images copied by builds
+    pub strip_image_metadata: bool,
+    /// Load images when scrolled to, with their size set from their headers
+    pub lazy_images: bool,
+    /// Whether to add the "What's new" page and feed
+    pub changelog: bool,
+    /// Whether the build writes every page to one file, see `combined`
+    pub all_html: bool,
+    /// Whether the build writes a `.json` file of metadata next to each
+    /// page, see `sidecar`
+    pub page_json: bool,
+    /// Minify the built HTML and CSS files, see `minify`
+    pub minify: bool,
+    /// Size in bytes under which the built assets are inlined, see
+    /// `inline_assets`
+    pub inline_assets: Option<u64>,
+    /// Write copies of the built assets named after their content, see `fingerprint`
+    pub fingerprint: bool,
+    /// Write `.gz` and `.br` siblings of the built text files, see `precompress`
+    pub precompress: bool,
+    /// Key builds sign the manifest of their output with, see `signing`
+    pub signing_key: Option<PathBuf>,
+    /// Months of pages the main feed holds, all of them when `None`, see
+    /// `feed::ARCHIVE_FEED_FILE`
+    pub feed_months: Option<u32>,
+    /// WebSub hub the feeds advertise and `pings` notifies of changes
+    pub websub_hub: Option<String>,
+    /// Widget showing the comments of pages, see `comments`
+    pub comments: Option<CommentsOptions>,
+    /// Script of the analytics service counting visits, see `analytics`
+    pub analytics: Option<AnalyticsOptions>,
+    /// Send webmentions after builds and receive them when serving, see
+    /// `webmentions`
+    pub webmentions: bool,
+    /// Entries of the navbar, see `menu::MenuEntry`
+    pub menu: Vec<MenuEntry>,
+    /// Steps rewriting the markdown of pages before it is parsed, in order
+    pub preprocessors: Vec<Preprocessor>,
+    /// Values of the `{{ vars.name }}` of pages, see `Preprocessor::Variables`
+    pub variables: BTreeMap<String, String>,
+    /// UI strings of the templates in other languages, see `translations`
+    pub translations: Translations,
+    /// Prefix the server is mounted under, like `/blog`, which served links
+    /// start with. Empty when serving at the root.
+    pub base_path: String,
+}
+
+impl Default for RenderOptions {
+    /// The settings of `blog` without flags or `blog.toml`
+    fn default() -> Self {
+        Self {
+            no_navigation: false,
+            code_theme: DEFAULT_THEME.to_string(),
+            code_theme_light: None,
+            line_numbers: false,
+            highlight_classes: false,
+            code_max_lines: None,
+            footnotes: FootnoteOptions::default(),
+            bibliography: None,
+            external_links: ExternalLinkOptions {
+                target: Some(external_links::DEFAULT_TARGET.to_string()),
+                rel: Some(external_links::DEFAULT_REL.to_string()),
+            },
+            typography: None,
+            base_url: None,
+            pretty_urls: false,
+            theme: None,
+            languages: Vec::new(),
+            language: None,
+            date_display: None,
+            nav_order: NavOrder::default(),
+            numbering: false,
+            related_pages: DEFAULT_RELATED_PAGES,
+            age_warning_years: None,
+            tag_styles: BTreeMap::new(),
+            fallback_page: None,
+            image_optimization: None,
+            image_sets: Default::default(),
+            strip_image_metadata: true,
+            lazy_images: false,
+            changelog: false,
+            all_html: false,
+            page_json: false,
+            minify: false,
+            inline_assets: None,
+            fingerprint: false,
+            precompress: false,
+            signing_key: None,
+            feed_months: None,
+            websub_hub: None,
+            comments: None,
+            analytics: None,
+            webmentions: false,
+            menu: Vec::new(),
+            preprocessors: vec![Preprocessor::Includes],
+            variables: BTreeMap::new(),
+            translations: Translations::new(),
+            base_path: String::new(),
+        }
+    }
+}
+
+/// Order pages are walked in by the prev/next links
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NavOrder {
+    /// Newest first, like the home page
+    #[default]
+    Date,
+    /// By file name
+    Alphabetical,
+    /// By title, in the collation order of the site's language
+    Title,
+}
+
+pub struct AppState {
+    pub store: Arc<dyn ContentStore>,
+    pub options: RenderOptions,
+    pub db: Option<db::Database>,
+    /// Whether to serve the GraphQL API at `/graphql`
+    pub graphql: bool,
+    /// Whether to render markdown editors send at `/live-preview`
+    pub live_preview: bool,
+    /// Sent with pages as `X-Content-Version`
+    pub generation: freshness::IndexGeneration,
+}
+
+/// Replaces embedded templates with those of `theme`, then with same-named
+/// files found in `docs_dir/templates`, if any.
+pub fn load_template_overrides(docs_dir: &FsPath, theme: Option<&str>) -> anyhow::Result<()> {
+    let overrides = read_template_overrides(docs_dir, theme)?;
+    if overrides.is_empty() {
+        return Ok(());
+    }
+
+    let names: Vec<&str> = overrides.iter().map(|(name, _)| *name).collect();
+    tracing::info!("Using template overrides: {}", names.join(", "));
+    TEMPLATES
+        .write()
+        .unwrap()
+        .add_raw_templates(overrides)
+        .map_err(|e| anyhow::anyhow!("Invalid template override: {:?}", e))?;
+    Ok(())
+}
+
+/// Reads the files of the theme and of `docs_dir/templates` that override
+/// an embedded template, the latter taking precedence.
+fn read_template_overrides(
+    docs_dir: &FsPath,
+    theme: Option<&str>,
+) -> anyhow::Result<Vec<(&'static str, String)>> {
+    let mut overrides = match theme {
+        Some(name) => themes::read_theme_templates(&themes::theme_dir(docs_dir, name)?)?,
+        None => Vec::new(),
+    };
+
+    let dir = docs_dir.join(TEMPLATES_DIR);
+    for (name, _) in EMBEDDED_TEMPLATES {
+        let path = dir.join(name);
+        if path.exists() {
+            overrides.retain(|(n, _)| n != name);
+            overrides.push((*name, std::fs::read_to_string(&path)?));
+        }
+    }
+    Ok(overrides)
+}
+
+/// Builds the blog's routes, ready to be served on their own or nested into
+/// an existing application with `Router::nest`.
+pub fn blog_router(state: Arc<AppState>) -> Router {
+    let mut router = Router::new()
+        .route("/", get(render_summary_handler))
+        .route("/{page}", get(render_page_handler))
+        .route("/{page}/", get(render_pretty_page_handler))
+        .route("/style.css", get(serve_css))
+        .route(&format!("/{}", CODE_THEME_CSS), get(serve_code_theme_css))
+        .route("/search.json", get(serve_search_index))
+        .route("/metrics", get(serve_metrics))
+        .route("/healthz", get(serve_health))
+        .route("/sitemap.xml", get(serve_sitemap))
+        .route("/feed.xml", get(serve_feed))
+        .route(&format!("/{}", ARCHIVE_FEED_FILE), get(serve_archive_feed))
+        .route(
+            &format!("/{}/{{tag}}/{}", TAGS_DIR, FEED_FILE),
+            get(serve_tag_feed),
+        )
+        .route(&format!("/{}", CHANGES_FEED_FILE), get(serve_changes_feed))
+        .route("/api/pages", get(api::pages_handler))
+        .route("/api/pages/{page}", get(api::page_details_handler))
+        .route("/compare/{page}", get(languages::compare_handler))
+        .route("/reader/{page}", get(reader::reader_handler));
+    if state.options.webmentions && state.db.is_some() {
+        router = router.route(
+            &format!("/{}", webmentions::ENDPOINT),
+            post(webmentions::receive_handler),
+        );
+    }
+    if state.live_preview {
+        router = router.route("/live-preview", get(live_preview::live_preview_handler));
+    }
+    if state.options.fallback_page.is_some() {
+        router = router.fallback(fallback::fallback_handler);
+    }
+    let router = router.with_state(state.clone());
+
+    if state.graphql {
+        router.merge(graphql::graphql_router(state))
+    } else {
+        router
+    }
+}
+
+/// Mounts `router` under `base_path`, see `RenderOptions::base_path`. The
+/// home page is at `base_path/`, which relative links resolve against, and
+/// the bare prefix redirects there.
+pub fn with_base_path(router: Router, base_path: &str) -> Router {
+    if base_path.is_empty() {
+        return router;
+    }
+    // Unlike `Router::nest`, stripping the prefix before `router` routes the
+    // request keeps its `/` route at `base_path/`
+    Router::new()
+        .fallback_service(router)
+        .layer(middleware::from_fn_with_state(
+            base_path.to_string(),
+            strip_base_path,
+        ))
+}
+
+async fn strip_base_path(
+    State(base_path): State<String>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let uri = request.uri();
+    if uri.path() == base_path {
+        return Redirect::permanent(&format!("{}/", base_path)).into_response();
+    }
+    let Some(path) = uri
+        .path()
+        .strip_prefix(&base_path)
+        .filter(|path| path.starts_with('/'))
+    else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let stripped = match uri.query() {
+        Some(query) => format!("{}?{}", path, query),
+        None => path.to_string(),
+    };
+    match stripped.parse() {
+        Ok(stripped) => {
+            *request.uri_mut() = stripped;
+            next.run(request).await
+        }
+        Err(_) => StatusCode::BAD_REQUEST.into_response(),
+    }
+}
+
+/// Loads an optional `.tmTheme` file into `THEME_SET` and returns the name of
+/// the code theme to use. An explicit `name` wins over the file's own theme.
+pub fn select_code_theme(
+    name: Option<String>,
+    theme_file: Option<&FsPath>,
+) -> anyhow::Result<String> {
+    let file_theme = theme_file.map(load_theme_file).transpose()?;
+    let name = name
+        .or(file_theme)
+        .unwrap_or_else(|| DEFAULT_THEME.to_string());
+
+    check_code_theme(&name)?;
+    Ok(name)
+}
+
+pub fn check_code_theme(name: &str) -> anyhow::Result<()> {
+    let set = THEME_SET.read().unwrap();
+    if set.themes.contains_key(name) {
+        return Ok(());
+    }
+    let available: Vec<&str> = set.themes.keys().map(|k| k.as_str()).collect();
+    anyhow::bail!(
+        "Unknown code theme '{}', available themes: {}",
+        name,
+        available.join(", ")
+    )
+}
+
+fn load_theme_file(path: &FsPath) -> anyhow::Result<String> {
+    let theme = ThemeSet::get_theme(path)
+        .map_err(|e| anyhow::anyhow!("Failed to load theme {:?}: {}", path, e))?;
+    let name = theme
+        .name
+        .clone()
+        .or_else(|| path.file_stem().map(|s| s.to_string_lossy().into_owned()))
+        .unwrap_or_else(|| path.display().to_string());
+
+    tracing::info!("Loaded code theme '{}' from {:?}", name, path);
+    THEME_SET
+        .write()
+        .unwrap()
+        .themes
+        .insert(name.clone(), theme);
+    Ok(name)
+}
+
+/// The published pages of `store` as the home page lists them: by weight,
+/// then newest first.
+pub async fn get_summary_data(store: &dyn ContentStore) -> Vec<Page> {
+    let names = match store.list_pages().await {
+        Ok(names) => names,
+        Err(e) => {
+            tracing::warn!("Failed to list pages: {}", e);
+            Vec::new()
+        }
+    };
+
+    let mut pages = Vec::new();
+    for filename in names {
+        let content = store.read_page(&filename).await.unwrap_or_default();
+        if !frontmatter::parse(&content).0.is_draft() {
+            pages.push(summarize_page(filename, &content));
+        }
+    }
+    sort_pages(&mut pages);
+    pages
+}
+
+/// Home page entry of a page, from its file name and raw markdown
+fn summarize_page(filename: String, content: &str) -> Page {
+    let (meta, body) = frontmatter::parse(content);
+    let metadata = PageMetadata::resolve(&filename, &meta, body);
+
+    let datetime = match dates::page_date(&filename, &meta) {
+        Ok(date) => date.map(|d| d.timestamp()),
+        Err(e) => {
+            tracing::warn!("{}: {}", filename, e);
+            None
+        }
+    };
+
+    Page {
+        filename,
+        title: metadata.title,
+        datetime,
+        weight: meta.weight,
+        excerpt: metadata.excerpt,
+        image: metadata.image,
+        views: None,
+        date: None,
+    }
+}
+
+fn sort_pages(pages: &mut [Page]) {
+    pages.sort_by(|a, b| {
+        weight_order(a.weight, b.weight)
+            .then_with(|| b.datetime.cmp(&a.datetime))
+            .then_with(|| a.filename.cmp(&b.filename))
+    });
+}
+
+/// Pages with a `weight` come first, lightest first; the rest keep their
+/// usual order.
+fn weight_order(a: Option<i64>, b: Option<i64>) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => a.cmp(&b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+/// Renders a page body, without its frontmatter, to an HTML fragment.
+/// `image_sizes` holds the size of its images when `lazy_images` is set, and
+/// `urls` resolves its links and images from where it is rendered.
+pub fn markdown_to_html(
+    body: &str,
+    options: &RenderOptions,
+    glossary: &Glossary,
+    bibliography: &Bibliography,
+    image_sizes: &ImageSizes,
+    urls: &UrlResolver,
+) -> String {
+    let mut md_options = Options::empty();
+    md_options.insert(
+        Options::ENABLE_TABLES
+            | Options::ENABLE_FOOTNOTES
+            | Options::ENABLE_STRIKETHROUGH
+            | Options::ENABLE_TASKLISTS,
+    );
+
+    let parser = MarkdownParser::new_ext(body, md_options);
+    let renderer = HeadingAnchorRenderer::new(parser);
+    let renderer = FigureRenderer::new(renderer, options.numbering);
+    let renderer = ImageRenderer::new(
+        renderer,
+        &options.image_sets,
+        image_sizes,
+        options.lazy_images,
+        urls,
+    );
+    // After images, which resolve their own, and before the glossary's links
+    let renderer = UrlRenderer::new(renderer, urls);
+    let renderer = CodeblockRenderer::new(
+        renderer,
+        &options.code_theme,
+        options.code_theme_light.as_deref(),
+        options.line_numbers,
+        options.highlight_classes,
+        options.code_max_lines,
+        ui_string(options, "show_all_lines"),
+    );
+    let renderer = CitationRenderer::new(renderer, bibliography);
+    let renderer = GlossaryRenderer::new(renderer, glossary);
+    let renderer = TypographyRenderer::new(renderer, options.typography);
+    let renderer = NumberingRenderer::new(renderer, options.numbering);
+    let renderer = FootnoteRenderer::new(renderer, &options.footnotes);
+    let renderer = ExternalLinkRenderer::new(
+        renderer,
+        &options.external_links,
+        options.base_url.as_deref(),
+    );
+    let mut html_output = String::new();
+    html::push_html(&mut html_output, renderer);
+    html_output
+}
+
+/// Link preview tags of a page, for Open Graph and Twitter cards
+#[derive(Serialize)]
+struct SocialMeta {
+    title: String,
+    description: Option<String>,
+    image: Option<String>,
+    url: Option<String>,
+}
+
+/// Renders the page `filename`, given its raw markdown, to a whole HTML
+/// document: its body through `markdown_to_html`, with navigation, glossary
+/// and related pages, in the `page.html` template. `is_static` tells whether
+/// it is for a build rather than served, and the time each phase took is
+/// added to `timings`. `webmentions` are the pages mentioning this one when
+/// it receives them.
+pub async fn render_markdown_to_html(
+    content: &str,
+    filename: &str,
+    store: &dyn ContentStore,
+    options: &RenderOptions,
+    is_static: bool,
+    timings: &mut PageTimings,
+    webmentions: Option<&[String]>,
+) -> String {
+    let no_navigation = options.no_navigation;
+    let (meta, content) = frontmatter::parse(content);
+    let metadata = PageMetadata::resolve(filename, &meta, content);
+    let page = PageInfo {
+        filename,
+        title: &metadata.title,
+    };
+    let content = preprocess(content, &page, store, options).await;
+
+    // Resolve links from where the page ends up
+    let (root, home_url) = link_roots(options, is_static, true);
+    let urls = UrlResolver::for_page(filename, options, is_static);
+
+    let glossary_url = format!(
+        "{}{}",
+        root,
+        page_url(GLOSSARY_FILE, is_static, options.pretty_urls)
+    );
+    let (content, glossary) = if filename == GLOSSARY_FILE {
+        (anchor_entries(&content), Glossary::default())
+    } else if meta.glossary == Some(false) {
+        (content, Glossary::default())
+    } else {
+        (content, Glossary::load(store, &glossary_url).await)
+    };
+    let bibliography = match meta.bibliography.as_ref().or(options.bibliography.as_ref()) {
+        Some(path) => Bibliography::load(store, path).await,
+        None => Bibliography::default(),
+    };
+    let image_sizes = if options.lazy_images {
+        images::image_sizes(store, &content, &urls).await
+    } else {
+        ImageSizes::new()
+    };
+    // Highlighting happens on this thread, within the markdown pass
+    take_highlight_time();
+    take_highlight_keys();
+    let started = Instant::now();
+    let html_output = markdown_to_html(
+        &content,
+        options,
+        &glossary,
+        &bibliography,
+        &image_sizes,
+        &urls,
+    );
+    timings.highlight = take_highlight_time();
+    record_page_highlights(filename, take_highlight_keys());
+    timings.parse = started.elapsed().saturating_sub(timings.highlight);
+
+    let (prev, next) = if no_navigation {
+        (None, None)
+    } else {
+        get_nav_links(
+            store,
+            filename,
+            options.nav_order,
+            options.language.as_deref(),
+        )
+        .await
+    };
+    let link = |s: String| {
+        if s == "." {
+            home_url.to_string()
+        } else {
+            urls.page(&s)
+        }
+    };
+    let prev = prev.map(link);
+    let next = next.map(link);
+
+    let page_series = match &meta.series {
+        Some(name) => series::page_series(store, name, filename, |f| link(f.to_string())).await,
+        None => None,
+    };
+    let series_url = meta
+        .series
+        .as_deref()
+        .map(|name| series::index_url(options, is_static, name));
+
+    let related_pages: Vec<RelatedPage> =
+        related::related_pages(store, filename, options.related_pages)
+            .await
+            .into_iter()
+            .map(|(filename, title)| RelatedPage {
+                url: link(filename),
+                title,
+            })
+            .collect();
+
+    let translations = store.translations(filename).await;
+    let alternates = languages::alternates(
+        options,
+        &translations,
+        &root,
+        &page_url(filename, is_static, options.pretty_urls),
+    );
+
+    let mut context = Context::new();
+    context.insert("root", &root);
+    context.insert("home_url", &home_url);
+    context.insert("alternates", &alternates);
+    if let Some(language) = &options.language {
+        context.insert("lang", language);
+    }
+    context.insert("title", filename);
+    context.insert("content", &html_output);
+    context.insert("prev_page", &prev);
+    context.insert("next_page", &next);
+    context.insert("related_pages", &related_pages);
+    if let Some(threshold) = options.age_warning_years
+        && let Ok(Some(date)) = dates::page_date(filename, &meta)
+    {
+        let years = dates::years_since(date);
+        if years >= threshold {
+            context.insert("age_warning", &AgeWarning { years });
+        }
+    }
+    let comments = options
+        .comments
+        .as_ref()
+        .filter(|_| meta.comments != Some(false))
+        .map(|comments| comments.embed(options.language.as_deref()));
+    context.insert("comments", &comments);
+    if let Some(webmentions) = webmentions {
+        context.insert(
+            "webmention_endpoint",
+            &format!("{}{}", root, webmentions::ENDPOINT),
+        );
+        context.insert("webmentions", &webmentions);
+    }
+    let tag_style = meta
+        .tags
+        .iter()
+        .find_map(|tag| options.tag_styles.get(tag).map(|style| (tag, style)));
+    if let Some((tag, style)) = tag_style {
+        context.insert("tag", tag);
+        context.insert("tag_style", style);
+    }
+    context.insert("series", &page_series);
+    context.insert("series_url", &series_url);
+    // Feeds are only written with a base URL, and only list dated pages
+    if options.base_url.is_some() && matches!(dates::page_date(filename, &meta), Ok(Some(_))) {
+        let mut tag_feeds: Vec<TagFeed> = Vec::new();
+        for tag in &meta.tags {
+            let file = tag_feed_file(tag);
+            if !scaffold::slugify(tag).is_empty() && !tag_feeds.iter().any(|f| f.file == file) {
+                tag_feeds.push(TagFeed {
+                    tag: tag.clone(),
+                    url: format!("{}{}", root, file),
+                    file,
+                });
+            }
+        }
+        context.insert("tag_feeds", &tag_feeds);
+    }
+    let location = Location::Page(CurrentPage {
+        filename,
+        title: &metadata.title,
+        tags: &meta.tags,
+    });
+    SiteNavigation::new(
+        &options.menu,
+        &location,
+        &root,
+        &home_url,
+        is_static,
+        options.pretty_urls,
+    )
+    .insert_into(&mut context);
+    analytics::insert_into(options, &mut context);
+    translations::insert_into(options, &mut context);
+    context.insert("no_navigation", &no_navigation);
+    context.insert("is_static", &is_static);
+    let canonical = meta.canonical.or_else(|| {
+        options
+            .base_url
+            .as_ref()
+            .map(|base| absolute_url(base, filename, is_static, options.pretty_urls))
+    });
+    context.insert(
+        "social",
+        &SocialMeta {
+            title: metadata.social_title,
+            description: metadata.social_description,
+            image: metadata
+                .image
+                .map(|image| metadata::image_url(&image, options.base_url.as_deref(), &root)),
+            url: canonical.clone(),
+        },
+    );
+    context.insert("canonical", &canonical);
+
+    let started = Instant::now();
+    let rendered = TEMPLATES
+        .read()
+        .unwrap()
+        .render("page.html", &context)
+        .unwrap_or_else(|e| format!("Error: {}", e));
+    timings.template = started.elapsed();
+    rendered
+}
+
+/// Renders the whole site into `out_dir`. Assets are copied along, minus
+/// the ones no page references when `prune_unused_assets` is set.
+pub async fn run_build(
+    docs_dir: PathBuf,
+    out_dir: PathBuf,
+    options: &RenderOptions,
+    prune_unused_assets: bool,
+) -> anyhow::Result<()> {
+    build_site(docs_dir, out_dir, options, prune_unused_assets, None).await
+}
+
+/// Like `run_build`, but renders only `page`, such as `post.md` or
+/// `post.fr.md` with `languages`, along with the indexes listing it: home
+/// page, search index, sitemap, feeds, series and "What's new" pages. Only
+/// the assets the page references are copied.
+pub async fn run_build_page(
+    docs_dir: PathBuf,
+    out_dir: PathBuf,
+    options: &RenderOptions,
+    page: &str,
+) -> anyhow::Result<()> {
+    build_site(docs_dir, out_dir, options, false, Some(page)).await
+}
+
+async fn build_site(
+    docs_dir: PathBuf,
+    out_dir: PathBuf,
+    options: &RenderOptions,
+    prune_unused_assets: bool,
+    only: Option<&str>,
+) -> anyhow::Result<()> {
+    tracing::info!("Building static site to: {:?}", out_dir);
+    if options.signing_key.is_some() && docs_dir == out_dir {
+        anyhow::bail!("Not signing a build in place, the manifest would list the sources");
+    }
+    let signing_key = options
+        .signing_key
+        .as_deref()
+        .map(|path| Ok::<_, anyhow::Error>((signing::load_key(path)?, path.to_path_buf())))
+        .transpose()?;
+    if options.languages.is_empty() {
+        let store = LocalStore::new(docs_dir.clone());
+        build_tree(
+            &store,
+            &docs_dir,
+            &out_dir,
+            options,
+            prune_unused_assets,
+            &out_dir,
+            only,
+        )
+        .await?;
+    } else {
+        languages::build_languages(&docs_dir, &out_dir, options, prune_unused_assets, only).await?;
+    }
+
+    // Kept from the previous builds, whose outputs may still be there
+    let mut record = BuildRecord::load(&out_dir).await;
+    record.all_html |= options.all_html;
+    record.page_json |= options.page_json;
+    let record = serde_json::to_string(&record)?;
+    tokio::fs::write(out_dir.join(BUILD_RECORD_FILE), record).await?;
+
+    // Build fallback page
+    if let Some(fallback_page) = &options.fallback_page {
+        let rendered = fallback::render_fallback(options, true, &[])?;
+        tokio::fs::write(out_dir.join(fallback_page), rendered).await?;
+    }
+
+    if options.minify && docs_dir == out_dir {
+        tracing::warn!("Not minifying when building in place");
+    } else if options.minify {
+        let out_dir = out_dir.clone();
+        tokio::task::spawn_blocking(move || minify::minify_tree(&out_dir)).await??;
+    }
+
+    if options.inline_assets.is_some() && docs_dir == out_dir {
+        tracing::warn!("Not inlining assets when building in place");
+    } else if let Some(max_size) = options.inline_assets {
+        let out_dir = out_dir.clone();
+        let base_url = options.base_url.clone();
+        let base_path = options.base_path.clone();
+        tokio::task::spawn_blocking(move || {
+            let site = fingerprint::SiteLocation {
+                base_url: base_url.as_deref(),
+                base_path: &base_path,
+            };
+            inline_assets::inline_tree(&out_dir, &site, max_size)
+        })
+        .await??;
+    }
+
+    // After minifying and inlining, which change the content the names come from
+    if options.fingerprint && docs_dir == out_dir {
+        tracing::warn!("Not fingerprinting assets when building in place");
+    } else if options.fingerprint {
+        let out_dir = out_dir.clone();
+        let base_url = options.base_url.clone();
+        let base_path = options.base_path.clone();
+        tokio::task::spawn_blocking(move || {
+            let site = fingerprint::SiteLocation {
+                base_url: base_url.as_deref(),
+                base_path: &base_path,
+            };
+            fingerprint::fingerprint_tree(&out_dir, &site)
+        })
+        .await??;
+    }
+
+    if options.precompress {
+        let out_dir = out_dir.clone();
+        tokio::task::spawn_blocking(move || precompress::precompress_tree(&out_dir)).await??;
+    }
+
+    // Sign the output, last so the manifest covers every file
+    if let Some((key, key_path)) = signing_key {
+        tokio::task::spawn_blocking(move || signing::sign_tree(&out_dir, &key, &key_path))
+            .await??;
+    }
+
+    tracing::info!("Build complete!");
+    Ok(())
+}
+
+/// Renders the pages of `store` into `out_dir`, along with the assets of
+/// `docs_dir` that are not in `out_root`, the root of the whole output.
+/// With `only`, the other pages and the assets they reference are left out.
+async fn build_tree(
+    store: &dyn ContentStore,
+    docs_dir: &FsPath,
+    out_dir: &FsPath,
+    options: &RenderOptions,
+    prune_unused_assets: bool,
+    out_root: &FsPath,
+    only: Option<&str>,
+) -> anyhow::Result<()> {
+    let page_assets = match only {
+        Some(page) => {
+            if !store.list_pages().await?.iter().any(|p| p == page) {
+                anyhow::bail!("No page named {}", page);
+            }
+            Some(
+                check::store_links(store)
+                    .await
+                    .remove(page)
+                    .unwrap_or_default(),
+            )
+        }
+        None => None,
+    };
+    let is_needed = |asset: &str| {
+        page_assets
+            .as_ref()
+            .is_none_or(|assets| assets.iter().any(|a| a == asset))
+    };
+
+    // Optimize images, before the pages offering the copies
+    let optimized;
+    let options = match &options.image_optimization {
+        Some(_) if docs_dir == out_dir => {
+            tracing::warn!("Not optimizing images when building in place");
+            options
+        }
+        Some(image_options) => {
+            let mut images = check::referenced_assets(docs_dir).await;
+            images.retain(|image| is_needed(image));
+            let sets = images::optimize_images(docs_dir, out_dir, images, image_options).await?;
+            optimized = RenderOptions {
+                image_sets: Arc::new(sets),
+                ..options.clone()
+            };
+            &optimized
+        }
+        None => options,
+    };
+
+    // Build summary
+    if !options.no_navigation {
+        let pages = get_summary_data(store).await;
+        // Rewrite filenames for static links in home page
+        let static_pages: Vec<Page> = pages
+            .into_iter()
+            .map(|mut p| {
+                p.filename = page_url(&p.filename, true, options.pretty_urls);
+                p.date = display_date(options, p.datetime);
+                p
+            })
+            .collect();
+
+        let (root, home_url) = link_roots(options, true, false);
+        let mut context = Context::new();
+        context.insert("root", &root);
+        context.insert("home_url", &home_url);
+        SiteNavigation::new(
+            &options.menu,
+            &Location::Home,
+            &root,
+            &home_url,
+            true,
+            options.pretty_urls,
+        )
+        .insert_into(&mut context);
+        analytics::insert_into(options, &mut context);
+        translations::insert_into(options, &mut context);
+        context.insert("title", ui_string(options, "pages"));
+        context.insert("files", &static_pages);
+        context.insert("is_static", &true);
+        context.insert("canonical", &options.base_url);
+        context.insert(
+            "alternates",
+            &languages::alternates(
+                options,
+                &options.languages,
+                &root,
+                languages::home_path(options),
+            ),
+        );
+        if let Some(language) = &options.language {
+            context.insert("lang", language);
+        }
+
+        let rendered = TEMPLATES.read().unwrap().render("home.html", &context)?;
+        tokio::fs::write(out_dir.join("index.html"), rendered).await?;
+
+        // Build search index
+        let index = build_search_index(store, true, options.pretty_urls).await;
+        tokio::fs::write(
+            out_dir.join(SEARCH_INDEX_FILE),
+            serde_json::to_string(&index)?,
+        )
+        .await?;
+    }
+
+    // Build sitemap and feed
+    if let Some(base_url) = &options.base_url {
+        let sitemap = build_sitemap(store, base_url, true, options.pretty_urls).await;
+        tokio::fs::write(out_dir.join(SITEMAP_FILE), sitemap).await?;
+        let hub = options.websub_hub.as_deref();
+        let window = DateWindow::recent(options.feed_months);
+        let feed = build_feed(store, base_url, true, options.pretty_urls, hub, &window).await;
+        tokio::fs::write(out_dir.join(FEED_FILE), feed).await?;
+        if options.feed_months.is_some() {
+            let archive = build_archive_feed(store, base_url, true, options.pretty_urls).await;
+            tokio::fs::write(out_dir.join(ARCHIVE_FEED_FILE), archive).await?;
+        }
+        for (file, feed) in build_tag_feeds(store, base_url, true, options.pretty_urls, hub).await {
+            let out_file = out_dir.join(file);
+            if let Some(parent) = out_file.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            tokio::fs::write(out_file, feed).await?;
+        }
+        if options.changelog {
+            let feed = build_changes_feed(store, base_url, true, options.pretty_urls, hub).await;
+            tokio::fs::write(out_dir.join(CHANGES_FEED_FILE), feed).await?;
+        }
+    }
+
+    write_stylesheets(out_dir, options).await?;
+
+    // Build pages
+    for filename in store.list_pages().await? {
+        if only.is_some_and(|page| page != filename) {
+            continue;
+        }
+        let mut timings = PageTimings::default();
+        let started = Instant::now();
+        let content = store.read_page(&filename).await?;
+        timings.read = started.elapsed();
+        if frontmatter::parse(&content).0.is_draft() {
+            tracing::info!("Skipped draft {}", filename);
+            continue;
+        }
+        for image in alt_text::find_missing_alt(&filename, &content) {
+            tracing::warn!(
+                "{}:{}: image {} has no alt text",
+                image.page,
+                image.line,
+                image.url
+            );
+        }
+        let rendered = render_markdown_to_html(
+            &content,
+            &filename,
+            store,
+            options,
+            true,
+            &mut timings,
+            None,
+        )
+        .await;
+        timings.record(&filename);
+
+        let out_file = out_dir.join(page_file(&filename, options.pretty_urls));
+        if let Some(parent) = out_file.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        if options.page_json {
+            match sidecar::sidecar_file(&filename, options.pretty_urls) {
+                Some(file) => {
+                    let sidecar = sidecar::page_sidecar(&filename, &content, options.pretty_urls);
+                    let json = serde_json::to_string_pretty(&sidecar)?;
+                    tokio::fs::write(out_dir.join(file), json).await?;
+                }
+                None => tracing::warn!(
+                    "Not writing the JSON sidecar of {}, a generated file is there",
+                    filename
+                ),
+            }
+        }
+        tokio::fs::write(out_file, rendered).await?;
+        tracing::info!("Generated {}", filename);
+    }
+
+    // Build series index
+    let pages = store.list_pages().await?;
+    if !pages.iter().any(|p| p == series::SERIES_FILE)
+        && let Some(rendered) = series::render_series_index(store, options, true).await?
+    {
+        let out_file = out_dir.join(page_file(series::SERIES_FILE, options.pretty_urls));
+        if let Some(parent) = out_file.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(out_file, rendered).await?;
+    }
+
+    // Build "What's new" page
+    if options.changelog && !pages.iter().any(|p| p == CHANGES_FILE) {
+        let rendered = changes::render_changes_page(store, options, true).await?;
+        let out_file = out_dir.join(page_file(CHANGES_FILE, options.pretty_urls));
+        if let Some(parent) = out_file.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(out_file, rendered).await?;
+    }
+
+    // Build the page holding every page, unless a page of the site is there
+    if options.all_html && combined::is_taken(&pages, options.pretty_urls) {
+        tracing::warn!(
+            "Not writing {}, a page of the site is built there",
+            combined::COMBINED_FILE
+        );
+    } else if options.all_html && only.is_none() {
+        let title = docs_dir
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let rendered = combined::render_combined(store, options, &title).await?;
+        tokio::fs::write(out_dir.join(combined::COMBINED_FILE), rendered).await?;
+    }
+
+    // Copy assets
+    let referenced = if prune_unused_assets {
+        Some(check::referenced_assets(docs_dir).await)
+    } else {
+        None
+    };
+    assets::copy_assets(
+        docs_dir,
+        out_dir,
+        |asset| {
+            let name = asset.to_string_lossy();
+            if docs_dir.join(asset).starts_with(out_root)
+                || languages::is_subtree_page(asset, &options.languages)
+                || !is_needed(&name)
+            {
+                return false;
+            }
+            let used = referenced
+                .as_ref()
+                .is_none_or(|r| r.contains(name.as_ref()));
+            if !used {
+                tracing::info!("Skipped unused asset {}", asset.display());
+            }
+            used
+        },
+        options.strip_image_metadata,
+    )
+    .await?;
+
+    // Copy theme assets, which templates find under `themes/<name>/assets/`
+    if let Some(theme) = options.theme.as_ref().filter(|_| only.is_none()) {
+        let assets_dir = FsPath::new(themes::THEMES_DIR)
+            .join(theme)
+            .join(themes::THEME_ASSETS_DIR);
+        if docs_dir.join(&assets_dir).is_dir() {
+            assets::copy_assets(
+                &docs_dir.join(&assets_dir),
+                &out_dir.join(&assets_dir),
+                |_| true,
+                options.strip_image_metadata,
+            )
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Files `run_build` can write to the output directory, relative to it. Keep
+/// this in sync with the build so `clean` never leaves anything behind.
+async fn build_artifacts(docs_dir: &FsPath, out_dir: &FsPath) -> anyhow::Result<Vec<String>> {
+    let mut artifacts = written_files(docs_dir, out_dir).await?;
+    // Along with what `--precompress` adds
+    let compressed: Vec<String> = artifacts
+        .iter()
+        .filter(|artifact| precompress::is_compressible(FsPath::new(artifact)))
+        .flat_map(|artifact| {
+            precompress::ENCODINGS
+                .iter()
+                .map(move |(_, extension)| format!("{}.{}", artifact, extension))
+        })
+        .collect();
+    artifacts.extend(compressed);
+    Ok(artifacts)
+}
+
+/// The copies `--fingerprint` wrote of `artifacts` in `out_dir`, whose names
+/// depend on their content
+async fn fingerprinted_copies(out_dir: &FsPath, artifacts: &[String]) -> Vec<String> {
+    let mut copies = Vec::new();
+    for artifact in artifacts {
+        let (dir, name) = artifact.rsplit_once('/').unwrap_or(("", artifact));
+        let Ok(mut entries) = tokio::fs::read_dir(out_dir.join(dir)).await else {
+            continue;
+        };
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+            if fingerprint::is_copy_of(&file_name, name) {
+                copies.push(
+                    FsPath::new(dir)
+                        .join(file_name)
+                        .to_string_lossy()
+                        .into_owned(),
+                );
+            }
+        }
+    }
+    copies
+}
+
+/// File of the output directory recording the optional outputs of the last
+/// build, so that `clean` and `check` list them whether `blog.toml` or flags
+/// enabled them
+const BUILD_RECORD_FILE: &str = ".build.json";
+
+/// Optional outputs the builds since the last `clean` wrote
+#[derive(Default, Serialize, Deserialize)]
+struct BuildRecord {
+    all_html: bool,
+    page_json: bool,
+}
+
+impl BuildRecord {
+    /// The record of `out_dir`, empty without one
+    async fn load(out_dir: &FsPath) -> Self {
+        let Ok(content) = tokio::fs::read_to_string(out_dir.join(BUILD_RECORD_FILE)).await else {
+            return Self::default();
+        };
+        serde_json::from_str(&content).unwrap_or_default()
+    }
+}
+
+/// The files `build_artifacts` lists, but for their compressed siblings
+async fn written_files(docs_dir: &FsPath, out_dir: &FsPath) -> anyhow::Result<Vec<String>> {
+    let mut config = config::Config::load(docs_dir)?;
+    // Along with what the flags of the last build enabled
+    let record = BuildRecord::load(out_dir).await;
+    config.all_html |= record.all_html;
+    config.page_json |= record.page_json;
+    let mut artifacts: Vec<String> = config.fallback_page.iter().cloned().collect();
+    artifacts.extend([
+        signing::MANIFEST_FILE.to_string(),
+        signing::SIGNATURE_FILE.to_string(),
+        BUILD_RECORD_FILE.to_string(),
+    ]);
+    if config.languages.is_empty() {
+        let store = LocalStore::new(docs_dir.to_path_buf());
+        artifacts.extend(tree_artifacts(&store, &config).await?);
+        return Ok(artifacts);
+    }
+
+    artifacts.extend([
+        "index.html".to_string(),
+        "style.css".to_string(),
+        CODE_THEME_CSS.to_string(),
+        SITEMAP_FILE.to_string(),
+    ]);
+    for (language, store) in languages::language_stores(docs_dir, &config.languages).await? {
+        for artifact in tree_artifacts(&store, &config).await? {
+            artifacts.push(format!("{}/{}", language, artifact));
+        }
+    }
+    Ok(artifacts)
+}
+
+/// Files `build_tree` can write for the pages of `store`, with the settings
+/// of `config`.
+async fn tree_artifacts(
+    store: &dyn ContentStore,
+    config: &config::Config,
+) -> anyhow::Result<Vec<String>> {
+    let mut artifacts = vec![
+        "index.html".to_string(),
+        "style.css".to_string(),
+        CODE_THEME_CSS.to_string(),
+        SEARCH_INDEX_FILE.to_string(),
+        SITEMAP_FILE.to_string(),
+        FEED_FILE.to_string(),
+        ARCHIVE_FEED_FILE.to_string(),
+        page_file(series::SERIES_FILE, false),
+        page_file(series::SERIES_FILE, true),
+        page_file(CHANGES_FILE, false),
+        page_file(CHANGES_FILE, true),
+        CHANGES_FEED_FILE.to_string(),
+    ];
+    let pages = store.list_pages().await?;
+    if config.all_html && !combined::is_taken(&pages, config.pretty_urls) {
+        artifacts.push(combined::COMBINED_FILE.to_string());
+    }
+    for filename in pages {
+        artifacts.push(page_file(&filename, false));
+        artifacts.push(page_file(&filename, true));
+        if config.page_json {
+            artifacts.extend(sidecar::sidecar_file(&filename, false));
+            artifacts.extend(sidecar::sidecar_file(&filename, true));
+        }
+    }
+    for slug in feed_tags(store).await {
+        artifacts.push(tag_feed_file(&slug));
+    }
+    Ok(artifacts)
+}
+
+/// Removes what `run_build` generated in `out_dir`, leaving the markdown
+/// sources and any other file untouched. Returns how many files were removed.
+pub async fn run_clean(docs_dir: PathBuf, out_dir: PathBuf) -> anyhow::Result<usize> {
+    let mut removed = 0;
+    let mut artifacts = build_artifacts(&docs_dir, &out_dir).await?;
+    let written = written_files(&docs_dir, &out_dir).await?;
+    artifacts.extend(fingerprinted_copies(&out_dir, &written).await);
+    for artifact in artifacts {
+        let path = out_dir.join(&artifact);
+        if tokio::fs::try_exists(&path).await? {
+            tokio::fs::remove_file(&path).await?;
+            tracing::info!("Removed {}", artifact);
+            removed += 1;
+
+            // Pretty URL and language directories, unless something else
+            // was put there
+            for dir in path.ancestors().skip(1) {
+                if dir == out_dir || tokio::fs::remove_dir(dir).await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+    tracing::info!("Clean complete, removed {} files", removed);
+    Ok(removed)
+}
+
+/// `GET /`, listing every page unless the query limits them to a window,
+/// see `dates::WindowQuery`.
+async fn render_summary_handler(
+    State(state): State<Arc<AppState>>,
+    client: Option<Extension<ClientInfo>>,
+    Query(query): Query<WindowQuery>,
+) -> impl IntoResponse {
+    if state.options.no_navigation {
+        return (StatusCode::NOT_FOUND, "Disabled").into_response();
+    }
+    let window = match query.window(DateWindow::default()) {
+        Ok(window) => window,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+    let pages = match &state.db {
+        Some(db) => match db.summary_data(state.store.as_ref()).await {
+            Ok(pages) => pages,
+            Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        },
+        None => get_summary_data(state.store.as_ref()).await,
+    };
+    let pages: Vec<Page> = window
+        .filter(pages, |page| page.datetime)
+        .into_iter()
+        .map(|mut p| {
+            p.filename = page_url(&p.filename, false, state.options.pretty_urls);
+            p.date = display_date(&state.options, p.datetime);
+            p
+        })
+        .collect();
+
+    let (root, home_url) = link_roots(&state.options, false, false);
+    let mut context = Context::new();
+    context.insert("root", &root);
+    context.insert("home_url", &home_url);
+    SiteNavigation::new(
+        &state.options.menu,
+        &Location::Home,
+        &root,
+        &home_url,
+        false,
+        state.options.pretty_urls,
+    )
+    .insert_into(&mut context);
+    analytics::insert_into(&state.options, &mut context);
+    translations::insert_into(&state.options, &mut context);
+    context.insert("title", ui_string(&state.options, "pages"));
+    context.insert("files", &pages);
+    context.insert("is_static", &false);
+    context.insert("canonical", &request_options(&state, client).base_url);
+
+    let rendered = TEMPLATES.read().unwrap().render("home.html", &context);
+    match rendered {
+        Ok(rendered) => {
+            let generation = state
+                .generation
+                .current(
+                    state.store.as_ref(),
+                    &state.options,
+                    &freshness::Artifact::Home,
+                )
+                .await;
+            let mut response = Html(rendered).into_response();
+            freshness::add_headers(&mut response, generation, None);
+            response
+        }
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+async fn render_page_handler(
+    State(state): State<Arc<AppState>>,
+    client: Option<Extension<ClientInfo>>,
+    Path(page): Path<String>,
+) -> Response {
+    // Relative links of pretty pages only resolve from `slug/`
+    if state.options.pretty_urls {
+        let slug = page.strip_suffix(".md").unwrap_or(&page);
+        return Redirect::permanent(&format!("{}/", slug)).into_response();
+    }
+    render_page(&state, &request_options(&state, client), page).await
+}
+
+async fn render_pretty_page_handler(
+    State(state): State<Arc<AppState>>,
+    client: Option<Extension<ClientInfo>>,
+    Path(page): Path<String>,
+) -> Response {
+    if !state.options.pretty_urls {
+        return Redirect::permanent(&format!("../{}", page)).into_response();
+    }
+    render_page(&state, &request_options(&state, client), page).await
+}
+
+/// Settings requests are answered with: the server's, with the site URL a
+/// trusted proxy reports as the base URL when none is configured.
+fn request_options(
+    state: &AppState,
+    client: Option<Extension<ClientInfo>>,
+) -> Cow<'_, RenderOptions> {
+    match client.and_then(|Extension(client)| client.origin) {
+        Some(origin) if state.options.base_url.is_none() => {
+            let mut options = state.options.clone();
+            options.base_url = Some(format!("{}{}", origin, served_root(&state.options)));
+            Cow::Owned(options)
+        }
+        _ => Cow::Borrowed(&state.options),
+    }
+}
+
+async fn render_page(state: &AppState, options: &RenderOptions, page: String) -> Response {
+    let filename = if page.ends_with(".md") {
+        page
+    } else {
+        format!("{}.md", page)
+    };
+    let mut timings = PageTimings::default();
+    let started = Instant::now();
+    let read = state.store.read_page(&filename).await;
+    timings.read = started.elapsed();
+    match read {
+        Ok(content) => {
+            if let Some(db) = &state.db
+                && let Err(e) = db.record_view(&filename)
+            {
+                tracing::warn!("Failed to record view of {}: {}", filename, e);
+            }
+            let webmentions = match &state.db {
+                Some(db) if options.webmentions => {
+                    Some(db.webmentions(&filename).unwrap_or_else(|e| {
+                        tracing::warn!("Failed to read the webmentions of {}: {}", filename, e);
+                        Vec::new()
+                    }))
+                }
+                _ => None,
+            };
+            let mut response = Html(
+                render_markdown_to_html(
+                    &content,
+                    &filename,
+                    state.store.as_ref(),
+                    options,
+                    false,
+                    &mut timings,
+                    webmentions.as_deref(),
+                )
+                .await,
+            )
+            .into_response();
+            timings.record(&filename);
+            freshness::add_headers(
+                &mut response,
+                state
+                    .generation
+                    .current(
+                        state.store.as_ref(),
+                        &state.options,
+                        &freshness::Artifact::Page(filename.clone()),
+                    )
+                    .await,
+                state.store.modified(&filename).await,
+            );
+            response
+        }
+        Err(_) if filename == series::SERIES_FILE => {
+            match series::render_series_index(state.store.as_ref(), options, false).await {
+                Ok(Some(rendered)) => Html(rendered).into_response(),
+                Ok(None) => fallback::not_found(state, &filename).await,
+                Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+            }
+        }
+        Err(_) if filename == CHANGES_FILE && options.changelog => {
+            match changes::render_changes_page(state.store.as_ref(), options, false).await {
+                Ok(rendered) => Html(rendered).into_response(),
+                Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+            }
+        }
+        Err(_) => fallback::not_found(state, &filename).await,
+    }
+}
+
+/// The site's stylesheet, importing `CODE_THEME_CSS` when code is
+/// highlighted with classes.
+fn render_css(options: &RenderOptions) -> anyhow::Result<String> {
+    let css = TEMPLATES
+        .read()
+        .unwrap()
+        .render("style.css", &Context::new())?;
+    if !options.highlight_classes {
+        return Ok(css);
+    }
+    // Imports must come before any other rule
+    Ok(format!("@import url(\"{}\");\n{}", CODE_THEME_CSS, css))
+}
+
+/// Writes `style.css`, and `CODE_THEME_CSS` if needed, to `out_dir`.
+pub async fn write_stylesheets(out_dir: &FsPath, options: &RenderOptions) -> anyhow::Result<()> {
+    tokio::fs::write(out_dir.join("style.css"), render_css(options)?).await?;
+    if options.highlight_classes {
+        let css = code_theme_css(&options.code_theme, options.code_theme_light.as_deref())?;
+        tokio::fs::write(out_dir.join(CODE_THEME_CSS), css).await?;
+    }
+    Ok(())
+}
+
+async fn serve_css(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    match render_css(&state.options) {
+        Ok(css) => Response::builder()
+            .header("content-type", "text/css")
+            .body(css.into())
+            .unwrap(),
+        Err(_) => (StatusCode::NOT_FOUND, "CSS not found").into_response(),
+    }
+}
+
+async fn serve_code_theme_css(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let options = &state.options;
+    if !options.highlight_classes {
+        return (StatusCode::NOT_FOUND, "Code is highlighted inline").into_response();
+    }
+    match code_theme_css(&options.code_theme, options.code_theme_light.as_deref()) {
+        Ok(css) => Response::builder()
+            .header("content-type", "text/css")
+            .body(css.into())
+            .unwrap(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+async fn serve_search_index(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    if state.options.no_navigation {
+        return (StatusCode::NOT_FOUND, "Disabled").into_response();
+    }
+    Json(build_search_index(state.store.as_ref(), false, state.options.pretty_urls).await)
+        .into_response()
+}
+
+async fn serve_sitemap(
+    State(state): State<Arc<AppState>>,
+    client: Option<Extension<ClientInfo>>,
+) -> impl IntoResponse {
+    let options = request_options(&state, client);
+    let Some(base_url) = &options.base_url else {
+        return (StatusCode::NOT_FOUND, "No base URL configured").into_response();
+    };
+    Response::builder()
+        .header("content-type", "application/xml")
+        .body(
+            build_sitemap(
+                state.store.as_ref(),
+                base_url,
+                false,
+                state.options.pretty_urls,
+            )
+            .await
+            .into(),
+        )
+        .unwrap()
+}
+
+/// `GET /feed.xml`, limited to `feed_months` unless the query asks for
+/// another window, see `dates::WindowQuery`.
+async fn serve_feed(
+    State(state): State<Arc<AppState>>,
+    client: Option<Extension<ClientInfo>>,
+    Query(query): Query<WindowQuery>,
+) -> impl IntoResponse {
+    let options = request_options(&state, client);
+    let Some(base_url) = &options.base_url else {
+        return (StatusCode::NOT_FOUND, "No base URL configured").into_response();
+    };
+    let window = match query.window(DateWindow::recent(options.feed_months)) {
+        Ok(window) => window,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+    let hub = state.options.websub_hub.as_deref();
+    let generation = state
+        .generation
+        .current(
+            state.store.as_ref(),
+            &state.options,
+            &freshness::Artifact::Feed,
+        )
+        .await;
+    let mut response = Response::builder()
+        .header("content-type", "application/atom+xml")
+        .header(freshness::CONTENT_VERSION_HEADER, generation);
+    if let Some(hub) = hub {
+        let self_url = format!("{}{}", base_url, FEED_FILE);
+        response = response.header("link", hub_link_header(&self_url, hub));
+    }
+    response
+        .body(
+            build_feed(
+                state.store.as_ref(),
+                base_url,
+                false,
+                state.options.pretty_urls,
+                hub,
+                &window,
+            )
+            .await
+            .into(),
+        )
+        .unwrap()
+}
+
+async fn serve_archive_feed(
+    State(state): State<Arc<AppState>>,
+    client: Option<Extension<ClientInfo>>,
+) -> impl IntoResponse {
+    let options = request_options(&state, client);
+    let Some(base_url) = &options.base_url else {
+        return (StatusCode::NOT_FOUND, "No base URL configured").into_response();
+    };
+    let feed = build_archive_feed(state.store.as_ref(), base_url, false, options.pretty_urls).await;
+    ([("content-type", "application/atom+xml")], feed).into_response()
+}
+
+/// `GET /tags/{tag}/feed.xml`, see `feed::tag_feed_file`
+async fn serve_tag_feed(
+    State(state): State<Arc<AppState>>,
+    client: Option<Extension<ClientInfo>>,
+    Path(tag): Path<String>,
+) -> impl IntoResponse {
+    let options = request_options(&state, client);
+    let Some(base_url) = &options.base_url else {
+        return (StatusCode::NOT_FOUND, "No base URL configured").into_response();
+    };
+    let hub = state.options.websub_hub.as_deref();
+    let store = state.store.as_ref();
+    let artifact = freshness::Artifact::TagFeed(tag.clone());
+    let generation = state
+        .generation
+        .current(store, &state.options, &artifact)
+        .await;
+    match build_tag_feed(store, base_url, false, options.pretty_urls, hub, &tag).await {
+        Some(feed) => (
+            [
+                ("content-type", "application/atom+xml".to_string()),
+                (freshness::CONTENT_VERSION_HEADER, generation.to_string()),
+            ],
+            feed,
+        )
+            .into_response(),
+        None => (StatusCode::NOT_FOUND, "No page has this tag").into_response(),
+    }
+}
+
+async fn serve_metrics() -> impl IntoResponse {
+    (
+        [
+            ("content-type", "text/plain; version=0.0.4"),
+            ("cache-control", "no-store"),
+        ],
+        timings::render_metrics(),
+    )
+}
+
+/// Answer of `/healthz`
+#[derive(Serialize)]
+struct Health {
+    version: &'static str,
+    /// Whether the pages of the content store could be listed
+    docs_readable: bool,
+    pages: usize,
+}
+
+/// Probe for orchestrators and uptime monitors: 200 while the content store
+/// can be read, 503 otherwise.
+async fn serve_health(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let pages = state.store.list_pages().await;
+    if let Err(e) = &pages {
+        tracing::warn!("Health check failed to list pages: {}", e);
+    }
+    let health = Health {
+        version: env!("CARGO_PKG_VERSION"),
+        docs_readable: pages.is_ok(),
+        pages: pages.map(|pages| pages.len()).unwrap_or_default(),
+    };
+    let status = if health.docs_readable {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, [("cache-control", "no-store")], Json(health))
+}
+
+async fn serve_changes_feed(
+    State(state): State<Arc<AppState>>,
+    client: Option<Extension<ClientInfo>>,
+) -> impl IntoResponse {
+    if !state.options.changelog {
+        return (StatusCode::NOT_FOUND, "No changelog configured").into_response();
+    }
+    let options = request_options(&state, client);
+    let Some(base_url) = &options.base_url else {
+        return (StatusCode::NOT_FOUND, "No base URL configured").into_response();
+    };
+    let hub = state.options.websub_hub.as_deref();
+    let mut response = Response::builder().header("content-type", "application/atom+xml");
+    if let Some(hub) = hub {
+        let self_url = format!("{}{}", base_url, CHANGES_FEED_FILE);
+        response = response.header("link", hub_link_header(&self_url, hub));
+    }
+    response
+        .body(
+            build_changes_feed(
+                state.store.as_ref(),
+                base_url,
+                false,
+                state.options.pretty_urls,
+                hub,
+            )
+            .await
+            .into(),
+        )
+        .unwrap()
+}
+
+/// Link to a page from the home page
+fn page_url(filename: &str, is_static: bool, pretty_urls: bool) -> String {
+    if pretty_urls {
+        format!("{}/", filename.trim_end_matches(".md"))
+    } else if is_static {
+        filename.replace(".md", ".html")
+    } else {
+        filename.to_string()
+    }
+}
+
+/// Path of a built page, relative to the output directory
+fn page_file(filename: &str, pretty_urls: bool) -> String {
+    if pretty_urls {
+        format!("{}/index.html", filename.trim_end_matches(".md"))
+    } else {
+        filename.replace(".md", ".html")
+    }
+}
+
+/// Prefix leading back to the site root, and the link to the home page, from
+/// a page or from the home page itself. Served under a base path, both are
+/// the base path itself.
+fn link_roots(options: &RenderOptions, is_static: bool, is_page: bool) -> (String, String) {
+    if !is_static && !options.base_path.is_empty() {
+        let root = served_root(options);
+        return (root.clone(), root);
+    }
+    let root = if options.pretty_urls && is_page {
+        "../"
+    } else {
+        ""
+    };
+    let home_url = match (options.pretty_urls, is_page, is_static) {
+        (true, true, _) => "../",
+        (false, _, true) => "index.html",
+        _ => "./",
+    };
+    (root.to_string(), home_url.to_string())
+}
+
+/// Absolute path of the site root when serving, `/` or the base path
+pub fn served_root(options: &RenderOptions) -> String {
+    format!("{}/", options.base_path)
+}
+
+/// Characters that can't appear as is in the path of a URL
+const PATH_ESCAPES: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+/// A page date formatted as configured, `None` leaving it to the browser
+fn display_date(options: &RenderOptions, datetime: Option<i64>) -> Option<String> {
+    options.date_display.as_ref()?.format(datetime?)
+}
+
+/// Public URL of a page, for canonical links, sitemaps and feeds
+pub fn absolute_url(base_url: &str, filename: &str, is_static: bool, pretty_urls: bool) -> String {
+    let path = page_url(filename, is_static, pretty_urls);
+    format!("{}{}", base_url, utf8_percent_encode(&path, PATH_ESCAPES))
+}
+
+/// Link to a page sharing tags with the rendered one
+#[derive(Serialize)]
+struct RelatedPage {
+    url: String,
+    title: String,
+}
+
+/// Banner of a page older than `age_warning_years`
+#[derive(Serialize)]
+struct AgeWarning {
+    /// Full years since the page was published
+    years: u32,
+}
+
+/// Feed of one of the rendered page's tags, see `feed::tag_feed_file`
+#[derive(Serialize)]
+struct TagFeed {
+    tag: String,
+    url: String,
+    #[serde(skip)]
+    file: String,
+}
+
+// Helper model for Tera
+mod ax_models {
+    use serde::{Deserialize, Serialize};
+    /// A page as listed on the home page
+    #[derive(Deserialize, Serialize, Clone)]
+    pub struct Page {
+        pub filename: String,
+        pub title: String,
+        /// Publication date as a Unix timestamp, see `dates::page_date`
+        pub datetime: Option<i64>,
+        pub weight: Option<i64>,
+        pub excerpt: Option<String>,
+        /// Social image, see `metadata::PageMetadata::image`
+        pub image: Option<String>,
+        /// Only known when serving with a database
+        pub views: Option<i64>,
+        /// `datetime` formatted with the configured date display, if any
+        pub date: Option<String>,
+    }
+}
+
+async fn get_nav_links(
+    store: &dyn ContentStore,
+    current_file: &str,
+    order: NavOrder,
+    language: Option<&str>,
+) -> (Option<String>, Option<String>) {
+    let files = nav_files(store, order, language).await;
+    let pos = files.iter().position(|f| f == current_file);
+    match pos {
+        Some(i) => {
+            let prev = if i == 0 {
+                Some(".".to_string())
+            } else {
+                files.get(i - 1).cloned()
+            };
+            let next = files.get(i + 1).cloned();
+            (prev, next)
+        }
+        None => (None, None),
+    }
+}
+
+/// Pages in the order the prev/next links walk them
+async fn nav_files(
+    store: &dyn ContentStore,
+    order: NavOrder,
+    language: Option<&str>,
+) -> Vec<String> {
+    match order {
+        // Same order as the home page
+        NavOrder::Date => get_summary_data(store)
+            .await
+            .into_iter()
+            .map(|p| p.filename)
+            .filter(|f| f != "SUMMARY.md")
+            .collect(),
+        NavOrder::Alphabetical => {
+            let mut files: Vec<String> = store
+                .list_pages()
+                .await
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|f| f != "SUMMARY.md")
+                .collect();
+
+            let mut weights: HashMap<String, Option<i64>> = HashMap::new();
+            for f in &files {
+                let content = store.read_page(f).await.unwrap_or_default();
+                let (meta, _) = frontmatter::parse(&content);
+                if !meta.is_draft() {
+                    weights.insert(f.clone(), meta.weight);
+                }
+            }
+            files.retain(|f| weights.contains_key(f));
+            files.sort_by(|a, b| weight_order(weights[a], weights[b]).then_with(|| a.cmp(b)));
+            files
+        }
+        NavOrder::Title => {
+            let mut pages: Vec<Page> = get_summary_data(store)
+                .await
+                .into_iter()
+                .filter(|p| p.filename != "SUMMARY.md")
+                .collect();
+            let collator = TitleCollator::new(language);
+            pages.sort_by(|a, b| {
+                weight_order(a.weight, b.weight)
+                    .then_with(|| collator.compare(&a.title, &b.title))
+                    .then_with(|| a.filename.cmp(&b.filename))
+            });
+            pages.into_iter().map(|p| p.filename).collect()
+        }
+    }
+}
@@ -0,0 +1,177 @@
+use pulldown_cmark::{CowStr, Event, Tag};
+use std::path::Path;
+
+use crate::{RenderOptions, page_url, served_root};
+
+/// Turns the URLs written in a page into ones that work from where the page
+/// ends up: links to pages point at their built or served URL, and links to
+/// files of the docs directory, like `../assets/pic.png`, are resolved
+/// against the page's directory and made relative to the page's depth.
+pub struct UrlResolver {
+    /// Leads back to the site root from the page
+    root: String,
+    /// Directory of the page in the docs directory, empty at its root
+    dir: String,
+    /// What absolute URLs like `/about.md` start from, see `served_root`
+    site_root: String,
+    is_static: bool,
+    pretty_urls: bool,
+}
+
+impl UrlResolver {
+    /// Resolver for a page at `root`, for pages rendered away from their own
+    /// URL such as the language comparison view.
+    pub fn new(root: &str, options: &RenderOptions, is_static: bool) -> Self {
+        Self {
+            root: root.to_string(),
+            dir: String::new(),
+            site_root: site_root(options, is_static),
+            is_static,
+            pretty_urls: options.pretty_urls,
+        }
+    }
+
+    /// Resolver for the page `filename`, rendered at its own URL. With
+    /// pretty URLs pages are one directory deeper, at `slug/`.
+    pub fn for_page(filename: &str, options: &RenderOptions, is_static: bool) -> Self {
+        let dir = Path::new(filename)
+            .parent()
+            .map(|dir| dir.to_string_lossy().replace('\\', "/"))
+            .unwrap_or_default();
+        let depth = dir.split('/').filter(|s| !s.is_empty()).count() + options.pretty_urls as usize;
+        Self {
+            root: "../".repeat(depth),
+            dir,
+            site_root: site_root(options, is_static),
+            is_static,
+            pretty_urls: options.pretty_urls,
+        }
+    }
+
+    /// Link to the page `filename` of the docs directory
+    pub fn page(&self, filename: &str) -> String {
+        format!(
+            "{}{}",
+            self.root,
+            page_url(filename, self.is_static, self.pretty_urls)
+        )
+    }
+
+    /// Path in the docs directory of a local URL written in the page, `None`
+    /// for external URLs and same-page anchors.
+    pub fn local_path(&self, url: &str) -> Option<String> {
+        let has_scheme = url
+            .split_once(':')
+            .is_some_and(|(scheme, _)| !scheme.contains(['/', '#', '?']));
+        if has_scheme || url.starts_with("//") || url.starts_with('#') {
+            return None;
+        }
+        let end = url.find(['#', '?']).unwrap_or(url.len());
+        let path = &url[..end];
+        let (base, path) = match path.strip_prefix('/') {
+            Some(path) => ("", path),
+            None => (self.dir.as_str(), path),
+        };
+
+        // Walk `..` and `.` segments, never above the docs directory
+        let mut segments: Vec<&str> = base.split('/').filter(|s| !s.is_empty()).collect();
+        for segment in path.split('/') {
+            match segment {
+                "" | "." => {}
+                ".." => {
+                    segments.pop();
+                }
+                segment => segments.push(segment),
+            }
+        }
+        let resolved = segments.join("/");
+        (!resolved.is_empty()).then_some(resolved)
+    }
+
+    /// URL to use in the rendered page for a URL written in it, `None` when
+    /// it is left as is.
+    pub fn resolve(&self, url: &str) -> Option<String> {
+        let mut path = self.local_path(url)?;
+        let end = url.find(['#', '?']).unwrap_or(url.len());
+        let suffix = &url[end..];
+        if path.ends_with(".md") {
+            let root = if url.starts_with('/') {
+                &self.site_root
+            } else {
+                &self.root
+            };
+            return Some(format!(
+                "{}{}{}",
+                root,
+                page_url(&path, self.is_static, self.pretty_urls),
+                suffix
+            ));
+        }
+        // Absolute URLs to files already work from any depth, once under
+        // the base path
+        if let Some(url) = url.strip_prefix('/') {
+            return (self.site_root != "/").then(|| format!("{}{}", self.site_root, url));
+        }
+        if url[..end].ends_with('/') {
+            path.push('/');
+        }
+        Some(format!("{}{}{}", self.root, path, suffix))
+    }
+}
+
+/// Built sites live at the root of their domain, served ones may not
+fn site_root(options: &RenderOptions, is_static: bool) -> String {
+    if is_static {
+        "/".to_string()
+    } else {
+        served_root(options)
+    }
+}
+
+/// Rewrites the destinations of links and images with a `UrlResolver`.
+pub struct UrlRenderer<'r, I> {
+    inner: I,
+    urls: &'r UrlResolver,
+}
+
+impl<'r, I> UrlRenderer<'r, I> {
+    pub fn new(inner: I, urls: &'r UrlResolver) -> Self {
+        Self { inner, urls }
+    }
+}
+
+impl<'a, I: Iterator<Item = Event<'a>>> Iterator for UrlRenderer<'_, I> {
+    type Item = Event<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let resolve = |dest_url: CowStr<'a>| match self.urls.resolve(&dest_url) {
+            Some(url) => CowStr::from(url),
+            None => dest_url,
+        };
+        match self.inner.next()? {
+            Event::Start(Tag::Link {
+                link_type,
+                dest_url,
+                title,
+                id,
+            }) => Some(Event::Start(Tag::Link {
+                link_type,
+                dest_url: resolve(dest_url),
+                title,
+                id,
+            })),
+            Event::Start(Tag::Image {
+                link_type,
+                dest_url,
+                title,
+                id,
+            }) => Some(Event::Start(Tag::Image {
+                link_type,
+                dest_url: resolve(dest_url),
+                title,
+                id,
+            })),
+            event => Some(event),
+        }
+    }
+}
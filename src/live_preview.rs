@@ -0,0 +1,107 @@
+use axum::{
+    extract::{
+        Query, State,
+        ws::{Message, WebSocket, WebSocketUpgrade},
+    },
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+use std::sync::Arc;
+
+use crate::preprocess::Preprocessor;
+use crate::timings::PageTimings;
+use crate::{AppState, RenderOptions, render_markdown_to_html};
+
+/// Name previews are rendered under when the editor gives none
+const DEFAULT_PAGE: &str = "preview.md";
+
+#[derive(Deserialize)]
+pub struct PreviewQuery {
+    /// File the markdown is from, like `post.md`, so that links, includes
+    /// and the page's title resolve as they will once it is saved
+    page: Option<String>,
+}
+
+/// `GET /live-preview`: a websocket an editor sends the markdown of a page
+/// to, frontmatter included, as text messages. Each is answered with the
+/// page rendered as the server would serve it, so unsaved changes can be
+/// previewed while typing. Only pages of the site itself and editors,
+/// which send no `Origin`, may connect, and previews read no other files.
+pub async fn live_preview_handler(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<PreviewQuery>,
+    headers: HeaderMap,
+    upgrade: WebSocketUpgrade,
+) -> Response {
+    if !is_same_origin(&headers) {
+        return (
+            StatusCode::FORBIDDEN,
+            "Cross-origin previews are not allowed",
+        )
+            .into_response();
+    }
+    let filename = match query.page {
+        Some(page) if page.ends_with(".md") => page,
+        Some(page) => format!("{}.md", page),
+        None => DEFAULT_PAGE.to_string(),
+    };
+    upgrade.on_upgrade(move |socket| preview(socket, state, filename))
+}
+
+/// Whether the request has no `Origin`, or one of the host it is sent to.
+/// Browsers send it with every websocket, so other sites can't connect.
+fn is_same_origin(headers: &HeaderMap) -> bool {
+    let Some(origin) = headers.get(header::ORIGIN) else {
+        return true;
+    };
+    let host = headers
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok());
+    let origin_host = origin
+        .to_str()
+        .ok()
+        .and_then(|origin| reqwest::Url::parse(origin).ok())
+        .and_then(|url| {
+            let host = url.host_str()?.to_string();
+            Some(match url.port() {
+                Some(port) => format!("{}:{}", host, port),
+                None => host,
+            })
+        });
+    origin_host.is_some_and(|origin| host.is_some_and(|host| host.eq_ignore_ascii_case(&origin)))
+}
+
+/// `options` without the preprocessors reading files or running commands:
+/// previews render what an unsaved page says, not what it pulls in
+fn preview_options(options: &RenderOptions) -> RenderOptions {
+    let mut options = options.clone();
+    options
+        .preprocessors
+        .retain(|preprocessor| matches!(preprocessor, Preprocessor::Variables));
+    options
+}
+
+async fn preview(mut socket: WebSocket, state: Arc<AppState>, filename: String) {
+    let options = preview_options(&state.options);
+    while let Some(Ok(message)) = socket.recv().await {
+        let markdown = match message {
+            Message::Text(text) => text,
+            Message::Close(_) => break,
+            _ => continue,
+        };
+        let html = render_markdown_to_html(
+            markdown.as_str(),
+            &filename,
+            state.store.as_ref(),
+            &options,
+            false,
+            &mut PageTimings::default(),
+            None,
+        )
+        .await;
+        if socket.send(Message::Text(html.into())).await.is_err() {
+            break;
+        }
+    }
+}
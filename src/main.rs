@@ -1,57 +1,121 @@
-use ax_models::Page;
-use axum::{
-    Router,
-    extract::{Path, State},
-    http::StatusCode,
-    response::{Html, IntoResponse, Response},
-    routing::get,
+use anyhow::Context;
+use blog::access_log::{AccessLog, with_access_log};
+use blog::analytics::AnalyticsOptions;
+use blog::cdn::{CdnOptions, with_cdn};
+use blog::comments::CommentsOptions;
+use blog::config::{CONFIG_FILE, Config, TagStyle};
+use blog::dates::DateDisplay;
+use blog::db::Database;
+use blog::exit::{self, Failure};
+use blog::external_links::{DEFAULT_REL, DEFAULT_TARGET, ExternalLinkOptions};
+use blog::footnotes::{FootnoteOptions, FootnotePosition};
+use blog::images::{DEFAULT_IMAGE_WIDTHS, ImageOptions};
+use blog::pings::{self, Pings};
+use blog::preprocess::Preprocessor;
+use blog::proxy::with_client_info;
+use blog::static_site::static_router;
+use blog::store::{ContentStore, LocalStore, WebDavStore};
+use blog::sync::{self, Remote};
+use blog::translations::load_translations;
+use blog::typography::TypographyLocale;
+use blog::{
+    AppState, DEFAULT_RELATED_PAGES, NavOrder, RenderOptions, TEMPLATES, alt_text, audit,
+    blog_router, check, check_code_theme, diff, fallback, load_template_overrides, migrate, pdf,
+    publish, run_build, run_build_page, run_clean, scaffold, select_code_theme, signing, snapshot,
+    stats, tags, themes, webmentions, with_base_path,
 };
-use clap::{Parser, Subcommand};
-use lazy_static::lazy_static;
-use pulldown_cmark::{Options, Parser as MarkdownParser, html};
+use clap::{Args, Parser, Subcommand};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 use std::sync::Arc;
-use std::{io::Cursor, path::PathBuf};
-use syntect::{highlighting::ThemeSet, parsing::SyntaxSet};
-use tera::{Context, Tera};
-use tokio::io::{AsyncBufReadExt, BufReader};
-
-mod codeblocks;
-use codeblocks::*;
-
-lazy_static! {
-    pub static ref TEMPLATES: Tera = {
-        let mut tera = Tera::default();
-        tera.add_raw_templates(vec![
-            ("_base.html", include_str!("../templates/_base.html")),
-            ("home.html", include_str!("../templates/home.html")),
-            ("page.html", include_str!("../templates/page.html")),
-            ("style.css", include_str!("../templates/style.css")),
-        ])
-        .unwrap();
-        tera
-    };
-    pub static ref SYNTAX_SET: SyntaxSet = SyntaxSet::load_defaults_newlines();
-    pub static ref THEME_SET: ThemeSet = {
-        let mut set = ThemeSet::load_defaults();
-        let theme_bytes = include_bytes!(env!("THEME_FILE_PATH"));
-        let mut cursor = Cursor::new(theme_bytes);
-        match syntect::highlighting::ThemeSet::load_from_reader(&mut cursor) {
-            Ok(theme) => {
-                set.themes.insert("Catppuccin Macchiato".to_string(), theme);
-            }
-            Err(e) => {
-                tracing::error!("Failed to load embedded theme: {}", e);
-            }
-        }
-        set
-    };
-}
 
 #[derive(Parser)]
-#[command(author, version, about = "A simple markdown book server/builder")]
+#[command(
+    author,
+    version,
+    about = "A simple markdown book server/builder",
+    after_help = "Exit codes: 1 for other errors, 2 for invalid arguments, 3 for an invalid \
+                  configuration, 4 for a failed build, 5 for problems found by a check"
+)]
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+
+    #[command(flatten)]
+    runtime: RuntimeArgs,
+
+    /// Log debug messages, such as the time each page takes to render
+    #[arg(short, long, global = true)]
+    verbose: bool,
+
+    /// Format of log messages: lines for people, or JSON objects for log
+    /// collectors such as Loki or Elasticsearch
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        default_value = "text",
+        env = "BLOG_LOG_FORMAT"
+    )]
+    log_format: LogFormat,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum LogFormat {
+    Text,
+    /// One JSON object per line, with the ID of the request being served
+    Json,
+}
+
+/// How commands print what they found
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum OutputFormat {
+    Text,
+    /// A JSON document, for CI pipelines and editors
+    Json,
+}
+
+/// Most threads for blocking work started per worker thread by default
+const BLOCKING_THREADS_PER_WORKER: usize = 8;
+
+/// Sizing of the async runtime. `serve` and `build` also read it from the
+/// site's `blog.toml`.
+#[derive(Args)]
+struct RuntimeArgs {
+    /// Threads serving requests and building pages [default: one per core]
+    #[arg(long, global = true)]
+    worker_threads: Option<usize>,
+
+    /// Most threads for blocking work such as encoding images [default: 8 per worker thread]
+    #[arg(long, global = true)]
+    blocking_threads: Option<usize>,
+}
+
+impl RuntimeArgs {
+    fn build_runtime(&self, config: &Config) -> anyhow::Result<tokio::runtime::Runtime> {
+        let workers = self
+            .worker_threads
+            .or(config.worker_threads)
+            .unwrap_or_else(|| {
+                std::thread::available_parallelism().map_or(1, std::num::NonZeroUsize::get)
+            });
+        let blocking = self
+            .blocking_threads
+            .or(config.blocking_threads)
+            .unwrap_or(workers * BLOCKING_THREADS_PER_WORKER);
+        if workers == 0 || blocking == 0 {
+            anyhow::bail!("Worker and blocking thread counts must be at least 1");
+        }
+
+        Ok(tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(workers)
+            .max_blocking_threads(blocking)
+            .enable_all()
+            .build()?)
+    }
 }
 
 #[derive(Subcommand)]
@@ -61,316 +125,1221 @@ enum Commands {
         /// Path to the directory containing markdown files
         path: PathBuf,
 
-        /// Whether the home page and navbar should be removed
-        #[arg(short, long)]
-        no_navigation: bool,
-
-        /// Port to listen on
+        /// Port to listen on, for the addresses given without one
         #[arg(short, long, default_value = "3456")]
         port: u16,
 
-        /// Whether to serve on 0.0.0.0 (local network)
-        #[arg(short = 'H', long)]
-        host: bool,
+        /// Address to listen on, IPv4 or IPv6 with an optional port, like
+        /// `0.0.0.0` or `[::]:8080`. Repeat to listen on several, overriding
+        /// `bind` [default: 127.0.0.1]
+        #[arg(short, long, value_name = "ADDR")]
+        bind: Vec<String>,
+
+        /// Serve every route under this prefix, like `/blog`, for a reverse
+        /// proxy forwarding a subpath, overriding `base_path`
+        #[arg(long)]
+        base_path: Option<String>,
+
+        /// Trust the X-Forwarded-For, X-Forwarded-Proto and X-Forwarded-Host
+        /// headers of a reverse proxy every request goes through
+        #[arg(long)]
+        trust_proxy: bool,
+
+        /// Read pages from this WebDAV collection instead of the directory,
+        /// which still provides `blog.toml` and templates
+        #[arg(long)]
+        webdav_url: Option<String>,
+
+        /// User to authenticate to the WebDAV server as
+        #[arg(long, requires = "webdav_url")]
+        webdav_user: Option<String>,
+
+        /// Password of the WebDAV user
+        #[arg(long, env = "BLOG_WEBDAV_PASSWORD", hide_env_values = true)]
+        webdav_password: Option<String>,
+
+        /// SQLite file keeping the page index, view counts and webmentions
+        /// across restarts
+        #[arg(long)]
+        database: Option<PathBuf>,
+
+        /// Serve a read-only GraphQL API over the site index at `/graphql`
+        #[arg(long)]
+        graphql: bool,
+
+        /// Accept markdown pushed by editors at `/live-preview` and answer
+        /// with the rendered page, for previews of unsaved changes
+        #[arg(long)]
+        live_preview: bool,
+
+        /// Requests to log, overriding `access_log` (default: all)
+        #[arg(long, value_enum)]
+        access_log: Option<AccessLog>,
+
+        /// Secret callers of `POST /hooks/purge` send as a bearer token,
+        /// enabling the webhook along with `cdn_purge_url`
+        #[arg(long, env = "BLOG_PURGE_HOOK_SECRET", hide_env_values = true)]
+        purge_hook_secret: Option<String>,
+
+        /// Bearer token of the request purging the CDN's cache
+        #[arg(long, env = "BLOG_CDN_PURGE_TOKEN", hide_env_values = true)]
+        cdn_purge_token: Option<String>,
+
+        #[command(flatten)]
+        render: RenderArgs,
     },
     /// Build static HTML files from the markdown directory
     Build {
         /// Path to the directory containing markdown files
         path: PathBuf,
 
-        /// Whether the home page and navbar should be removed
+        /// Output directory (defaults to the input directory)
+        #[arg(short, long)]
+        out_dir: Option<PathBuf>,
+
+        /// Don't copy assets that no page references
+        #[arg(long)]
+        prune_unused_assets: bool,
+
+        /// Don't send `ping_urls` and `websub_hub` their pings, nor webmentions
+        #[arg(long)]
+        no_ping: bool,
+
+        /// Rebuild only this page, like `post.md`, and the indexes listing it
+        #[arg(long, value_name = "PAGE", conflicts_with = "prune_unused_assets")]
+        only: Option<String>,
+
+        #[command(flatten)]
+        render: RenderArgs,
+    },
+    /// Serve a built site as is, preferring the files written by --precompress
+    ServeStatic {
+        /// Path to the built site
+        path: PathBuf,
+
+        /// Port to listen on, for the addresses given without one
+        #[arg(short, long, default_value = "3456")]
+        port: u16,
+
+        /// Address to listen on, IPv4 or IPv6 with an optional port. Repeat
+        /// to listen on several [default: 127.0.0.1]
+        #[arg(short, long, value_name = "ADDR")]
+        bind: Vec<String>,
+    },
+    /// Show which output files a build would add, remove or modify
+    Diff {
+        /// Path to the directory containing markdown files
+        path: PathBuf,
+
+        /// Output directory to compare with (defaults to the input directory)
+        #[arg(short, long)]
+        out_dir: Option<PathBuf>,
+
+        /// Also print a unified diff of modified files
+        #[arg(short, long)]
+        content: bool,
+
+        /// Format of the report
+        #[arg(long, value_enum, default_value = "text")]
+        output: OutputFormat,
+
+        /// Don't copy assets that no page references
+        #[arg(long)]
+        prune_unused_assets: bool,
+
+        #[command(flatten)]
+        render: RenderArgs,
+    },
+    /// Report orphan pages, unused assets, invalid dates and images without
+    /// alt text
+    Check {
+        /// Path to the directory containing markdown files
+        path: PathBuf,
+
+        /// Check as if the home page and navbar were removed
         #[arg(short, long)]
         no_navigation: bool,
 
-        /// Output directory (defaults to the input directory)
+        /// Format of the report
+        #[arg(long, value_enum, default_value = "text")]
+        output: OutputFormat,
+    },
+    /// Report links and images pointing at missing files or headings
+    CheckLinks {
+        /// Path to the directory containing markdown files
+        path: PathBuf,
+
+        /// Also request web URLs and report those that fail
+        #[arg(short, long)]
+        external: bool,
+
+        /// Format of the report
+        #[arg(long, value_enum, default_value = "text")]
+        output: OutputFormat,
+    },
+    /// Go through the images without alt text, asking for one and writing it
+    /// into the page
+    AltText {
+        /// Directory containing the markdown files
+        #[arg(default_value = ".")]
+        path: PathBuf,
+
+        /// Command suggesting alt texts, overriding `alt_text_command`
+        #[arg(short, long)]
+        command: Option<String>,
+    },
+    /// Audit the site's templates for unescaped untrusted content
+    Audit {
+        /// Path to the directory containing markdown files
+        path: PathBuf,
+
+        /// Format of the report
+        #[arg(long, value_enum, default_value = "text")]
+        output: OutputFormat,
+    },
+    /// Remove the files generated by `build`, leaving the markdown sources untouched
+    Clean {
+        /// Path to the directory containing markdown files
+        path: PathBuf,
+
+        /// Output directory that was built into (defaults to the input directory)
+        #[arg(short, long)]
+        out_dir: Option<PathBuf>,
+    },
+    /// Print statistics about the site's content
+    Stats {
+        /// Path to the directory containing markdown files
+        path: PathBuf,
+
+        /// Format of the statistics
+        #[arg(long, value_enum, default_value = "text")]
+        output: OutputFormat,
+    },
+    /// Create a new blog with a starter config and an example post
+    Init {
+        /// Directory to create the blog in
+        path: PathBuf,
+
+        /// Also copy the embedded templates into `templates/` for customization
+        #[arg(short, long)]
+        templates: bool,
+    },
+    /// Create a key to sign builds with, printing its public key
+    Keygen {
+        /// File to write the private key to
+        path: PathBuf,
+    },
+    /// Check that a built site is the one a signed build published
+    Verify {
+        /// Directory the site was built into
+        path: PathBuf,
+
+        /// Public key printed by `blog keygen`
+        #[arg(short = 'k', long, env = "BLOG_PUBLIC_KEY")]
+        public_key: String,
+    },
+    /// Write the embedded templates and stylesheet to a directory, as a
+    /// starting point for customizing them
+    Templates {
+        /// Directory to write the templates to
+        #[arg(short, long)]
+        export: PathBuf,
+
+        /// Overwrite files that already exist
+        #[arg(short, long)]
+        force: bool,
+    },
+    /// Manage site themes
+    Theme {
+        #[command(subcommand)]
+        command: ThemeCommands,
+    },
+    /// Crawl a served site and write it as a static site, pages served
+    /// without `.html` URLs written as `.html` files
+    Snapshot {
+        /// URL of a running `blog serve`, or a directory of markdown files to
+        /// serve and crawl in-process
+        source: String,
+
+        /// Directory to write the crawled files to
+        out_dir: PathBuf,
+
+        /// Settings of the in-process server, when crawling a directory
+        #[command(flatten)]
+        render: RenderArgs,
+    },
+    /// Export pages to other formats
+    Export {
+        #[command(subcommand)]
+        command: ExportCommands,
+    },
+    /// Send local changes to the sync remote and fetch the remote's,
+    /// stopping on files changed on both sides
+    Sync {
+        /// Directory containing the markdown files
+        #[arg(default_value = ".")]
+        path: PathBuf,
+
+        /// Remote to sync with, overriding `sync_remote`
+        #[arg(short, long)]
+        remote: Option<String>,
+    },
+    /// Rewrite every page's frontmatter to the current format, moving file
+    /// name dates to `date` and lowercasing tags
+    MigrateFrontmatter {
+        /// Directory containing the markdown files
+        #[arg(default_value = ".")]
+        path: PathBuf,
+
+        /// Only print what would change
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Make a draft (`published: false`) live: flip the flag, date it now and
+    /// name the file after its title
+    Publish {
+        /// Draft to publish, like `my-draft@1700000000.md`
+        page: String,
+
+        /// Directory containing the markdown files
+        #[arg(short, long, default_value = ".")]
+        dir: PathBuf,
+
+        /// Commit the published page to the git repository of the directory
+        #[arg(short, long)]
+        commit: bool,
+
+        /// Rebuild the site into this directory afterwards, updating the home
+        /// page, feeds and other indexes
         #[arg(short, long)]
         out_dir: Option<PathBuf>,
+
+        #[command(flatten)]
+        render: RenderArgs,
+    },
+    /// Create a new post, named after its title and the current time
+    New {
+        /// Title of the post
+        title: String,
+
+        /// Directory containing the markdown files
+        #[arg(short, long, default_value = ".")]
+        dir: PathBuf,
     },
 }
 
-struct AppState {
-    docs_dir: PathBuf,
-    no_navigation: bool,
+#[derive(Subcommand)]
+enum ThemeCommands {
+    /// Install a theme package from a directory or a git URL
+    Install {
+        /// Theme directory, or git repository to clone
+        source: String,
+
+        /// Directory containing the markdown files
+        #[arg(short, long, default_value = ".")]
+        dir: PathBuf,
+
+        /// Replace an installed theme of the same name
+        #[arg(short, long)]
+        force: bool,
+    },
 }
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    lazy_static::initialize(&TEMPLATES);
-    tracing_subscriber::fmt()
-        .with_max_level(tracing::Level::INFO)
-        .init();
+#[derive(Subcommand)]
+enum ExportCommands {
+    /// Render pages to PDF, through print-ready HTML and `pdf_command`
+    Pdf {
+        /// Path to the directory containing markdown files
+        path: PathBuf,
 
-    let cli = Cli::parse();
+        /// Page to export, like `post.md`. Repeat for several [default: every page]
+        #[arg(short, long = "page", value_name = "PAGE")]
+        pages: Vec<String>,
 
-    match cli.command {
-        Commands::Serve {
-            path,
-            port,
-            host,
-            no_navigation,
-        } => {
-            let abs_path = std::fs::canonicalize(&path)?;
-            let shared_state = Arc::new(AppState {
-                docs_dir: abs_path,
-                no_navigation,
-            });
-            let app = Router::new()
-                .route("/", get(render_summary_handler))
-                .route("/{page}", get(render_page_handler))
-                .route("/style.css", get(serve_css))
-                .with_state(shared_state);
-
-            let addr = if host {
-                format!("0.0.0.0:{}", port)
-            } else {
-                format!("127.0.0.1:{}", port)
-            };
-            let listener = tokio::net::TcpListener::bind(&addr).await?;
-            tracing::info!("Listening on http://{}", addr);
-            axum::serve(listener, app).await?;
-        }
-        Commands::Build {
-            path,
-            no_navigation,
-            out_dir,
-        } => {
-            let abs_path = std::fs::canonicalize(&path)?;
-            let output_path = out_dir.unwrap_or_else(|| abs_path.clone());
-            tokio::fs::create_dir_all(&output_path).await?;
+        /// Put the pages one after the other in a single PDF
+        #[arg(long)]
+        concat: bool,
 
-            run_build(abs_path, output_path, no_navigation).await?;
-        }
-    }
-    Ok(())
+        /// Directory to write a PDF per page to, or with --concat, the PDF file
+        #[arg(short, long)]
+        out: PathBuf,
+
+        /// Command turning HTML into PDF, overriding `pdf_command` [default: weasyprint]
+        #[arg(long)]
+        command: Option<String>,
+
+        #[command(flatten)]
+        render: RenderArgs,
+    },
 }
 
-async fn get_summary_data(docs_dir: &PathBuf) -> Vec<Page> {
-    let mut pages = Vec::new();
-    if let Ok(mut entries) = tokio::fs::read_dir(docs_dir).await {
-        while let Ok(Some(entry)) = entries.next_entry().await {
-            let path = entry.path();
-            if path.extension().and_then(|s| s.to_str()) != Some("md") {
-                continue;
-            }
+/// Rendering flags shared by `serve` and `build`, overriding `blog.toml`
+#[derive(Args)]
+struct RenderArgs {
+    /// Whether the home page and navbar should be removed
+    #[arg(short, long)]
+    no_navigation: bool,
 
-            let filename = entry.file_name();
-            let filename_str = filename.to_str().unwrap_or("");
+    /// Syntax highlighting theme for code blocks [default: Catppuccin Macchiato]
+    #[arg(long)]
+    code_theme: Option<String>,
 
-            let title = if let Ok(file) = tokio::fs::File::open(&path).await {
-                let mut reader = BufReader::new(file);
-                let mut line = String::new();
-                match reader.read_line(&mut line).await {
-                    Ok(_) => line.trim_start_matches('#').trim().to_string(),
-                    Err(_) => filename_str.to_string(),
-                }
-            } else {
-                filename_str.to_string()
-            };
+    /// Custom .tmTheme file to load (used as the code theme unless --code-theme is given)
+    #[arg(long)]
+    theme_file: Option<PathBuf>,
 
-            let datetime = filename_str
-                .split_once('@')
-                .and_then(|(_, ts_with_ext)| ts_with_ext.split('.').next())
-                .map(|dt| dt.to_string())
-                .unwrap_or_else(|| "Invalid Date".to_string());
+    /// Syntax highlighting theme used when the reader prefers a light color scheme
+    #[arg(long)]
+    code_theme_light: Option<String>,
 
-            pages.push(Page {
-                filename: filename_str.to_string(),
-                title,
-                datetime,
-            });
+    /// Number the lines of code blocks, unless a block's fence says `nolinenos`
+    #[arg(long)]
+    line_numbers: bool,
+
+    /// Highlight code with classes styled by code-theme.css instead of inline styles
+    #[arg(long)]
+    highlight_classes: bool,
+
+    /// Show code blocks longer than this many lines collapsed, with a button to expand them
+    #[arg(long)]
+    code_max_lines: Option<usize>,
+
+    /// Where footnote definitions are rendered [default: inline]
+    #[arg(long, value_enum)]
+    footnotes: Option<FootnotePosition>,
+
+    /// Title shown above gathered footnotes
+    #[arg(long)]
+    footnote_title: Option<String>,
+
+    /// Whether to draw a separator above gathered footnotes
+    #[arg(long)]
+    footnote_separator: bool,
+
+    /// BibTeX or CSL-JSON file of the works pages cite as [@key]
+    #[arg(long)]
+    bibliography: Option<String>,
+
+    /// Language of the site, like "fr", whose UI strings the pages show,
+    /// unless building one tree per language
+    #[arg(long)]
+    locale: Option<String>,
+
+    /// Insert non-breaking spaces following the given locale's typography rules
+    #[arg(long, value_enum)]
+    typography: Option<TypographyLocale>,
+
+    /// Public URL of the site, used for canonical links and the sitemap
+    #[arg(long)]
+    base_url: Option<String>,
+
+    /// Write pages to `slug/index.html` and link to `slug/`, without extensions
+    #[arg(long)]
+    pretty_urls: bool,
+
+    /// Write a page with a search for unknown URLs to this file, like 404.html,
+    /// and answer unknown URLs with it when serving
+    #[arg(long)]
+    fallback_page: Option<String>,
+
+    /// Site theme to use, installed with `blog theme install`
+    #[arg(long)]
+    theme: Option<String>,
+
+    /// Number section headings, like "2.3 Installation"
+    #[arg(long)]
+    numbering: bool,
+
+    /// Number of pages sharing tags listed under each page, 0 to list none [default: 3]
+    #[arg(long)]
+    related_pages: Option<usize>,
+
+    /// Show a banner on pages published at least this many years ago
+    #[arg(long)]
+    age_warning_years: Option<u32>,
+
+    /// Order of the prev/next links between pages [default: date]
+    #[arg(long, value_enum)]
+    nav_order: Option<NavOrder>,
+
+    /// strftime format for displayed dates, e.g. "%d/%m/%Y" [default: reader's locale]
+    #[arg(long)]
+    date_format: Option<String>,
+
+    /// IANA timezone for displayed dates, e.g. "Europe/Paris" [default: UTC]
+    #[arg(long)]
+    timezone: Option<String>,
+
+    /// Write resized copies of the images pages show, offered through srcset
+    #[arg(long)]
+    optimize_images: bool,
+
+    /// Copy images with their EXIF and XMP metadata, such as GPS coordinates
+    #[arg(long)]
+    keep_image_metadata: bool,
+
+    /// Load images only when scrolled to, with their size set from the image files
+    #[arg(long)]
+    lazy_images: bool,
+
+    /// Add a "What's new" page and feed of the latest page changes
+    #[arg(long)]
+    changelog: bool,
+
+    /// Also build all.html, every page in one self-contained file with a table of contents
+    #[arg(long)]
+    all_html: bool,
+
+    /// Write a .json file of metadata next to each built page
+    #[arg(long)]
+    page_json: bool,
+
+    /// Limit the main feed to the pages of the last N months, with every
+    /// page in archive.xml
+    #[arg(long, value_name = "N")]
+    feed_months: Option<u32>,
+
+    /// Minify the built HTML and CSS files
+    #[arg(long)]
+    minify: bool,
+
+    /// Inline the built images, fonts and stylesheets smaller than this many
+    /// bytes into the pages using them
+    #[arg(long, value_name = "BYTES")]
+    inline_assets: Option<u64>,
+
+    /// Link pages to copies of the built assets named after their content,
+    /// like style.1a2b3c4d.css, to cache them forever
+    #[arg(long)]
+    fingerprint: bool,
+
+    /// Write .gz and .br copies of the built text files for web servers to
+    /// send precompressed
+    #[arg(long)]
+    precompress: bool,
+
+    /// Sign the built files with this key, created by `blog keygen`
+    #[arg(long, env = "BLOG_SIGNING_KEY")]
+    signing_key: Option<PathBuf>,
+}
+
+impl RenderArgs {
+    /// Merges the flags with the site's `blog.toml` and loads its template
+    /// overrides.
+    fn into_options(self, docs_dir: &Path) -> anyhow::Result<RenderOptions> {
+        let config = Config::load(docs_dir)?;
+        tags::set_tag_aliases(&config.tag_aliases).context(Failure::Config)?;
+        let theme = self.theme.or(config.theme);
+        load_template_overrides(docs_dir, theme.as_deref())?;
+
+        let theme_file = self.theme_file.or(config.theme_file);
+        let code_theme_light = self.code_theme_light.or(config.code_theme_light);
+        if let Some(light) = &code_theme_light {
+            check_code_theme(light)?;
         }
+        let date_format = self.date_format.or(config.date_format);
+        let timezone = self.timezone.or(config.timezone);
+        // Language trees each set theirs
+        let locale = self
+            .locale
+            .or(config.locale)
+            .filter(|_| config.languages.is_empty());
+        let date_display = if date_format.is_some() || timezone.is_some() {
+            Some(DateDisplay::new(date_format, timezone.as_deref())?)
+        } else {
+            None
+        };
+        Ok(RenderOptions {
+            no_navigation: self.no_navigation || config.no_navigation,
+            code_theme: select_code_theme(
+                self.code_theme.or(config.code_theme),
+                theme_file.as_deref(),
+            )?,
+            code_theme_light,
+            line_numbers: self.line_numbers || config.line_numbers,
+            highlight_classes: self.highlight_classes || config.highlight_classes,
+            code_max_lines: self.code_max_lines.or(config.code_max_lines),
+            footnotes: FootnoteOptions {
+                position: self.footnotes.or(config.footnotes).unwrap_or_default(),
+                title: self.footnote_title.or(config.footnote_title),
+                separator: self.footnote_separator || config.footnote_separator,
+            },
+            bibliography: self.bibliography.or(config.bibliography),
+            external_links: ExternalLinkOptions {
+                target: external_link_attribute(config.external_link_target, DEFAULT_TARGET),
+                rel: external_link_attribute(config.external_link_rel, DEFAULT_REL),
+            },
+            typography: self.typography.or(config.typography),
+            menu: config.menu,
+            preprocessors: config
+                .preprocessors
+                .unwrap_or_else(|| vec![Preprocessor::Includes]),
+            variables: config.variables,
+            translations: load_translations(docs_dir, config.translations)?,
+            base_path: String::new(),
+            base_url: self
+                .base_url
+                .or(config.base_url)
+                .map(|url| normalize_base_url(&url))
+                .transpose()?,
+            pretty_urls: self.pretty_urls || config.pretty_urls,
+            theme,
+            languages: check_languages(config.languages)?,
+            language: locale,
+            date_display,
+            nav_order: self.nav_order.or(config.nav_order).unwrap_or_default(),
+            numbering: self.numbering || config.numbering,
+            tag_styles: check_tag_styles(config.tag_styles)?,
+            fallback_page: self
+                .fallback_page
+                .or(config.fallback_page)
+                .map(|page| fallback::check_fallback_page(&page).map(|_| page))
+                .transpose()?,
+            image_optimization: (self.optimize_images || config.optimize_images).then(|| {
+                ImageOptions {
+                    widths: config
+                        .image_widths
+                        .unwrap_or_else(|| DEFAULT_IMAGE_WIDTHS.to_vec()),
+                    formats: config.image_formats,
+                }
+            }),
+            image_sets: Default::default(),
+            strip_image_metadata: !(self.keep_image_metadata || config.keep_image_metadata),
+            lazy_images: self.lazy_images || config.lazy_images,
+            changelog: self.changelog || config.changelog,
+            all_html: self.all_html || config.all_html,
+            page_json: self.page_json || config.page_json,
+            feed_months: match self.feed_months.or(config.feed_months) {
+                Some(0) => anyhow::bail!("feed_months must be at least 1"),
+                months => months,
+            },
+            minify: self.minify || config.minify,
+            inline_assets: self.inline_assets.or(config.inline_assets),
+            fingerprint: self.fingerprint || config.fingerprint,
+            precompress: self.precompress || config.precompress,
+            signing_key: self
+                .signing_key
+                .or(config.signing_key)
+                .map(|key| check_signing_key(docs_dir, key))
+                .transpose()?,
+            websub_hub: config.websub_hub,
+            webmentions: config.webmentions,
+            comments: config
+                .comments
+                .map(|provider| {
+                    let comments = CommentsOptions {
+                        provider,
+                        repo: config.comments_repo.unwrap_or_default(),
+                        repo_id: config.comments_repo_id,
+                        category: config.comments_category,
+                        category_id: config.comments_category_id,
+                        mapping: config.comments_mapping,
+                        label: config.comments_label,
+                        theme_light: config.comments_theme_light,
+                        theme_dark: config.comments_theme_dark,
+                    };
+                    comments.check().map(|_| comments)
+                })
+                .transpose()?,
+            analytics: match (config.analytics, config.analytics_site) {
+                (Some(provider), Some(site)) => Some(AnalyticsOptions {
+                    provider,
+                    site,
+                    script_url: config.analytics_script,
+                }),
+                (Some(_), None) => anyhow::bail!("Analytics need `analytics_site`"),
+                (None, _) => None,
+            },
+            age_warning_years: self.age_warning_years.or(config.age_warning_years),
+            related_pages: self
+                .related_pages
+                .or(config.related_pages)
+                .unwrap_or(DEFAULT_RELATED_PAGES),
+        })
     }
-    pages.sort_by(|a, b| b.datetime.cmp(&a.datetime));
-    pages
 }
 
-async fn render_markdown_to_html(
-    content: &str,
-    filename: &str,
-    docs_dir: &PathBuf,
-    no_navigation: bool,
-    is_static: bool,
-) -> String {
-    let mut options = Options::empty();
-    options.insert(
-        Options::ENABLE_TABLES
-            | Options::ENABLE_FOOTNOTES
-            | Options::ENABLE_STRIKETHROUGH
-            | Options::ENABLE_TASKLISTS,
-    );
-
-    let parser = MarkdownParser::new_ext(content, options);
-    let renderer = CodeblockRenderer::new(parser);
-    let mut html_output = String::new();
-    html::push_html(&mut html_output, renderer);
-
-    let (mut prev, mut next) = if no_navigation {
-        (None, None)
+/// Checks that `url` is absolute and makes it end with `/`, so page names can
+/// be appended to it.
+fn normalize_base_url(url: &str) -> anyhow::Result<String> {
+    let parsed = reqwest::Url::parse(url)
+        .map_err(|e| anyhow::anyhow!("Invalid base URL '{}': {}", url, e))?;
+    let url = parsed.to_string();
+    Ok(if url.ends_with('/') {
+        url
     } else {
-        get_nav_links(docs_dir, filename)
-    };
+        format!("{}/", url)
+    })
+}
 
-    // If building statically, rewrite .md links to .html
-    if is_static {
-        prev = prev.map(|s| {
-            if s == "." {
-                "index.html".to_string()
-            } else {
-                s.replace(".md", ".html")
-            }
-        });
-        next = next.map(|s| s.replace(".md", ".html"));
+/// The configured value of an attribute of external links, an empty one
+/// leaving the attribute out.
+fn external_link_attribute(value: Option<String>, default: &str) -> Option<String> {
+    let value = value.unwrap_or_else(|| default.to_string());
+    (!value.is_empty()).then_some(value)
+}
+
+fn check_tag_styles(
+    styles: BTreeMap<String, TagStyle>,
+) -> anyhow::Result<BTreeMap<String, TagStyle>> {
+    for (tag, style) in &styles {
+        style.check(tag)?;
     }
+    Ok(styles)
+}
 
-    let mut context = Context::new();
-    context.insert("title", filename);
-    context.insert("content", &html_output);
-    context.insert("prev_page", &prev);
-    context.insert("next_page", &next);
-    context.insert("no_navigation", &no_navigation);
-    context.insert("is_static", &is_static);
-
-    TEMPLATES
-        .render("page.html", &context)
-        .unwrap_or_else(|e| format!("Error: {}", e))
+/// The addresses of `--bind`, or the loopback one
+fn bind_addresses(bind: &[String], port: u16) -> anyhow::Result<Vec<SocketAddr>> {
+    if bind.is_empty() {
+        return Ok(vec![SocketAddr::from(([127, 0, 0, 1], port))]);
+    }
+    bind.iter()
+        .map(|addr| parse_bind_address(addr, port))
+        .collect()
 }
 
-async fn run_build(docs_dir: PathBuf, out_dir: PathBuf, no_navigation: bool) -> anyhow::Result<()> {
-    tracing::info!("Building static site to: {:?}", out_dir);
-
-    // Build summary
-    if !no_navigation {
-        let pages = get_summary_data(&docs_dir).await;
-        // Rewrite filenames for static links in home page
-        let static_pages: Vec<Page> = pages
-            .into_iter()
-            .map(|mut p| {
-                p.filename = p.filename.replace(".md", ".html");
-                p
-            })
-            .collect();
-
-        let mut context = Context::new();
-        context.insert("title", "Pages");
-        context.insert("files", &static_pages);
-        context.insert("is_static", &true);
-
-        let rendered = TEMPLATES.render("home.html", &context)?;
-        tokio::fs::write(out_dir.join("index.html"), rendered).await?;
+/// Serves `app` on each of `addrs` until one of the servers fails.
+async fn serve_app(app: axum::Router, addrs: &[SocketAddr], base_path: &str) -> anyhow::Result<()> {
+    let app = app.into_make_service_with_connect_info::<SocketAddr>();
+    let mut servers = tokio::task::JoinSet::new();
+    for addr in addrs {
+        let listener = tokio::net::TcpListener::bind(addr)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to listen on {}: {}", addr, e))?;
+        tracing::info!("Listening on http://{}{}/", addr, base_path);
+        let app = app.clone();
+        servers.spawn(async move { axum::serve(listener, app).await });
+    }
+    while let Some(served) = servers.join_next().await {
+        served??;
     }
+    Ok(())
+}
 
-    // Build css
-    let css = TEMPLATES.render("style.css", &Context::new())?;
-    tokio::fs::write(out_dir.join("style.css"), css).await?;
-
-    // Build pages
-    let mut entries = tokio::fs::read_dir(&docs_dir).await?;
-    while let Some(entry) = entries.next_entry().await? {
-        let path = entry.path();
-        if path.extension().and_then(|s| s.to_str()) == Some("md") {
-            let filename = entry.file_name().to_str().unwrap().to_string();
-            let content = tokio::fs::read_to_string(&path).await?;
-            let rendered =
-                render_markdown_to_html(&content, &filename, &docs_dir, no_navigation, true).await;
-
-            let out_file = out_dir.join(filename.replace(".md", ".html"));
-            tokio::fs::write(out_file, rendered).await?;
-            tracing::info!("Generated {}", filename);
-        }
+/// Parses an address to listen on: an IP address, listened on at `port`, or
+/// an IP address and port, like `127.0.0.1:8080` or `[::1]:8080`.
+fn parse_bind_address(addr: &str, port: u16) -> anyhow::Result<SocketAddr> {
+    if let Ok(addr) = addr.parse::<SocketAddr>() {
+        return Ok(addr);
     }
+    let ip = addr.trim_start_matches('[').trim_end_matches(']');
+    ip.parse::<IpAddr>()
+        .map(|ip| SocketAddr::new(ip, port))
+        .map_err(|_| {
+            anyhow::anyhow!(
+                "Invalid bind address '{}', expected an IP address with an optional port",
+                addr
+            )
+        })
+}
 
-    tracing::info!("Build complete!");
-    Ok(())
+/// Name of the page `--only` refers to, given as a name with or without
+/// `.md`, or as the path of the file
+fn page_name(docs_dir: &std::path::Path, page: &str) -> String {
+    let name = std::fs::canonicalize(page)
+        .ok()
+        .and_then(|path| {
+            path.strip_prefix(docs_dir)
+                .ok()
+                .map(|relative| relative.to_string_lossy().replace('\\', "/"))
+        })
+        .unwrap_or_else(|| page.trim_start_matches("./").to_string());
+    if name.ends_with(".md") {
+        name
+    } else {
+        format!("{}.md", name)
+    }
 }
 
-async fn render_summary_handler(State(state): State<Arc<AppState>>) -> impl IntoResponse {
-    if state.no_navigation {
-        return (StatusCode::NOT_FOUND, "Disabled").into_response();
+/// Makes `path` start with `/` and drops its trailing one, `/` itself
+/// becoming empty, so routes and page names can be appended to it.
+fn normalize_base_path(path: &str) -> anyhow::Result<String> {
+    let trimmed = path.trim_matches('/');
+    if trimmed.is_empty() {
+        return Ok(String::new());
     }
-    let pages = get_summary_data(&state.docs_dir).await;
-    let mut context = Context::new();
-    context.insert("title", "Pages");
-    context.insert("files", &pages);
-    context.insert("is_static", &false);
-
-    match TEMPLATES.render("home.html", &context) {
-        Ok(rendered) => Html(rendered).into_response(),
-        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    let valid = trimmed.split('/').all(|segment| {
+        !matches!(segment, "" | "." | "..")
+            && !segment.contains(['?', '#', '{', '}', '*', '%', ' '])
+    });
+    if !valid {
+        anyhow::bail!("Invalid base path '{}', use a path like /blog", path);
     }
+    Ok(format!("/{}", trimmed))
 }
 
-async fn render_page_handler(
-    State(state): State<Arc<AppState>>,
-    Path(page): Path<String>,
-) -> impl IntoResponse {
-    let filename = if page.ends_with(".md") {
-        page
-    } else {
-        format!("{}.md", page)
-    };
-    let file_path = state.docs_dir.join(&filename);
-
-    match tokio::fs::read_to_string(&file_path).await {
-        Ok(content) => Html(
-            render_markdown_to_html(
-                &content,
-                &filename,
-                &state.docs_dir,
-                state.no_navigation,
-                false,
-            )
-            .await,
-        ),
-        Err(_) => Html("<h1>404</h1><p>Page not found</p>".to_string()),
+/// Checks that the signing key is outside the docs directory, where builds
+/// would publish it along with the assets.
+fn check_signing_key(docs_dir: &Path, key: PathBuf) -> anyhow::Result<PathBuf> {
+    let key = std::path::absolute(&key)?;
+    if key.starts_with(docs_dir) {
+        anyhow::bail!(
+            "The signing key {:?} must not be in the docs directory, builds would publish it",
+            key
+        );
     }
+    Ok(key)
 }
 
-async fn serve_css() -> impl IntoResponse {
-    match TEMPLATES.render("style.css", &Context::new()) {
-        Ok(css) => Response::builder()
-            .header("content-type", "text/css")
-            .body(css.into())
-            .unwrap(),
-        Err(_) => (StatusCode::NOT_FOUND, "CSS not found").into_response(),
+/// Checks that language codes are fit for file names and URLs.
+fn check_languages(languages: Vec<String>) -> anyhow::Result<Vec<String>> {
+    for language in &languages {
+        let valid = !language.is_empty()
+            && language
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-');
+        if !valid {
+            anyhow::bail!("Invalid language code '{}'", language);
+        }
     }
+    Ok(languages)
 }
 
-// Helper model for Tera
-mod ax_models {
-    use serde::{Deserialize, Serialize};
-    #[derive(Deserialize, Serialize, Clone)]
-    pub struct Page {
-        pub filename: String,
-        pub title: String,
-        pub datetime: String,
+fn main() -> ExitCode {
+    lazy_static::initialize(&TEMPLATES);
+    let cli = Cli::parse();
+    // Logs go to stderr, leaving stdout to what commands print
+    let subscriber = tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .with_max_level(if cli.verbose {
+            tracing::Level::DEBUG
+        } else {
+            tracing::Level::INFO
+        });
+    match cli.log_format {
+        LogFormat::Text => subscriber.init(),
+        LogFormat::Json => subscriber
+            .json()
+            .with_current_span(true)
+            .with_span_list(false)
+            .init(),
+    }
+
+    match start(cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {:?}", e);
+            ExitCode::from(exit::exit_code(&e))
+        }
     }
 }
 
-fn get_nav_links(dir: &PathBuf, current_file: &str) -> (Option<String>, Option<String>) {
-    let mut files: Vec<String> = std::fs::read_dir(dir)
-        .unwrap()
-        .filter_map(|entry| {
-            let path = entry.ok()?.path();
-            if path.extension()? == "md" && path.file_name()? != "SUMMARY.md" {
-                Some(path.file_name()?.to_str()?.to_string())
+fn start(cli: Cli) -> anyhow::Result<()> {
+    let config = match &cli.command {
+        Commands::Serve { path, .. } | Commands::Build { path, .. } => Config::load(path)?,
+        _ => Config::default(),
+    };
+    cli.runtime
+        .build_runtime(&config)?
+        .block_on(run(cli.command))
+}
+
+/// Prints what a command found as pretty JSON.
+fn print_json(value: &impl Serialize) -> anyhow::Result<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+async fn run(command: Commands) -> anyhow::Result<()> {
+    match command {
+        Commands::Serve {
+            path,
+            port,
+            bind,
+            base_path,
+            trust_proxy,
+            webdav_url,
+            webdav_user,
+            webdav_password,
+            database,
+            graphql,
+            live_preview,
+            access_log,
+            purge_hook_secret,
+            cdn_purge_token,
+            render,
+        } => {
+            let abs_path = std::fs::canonicalize(&path)?;
+            let config = Config::load(&abs_path)?;
+            let access_log = access_log.or(config.access_log).unwrap_or_default();
+            let bind = if bind.is_empty() { config.bind } else { bind };
+            let addrs = bind_addresses(&bind, port)?;
+            let cdn = CdnOptions {
+                max_age: config.cache_max_age,
+                surrogate_max_age: config.surrogate_max_age,
+                stale_while_revalidate: config.stale_while_revalidate,
+                purge_url: config.cdn_purge_url,
+                purge_body: config.cdn_purge_body,
+                purge_token: cdn_purge_token,
+                hook_secret: purge_hook_secret,
+            };
+            let mut options = render.into_options(&abs_path).context(Failure::Config)?;
+            if let Some(base_path) = base_path.or(config.base_path) {
+                options.base_path = normalize_base_path(&base_path)?;
+            }
+            let pings = Pings::new(&options, config.ping_urls);
+            let store: Arc<dyn ContentStore> = match webdav_url {
+                Some(url) => {
+                    let credentials =
+                        webdav_user.map(|user| (user, webdav_password.unwrap_or_default()));
+                    tracing::info!("Reading pages from {}", url);
+                    Arc::new(WebDavStore::new(&url, credentials)?)
+                }
+                None => Arc::new(LocalStore::new(abs_path)),
+            };
+            let db = database.as_deref().map(Database::open).transpose()?;
+            if let Some(pings) = pings {
+                tokio::spawn(pings::watch(store.clone(), pings));
+            }
+            let shared_state = Arc::new(AppState {
+                store,
+                options,
+                db,
+                graphql,
+                live_preview,
+                generation: Default::default(),
+            });
+            let base_path = shared_state.options.base_path.clone();
+            let app = with_cdn(blog_router(shared_state), cdn);
+            let app = with_base_path(app, &base_path);
+            let app = with_access_log(app, access_log);
+            let app = with_client_info(app, trust_proxy || config.trust_proxy);
+
+            serve_app(app, &addrs, &base_path).await?;
+        }
+        Commands::ServeStatic { path, port, bind } => {
+            let dir = std::fs::canonicalize(&path)?;
+            let addrs = bind_addresses(&bind, port)?;
+            tracing::info!("Serving {:?}", dir);
+            let app = with_access_log(static_router(dir), AccessLog::default());
+            serve_app(app, &addrs, "").await?;
+        }
+        Commands::Build {
+            path,
+            out_dir,
+            prune_unused_assets,
+            no_ping,
+            only,
+            render,
+        } => {
+            let abs_path = std::fs::canonicalize(&path)?;
+            let options = render.into_options(&abs_path).context(Failure::Config)?;
+            let output_path = out_dir.unwrap_or_else(|| abs_path.clone());
+            tokio::fs::create_dir_all(&output_path).await?;
+            let output_path = std::fs::canonicalize(&output_path)?;
+
+            let config = Config::load(&abs_path)?;
+            match only {
+                Some(page) => {
+                    let page = page_name(&abs_path, &page);
+                    run_build_page(abs_path.clone(), output_path, &options, &page)
+                        .await
+                        .context(Failure::Build)?
+                }
+                None => run_build(abs_path.clone(), output_path, &options, prune_unused_assets)
+                    .await
+                    .context(Failure::Build)?,
+            }
+            if !no_ping && let Some(pings) = Pings::new(&options, config.ping_urls) {
+                pings.send().await;
+            }
+            if !no_ping && options.webmentions {
+                webmentions::send_webmentions(&abs_path, &options).await?;
+            }
+        }
+        Commands::Diff {
+            path,
+            out_dir,
+            content,
+            output,
+            prune_unused_assets,
+            render,
+        } => {
+            let abs_path = std::fs::canonicalize(&path)?;
+            let options = render.into_options(&abs_path).context(Failure::Config)?;
+            let output_path = match out_dir {
+                Some(dir) => std::path::absolute(dir)?,
+                None => abs_path.clone(),
+            };
+
+            let report = diff::run_diff(
+                abs_path,
+                output_path,
+                &options,
+                prune_unused_assets,
+                content,
+            )
+            .await
+            .context(Failure::Build)?;
+            if output == OutputFormat::Json {
+                print_json(&report)?;
+            } else if report.is_empty() {
+                println!("No changes");
             } else {
-                None
+                print!("{}", report);
             }
-        })
-        .collect();
-
-    files.sort();
-    let pos = files.iter().position(|f| f == current_file);
-    match pos {
-        Some(i) => {
-            let prev = if i == 0 {
-                Some(".".to_string())
+        }
+        Commands::Check {
+            path,
+            no_navigation,
+            output,
+        } => {
+            let abs_path = std::fs::canonicalize(&path)?;
+            let config = Config::load(&abs_path)?;
+            tags::set_tag_aliases(&config.tag_aliases).context(Failure::Config)?;
+            let no_navigation = no_navigation || config.no_navigation;
+            let report = check::run_check(&abs_path, no_navigation).await?;
+
+            if output == OutputFormat::Json {
+                print_json(&report)?;
+            } else {
+                for page in &report.orphans {
+                    println!("orphan page: {}", page);
+                }
+                for asset in &report.unused_assets {
+                    println!("unused asset: {}", asset.display());
+                }
+                for (page, reason) in &report.invalid_dates {
+                    println!("invalid date: {}: {}", page, reason);
+                }
+                for image in &report.missing_alt {
+                    println!(
+                        "missing alt text: {}:{}: {}",
+                        image.page, image.line, image.url
+                    );
+                }
+            }
+            if !report.is_empty() {
+                return Err(anyhow::anyhow!(
+                    "Found {} orphan pages, {} unused assets, {} invalid dates and {} images without alt text",
+                    report.orphans.len(),
+                    report.unused_assets.len(),
+                    report.invalid_dates.len(),
+                    report.missing_alt.len()
+                )
+                .context(Failure::Problems));
+            }
+            if output == OutputFormat::Text {
+                println!("No problems found");
+            }
+        }
+        Commands::CheckLinks {
+            path,
+            external,
+            output,
+        } => {
+            let abs_path = std::fs::canonicalize(&path)?;
+            let broken = check::check_links(&abs_path, external).await?;
+
+            if output == OutputFormat::Json {
+                print_json(&broken)?;
             } else {
-                files.get(i - 1).cloned()
+                for link in &broken {
+                    println!(
+                        "{}:{}: {} ({})",
+                        link.page, link.line, link.url, link.reason
+                    );
+                }
+            }
+            if !broken.is_empty() {
+                return Err(anyhow::anyhow!("Found {} broken links", broken.len())
+                    .context(Failure::Problems));
+            }
+            if output == OutputFormat::Text {
+                println!("No broken links found");
+            }
+        }
+        Commands::AltText { path, command } => {
+            let abs_path = std::fs::canonicalize(&path)?;
+            let command = command.or(Config::load(&abs_path)?.alt_text_command);
+            let filled = alt_text::fill_missing_alt(&abs_path, command.as_deref()).await?;
+            println!("Wrote {} alt texts", filled);
+        }
+        Commands::Audit { path, output } => {
+            let abs_path = std::fs::canonicalize(&path)?;
+            let theme = Config::load(&abs_path)?.theme;
+            load_template_overrides(&abs_path, theme.as_deref())?;
+            let findings = audit::audit_templates(&abs_path, theme.as_deref())?;
+
+            if output == OutputFormat::Json {
+                print_json(&findings)?;
+            } else {
+                for finding in &findings {
+                    println!("{}", finding);
+                }
+            }
+            if !findings.is_empty() {
+                return Err(anyhow::anyhow!("Found {} template issues", findings.len())
+                    .context(Failure::Problems));
+            }
+            if output == OutputFormat::Text {
+                println!("No problems found");
+            }
+        }
+        Commands::Clean { path, out_dir } => {
+            let abs_path = std::fs::canonicalize(&path)?;
+            let output_path = out_dir.unwrap_or_else(|| abs_path.clone());
+
+            run_clean(abs_path, output_path).await?;
+        }
+        Commands::Stats { path, output } => {
+            let abs_path = std::fs::canonicalize(&path)?;
+            tags::set_tag_aliases(&Config::load(&abs_path)?.tag_aliases)
+                .context(Failure::Config)?;
+            let stats = stats::collect_stats(&abs_path).await;
+            match output {
+                OutputFormat::Text => print!("{}", stats),
+                OutputFormat::Json => print_json(&stats)?,
+            }
+        }
+        Commands::Init { path, templates } => {
+            scaffold::init_site(&path, templates)?;
+        }
+        Commands::Keygen { path } => {
+            let public_key = signing::generate_key(&path)?;
+            println!("Wrote the signing key to {}", path.display());
+            println!("Public key: {}", public_key);
+        }
+        Commands::Verify { path, public_key } => {
+            let problems = signing::verify_tree(&path, &public_key)?;
+            for problem in &problems {
+                println!("{}", problem);
+            }
+            if !problems.is_empty() {
+                return Err(anyhow::anyhow!(
+                    "Found {} files not matching the signed manifest",
+                    problems.len()
+                )
+                .context(Failure::Problems));
+            }
+            println!("Every file matches the signed manifest");
+        }
+        Commands::Templates { export, force } => {
+            scaffold::export_templates(&export, force)?;
+        }
+        Commands::Theme {
+            command: ThemeCommands::Install { source, dir, force },
+        } => {
+            let name = themes::install_theme(&dir, &source, force)?;
+            println!(
+                "Installed theme '{}', enable it with `theme = \"{}\"` in {}",
+                name, name, CONFIG_FILE
+            );
+        }
+        Commands::Export {
+            command:
+                ExportCommands::Pdf {
+                    path,
+                    pages,
+                    concat,
+                    out,
+                    command,
+                    render,
+                },
+        } => {
+            let abs_path = std::fs::canonicalize(&path)?;
+            let options = render.into_options(&abs_path).context(Failure::Config)?;
+            let command = command
+                .or(Config::load(&abs_path)?.pdf_command)
+                .unwrap_or_else(|| pdf::DEFAULT_PDF_COMMAND.to_string());
+            let pages: Vec<String> = pages
+                .iter()
+                .map(|page| page_name(&abs_path, page))
+                .collect();
+            let written = pdf::export_pdf(&abs_path, &options, &pages, concat, &out, &command)
+                .await
+                .context(Failure::Build)?;
+            for file in written {
+                println!("Wrote {}", file.display());
+            }
+        }
+        Commands::Snapshot {
+            source,
+            out_dir,
+            render,
+        } => {
+            let base = if source.starts_with("http://") || source.starts_with("https://") {
+                reqwest::Url::parse(&source)
+                    .map_err(|e| anyhow::anyhow!("Invalid URL {}: {}", source, e))?
+            } else {
+                let abs_path = std::fs::canonicalize(&source)?;
+                let options = render.into_options(&abs_path).context(Failure::Config)?;
+                let state = Arc::new(AppState {
+                    store: Arc::new(LocalStore::new(abs_path)),
+                    options,
+                    db: None,
+                    graphql: false,
+                    live_preview: false,
+                    generation: Default::default(),
+                });
+                snapshot::serve_locally(blog_router(state)).await?
             };
-            let next = files.get(i + 1).cloned();
-            (prev, next)
+            let written = snapshot::snapshot(&base, &out_dir)
+                .await
+                .context(Failure::Build)?;
+            println!("Wrote {} files to {}", written.len(), out_dir.display());
+        }
+        Commands::Sync { path, remote } => {
+            let abs_path = std::fs::canonicalize(&path)?;
+            let remote = Remote::new(&abs_path, remote.or(Config::load(&abs_path)?.sync_remote))?;
+            sync::sync(&abs_path, &remote)?;
+        }
+        Commands::MigrateFrontmatter { path, dry_run } => {
+            let abs_path = std::fs::canonicalize(&path)?;
+            let migrations = migrate::migrate_frontmatter(&abs_path, dry_run)?;
+            if migrations.is_empty() {
+                println!("Every page is up to date");
+            }
+            for migration in migrations {
+                if migration.to == migration.from {
+                    println!("{}: {}", migration.from, migration.changes.join(", "));
+                } else {
+                    println!(
+                        "{} -> {}: {}",
+                        migration.from,
+                        migration.to,
+                        migration.changes.join(", ")
+                    );
+                }
+            }
+        }
+        Commands::Publish {
+            page,
+            dir,
+            commit,
+            out_dir,
+            render,
+        } => {
+            let abs_path = std::fs::canonicalize(&dir)?;
+            let published = publish::publish(&abs_path, &page, commit)?;
+            if published.to == published.from {
+                println!("Published {}", published.to);
+            } else {
+                println!("Published {} as {}", published.from, published.to);
+            }
+
+            match out_dir {
+                Some(out_dir) => {
+                    let options = render.into_options(&abs_path).context(Failure::Config)?;
+                    tokio::fs::create_dir_all(&out_dir).await?;
+                    let output_path = std::fs::canonicalize(&out_dir)?;
+                    let config = Config::load(&abs_path)?;
+                    run_build(abs_path.clone(), output_path, &options, false)
+                        .await
+                        .context(Failure::Build)?;
+                    if let Some(pings) = Pings::new(&options, config.ping_urls) {
+                        pings.send().await;
+                    }
+                    if options.webmentions {
+                        webmentions::send_webmentions(&abs_path, &options).await?;
+                    }
+                }
+                None => println!("Run `blog build` to update the site"),
+            }
+        }
+        Commands::New { title, dir } => {
+            scaffold::new_post(&dir, &title)?;
         }
-        None => (None, None),
     }
+    Ok(())
 }
@@ -0,0 +1,158 @@
+use serde::{Deserialize, Serialize};
+
+use crate::page_url;
+
+/// An entry of the navbar, see `[[menu]]`
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct MenuEntry {
+    pub title: String,
+    /// A page like "about.md", "" for the home page, or a web URL
+    pub link: String,
+    /// Makes the entry a section holding the pages with one of these tags
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+impl MenuEntry {
+    fn is_external(&self) -> bool {
+        self.link.contains("://")
+    }
+
+    fn page(&self) -> &str {
+        self.link.trim_start_matches("./").trim_start_matches('/')
+    }
+
+    /// Whether the page is in the entry's section
+    fn holds(&self, page: &CurrentPage) -> bool {
+        self.tags.iter().any(|tag| page.tags.contains(tag))
+    }
+
+    /// Whether the entry leads to `location`
+    fn leads_to(&self, location: &Location) -> bool {
+        if self.is_external() {
+            return false;
+        }
+        match location {
+            Location::Home => self.page().is_empty(),
+            Location::Page(page) => self.page() == page.filename || self.holds(page),
+            Location::Elsewhere => false,
+        }
+    }
+}
+
+/// Where the page being rendered is
+pub enum Location<'a> {
+    Home,
+    Page(CurrentPage<'a>),
+    /// Generated pages such as "What's new", which no entry leads to
+    Elsewhere,
+}
+
+/// A page of the docs directory, as the menu needs it
+pub struct CurrentPage<'a> {
+    pub filename: &'a str,
+    pub title: &'a str,
+    pub tags: &'a [String],
+}
+
+/// A menu entry as templates get it
+#[derive(Serialize, Debug, Clone)]
+pub struct MenuItem {
+    pub title: String,
+    pub url: String,
+    /// Whether the page being rendered is this entry, or in its section
+    pub is_active: bool,
+}
+
+/// A step of the breadcrumbs trail, from the home page down to the current page
+#[derive(Serialize, Debug, Clone)]
+pub struct Crumb {
+    /// Page of the docs directory, empty for the home page and web URLs
+    pub filename: String,
+    pub title: String,
+    pub url: String,
+}
+
+/// Where a page stands in the site, set in the template context as `menu`
+/// and `breadcrumbs` so navbars can highlight it without comparing URLs.
+pub struct SiteNavigation {
+    pub menu: Vec<MenuItem>,
+    pub breadcrumbs: Vec<Crumb>,
+    /// Title of the section entry holding the page, if any
+    pub section: Option<String>,
+}
+
+impl SiteNavigation {
+    /// Navigation from `location`, where `root` and `home_url` are those of
+    /// `link_roots`.
+    pub fn new(
+        entries: &[MenuEntry],
+        location: &Location,
+        root: &str,
+        home_url: &str,
+        is_static: bool,
+        pretty_urls: bool,
+    ) -> Self {
+        let url = |entry: &MenuEntry| {
+            if entry.is_external() {
+                entry.link.clone()
+            } else if entry.page().is_empty() {
+                home_url.to_string()
+            } else {
+                format!("{}{}", root, page_url(entry.page(), is_static, pretty_urls))
+            }
+        };
+        let menu = entries
+            .iter()
+            .map(|entry| MenuItem {
+                title: entry.title.clone(),
+                url: url(entry),
+                is_active: entry.leads_to(location),
+            })
+            .collect();
+
+        let mut breadcrumbs = vec![Crumb {
+            filename: String::new(),
+            title: "Home".to_string(),
+            url: home_url.to_string(),
+        }];
+        let mut section = None;
+        if let Location::Page(page) = location {
+            if let Some(entry) = entries
+                .iter()
+                .find(|entry| entry.holds(page) && entry.page() != page.filename)
+            {
+                breadcrumbs.push(Crumb {
+                    filename: if entry.is_external() {
+                        String::new()
+                    } else {
+                        entry.page().to_string()
+                    },
+                    title: entry.title.clone(),
+                    url: url(entry),
+                });
+                section = Some(entry.title.clone());
+            }
+            breadcrumbs.push(Crumb {
+                filename: page.filename.to_string(),
+                title: page.title.to_string(),
+                url: format!(
+                    "{}{}",
+                    root,
+                    page_url(page.filename, is_static, pretty_urls)
+                ),
+            });
+        }
+        Self {
+            menu,
+            breadcrumbs,
+            section,
+        }
+    }
+
+    pub fn insert_into(&self, context: &mut tera::Context) {
+        context.insert("menu", &self.menu);
+        context.insert("breadcrumbs", &self.breadcrumbs);
+    }
+}
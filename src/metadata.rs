@@ -0,0 +1,66 @@
+use serde::Serialize;
+
+use crate::excerpt;
+use crate::frontmatter::Frontmatter;
+
+/// How a page presents itself outside its own body: in listings, feeds, the
+/// APIs, the search index and link previews. They all get it from
+/// `PageMetadata::resolve`, so frontmatter overrides apply the same way
+/// everywhere.
+#[derive(Serialize, Debug, Clone)]
+pub struct PageMetadata {
+    /// First line of the body, else the file name
+    pub title: String,
+    /// `description`, else the excerpt of the body, see `excerpt::excerpt`
+    pub excerpt: Option<String>,
+    /// `og_title`, else `title`
+    pub social_title: String,
+    /// `og_description`, else `excerpt`
+    pub social_description: Option<String>,
+    /// `og_image`, else `cover`: a path in the docs directory or a web URL
+    pub image: Option<String>,
+}
+
+impl PageMetadata {
+    pub fn resolve(filename: &str, meta: &Frontmatter, body: &str) -> Self {
+        let title = body
+            .lines()
+            .find(|line| !line.trim().is_empty())
+            .map(|line| line.trim_start_matches('#').trim().to_string())
+            .unwrap_or_else(|| filename.to_string());
+        let excerpt = excerpt::excerpt(meta, body);
+        Self {
+            social_title: meta.og_title.clone().unwrap_or_else(|| title.clone()),
+            social_description: meta.og_description.clone().or_else(|| excerpt.clone()),
+            title,
+            excerpt,
+            image: meta
+                .og_image
+                .as_deref()
+                .or(meta.cover.as_deref())
+                .map(image_path),
+        }
+    }
+}
+
+/// Web URLs as is, paths relative to the docs directory
+fn image_path(image: &str) -> String {
+    if image.contains("://") {
+        image.to_string()
+    } else {
+        image
+            .trim_start_matches("./")
+            .trim_start_matches('/')
+            .to_string()
+    }
+}
+
+/// Link to a page image as resolved by `PageMetadata`: absolute under
+/// `base_url` when there is one, as link previews and feeds need, else
+/// relative to `root`, the way back to the site root.
+pub fn image_url(image: &str, base_url: Option<&str>, root: &str) -> String {
+    if image.contains("://") {
+        return image.to_string();
+    }
+    format!("{}{}", base_url.unwrap_or(root), image)
+}
@@ -0,0 +1,133 @@
+use chrono::DateTime;
+use serde_yaml::{Mapping, Value};
+use std::path::Path;
+
+use crate::frontmatter;
+use crate::store::write_atomic;
+
+/// Directory of the docs directory holding the originals of migrated pages
+pub const BACKUP_DIR: &str = ".frontmatter-backup";
+
+/// A page whose frontmatter `migrate_frontmatter` rewrote
+pub struct Migration {
+    pub from: String,
+    pub to: String,
+    /// What changed, like "moved the file name date to `date`"
+    pub changes: Vec<String>,
+}
+
+/// Rewrites the frontmatter of every page of `docs_dir` to the current
+/// format: `slug@timestamp.md` dates move to a `date` field, dropping the
+/// timestamp from the file name, and tags are lowercased without duplicates.
+/// Originals are copied to a timestamped directory of `BACKUP_DIR` first.
+/// With `dry_run`, only reports what would change.
+pub fn migrate_frontmatter(docs_dir: &Path, dry_run: bool) -> anyhow::Result<Vec<Migration>> {
+    let mut filenames: Vec<String> = std::fs::read_dir(docs_dir)?
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter(|name| name.ends_with(".md"))
+        .collect();
+    filenames.sort();
+
+    let backup_dir = docs_dir
+        .join(BACKUP_DIR)
+        .join(chrono::Utc::now().format("%Y%m%d-%H%M%S").to_string());
+    let mut migrations = Vec::new();
+    for filename in filenames {
+        let content = std::fs::read_to_string(docs_dir.join(&filename))?;
+        let (migration, migrated) = match migrate_page(&filename, &content) {
+            Ok(Some(migrated)) => migrated,
+            Ok(None) => continue,
+            Err(e) => {
+                tracing::warn!("Skipping {}: {}", filename, e);
+                continue;
+            }
+        };
+
+        let target = docs_dir.join(&migration.to);
+        if migration.to != migration.from && target.exists() {
+            tracing::warn!(
+                "Skipping {}: {} already exists",
+                migration.from,
+                migration.to
+            );
+            continue;
+        }
+        if !dry_run {
+            std::fs::create_dir_all(&backup_dir)?;
+            std::fs::copy(
+                docs_dir.join(&migration.from),
+                backup_dir.join(&migration.from),
+            )?;
+            write_atomic(&target, migrated.as_bytes())?;
+            if migration.to != migration.from {
+                std::fs::remove_file(docs_dir.join(&migration.from))?;
+            }
+        }
+        migrations.push(migration);
+    }
+
+    if !dry_run && !migrations.is_empty() {
+        tracing::info!("Backed up the original pages to {:?}", backup_dir);
+    }
+    Ok(migrations)
+}
+
+/// The migration of one page and its new content, or `None` when it is
+/// already in the current format.
+fn migrate_page(filename: &str, content: &str) -> anyhow::Result<Option<(Migration, String)>> {
+    let (mut meta, body) = match frontmatter::split(content) {
+        Some((yaml, body)) => (
+            serde_yaml::from_str::<Option<Mapping>>(yaml)?.unwrap_or_default(),
+            body,
+        ),
+        None => (Mapping::new(), content),
+    };
+    let mut changes = Vec::new();
+
+    // Move the file name date to the frontmatter
+    let mut to = filename.to_string();
+    if let Some((slug, rest)) = filename.split_once('@') {
+        let (timestamp, extension) = rest.split_once('.').unwrap_or((rest, "md"));
+        let date = timestamp
+            .parse()
+            .ok()
+            .and_then(|ts| DateTime::from_timestamp(ts, 0))
+            .ok_or_else(|| anyhow::anyhow!("invalid timestamp '{}' in file name", timestamp))?;
+        if meta.contains_key("date") {
+            changes.push("dropped the file name date, `date` is set".to_string());
+        } else {
+            meta.insert("date".into(), date.to_rfc3339().into());
+            changes.push("moved the file name date to `date`".to_string());
+        }
+        to = format!("{}.{}", slug, extension);
+    }
+
+    // Normalize tags
+    if let Some(Value::Sequence(tags)) = meta.get_mut("tags") {
+        let mut normalized: Vec<Value> = Vec::new();
+        for tag in tags.iter() {
+            let tag = match tag {
+                Value::String(tag) => Value::String(tag.trim().to_lowercase()),
+                other => other.clone(),
+            };
+            if !normalized.contains(&tag) {
+                normalized.push(tag);
+            }
+        }
+        if normalized != *tags {
+            *tags = normalized;
+            changes.push("normalized tags".to_string());
+        }
+    }
+
+    if changes.is_empty() {
+        return Ok(None);
+    }
+    let migrated = format!("---\n{}---\n{}", serde_yaml::to_string(&meta)?, body);
+    let migration = Migration {
+        from: filename.to_string(),
+        to,
+        changes,
+    };
+    Ok(Some((migration, migrated)))
+}
@@ -0,0 +1,385 @@
+use std::path::{Path, PathBuf};
+
+/// Elements whose text keeps its whitespace
+const PREFORMATTED: &[&str] = &["pre", "textarea"];
+
+/// Elements whose content isn't HTML
+const RAW_TEXT: &[&str] = &["script", "style"];
+
+/// Elements the whitespace around which doesn't render
+const BLOCKS: &[&str] = &[
+    "!doctype",
+    "html",
+    "head",
+    "body",
+    "title",
+    "meta",
+    "link",
+    "base",
+    "script",
+    "style",
+    "noscript",
+    "template",
+    "nav",
+    "header",
+    "footer",
+    "main",
+    "article",
+    "section",
+    "aside",
+    "div",
+    "p",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "ul",
+    "ol",
+    "li",
+    "dl",
+    "dt",
+    "dd",
+    "table",
+    "caption",
+    "colgroup",
+    "thead",
+    "tbody",
+    "tfoot",
+    "tr",
+    "th",
+    "td",
+    "figure",
+    "figcaption",
+    "blockquote",
+    "details",
+    "summary",
+    "form",
+    "fieldset",
+    "pre",
+    "hr",
+];
+
+/// Minifies the HTML and CSS files of `out_dir` in place, for builds with
+/// `minify`. Returns how many bytes were saved.
+pub fn minify_tree(out_dir: &Path) -> anyhow::Result<usize> {
+    let mut files = Vec::new();
+    collect_files(out_dir, &mut files)?;
+    let mut saved = 0;
+    for path in &files {
+        let content = std::fs::read_to_string(path)?;
+        let minified = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("css") => minify_css(&content),
+            _ => minify_html(&content),
+        };
+        if minified.len() < content.len() {
+            saved += content.len() - minified.len();
+            std::fs::write(path, minified)?;
+        }
+    }
+    tracing::info!(
+        "Minified {} files, saving {} KiB",
+        files.len(),
+        saved / 1024
+    );
+    Ok(saved)
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> anyhow::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        if entry.file_type()?.is_dir() {
+            collect_files(&path, files)?;
+        } else if matches!(
+            path.extension().and_then(|extension| extension.to_str()),
+            Some("html" | "css")
+        ) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Drops comments and the whitespace that doesn't render, keeps the text of
+/// `<pre>` as is, and minifies `<style>` elements and `style` attributes.
+/// Adjacent spans with the same attributes, as highlighted code is full of,
+/// are merged.
+pub fn minify_html(html: &str) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+    // Whitespace was skipped since the last thing written
+    let mut pending_space = false;
+    // Whitespace next to what was last written doesn't render
+    let mut after_block = true;
+    let mut preformatted = 0usize;
+    let mut open_spans: Vec<String> = Vec::new();
+    // Where the last `</span>` was written, and the tag it closed
+    let mut closed_span: Option<(usize, String)> = None;
+
+    while let Some(c) = rest.chars().next() {
+        if c == '<' {
+            if let Some(comment) = rest.strip_prefix("<!--") {
+                match comment.find("-->") {
+                    Some(end) => rest = &comment[end + 3..],
+                    None => {
+                        out.push_str(rest);
+                        rest = "";
+                    }
+                }
+                continue;
+            }
+            if let Some((tag, name, closing)) = split_tag(rest) {
+                rest = &rest[tag.len()..];
+                let tag = minify_tag(tag);
+                let is_block = BLOCKS.contains(&name.as_str());
+
+                if name == "span"
+                    && !closing
+                    && !pending_space
+                    && let Some((position, opening)) = closed_span.take()
+                    && out.len() == position + "</span>".len()
+                    && opening == tag
+                    && !tag.contains(" id=")
+                {
+                    out.truncate(position);
+                    open_spans.push(tag);
+                    continue;
+                }
+                closed_span = None;
+
+                if pending_space && !after_block && !is_block {
+                    out.push(' ');
+                }
+                pending_space = false;
+                if name == "span" {
+                    if closing {
+                        closed_span = open_spans.pop().map(|opening| (out.len(), opening));
+                    } else {
+                        open_spans.push(tag.clone());
+                    }
+                }
+                out.push_str(&tag);
+                after_block = is_block;
+
+                if PREFORMATTED.contains(&name.as_str()) {
+                    preformatted = if closing {
+                        preformatted.saturating_sub(1)
+                    } else {
+                        preformatted + 1
+                    };
+                }
+                if RAW_TEXT.contains(&name.as_str()) && !closing {
+                    let end = find_closing(rest, &name).unwrap_or(rest.len());
+                    let content = &rest[..end];
+                    if name == "style" {
+                        out.push_str(&minify_css(content));
+                    } else {
+                        out.push_str(content.trim());
+                    }
+                    rest = &rest[end..];
+                }
+                continue;
+            }
+        }
+
+        rest = &rest[c.len_utf8()..];
+        closed_span = None;
+        if preformatted > 0 {
+            out.push(c);
+            after_block = false;
+        } else if c.is_whitespace() {
+            pending_space = true;
+        } else {
+            if pending_space && !after_block {
+                out.push(' ');
+            }
+            pending_space = false;
+            out.push(c);
+            after_block = false;
+        }
+    }
+    out
+}
+
+/// The tag `html` starts with, its lowercase name and whether it closes an
+/// element, `None` if the `<` doesn't start a tag
+fn split_tag(html: &str) -> Option<(&str, String, bool)> {
+    let after = &html[1..];
+    let (closing, name_start) = match after.strip_prefix('/') {
+        Some(name) => (true, name),
+        None => (false, after),
+    };
+    let first = name_start.chars().next()?;
+    if !first.is_ascii_alphabetic() && first != '!' {
+        return None;
+    }
+    let name_end = name_start
+        .find(|c: char| c.is_whitespace() || c == '>' || c == '/')
+        .unwrap_or(name_start.len());
+    let name = name_start[..name_end].to_ascii_lowercase();
+
+    let mut quote = None;
+    for (i, c) in html.char_indices().skip(1) {
+        match (quote, c) {
+            (None, '"' | '\'') if !closing && first != '!' => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, '>') => return Some((&html[..=i], name, closing)),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Start of the tag closing the `name` element in `html`
+fn find_closing(html: &str, name: &str) -> Option<usize> {
+    let lowercase = html.to_ascii_lowercase();
+    let closing = format!("</{}", name);
+    lowercase.find(&closing)
+}
+
+/// `tag` with single spaces between its attributes, and its `style`
+/// attribute minified
+fn minify_tag(tag: &str) -> String {
+    let inner = &tag[1..tag.len() - 1];
+    let name_end = inner.find(char::is_whitespace).unwrap_or(inner.len());
+    let mut out = String::with_capacity(tag.len());
+    out.push('<');
+    out.push_str(&inner[..name_end]);
+
+    let mut rest = inner[name_end..].trim_start();
+    while let Some(first) = rest.chars().next() {
+        let name_end = rest[first.len_utf8()..]
+            .find(|c: char| c.is_whitespace() || c == '=')
+            .map_or(rest.len(), |end| end + first.len_utf8());
+        let name = &rest[..name_end];
+        out.push(' ');
+        out.push_str(name);
+        rest = rest[name_end..].trim_start();
+
+        if let Some(value) = rest.strip_prefix('=') {
+            let value = value.trim_start();
+            out.push('=');
+            match value.chars().next() {
+                Some(quote @ ('"' | '\'')) => {
+                    let end = value[1..].find(quote).map_or(value.len(), |end| end + 1);
+                    let content = &value[1..end];
+                    out.push(quote);
+                    if name.eq_ignore_ascii_case("style") {
+                        out.push_str(&minify_declarations(content));
+                    } else {
+                        out.push_str(content);
+                    }
+                    out.push(quote);
+                    rest = value.get(end + 1..).unwrap_or_default();
+                }
+                _ => {
+                    let end = value.find(char::is_whitespace).unwrap_or(value.len());
+                    out.push_str(&value[..end]);
+                    rest = &value[end..];
+                }
+            }
+            rest = rest.trim_start();
+        }
+    }
+    out.push('>');
+    out
+}
+
+/// Drops comments, the whitespace that doesn't matter and the last `;` of
+/// each block
+pub fn minify_css(css: &str) -> String {
+    minify_css_with(css, false)
+}
+
+/// `minify_css` for the declarations of a `style` attribute, where colors
+/// like `#aabbcc` also become `#abc`
+fn minify_declarations(css: &str) -> String {
+    let mut minified = minify_css_with(css, true);
+    if minified.ends_with(';') {
+        minified.pop();
+    }
+    minified
+}
+
+fn minify_css_with(css: &str, short_colors: bool) -> String {
+    let mut out = String::with_capacity(css.len());
+    let mut rest = css;
+    let mut pending_space = false;
+    // Spaces after punctuation don't matter
+    let mut after_punctuation = true;
+
+    while let Some(c) = rest.chars().next() {
+        if let Some(comment) = rest.strip_prefix("/*") {
+            rest = comment.find("*/").map_or("", |end| &comment[end + 2..]);
+            pending_space = true;
+            continue;
+        }
+        rest = &rest[c.len_utf8()..];
+        match c {
+            c if c.is_whitespace() => pending_space = true,
+            '{' | '}' | ';' | ',' | '>' => {
+                if c == '}' && out.ends_with(';') {
+                    out.pop();
+                }
+                out.push(c);
+                pending_space = false;
+                after_punctuation = true;
+            }
+            _ => {
+                if pending_space && !after_punctuation {
+                    out.push(' ');
+                }
+                pending_space = false;
+                after_punctuation = c == ':';
+                out.push(c);
+                match c {
+                    '"' | '\'' => {
+                        let mut escaped = false;
+                        let end = rest
+                            .char_indices()
+                            .find(|&(_, s)| {
+                                let found = s == c && !escaped;
+                                escaped = s == '\\' && !escaped;
+                                found
+                            })
+                            .map_or(rest.len(), |(i, _)| i + 1);
+                        out.push_str(&rest[..end]);
+                        rest = &rest[end..];
+                    }
+                    '#' if short_colors => {
+                        if let Some(short) = short_color(rest) {
+                            out.push_str(&short);
+                            rest = &rest[6..];
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+    out
+}
+
+/// `aabbcc`, what follows a `#`, as `abc`
+fn short_color(hex: &str) -> Option<String> {
+    let bytes = hex.as_bytes();
+    if bytes.len() < 6
+        || !bytes[..6].iter().all(u8::is_ascii_hexdigit)
+        || bytes.get(6).is_some_and(|b| b.is_ascii_alphanumeric())
+    {
+        return None;
+    }
+    let pairs: Vec<u8> = bytes[..6]
+        .chunks(2)
+        .map(|pair| pair[0].to_ascii_lowercase())
+        .collect();
+    bytes[..6]
+        .chunks(2)
+        .all(|pair| pair[0].eq_ignore_ascii_case(&pair[1]))
+        .then(|| String::from_utf8_lossy(&pairs).into_owned())
+}
@@ -0,0 +1,59 @@
+use pulldown_cmark::{CowStr, Event, HeadingLevel, Tag};
+
+/// Prefixes section headings with their number, "2.3 Installation", for
+/// documentation-style sites. The page title (`h1`) is left alone and `h2`
+/// headings are the top level.
+pub struct NumberingRenderer<'a, I> {
+    inner: I,
+    enabled: bool,
+    /// Count of headings seen at each level from `h2` to `h6`
+    counters: [usize; 5],
+    pending: Option<Event<'a>>,
+}
+
+impl<'a, I: Iterator<Item = Event<'a>>> NumberingRenderer<'a, I> {
+    pub fn new(inner: I, enabled: bool) -> Self {
+        Self {
+            inner,
+            enabled,
+            counters: [0; 5],
+            pending: None,
+        }
+    }
+}
+
+impl<'a, I: Iterator<Item = Event<'a>>> Iterator for NumberingRenderer<'a, I> {
+    type Item = Event<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(event) = self.pending.take() {
+            return Some(event);
+        }
+
+        let event = self.inner.next()?;
+        if !self.enabled {
+            return Some(event);
+        }
+        let Event::Start(Tag::Heading { level, .. }) = &event else {
+            return Some(event);
+        };
+        if *level == HeadingLevel::H1 {
+            return Some(event);
+        }
+
+        let depth = *level as usize - 2;
+        self.counters[depth] += 1;
+        self.counters[depth + 1..].fill(0);
+        let number = self.counters[..=depth]
+            .iter()
+            .map(|n| n.to_string())
+            .collect::<Vec<_>>()
+            .join(".");
+
+        self.pending = Some(Event::Html(CowStr::from(format!(
+            "<span class=\"heading-number\">{}</span> ",
+            number
+        ))));
+        Some(event)
+    }
+}
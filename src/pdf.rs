@@ -0,0 +1,175 @@
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tera::Context;
+
+use crate::citations::Bibliography;
+use crate::glossary::Glossary;
+use crate::images::ImageSizes;
+use crate::links::UrlResolver;
+use crate::metadata::PageMetadata;
+use crate::preprocess::{PageInfo, preprocess};
+use crate::store::{ContentStore, LocalStore};
+use crate::{RenderOptions, TEMPLATES, frontmatter, get_summary_data, markdown_to_html};
+
+/// Command turning print-ready HTML into PDF when `pdf_command` isn't set
+pub const DEFAULT_PDF_COMMAND: &str = "weasyprint";
+
+/// A page of `print.html`
+#[derive(Serialize)]
+pub struct PrintPage {
+    pub title: String,
+    pub content: String,
+}
+
+/// Renders `pages` of `docs_dir`, every page by default in the home page's
+/// order, to PDF. With `concat`, they all go into the file `out`, one after
+/// the other; otherwise `out` is a directory getting a PDF per page. Returns
+/// the written files.
+pub async fn export_pdf(
+    docs_dir: &Path,
+    options: &RenderOptions,
+    pages: &[String],
+    concat: bool,
+    out: &Path,
+    command: &str,
+) -> anyhow::Result<Vec<PathBuf>> {
+    let store = LocalStore::new(docs_dir.to_path_buf());
+    let pages = if pages.is_empty() {
+        get_summary_data(&store)
+            .await
+            .into_iter()
+            .map(|page| page.filename)
+            .collect()
+    } else {
+        pages.to_vec()
+    };
+    if pages.is_empty() {
+        anyhow::bail!("No pages to export");
+    }
+
+    let options = print_options(options);
+    let mut rendered = Vec::new();
+    for filename in &pages {
+        rendered.push(print_page(&store, &options, filename).await?);
+    }
+
+    let temp_dir = tempfile::tempdir()?;
+    let mut written = Vec::new();
+    if concat {
+        let title = match rendered.as_slice() {
+            [page] => page.title.clone(),
+            _ => docs_dir
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+        };
+        let html = temp_dir.path().join("print.html");
+        std::fs::write(&html, render_print(docs_dir, &options, &title, &rendered)?)?;
+        html_to_pdf(command, &html, out)?;
+        written.push(out.to_path_buf());
+    } else {
+        std::fs::create_dir_all(out)?;
+        for (filename, page) in pages.iter().zip(rendered) {
+            let stem = filename.strip_suffix(".md").unwrap_or(filename);
+            let html = temp_dir.path().join(format!("{}.html", stem));
+            let pdf = out.join(format!("{}.pdf", stem));
+            let title = page.title.clone();
+            std::fs::write(&html, render_print(docs_dir, &options, &title, &[page])?)?;
+            html_to_pdf(command, &html, &pdf)?;
+            written.push(pdf);
+        }
+    }
+    Ok(written)
+}
+
+/// `options` for pages read off a single file: code keeps its highlighting
+/// through inline styles, and shows in full
+pub fn print_options(options: &RenderOptions) -> RenderOptions {
+    let mut options = options.clone();
+    options.highlight_classes = false;
+    options.code_max_lines = None;
+    options.image_optimization = None;
+    options.lazy_images = false;
+    options
+}
+
+/// The content of the page `filename`, rendered as when building
+pub async fn print_page(
+    store: &dyn ContentStore,
+    options: &RenderOptions,
+    filename: &str,
+) -> anyhow::Result<PrintPage> {
+    let content = store.read_page(filename).await?;
+    let (meta, body) = frontmatter::parse(&content);
+    let metadata = PageMetadata::resolve(filename, &meta, body);
+    let page = PageInfo {
+        filename,
+        title: &metadata.title,
+    };
+    let body = preprocess(body, &page, store, options).await;
+    let bibliography = match meta.bibliography.as_ref().or(options.bibliography.as_ref()) {
+        Some(path) => Bibliography::load(store, path).await,
+        None => Bibliography::default(),
+    };
+    let content = markdown_to_html(
+        &body,
+        options,
+        &Glossary::default(),
+        &bibliography,
+        &ImageSizes::new(),
+        &UrlResolver::new("", options, true),
+    );
+    Ok(PrintPage {
+        title: metadata.title,
+        content,
+    })
+}
+
+/// `print.html` showing `pages`, resolving the relative URLs of their
+/// images from `docs_dir`
+fn render_print(
+    docs_dir: &Path,
+    options: &RenderOptions,
+    title: &str,
+    pages: &[PrintPage],
+) -> anyhow::Result<String> {
+    let mut context = Context::new();
+    context.insert("title", title);
+    context.insert("pages", pages);
+    let base_url = reqwest::Url::from_directory_path(docs_dir)
+        .map_err(|_| anyhow::anyhow!("Invalid docs directory {:?}", docs_dir))?;
+    context.insert("base_url", base_url.as_str());
+    if let Some(language) = &options.language {
+        context.insert("lang", language);
+    }
+    Ok(TEMPLATES.read().unwrap().render("print.html", &context)?)
+}
+
+/// Runs `command` through `sh` to turn the HTML file `html` into the PDF
+/// file `pdf`. They are given as `$1` and `$2`, appended to the command
+/// unless it uses them, like `chromium --headless --print-to-pdf="$2" "$1"`.
+pub fn html_to_pdf(command: &str, html: &Path, pdf: &Path) -> anyhow::Result<()> {
+    let script = if command.contains("$1") || command.contains("$2") {
+        command.to_string()
+    } else {
+        format!("{} \"$1\" \"$2\"", command)
+    };
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(script)
+        .arg("sh")
+        .arg(html)
+        .arg(pdf)
+        .output()
+        .map_err(|e| anyhow::anyhow!("Failed to run {}: {}", command, e))?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "{} failed on {}: {}",
+            command,
+            pdf.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
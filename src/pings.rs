@@ -0,0 +1,183 @@
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, utf8_percent_encode};
+use std::collections::BTreeSet;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::changes::CHANGES_FEED_FILE;
+use crate::dates::DateWindow;
+use crate::store::ContentStore;
+use crate::{
+    FEED_FILE, RenderOptions, SITEMAP_FILE, build_changes_feed, build_feed, get_summary_data,
+};
+
+/// How often `watch` looks for newly published pages and feed changes
+const WATCH_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Characters escaped in the URLs passed in query strings
+pub const QUERY_ESCAPES: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// Endpoints told when the site changes: ping URLs, such as a search
+/// engine's, and a WebSub hub
+pub struct Pings {
+    /// URLs requested with GET, where `{sitemap}` and `{feed}` are replaced
+    /// with the URL of the sitemap and of the feed
+    urls: Vec<String>,
+    /// Hub told about each feed with a WebSub publish request
+    websub_hub: Option<String>,
+    sitemap_url: String,
+    /// The feeds of each language, or of the only one
+    feed_urls: Vec<String>,
+    /// Settings the served feeds are rendered with
+    options: RenderOptions,
+}
+
+impl Pings {
+    /// Pings for the site of `options`, `None` when there is nothing to ping
+    /// or no base URL to tell them about.
+    pub fn new(options: &RenderOptions, urls: Vec<String>) -> Option<Self> {
+        if urls.is_empty() && options.websub_hub.is_none() {
+            return None;
+        }
+        let Some(base_url) = &options.base_url else {
+            tracing::warn!("Not sending pings without a base URL");
+            return None;
+        };
+        let roots = if options.languages.is_empty() {
+            vec![base_url.clone()]
+        } else {
+            options
+                .languages
+                .iter()
+                .map(|language| format!("{}{}/", base_url, language))
+                .collect()
+        };
+        let mut feed_urls = Vec::new();
+        for root in roots {
+            feed_urls.push(format!("{}{}", root, FEED_FILE));
+            if options.changelog {
+                feed_urls.push(format!("{}{}", root, CHANGES_FEED_FILE));
+            }
+        }
+        Some(Self {
+            urls,
+            websub_hub: options.websub_hub.clone(),
+            sitemap_url: format!("{}{}", base_url, SITEMAP_FILE),
+            feed_urls,
+            options: options.clone(),
+        })
+    }
+
+    /// Sends every ping, logging the ones that fail.
+    pub async fn send(&self) {
+        self.send_requests(true).await
+    }
+
+    /// Tells the WebSub hub, if any, that the feeds changed.
+    pub async fn notify_hub(&self) {
+        self.send_requests(false).await
+    }
+
+    async fn send_requests(&self, with_urls: bool) {
+        let client = match reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+        {
+            Ok(client) => client,
+            Err(e) => {
+                tracing::warn!("Failed to send pings: {}", e);
+                return;
+            }
+        };
+        let encode = |url: &str| utf8_percent_encode(url, QUERY_ESCAPES).to_string();
+
+        let mut requests = Vec::new();
+        for url in self.urls.iter().filter(|_| with_urls) {
+            let url = url.replace("{sitemap}", &encode(&self.sitemap_url));
+            if url.contains("{feed}") {
+                for feed_url in &self.feed_urls {
+                    let url = url.replace("{feed}", &encode(feed_url));
+                    requests.push((url.clone(), client.get(url)));
+                }
+            } else {
+                requests.push((url.clone(), client.get(url)));
+            }
+        }
+        if let Some(hub) = &self.websub_hub {
+            for feed_url in &self.feed_urls {
+                let request = client
+                    .post(hub)
+                    .header(
+                        reqwest::header::CONTENT_TYPE,
+                        "application/x-www-form-urlencoded",
+                    )
+                    .body(format!("hub.mode=publish&hub.url={}", encode(feed_url)));
+                requests.push((hub.clone(), request));
+            }
+        }
+
+        for (url, request) in requests {
+            match request.send().await {
+                Ok(response) if response.status().is_success() => {
+                    tracing::info!("Pinged {}", url);
+                }
+                Ok(response) => tracing::warn!("Ping to {} answered {}", url, response.status()),
+                Err(e) => tracing::warn!("Failed to ping {}: {}", url, e),
+            }
+        }
+    }
+}
+
+/// Fingerprint of the feeds served for `store`
+async fn feeds_fingerprint(store: &dyn ContentStore, options: &RenderOptions) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    if let Some(base_url) = &options.base_url {
+        let hub = options.websub_hub.as_deref();
+        let window = DateWindow::recent(options.feed_months);
+        build_feed(store, base_url, false, options.pretty_urls, hub, &window)
+            .await
+            .hash(&mut hasher);
+        if options.changelog {
+            build_changes_feed(store, base_url, false, options.pretty_urls, hub)
+                .await
+                .hash(&mut hasher);
+        }
+    }
+    hasher.finish()
+}
+
+/// Sends `pings` whenever a page of `store` is published, and notifies the
+/// WebSub hub whenever the feeds change otherwise, checking every
+/// `WATCH_INTERVAL`. What was there when serving started doesn't count.
+pub async fn watch(store: Arc<dyn ContentStore>, pings: Pings) {
+    let published = async |store: &dyn ContentStore| {
+        get_summary_data(store)
+            .await
+            .into_iter()
+            .map(|page| page.filename)
+            .collect::<BTreeSet<String>>()
+    };
+    let mut known = published(store.as_ref()).await;
+    let mut feeds = feeds_fingerprint(store.as_ref(), &pings.options).await;
+    let mut interval = tokio::time::interval(WATCH_INTERVAL);
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        let current = published(store.as_ref()).await;
+        let current_feeds = feeds_fingerprint(store.as_ref(), &pings.options).await;
+        let new = current.difference(&known).count();
+        if new > 0 {
+            tracing::info!("Found {} newly published pages", new);
+            pings.send().await;
+        } else if current_feeds != feeds {
+            tracing::info!("Found changes to the feeds");
+            pings.notify_hub().await;
+        }
+        known = current;
+        feeds = current_feeds;
+    }
+}
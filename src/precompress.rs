@@ -0,0 +1,105 @@
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Extensions of the files worth compressing ahead of time
+const COMPRESSIBLE: &[&str] = &["html", "css", "js", "svg", "xml", "json"];
+
+/// Encodings written next to each file, by the extension they add
+pub const ENCODINGS: &[(&str, &str)] = &[("br", "br"), ("gzip", "gz")];
+
+/// Whether builds with `precompress` compress the file at `path`
+pub fn is_compressible(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| COMPRESSIBLE.contains(&extension))
+}
+
+/// Writes a `.gz` and a `.br` sibling of every HTML, CSS, JavaScript, SVG,
+/// XML and JSON file of `out_dir`, for web servers such as nginx and Caddy
+/// to send instead. Siblings that wouldn't be smaller are removed, and
+/// `.br` ones are skipped when the `brotli` command isn't installed.
+/// Returns how many files were written.
+pub fn precompress_tree(out_dir: &Path) -> anyhow::Result<usize> {
+    let mut files = Vec::new();
+    collect_files(out_dir, &mut files)?;
+    let mut brotli = true;
+    let mut written = 0;
+    for path in files {
+        let content = std::fs::read(&path)?;
+        written += write_smaller(&sibling(&path, "gz"), &gzip(&content)?, content.len())?;
+        if brotli {
+            match compress_brotli(&path) {
+                Ok(compressed) => {
+                    written += write_smaller(&sibling(&path, "br"), &compressed, content.len())?
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    tracing::warn!("Not writing .br files, the brotli command isn't installed");
+                    brotli = false;
+                }
+                Err(e) => tracing::warn!("Failed to compress {:?} with brotli: {}", path, e),
+            }
+        }
+    }
+    tracing::info!("Precompressed {} files", written);
+    Ok(written)
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> anyhow::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        if entry.file_type()?.is_dir() {
+            collect_files(&path, files)?;
+        } else if is_compressible(&path) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// `page.html` becomes `page.html.gz`
+pub fn sibling(path: &Path, extension: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(extension);
+    PathBuf::from(name)
+}
+
+fn gzip(content: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(content)?;
+    encoder.finish()
+}
+
+fn compress_brotli(path: &Path) -> std::io::Result<Vec<u8>> {
+    let output = Command::new("brotli")
+        .args(["--best", "--stdout", "--"])
+        .arg(path)
+        .output()?;
+    if !output.status.success() {
+        return Err(std::io::Error::other(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+    Ok(output.stdout)
+}
+
+/// Writes `compressed` to `path` if it is smaller than the original, else
+/// removes what a previous build left there. Returns how many files were
+/// written.
+fn write_smaller(path: &Path, compressed: &[u8], original: usize) -> anyhow::Result<usize> {
+    if compressed.len() < original {
+        std::fs::write(path, compressed)?;
+        return Ok(1);
+    }
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(0),
+    }
+}
@@ -0,0 +1,225 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+
+use crate::RenderOptions;
+use crate::includes::{expand_includes, parse_args};
+use crate::store::ContentStore;
+
+/// Directory of the shortcode templates, within the templates directory
+pub const SHORTCODES_DIR: &str = "templates/shortcodes";
+
+/// A step of `preprocessors`, which rewrite the markdown of pages, in order,
+/// before it is parsed
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Preprocessor {
+    /// `{{ include_code(path="src/main.rs") }}`, see `includes::expand_includes`
+    Includes,
+    /// `{{ name(arg="value") }}`, rendering `templates/shortcodes/name.md`
+    /// with the arguments as variables
+    Shortcodes,
+    /// `{{ site.base_url }}`, `{{ page.title }}` and the `{{ vars.name }}`
+    /// of `[variables]`
+    Variables,
+    /// Command run through `sh`, given the markdown on its standard input
+    /// and the page's file name in `BLOG_PAGE`, printing the new markdown
+    Command(String),
+}
+
+/// What preprocessors know of the page they run on
+pub struct PageInfo<'a> {
+    pub filename: &'a str,
+    pub title: &'a str,
+}
+
+/// Runs the `preprocessors` of `options` on `body`, the markdown of a page
+/// without its frontmatter. A failing step leaves the markdown as it was,
+/// with a warning.
+pub async fn preprocess(
+    body: &str,
+    page: &PageInfo<'_>,
+    store: &dyn ContentStore,
+    options: &RenderOptions,
+) -> String {
+    let mut body = body.to_string();
+    for preprocessor in &options.preprocessors {
+        body = match preprocessor {
+            Preprocessor::Includes => expand_includes(&body, store).await,
+            Preprocessor::Shortcodes => expand_shortcodes(&body, page, store).await,
+            Preprocessor::Variables => substitute_variables(&body, page, options),
+            Preprocessor::Command(command) => match run_command(command, &body, page).await {
+                Ok(output) => output,
+                Err(e) => {
+                    tracing::warn!("{}: {}", page.filename, e);
+                    body
+                }
+            },
+        };
+    }
+    body
+}
+
+/// Replaces the `{{ ... }}` expressions outside of code blocks that
+/// `expand` returns something for, given what is between the braces.
+fn replace_expressions(body: &str, mut expand: impl FnMut(&str) -> Option<String>) -> String {
+    let mut output = String::with_capacity(body.len());
+    let mut fence: Option<&str> = None;
+    for line in body.split_inclusive('\n') {
+        // Leave code blocks alone, they may be documenting the syntax
+        let trimmed = line.trim();
+        let marker = trimmed.trim_start_matches('`');
+        let ticks = &trimmed[..trimmed.len() - marker.len()];
+        if ticks.len() >= 3 {
+            match fence {
+                Some(open) if ticks.len() >= open.len() && marker.is_empty() => fence = None,
+                None => fence = Some(ticks),
+                _ => {}
+            }
+        }
+        if fence.is_some() || ticks.len() >= 3 {
+            output.push_str(line);
+            continue;
+        }
+
+        let mut rest = line;
+        while let Some(start) = rest.find("{{") {
+            let Some(length) = rest[start..].find("}}") else {
+                break;
+            };
+            let end = start + length + "}}".len();
+            output.push_str(&rest[..start]);
+            match expand(rest[start + "{{".len()..start + length].trim()) {
+                Some(expanded) => output.push_str(&expanded),
+                None => output.push_str(&rest[start..end]),
+            }
+            rest = &rest[end..];
+        }
+        output.push_str(rest);
+    }
+    output
+}
+
+/// Name and arguments of a `name(arg="value")` call
+fn parse_call(expression: &str) -> Option<(&str, &str)> {
+    let (name, args) = expression.strip_suffix(')')?.split_once('(')?;
+    let name = name.trim();
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    valid.then_some((name, args))
+}
+
+/// Names of the shortcodes `body` calls
+fn shortcode_names(body: &str) -> Vec<String> {
+    // `include_code` is the `Includes` step's
+    let mut names = Vec::new();
+    replace_expressions(body, |expression| {
+        if let Some((name, _)) = parse_call(expression)
+            && name != "include_code"
+            && !names.iter().any(|n| n == name)
+        {
+            names.push(name.to_string());
+        }
+        None
+    });
+    names
+}
+
+fn shortcode_file(name: &str) -> String {
+    format!("{}/{}.md", SHORTCODES_DIR, name)
+}
+
+/// Templates of the shortcodes `body` calls
+pub fn shortcode_files(body: &str) -> Vec<String> {
+    shortcode_names(body)
+        .iter()
+        .map(|name| shortcode_file(name))
+        .collect()
+}
+
+async fn expand_shortcodes(body: &str, page: &PageInfo<'_>, store: &dyn ContentStore) -> String {
+    let mut templates = HashMap::new();
+    for name in shortcode_names(body) {
+        let path = shortcode_file(&name);
+        match store.read_file(&path).await {
+            Ok(template) => {
+                templates.insert(name, template);
+            }
+            Err(_) => tracing::warn!("{}: no shortcode {}, see {}", page.filename, name, path),
+        }
+    }
+
+    replace_expressions(body, |expression| {
+        let (name, args) = parse_call(expression)?;
+        let template = templates.get(name)?;
+        let rendered = parse_args(args).and_then(|args| {
+            let mut context = tera::Context::new();
+            context.insert("page", page.filename);
+            for (key, value) in args {
+                context.insert(key, &value);
+            }
+            tera::Tera::one_off(template, &context, false).map_err(Into::into)
+        });
+        match rendered {
+            Ok(rendered) => Some(rendered.trim_end_matches('\n').to_string()),
+            Err(e) => {
+                tracing::warn!("{}: failed to expand {}: {}", page.filename, name, e);
+                None
+            }
+        }
+    })
+}
+
+fn substitute_variables(body: &str, page: &PageInfo<'_>, options: &RenderOptions) -> String {
+    replace_expressions(body, |expression| {
+        let (scope, name) = expression.split_once('.')?;
+        let value = match (scope, name) {
+            ("site", "base_url") => options.base_url.clone(),
+            ("site", "base_path") => Some(options.base_path.clone()),
+            ("site", "language") => options
+                .language
+                .clone()
+                .or_else(|| options.languages.first().cloned()),
+            ("page", "filename") => Some(page.filename.to_string()),
+            ("page", "title") => Some(page.title.to_string()),
+            ("vars", name) => options.variables.get(name).cloned(),
+            ("site" | "page", _) => None,
+            _ => return None,
+        };
+        if value.is_none() {
+            tracing::warn!("{}: {} has no value", page.filename, expression);
+        }
+        value
+    })
+}
+
+async fn run_command(command: &str, body: &str, page: &PageInfo<'_>) -> anyhow::Result<String> {
+    let mut child = tokio::process::Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .env("BLOG_PAGE", page.filename)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| anyhow::anyhow!("Failed to run {}: {}", command, e))?;
+    // Written while the output is read, the pipes being bounded
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let input = body.to_string();
+    let writer = tokio::spawn(async move { stdin.write_all(input.as_bytes()).await });
+    let output = child.wait_with_output().await?;
+    // The command may not read all of its input
+    let _ = writer.await;
+    if !output.status.success() {
+        anyhow::bail!(
+            "{} failed: {}",
+            command,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    String::from_utf8(output.stdout)
+        .map_err(|_| anyhow::anyhow!("{} printed invalid UTF-8", command))
+}
@@ -0,0 +1,85 @@
+use axum::{
+    Router,
+    extract::{ConnectInfo, Request, State},
+    http::HeaderMap,
+    middleware::{self, Next},
+    response::Response,
+};
+use std::net::{IpAddr, SocketAddr};
+
+/// Who sent a request, as the server or, with `--trust-proxy`, the reverse
+/// proxy in front of it saw them. Set on every request by
+/// `with_client_info`, for logging and canonical URLs.
+#[derive(Clone, Debug)]
+pub struct ClientInfo {
+    /// Address of the client, `None` when unknown
+    pub ip: Option<IpAddr>,
+    /// Scheme and host the client asked for, like `https://example.com`, as
+    /// reported by a trusted proxy
+    pub origin: Option<String>,
+}
+
+/// Records the `ClientInfo` of each request `router` answers. Trusting the
+/// proxy takes the client address from the last `X-Forwarded-For` entry,
+/// the one the proxy added, and the origin from `X-Forwarded-Proto` and
+/// `X-Forwarded-Host` or `Host`. Without it these headers are ignored, since
+/// anyone can send them.
+pub fn with_client_info(router: Router, trust_proxy: bool) -> Router {
+    router.layer(middleware::from_fn_with_state(trust_proxy, record_client))
+}
+
+async fn record_client(
+    State(trust_proxy): State<bool>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    // Missing when the router is served without `into_make_service_with_connect_info`
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+    let client = if trust_proxy {
+        let headers = request.headers();
+        ClientInfo {
+            ip: forwarded_for(headers).or(peer),
+            origin: forwarded_origin(headers),
+        }
+    } else {
+        ClientInfo {
+            ip: peer,
+            origin: None,
+        }
+    };
+    request.extensions_mut().insert(client);
+    next.run(request).await
+}
+
+/// Address the proxy appended to `X-Forwarded-For`, its last entry
+fn forwarded_for(headers: &HeaderMap) -> Option<IpAddr> {
+    let value = headers.get_all("x-forwarded-for").iter().next_back()?;
+    let last = value.to_str().ok()?.rsplit(',').next()?.trim();
+    // Some proxies add the port, `[::1]:1234` or `1.2.3.4:1234`
+    last.parse::<IpAddr>()
+        .or_else(|_| last.parse::<SocketAddr>().map(|addr| addr.ip()))
+        .ok()
+}
+
+fn forwarded_origin(headers: &HeaderMap) -> Option<String> {
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split(',').next())
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+    };
+    let scheme = match header("x-forwarded-proto") {
+        Some(scheme) if scheme.eq_ignore_ascii_case("https") => "https",
+        _ => "http",
+    };
+    let host = header("x-forwarded-host").or_else(|| header("host"))?;
+    let valid = host
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | ':' | '[' | ']'));
+    valid.then(|| format!("{}://{}", scheme, host.to_ascii_lowercase()))
+}
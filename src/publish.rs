@@ -0,0 +1,117 @@
+use serde_yaml::{Mapping, Value};
+use std::path::Path;
+use std::process::Command;
+
+use crate::scaffold::slugify;
+use crate::store::write_atomic;
+use crate::{frontmatter, summarize_page};
+
+/// A draft `publish` made live
+pub struct Published {
+    pub from: String,
+    pub to: String,
+    pub title: String,
+}
+
+/// Makes the draft `page` of `docs_dir` live: `published` becomes true, the
+/// date is set to now and the file is renamed after its title, as
+/// `slug@timestamp.md` or `slug.md` depending on which the draft used. With
+/// `commit`, the change is committed to the git repository of `docs_dir`.
+pub fn publish(docs_dir: &Path, page: &str, commit: bool) -> anyhow::Result<Published> {
+    let from = if page.ends_with(".md") {
+        page.to_string()
+    } else {
+        format!("{}.md", page)
+    };
+    let content = std::fs::read_to_string(docs_dir.join(&from))
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", from, e))?;
+    let (mut meta, body) = match frontmatter::split(&content) {
+        Some((yaml, body)) => (
+            serde_yaml::from_str::<Option<Mapping>>(yaml)?.unwrap_or_default(),
+            body,
+        ),
+        None => (Mapping::new(), content.as_str()),
+    };
+    if meta.get("published") != Some(&Value::Bool(false)) {
+        anyhow::bail!("{} is not a draft, it has no `published: false`", from);
+    }
+
+    // Flip the flag and date the page
+    let now = chrono::Utc::now();
+    meta.insert("published".into(), true.into());
+    if meta.contains_key("date") {
+        meta.insert("date".into(), now.to_rfc3339().into());
+    }
+
+    // Name the file after the title
+    let title = summarize_page(from.clone(), &content).title;
+    let current_slug = from
+        .trim_end_matches(".md")
+        .split('@')
+        .next()
+        .unwrap_or_default();
+    let slug = Some(slugify(&title))
+        .filter(|slug| !slug.is_empty())
+        .unwrap_or_else(|| current_slug.to_string());
+    let to = if from.contains('@') {
+        format!("{}@{}.md", slug, now.timestamp())
+    } else {
+        format!("{}.md", slug)
+    };
+    if to != from && docs_dir.join(&to).exists() {
+        anyhow::bail!("Cannot rename {} to {}, it already exists", from, to);
+    }
+
+    let published = format!("---\n{}---\n{}", serde_yaml::to_string(&meta)?, body);
+    write_atomic(&docs_dir.join(&to), published.as_bytes())?;
+    if to != from {
+        std::fs::remove_file(docs_dir.join(&from))?;
+    }
+
+    if commit {
+        commit_published(docs_dir, &from, &to, &title)?;
+    }
+    Ok(Published { from, to, title })
+}
+
+/// Commits the publication of `title`, and only it, to git.
+fn commit_published(docs_dir: &Path, from: &str, to: &str, title: &str) -> anyhow::Result<()> {
+    let git = |args: &[&str]| {
+        Command::new("git")
+            .arg("-C")
+            .arg(docs_dir)
+            .args(args)
+            .output()
+            .map_err(|e| anyhow::anyhow!("Failed to run git: {}", e))
+    };
+    let run = |args: &[&str]| -> anyhow::Result<()> {
+        let output = git(args)?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "git {} failed: {}",
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(())
+    };
+
+    // A draft that was never committed has nothing to remove
+    let mut paths = vec![to];
+    if from != to
+        && git(&["ls-files", "--error-unmatch", "--", from])?
+            .status
+            .success()
+    {
+        run(&["rm", "--cached", "--quiet", "--", from])?;
+        paths.push(from);
+    }
+    run(&["add", "--", to])?;
+
+    let message = format!("Publish {}", title);
+    let mut args = vec!["commit", "--quiet", "-m", &message, "--"];
+    args.extend(paths);
+    run(&args)?;
+    tracing::info!("Committed {}", to);
+    Ok(())
+}
@@ -0,0 +1,71 @@
+use axum::{
+    extract::{Path, State},
+    response::{Html, IntoResponse, Response},
+};
+use pulldown_cmark::{Options, Parser as MarkdownParser, html};
+use std::sync::Arc;
+use tera::Context;
+
+use crate::analytics;
+use crate::figures::FigureRenderer;
+use crate::preprocess::{PageInfo, preprocess};
+use crate::translations;
+use crate::{
+    AppState, TEMPLATES, display_date, fallback, frontmatter, page_url, served_root, summarize_page,
+};
+
+/// `GET /reader/{page}`: the page as bare semantic HTML, without
+/// highlighting, scripts or navigation, for e-readers and text browsers.
+pub async fn reader_handler(
+    State(state): State<Arc<AppState>>,
+    Path(page): Path<String>,
+) -> Response {
+    let filename = if page.ends_with(".md") {
+        page
+    } else {
+        format!("{}.md", page)
+    };
+    let Ok(content) = state.store.read_page(&filename).await else {
+        return fallback::not_found(&state, &filename).await;
+    };
+    let summary = summarize_page(filename.clone(), &content);
+    let (_, body) = frontmatter::parse(&content);
+    let page = PageInfo {
+        filename: &filename,
+        title: &summary.title,
+    };
+    let body = preprocess(body, &page, state.store.as_ref(), &state.options).await;
+
+    let parser = MarkdownParser::new_ext(
+        &body,
+        Options::ENABLE_TABLES
+            | Options::ENABLE_FOOTNOTES
+            | Options::ENABLE_STRIKETHROUGH
+            | Options::ENABLE_TASKLISTS,
+    );
+    let renderer = FigureRenderer::new(parser, state.options.numbering);
+    let mut html_output = String::new();
+    html::push_html(&mut html_output, renderer);
+
+    let mut context = Context::new();
+    context.insert("title", &summary.title);
+    context.insert("content", &html_output);
+    let root = served_root(&state.options);
+    context.insert("root", &root);
+    context.insert(
+        "page_url",
+        &format!(
+            "{}{}",
+            root,
+            page_url(&filename, false, state.options.pretty_urls)
+        ),
+    );
+    context.insert("date", &display_date(&state.options, summary.datetime));
+    context.insert("lang", &state.options.language);
+    analytics::insert_into(&state.options, &mut context);
+    translations::insert_into(&state.options, &mut context);
+    match TEMPLATES.read().unwrap().render("reader.html", &context) {
+        Ok(rendered) => Html(rendered).into_response(),
+        Err(e) => Html(format!("Error: {}", e)).into_response(),
+    }
+}
@@ -0,0 +1,41 @@
+use crate::store::ContentStore;
+use crate::{frontmatter, get_summary_data};
+
+/// Up to `limit` pages sharing the most tags with `filename`, most shared
+/// first, then in home page order. Pages sharing no tag are not related.
+pub async fn related_pages(
+    store: &dyn ContentStore,
+    filename: &str,
+    limit: usize,
+) -> Vec<(String, String)> {
+    if limit == 0 {
+        return Vec::new();
+    }
+    let tags = |content: &str| frontmatter::parse(content).0.tags;
+    let own_tags = tags(&store.read_page(filename).await.unwrap_or_default());
+    if own_tags.is_empty() {
+        return Vec::new();
+    }
+
+    let mut related = Vec::new();
+    for page in get_summary_data(store).await {
+        if page.filename == filename {
+            continue;
+        }
+        let content = store.read_page(&page.filename).await.unwrap_or_default();
+        let shared = tags(&content)
+            .iter()
+            .filter(|tag| own_tags.contains(tag))
+            .count();
+        if shared > 0 {
+            related.push((shared, page.filename, page.title));
+        }
+    }
+    // Stable, so equally related pages keep the home page order
+    related.sort_by_key(|(shared, _, _)| std::cmp::Reverse(*shared));
+    related
+        .into_iter()
+        .take(limit)
+        .map(|(_, filename, title)| (filename, title))
+        .collect()
+}
@@ -0,0 +1,149 @@
+use std::path::{Path, PathBuf};
+
+use crate::config::{CONFIG_FILE, STARTER_CONFIG};
+use crate::{EMBEDDED_TEMPLATES, TEMPLATES_DIR};
+
+const EXAMPLE_POST: &str = r#"# Hello, world
+
+This is your first post. Every markdown file in this directory becomes a page,
+and the first line is used as its title on the home page.
+
+Files are named `slug@timestamp.md`, where the timestamp (in seconds since the
+Unix epoch) is the publication date used to sort the home page.
+
+```rust
+fn main() {
+    println!("Hello, world!");
+}
+```
+"#;
+
+/// Creates a new blog in `dir`: a starter `blog.toml`, an example post and,
+/// if asked, a copy of the embedded templates to customize. Existing files
+/// are never overwritten.
+pub fn init_site(dir: &Path, with_templates: bool) -> anyhow::Result<()> {
+    std::fs::create_dir_all(dir)?;
+
+    write_new(&dir.join(CONFIG_FILE), STARTER_CONFIG)?;
+
+    let timestamp = chrono::Utc::now().timestamp();
+    write_new(
+        &dir.join(format!("hello-world@{}.md", timestamp)),
+        EXAMPLE_POST,
+    )?;
+
+    if with_templates {
+        export_templates(&dir.join(TEMPLATES_DIR), false)?;
+    }
+
+    tracing::info!("Initialized blog in {:?}", dir);
+    Ok(())
+}
+
+/// Guide to the templates written next to them by `export_templates`
+const TEMPLATES_GUIDE: &str = "README.md";
+
+/// Writes the embedded templates and stylesheet into `dir`, along with a
+/// guide to how they extend each other. Existing files are kept unless
+/// `force` is set.
+pub fn export_templates(dir: &Path, force: bool) -> anyhow::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    let write = |path: &Path, content: &str| {
+        if force {
+            std::fs::write(path, content)?;
+            tracing::info!("Wrote {:?}", path);
+            Ok(())
+        } else {
+            write_new(path, content)
+        }
+    };
+    for (name, content) in EMBEDDED_TEMPLATES {
+        write(&dir.join(name), content)?;
+    }
+    write(&dir.join(TEMPLATES_GUIDE), &templates_guide())
+}
+
+/// Markdown guide listing each template, the one it extends and the blocks
+/// it defines.
+fn templates_guide() -> String {
+    let mut guide = String::from(
+        "# Templates\n\n\
+         These are the templates blog renders pages with, written in Tera. Copy\n\
+         the ones to change into the `templates/` directory of the site, or of a\n\
+         theme, keeping their names: each replaces the embedded template of the\n\
+         same name, and the others stay as they are.\n\n\
+         Templates extending another only need to redefine its blocks, see\n\
+         https://keats.github.io/tera/docs/#inheritance.\n\n\
+         | Template | Extends | Blocks |\n\
+         | --- | --- | --- |\n",
+    );
+    for (name, content) in EMBEDDED_TEMPLATES {
+        let extends = tags(content, "extends")
+            .next()
+            .map(|parent| format!("`{}`", parent.trim_matches('"')))
+            .unwrap_or_default();
+        let blocks: Vec<String> = tags(content, "block")
+            .map(|block| format!("`{}`", block))
+            .collect();
+        guide.push_str(&format!(
+            "| `{}` | {} | {} |\n",
+            name,
+            extends,
+            blocks.join(", ")
+        ));
+    }
+    guide
+}
+
+/// Arguments of the `{% keyword ... %}` tags of a template
+fn tags<'t>(template: &'t str, keyword: &'t str) -> impl Iterator<Item = &'t str> {
+    template.split("{%").skip(1).filter_map(move |tag| {
+        let tag = tag.split("%}").next()?.trim_matches(['-', ' ']);
+        tag.strip_prefix(keyword)?
+            .strip_prefix(' ')
+            .map(|argument| argument.trim())
+    })
+}
+
+fn write_new(path: &Path, content: &str) -> anyhow::Result<()> {
+    if path.exists() {
+        tracing::warn!("{:?} already exists, skipping", path);
+        return Ok(());
+    }
+    std::fs::write(path, content)?;
+    tracing::info!("Created {:?}", path);
+    Ok(())
+}
+
+/// Creates `slug@timestamp.md` in `dir` for a new post titled `title`, with
+/// an empty frontmatter skeleton, and returns its path.
+pub fn new_post(dir: &Path, title: &str) -> anyhow::Result<PathBuf> {
+    let slug = slugify(title);
+    if slug.is_empty() {
+        anyhow::bail!("Cannot derive a file name from title '{}'", title);
+    }
+
+    let timestamp = chrono::Utc::now().timestamp();
+    let path = dir.join(format!("{}@{}.md", slug, timestamp));
+    if path.exists() {
+        anyhow::bail!("{:?} already exists", path);
+    }
+
+    let content = format!(
+        "---\n# description: One line summary for the home page\n# cover: images/cover.jpg, shown in link previews and feeds\n# tags: []\n# weight: 0\n# canonical: https://example.com/original-post\n# date: 2025-01-03, overrides the timestamp in the file name\n# published: false, keeps it a draft until `blog publish`\n---\n\n# {}\n\n",
+        title
+    );
+    std::fs::write(&path, content)?;
+    tracing::info!("Created {:?}", path);
+    Ok(path)
+}
+
+/// Lowercases `title` and joins its alphanumeric runs with dashes.
+pub fn slugify(title: &str) -> String {
+    title
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .collect::<Vec<_>>()
+        .join("-")
+}
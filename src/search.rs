@@ -0,0 +1,73 @@
+use crate::metadata::image_url;
+use crate::store::ContentStore;
+use crate::{frontmatter, get_summary_data, page_url};
+use pulldown_cmark::{Event, Options, Parser as MarkdownParser, Tag, TagEnd};
+use serde::Serialize;
+
+pub const SEARCH_INDEX_FILE: &str = "search.json";
+
+/// One page of the client-side search index
+#[derive(Serialize)]
+pub struct SearchEntry {
+    pub url: String,
+    pub title: String,
+    pub excerpt: Option<String>,
+    /// Social image, from the site root unless on the web
+    pub image: Option<String>,
+    pub headings: Vec<String>,
+    pub text: String,
+}
+
+/// Indexes every page of `store`, with URLs pointing at the `.html` output
+/// when `is_static` is set, or at `slug/` with `pretty_urls`.
+pub async fn build_search_index(
+    store: &dyn ContentStore,
+    is_static: bool,
+    pretty_urls: bool,
+) -> Vec<SearchEntry> {
+    let mut entries = Vec::new();
+    for page in get_summary_data(store).await {
+        let Ok(content) = store.read_page(&page.filename).await else {
+            continue;
+        };
+        let (_, body) = frontmatter::parse(&content);
+        let (headings, text) = extract_text(body);
+
+        entries.push(SearchEntry {
+            url: page_url(&page.filename, is_static, pretty_urls),
+            title: page.title,
+            excerpt: page.excerpt,
+            image: page.image.map(|image| image_url(&image, None, "")),
+            headings,
+            text,
+        });
+    }
+    entries
+}
+
+/// Returns the headings and the plain text of a markdown document.
+fn extract_text(markdown: &str) -> (Vec<String>, String) {
+    let mut headings = Vec::new();
+    let mut text = String::new();
+    let mut heading: Option<String> = None;
+
+    for event in
+        MarkdownParser::new_ext(markdown, Options::ENABLE_TABLES | Options::ENABLE_FOOTNOTES)
+    {
+        match event {
+            Event::Start(Tag::Heading { .. }) => heading = Some(String::new()),
+            Event::End(TagEnd::Heading(_)) => headings.extend(heading.take()),
+            Event::Text(t) | Event::Code(t) => match &mut heading {
+                Some(h) => h.push_str(&t),
+                None => text.push_str(&t),
+            },
+            // Keep words of adjacent blocks, cells and lines apart
+            Event::SoftBreak | Event::HardBreak | Event::End(_) => text.push(' '),
+            _ => {}
+        }
+    }
+    (
+        headings,
+        text.split_whitespace().collect::<Vec<_>>().join(" "),
+    )
+}
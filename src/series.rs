@@ -0,0 +1,159 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use tera::Context;
+
+use crate::analytics;
+use crate::menu::{Location, SiteNavigation};
+use crate::scaffold::slugify;
+use crate::store::ContentStore;
+use crate::translations::{self, ui_string};
+use crate::{RenderOptions, TEMPLATES, frontmatter, get_summary_data, link_roots, page_url};
+
+/// Name of the generated series index, as if it were a page
+pub const SERIES_FILE: &str = "series.md";
+
+#[derive(Serialize)]
+pub struct SeriesPart {
+    pub url: String,
+    pub title: String,
+    /// Whether this is the page being rendered
+    pub current: bool,
+}
+
+/// A series as shown on one of its parts or on the series index
+#[derive(Serialize)]
+pub struct Series {
+    pub name: String,
+    /// Id of the series on the index page
+    pub anchor: String,
+    /// 1-based position of the current page, 0 on the index
+    pub position: usize,
+    pub parts: Vec<SeriesPart>,
+}
+
+/// File names and titles of the pages of each series, in reading order: by
+/// `part`, then oldest first for pages without one.
+pub async fn collect_series(store: &dyn ContentStore) -> BTreeMap<String, Vec<(String, String)>> {
+    let mut series: BTreeMap<String, Vec<_>> = BTreeMap::new();
+    for page in get_summary_data(store).await {
+        let content = store.read_page(&page.filename).await.unwrap_or_default();
+        let (meta, _) = frontmatter::parse(&content);
+        if let Some(name) = meta.series {
+            series.entry(name).or_default().push((
+                meta.part.is_none(),
+                meta.part,
+                page.datetime,
+                page,
+            ));
+        }
+    }
+    series
+        .into_iter()
+        .map(|(name, mut pages)| {
+            pages.sort_by_key(|(no_part, part, datetime, _)| (*no_part, *part, *datetime));
+            let pages = pages
+                .into_iter()
+                .map(|(_, _, _, page)| (page.filename, page.title))
+                .collect();
+            (name, pages)
+        })
+        .collect()
+}
+
+/// The series `name` as seen from `filename`, with links made by `link`.
+pub async fn page_series(
+    store: &dyn ContentStore,
+    name: &str,
+    filename: &str,
+    link: impl Fn(&str) -> String,
+) -> Option<Series> {
+    let pages = collect_series(store).await.remove(name)?;
+    let position = pages.iter().position(|(f, _)| f == filename)? + 1;
+    Some(Series {
+        name: name.to_string(),
+        anchor: anchor(name),
+        position,
+        parts: pages
+            .into_iter()
+            .map(|(f, title)| SeriesPart {
+                url: link(&f),
+                current: f == filename,
+                title,
+            })
+            .collect(),
+    })
+}
+
+/// Link to the entry of series `name` on the index page
+pub fn index_url(options: &RenderOptions, is_static: bool, name: &str) -> String {
+    let (root, _) = link_roots(options, is_static, true);
+    format!(
+        "{}{}#{}",
+        root,
+        page_url(SERIES_FILE, is_static, options.pretty_urls),
+        anchor(name)
+    )
+}
+
+fn anchor(name: &str) -> String {
+    format!("series-{}", slugify(name))
+}
+
+/// Renders the index of every series, `None` when there is none.
+pub async fn render_series_index(
+    store: &dyn ContentStore,
+    options: &RenderOptions,
+    is_static: bool,
+) -> anyhow::Result<Option<String>> {
+    let (root, home_url) = link_roots(options, is_static, true);
+    let series: Vec<Series> = collect_series(store)
+        .await
+        .into_iter()
+        .map(|(name, pages)| Series {
+            anchor: anchor(&name),
+            name,
+            position: 0,
+            parts: pages
+                .into_iter()
+                .map(|(filename, title)| SeriesPart {
+                    url: format!(
+                        "{}{}",
+                        root,
+                        page_url(&filename, is_static, options.pretty_urls)
+                    ),
+                    title,
+                    current: false,
+                })
+                .collect(),
+        })
+        .collect();
+    if series.is_empty() {
+        return Ok(None);
+    }
+
+    let mut context = Context::new();
+    context.insert("root", &root);
+    context.insert("home_url", &home_url);
+    SiteNavigation::new(
+        &options.menu,
+        &Location::Elsewhere,
+        &root,
+        &home_url,
+        is_static,
+        options.pretty_urls,
+    )
+    .insert_into(&mut context);
+    analytics::insert_into(options, &mut context);
+    translations::insert_into(options, &mut context);
+    context.insert("title", ui_string(options, "series"));
+    context.insert("series", &series);
+    context.insert("is_static", &is_static);
+    context.insert("no_navigation", &options.no_navigation);
+    context.insert("canonical", &None::<String>);
+    if let Some(language) = &options.language {
+        context.insert("lang", language);
+    }
+    Ok(Some(
+        TEMPLATES.read().unwrap().render("series.html", &context)?,
+    ))
+}
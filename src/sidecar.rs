@@ -0,0 +1,87 @@
+use pulldown_cmark::{Event, Options, Parser as MarkdownParser, Tag};
+use serde::Serialize;
+use std::collections::BTreeSet;
+
+use crate::anchors::page_anchors;
+use crate::check::page_links;
+use crate::metadata::PageMetadata;
+use crate::search::SEARCH_INDEX_FILE;
+use crate::signing::MANIFEST_FILE;
+use crate::stats::word_count;
+use crate::{dates, frontmatter, page_file, page_url};
+
+/// JSON files builds write besides the sidecars
+const GENERATED_FILES: &[&str] = &[SEARCH_INDEX_FILE, MANIFEST_FILE];
+
+/// Path of the sidecar of the page `filename` relative to the output
+/// directory, like `post.json`, or `None` when a generated file is there,
+/// like `search.json` for `search.md`
+pub fn sidecar_file(filename: &str, pretty_urls: bool) -> Option<String> {
+    let html = page_file(filename, pretty_urls);
+    let file = format!("{}.json", html.strip_suffix(".html").unwrap_or(&html));
+    (!GENERATED_FILES.contains(&file.as_str())).then_some(file)
+}
+
+/// What the `.json` file built next to a page holds, see `page_json`
+#[derive(Serialize)]
+pub struct PageSidecar {
+    pub filename: String,
+    /// Of the built page, relative to the site root
+    pub url: String,
+    pub title: String,
+    /// Publication date as a Unix timestamp, see `dates::page_date`
+    pub datetime: Option<i64>,
+    /// The frontmatter block as written, custom keys included
+    pub frontmatter: serde_json::Value,
+    pub tags: Vec<String>,
+    pub excerpt: Option<String>,
+    pub words: usize,
+    /// Headings and other ids the page can be linked to at
+    pub anchors: BTreeSet<String>,
+    /// Files of the docs directory the page links to, see `check::page_links`
+    pub links: Vec<String>,
+    /// Web URLs the page links to
+    pub external_links: Vec<String>,
+}
+
+/// The sidecar of the page `filename`, from its raw markdown `content`
+pub fn page_sidecar(filename: &str, content: &str, pretty_urls: bool) -> PageSidecar {
+    let (meta, body) = frontmatter::parse(content);
+    let metadata = PageMetadata::resolve(filename, &meta, body);
+    let raw = frontmatter::split(content)
+        .and_then(|(yaml, _)| serde_yaml::from_str(yaml).ok())
+        .unwrap_or(serde_json::Value::Null);
+
+    let mut links: Vec<String> = Vec::new();
+    for link in page_links(body) {
+        if !links.contains(&link) {
+            links.push(link);
+        }
+    }
+    let mut external_links: Vec<String> = Vec::new();
+    for event in MarkdownParser::new_ext(body, Options::ENABLE_TABLES | Options::ENABLE_FOOTNOTES) {
+        if let Event::Start(Tag::Link { dest_url, .. }) = event
+            && dest_url.contains("://")
+            && !external_links.iter().any(|url| **url == *dest_url)
+        {
+            external_links.push(dest_url.to_string());
+        }
+    }
+
+    PageSidecar {
+        filename: filename.to_string(),
+        url: page_url(filename, true, pretty_urls),
+        title: metadata.title,
+        datetime: dates::page_date(filename, &meta)
+            .ok()
+            .flatten()
+            .map(|date| date.timestamp()),
+        frontmatter: raw,
+        excerpt: metadata.excerpt,
+        words: word_count(body),
+        anchors: page_anchors(filename, body),
+        links,
+        external_links,
+        tags: meta.tags,
+    }
+}
@@ -0,0 +1,122 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use ring::rand::SystemRandom;
+use ring::signature::{ED25519, Ed25519KeyPair, KeyPair, UnparsedPublicKey};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+
+use crate::sync::hash_tree;
+
+/// Manifest of the built files written by builds with a signing key
+pub const MANIFEST_FILE: &str = "content-manifest.json";
+
+/// Detached Ed25519 signature of `MANIFEST_FILE`, base64 encoded
+pub const SIGNATURE_FILE: &str = "content-manifest.json.sig";
+
+/// What a signed build published
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    /// Key the manifest is signed with, base64 encoded
+    public_key: String,
+    /// SHA-256 of every file of the output directory, by relative path
+    files: BTreeMap<String, String>,
+}
+
+/// Creates a signing key at `path`, which must not exist, readable only by
+/// its owner, and returns its public key for readers and mirrors to verify
+/// builds with.
+pub fn generate_key(path: &Path) -> anyhow::Result<String> {
+    let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new())
+        .map_err(|_| anyhow::anyhow!("Failed to generate a signing key"))?;
+    let key = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref())
+        .map_err(|_| anyhow::anyhow!("Failed to generate a signing key"))?;
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path).map_err(|e| match e.kind() {
+        std::io::ErrorKind::AlreadyExists => anyhow::anyhow!("{:?} already exists", path),
+        _ => anyhow::anyhow!("Failed to create signing key {:?}: {}", path, e),
+    })?;
+    file.write_all(format!("{}\n", BASE64.encode(pkcs8.as_ref())).as_bytes())?;
+    Ok(BASE64.encode(key.public_key().as_ref()))
+}
+
+/// Reads a signing key written by `generate_key`.
+pub fn load_key(path: &Path) -> anyhow::Result<Ed25519KeyPair> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read signing key {:?}: {}", path, e))?;
+    let pkcs8 = BASE64
+        .decode(content.trim())
+        .map_err(|e| anyhow::anyhow!("Invalid signing key {:?}: {}", path, e))?;
+    Ed25519KeyPair::from_pkcs8(&pkcs8)
+        .map_err(|e| anyhow::anyhow!("Invalid signing key {:?}: {}", path, e))
+}
+
+/// Writes the manifest of the files of `out_dir`, but for the signing key at
+/// `key_path`, and its signature.
+pub fn sign_tree(out_dir: &Path, key: &Ed25519KeyPair, key_path: &Path) -> anyhow::Result<()> {
+    let mut files = hash_tree(out_dir)?;
+    files.remove(MANIFEST_FILE);
+    files.remove(SIGNATURE_FILE);
+    // Never vouch for the key itself, should it be within the output
+    if let Ok(key_path) = std::path::absolute(key_path)
+        && let Ok(out_dir) = std::path::absolute(out_dir)
+        && let Ok(relative) = key_path.strip_prefix(out_dir)
+    {
+        files.remove(&relative.to_string_lossy().replace('\\', "/"));
+    }
+    let manifest = serde_json::to_string_pretty(&Manifest {
+        public_key: BASE64.encode(key.public_key().as_ref()),
+        files,
+    })?;
+    let signature = key.sign(manifest.as_bytes());
+    std::fs::write(out_dir.join(MANIFEST_FILE), &manifest)?;
+    std::fs::write(
+        out_dir.join(SIGNATURE_FILE),
+        format!("{}\n", BASE64.encode(signature.as_ref())),
+    )?;
+    tracing::info!("Signed {}", MANIFEST_FILE);
+    Ok(())
+}
+
+/// Checks that `dir` holds a manifest signed by `public_key` and exactly the
+/// files it lists. Returns the problems found, if any.
+pub fn verify_tree(dir: &Path, public_key: &str) -> anyhow::Result<Vec<String>> {
+    let public_key = BASE64
+        .decode(public_key.trim())
+        .map_err(|e| anyhow::anyhow!("Invalid public key: {}", e))?;
+    let manifest = std::fs::read(dir.join(MANIFEST_FILE))
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", MANIFEST_FILE, e))?;
+    let signature = std::fs::read_to_string(dir.join(SIGNATURE_FILE))
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", SIGNATURE_FILE, e))?;
+    let signature = BASE64
+        .decode(signature.trim())
+        .map_err(|e| anyhow::anyhow!("Invalid signature: {}", e))?;
+    if UnparsedPublicKey::new(&ED25519, &public_key)
+        .verify(&manifest, &signature)
+        .is_err()
+    {
+        anyhow::bail!("{} isn't signed by this key", MANIFEST_FILE);
+    }
+
+    let manifest: Manifest = serde_json::from_slice(&manifest)?;
+    let mut actual = hash_tree(dir)?;
+    actual.remove(MANIFEST_FILE);
+    actual.remove(SIGNATURE_FILE);
+    let mut problems = Vec::new();
+    for (path, hash) in &manifest.files {
+        match actual.remove(path) {
+            Some(actual) if actual == *hash => {}
+            Some(_) => problems.push(format!("modified: {}", path)),
+            None => problems.push(format!("missing: {}", path)),
+        }
+    }
+    for path in actual.keys() {
+        problems.push(format!("not signed: {}", path));
+    }
+    Ok(problems)
+}
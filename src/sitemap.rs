@@ -0,0 +1,43 @@
+use chrono::DateTime;
+use pulldown_cmark_escape::escape_html;
+
+use crate::store::ContentStore;
+use crate::{absolute_url, get_summary_data};
+
+pub const SITEMAP_FILE: &str = "sitemap.xml";
+
+/// Lists the home page and every page as absolute URLs under `base_url`.
+pub async fn build_sitemap(
+    store: &dyn ContentStore,
+    base_url: &str,
+    is_static: bool,
+    pretty_urls: bool,
+) -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n",
+    );
+    push_url(&mut xml, base_url, None);
+    for page in get_summary_data(store).await {
+        let url = absolute_url(base_url, &page.filename, is_static, pretty_urls);
+        let lastmod = page
+            .datetime
+            .and_then(|ts| DateTime::from_timestamp(ts, 0))
+            .map(|dt| dt.format("%Y-%m-%d").to_string());
+        push_url(&mut xml, &url, lastmod.as_deref());
+    }
+    xml.push_str("</urlset>\n");
+    xml
+}
+
+fn push_url(xml: &mut String, loc: &str, lastmod: Option<&str>) {
+    xml.push_str("  <url><loc>");
+    let _ = escape_html(&mut *xml, loc);
+    xml.push_str("</loc>");
+    if let Some(lastmod) = lastmod {
+        xml.push_str("<lastmod>");
+        xml.push_str(lastmod);
+        xml.push_str("</lastmod>");
+    }
+    xml.push_str("</url>\n");
+}
@@ -0,0 +1,194 @@
+use axum::Router;
+use reqwest::Url;
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::fingerprint::{css_url_spans, rewrite_html_urls};
+use crate::static_site::is_safe_segment;
+
+/// Requested along with the home page, since scripts fetch them
+const SEEDS: &[&str] = &["search.json", "sitemap.xml", "feed.xml"];
+
+/// A response of the crawled site
+struct Fetched {
+    is_html: bool,
+    body: Vec<u8>,
+}
+
+/// Serves `app` on a free port of localhost until the process exits, and
+/// returns its URL, for `snapshot` to crawl a site without a running server.
+pub async fn serve_locally(app: Router) -> anyhow::Result<Url> {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    let app = app.into_make_service_with_connect_info::<SocketAddr>();
+    tokio::spawn(async move { axum::serve(listener, app).await });
+    Ok(Url::parse(&format!("http://{}/", addr))?)
+}
+
+/// Crawls the site served at `base`, from its home page through the links
+/// and assets of its pages and stylesheets, and writes what it answers to
+/// `out_dir` as a static site. Only URLs under `base` are followed. Pages
+/// served at URLs not ending in `.html`, like `post.md`, are written as
+/// `post.html`, with the links to them rewritten. Returns the written files.
+pub async fn snapshot(base: &Url, out_dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut base = base.clone();
+    if !base.path().ends_with('/') {
+        base.set_path(&format!("{}/", base.path()));
+    }
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(30))
+        .build()?;
+
+    let mut queue: VecDeque<Url> = VecDeque::from([base.clone()]);
+    let mut seen: HashSet<String> = HashSet::from([String::new()]);
+    for seed in SEEDS {
+        queue.push_back(base.join(seed)?);
+        seen.insert(seed.to_string());
+    }
+
+    // Responses by path relative to `base`
+    let mut fetched: BTreeMap<String, Fetched> = BTreeMap::new();
+    while let Some(url) = queue.pop_front() {
+        let response = match client.get(url.clone()).send().await {
+            Ok(response) => response,
+            Err(e) => {
+                tracing::warn!("Failed to fetch {}: {}", url, e);
+                continue;
+            }
+        };
+        let Some(path) = relative_path(&base, response.url()) else {
+            continue;
+        };
+        if !response.status().is_success() {
+            if !SEEDS.contains(&path.as_str()) {
+                tracing::warn!("{} answered {}", url, response.status());
+            }
+            continue;
+        }
+        let content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        let page_url = response.url().clone();
+        let body = match response.bytes().await {
+            Ok(body) => body.to_vec(),
+            Err(e) => {
+                tracing::warn!("Failed to fetch {}: {}", url, e);
+                continue;
+            }
+        };
+
+        let mut found: Vec<String> = Vec::new();
+        let text = String::from_utf8_lossy(&body);
+        let is_html = content_type.starts_with("text/html");
+        if is_html {
+            rewrite_html_urls(&text, |name, url| {
+                // `content` is a URL only in the social tags, written absolute
+                if name != "content" || url.contains("://") {
+                    found.push(url.to_string());
+                }
+                None
+            });
+        } else if content_type.starts_with("text/css") {
+            for (start, end) in css_url_spans(&text) {
+                found.push(text[start..end].to_string());
+            }
+        }
+        for link in found {
+            if let Ok(target) = page_url.join(&link)
+                && let Some(target_path) = relative_path(&base, &target)
+                && seen.insert(target_path)
+            {
+                queue.push_back(target);
+            }
+        }
+        fetched.insert(path, Fetched { is_html, body });
+    }
+
+    if fetched.is_empty() {
+        anyhow::bail!("{} answered nothing to snapshot", base);
+    }
+
+    // Pages whose URL a static host wouldn't serve as HTML
+    let renamed: HashSet<&String> = fetched
+        .iter()
+        .filter(|(path, response)| {
+            response.is_html && !path.is_empty() && !path.ends_with('/') && !path.ends_with(".html")
+        })
+        .map(|(path, _)| path)
+        .collect();
+
+    let mut written = Vec::new();
+    for (path, response) in &fetched {
+        let file = out_dir.join(file_name(path, renamed.contains(path)));
+        if let Some(parent) = file.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        if response.is_html && !renamed.is_empty() {
+            let page_url = base.join(path)?;
+            let html = rewrite_html_urls(&String::from_utf8_lossy(&response.body), |_, url| {
+                // Links within the page stay as they are
+                if url.is_empty() || url.starts_with(['#', '?']) {
+                    return None;
+                }
+                let target = page_url.join(url).ok()?;
+                let target_path = relative_path(&base, &target)?;
+                renamed.contains(&target_path).then(|| html_url(url))
+            });
+            tokio::fs::write(&file, html).await?;
+        } else {
+            tokio::fs::write(&file, &response.body).await?;
+        }
+        written.push(file);
+    }
+    tracing::info!("Wrote {} files of {}", written.len(), base);
+    Ok(written)
+}
+
+/// Path of `url` relative to `base`, without its query and fragment, or
+/// `None` when it isn't under `base`
+fn relative_path(base: &Url, url: &Url) -> Option<String> {
+    if url.origin() != base.origin() {
+        return None;
+    }
+    let path = url.path().strip_prefix(base.path())?;
+    let path = percent_encoding::percent_decode_str(path)
+        .decode_utf8()
+        .ok()?;
+    // Never write outside of the output directory: decoded, the path could
+    // be absolute, like `%2Ftmp`, or climb out of it. Only directories end
+    // with an empty segment.
+    let directory = path.strip_suffix('/').unwrap_or(&path);
+    let is_safe = directory.is_empty()
+        || directory
+            .split('/')
+            .all(|segment| !segment.is_empty() && segment != "." && is_safe_segment(segment));
+    is_safe.then(|| path.into_owned())
+}
+
+/// File of the output directory holding the response at `path`
+fn file_name(path: &str, renamed: bool) -> String {
+    if path.is_empty() || path.ends_with('/') {
+        format!("{}index.html", path)
+    } else if renamed {
+        format!("{}.html", path.strip_suffix(".md").unwrap_or(path))
+    } else {
+        path.to_string()
+    }
+}
+
+/// `url`, linking to a renamed page, pointing at its file instead
+fn html_url(url: &str) -> String {
+    let end = url.find(['?', '#']).unwrap_or(url.len());
+    let (path, suffix) = url.split_at(end);
+    let suffix = suffix.find('#').map_or("", |i| &suffix[i..]);
+    format!(
+        "{}.html{}",
+        path.strip_suffix(".md").unwrap_or(path),
+        suffix
+    )
+}
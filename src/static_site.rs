@@ -0,0 +1,158 @@
+use axum::{
+    Router,
+    extract::State,
+    http::{HeaderMap, HeaderValue, Method, StatusCode, Uri, header},
+    response::{IntoResponse, Redirect, Response},
+};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::fingerprint::is_fingerprinted;
+use crate::precompress::{ENCODINGS, is_compressible, sibling};
+
+/// Page sent for missing files, when the build wrote one
+const NOT_FOUND_PAGE: &str = "404.html";
+
+/// `Cache-Control` of the copies written by `--fingerprint`, whose content
+/// never changes
+const IMMUTABLE: &str = "public, max-age=31536000, immutable";
+
+/// Serves a built site from `dir` the way a static web server would, with
+/// `index.html` for directories and the `.br` or `.gz` sibling of a file
+/// written by `--precompress` when the client accepts it. Fingerprinted
+/// copies are sent to be cached for a year.
+pub fn static_router(dir: PathBuf) -> Router {
+    Router::new().fallback(serve_file).with_state(Arc::new(dir))
+}
+
+async fn serve_file(
+    State(dir): State<Arc<PathBuf>>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+) -> Response {
+    if method != Method::GET && method != Method::HEAD {
+        return StatusCode::METHOD_NOT_ALLOWED.into_response();
+    }
+    let path = percent_encoding::percent_decode_str(uri.path()).decode_utf8_lossy();
+    let Some(relative) = safe_path(&path) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let mut file = dir.join(&relative);
+    if tokio::fs::metadata(&file).await.is_ok_and(|m| m.is_dir()) {
+        // Relative links of `dir/index.html` only resolve from `dir/`
+        if !path.ends_with('/') {
+            return Redirect::permanent(&format!("{}/", uri.path())).into_response();
+        }
+        file = file.join("index.html");
+    }
+    match send_file(&file, &headers, StatusCode::OK).await {
+        Some(mut response) => {
+            let name = file.file_name().unwrap_or_default().to_string_lossy();
+            if is_fingerprinted(&name) {
+                response
+                    .headers_mut()
+                    .insert(header::CACHE_CONTROL, HeaderValue::from_static(IMMUTABLE));
+            }
+            response
+        }
+        None => send_file(&dir.join(NOT_FOUND_PAGE), &headers, StatusCode::NOT_FOUND)
+            .await
+            .unwrap_or_else(|| StatusCode::NOT_FOUND.into_response()),
+    }
+}
+
+/// `path` relative to the served directory, `None` if it tries to leave it
+fn safe_path(path: &str) -> Option<PathBuf> {
+    let mut relative = PathBuf::new();
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => {}
+            segment if !is_safe_segment(segment) => return None,
+            segment => relative.push(segment),
+        }
+    }
+    Some(relative)
+}
+
+/// Whether a segment of a decoded URL path stays in the directory it is in
+pub fn is_safe_segment(segment: &str) -> bool {
+    segment != ".." && !segment.contains('\\')
+}
+
+/// The file at `path`, or its precompressed sibling the client accepts
+async fn send_file(path: &Path, headers: &HeaderMap, status: StatusCode) -> Option<Response> {
+    let content_type = content_type(path);
+    if is_compressible(path) {
+        for (encoding, extension) in ENCODINGS {
+            if !accepts(headers, encoding) {
+                continue;
+            }
+            if let Ok(body) = tokio::fs::read(sibling(path, extension)).await {
+                let mut response = (status, body).into_response();
+                let response_headers = response.headers_mut();
+                response_headers
+                    .insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+                response_headers
+                    .insert(header::CONTENT_ENCODING, HeaderValue::from_static(encoding));
+                response_headers.insert(header::VARY, HeaderValue::from_static("accept-encoding"));
+                return Some(response);
+            }
+        }
+    }
+    let body = tokio::fs::read(path).await.ok()?;
+    let mut response = (status, body).into_response();
+    response
+        .headers_mut()
+        .insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+    if is_compressible(path) {
+        response
+            .headers_mut()
+            .insert(header::VARY, HeaderValue::from_static("accept-encoding"));
+    }
+    Some(response)
+}
+
+/// Whether `Accept-Encoding` lists `encoding`, without `q=0`
+fn accepts(headers: &HeaderMap, encoding: &str) -> bool {
+    headers
+        .get_all(header::ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|item| {
+            let mut parts = item.split(';').map(str::trim);
+            parts
+                .next()
+                .is_some_and(|name| name.eq_ignore_ascii_case(encoding))
+                && !parts.any(|param| matches!(param, "q=0" | "q=0.0" | "q=0.00" | "q=0.000"))
+        })
+}
+
+/// `Content-Type` of the file `path`, by extension
+pub fn content_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    match extension.as_str() {
+        "html" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" => "text/javascript; charset=utf-8",
+        "json" => "application/json",
+        "xml" => "application/xml",
+        "svg" => "image/svg+xml",
+        "txt" | "md" => "text/plain; charset=utf-8",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "avif" => "image/avif",
+        "ico" => "image/x-icon",
+        "pdf" => "application/pdf",
+        "woff2" => "font/woff2",
+        "woff" => "font/woff",
+        _ => "application/octet-stream",
+    }
+}
@@ -0,0 +1,132 @@
+use chrono::DateTime;
+use pulldown_cmark::{CodeBlockKind, Event, Options, Parser as MarkdownParser, Tag, TagEnd};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+use crate::codeblocks::fence_language;
+use crate::store::{ContentStore, LocalStore};
+use crate::{frontmatter, get_summary_data};
+
+/// Numbers for a single page
+#[derive(Serialize)]
+pub struct PageStats {
+    pub filename: String,
+    pub title: String,
+    pub words: usize,
+}
+
+/// Totals over every page of a site, as printed by `blog stats`
+#[derive(Default, Serialize)]
+pub struct SiteStats {
+    pub pages: Vec<PageStats>,
+    pub words: usize,
+    pub images: usize,
+    pub code_blocks: BTreeMap<String, usize>,
+    pub tags: BTreeMap<String, usize>,
+    /// Keyed by `YYYY-MM`
+    pub posts_per_month: BTreeMap<String, usize>,
+}
+
+pub async fn collect_stats(docs_dir: &Path) -> SiteStats {
+    let store = LocalStore::new(docs_dir.to_path_buf());
+    let mut stats = SiteStats::default();
+
+    for page in get_summary_data(&store).await {
+        let Ok(content) = store.read_page(&page.filename).await else {
+            continue;
+        };
+        let (meta, body) = frontmatter::parse(&content);
+
+        let words = count_markdown(body, &mut stats);
+        stats.words += words;
+
+        for tag in meta.tags {
+            *stats.tags.entry(tag).or_default() += 1;
+        }
+        if let Some(date) = page.datetime.and_then(|ts| DateTime::from_timestamp(ts, 0)) {
+            let month = date.format("%Y-%m").to_string();
+            *stats.posts_per_month.entry(month).or_default() += 1;
+        }
+
+        stats.pages.push(PageStats {
+            filename: page.filename,
+            title: page.title,
+            words,
+        });
+    }
+    stats
+}
+
+/// Number of words of `markdown`, code excluded
+pub fn word_count(markdown: &str) -> usize {
+    count_markdown(markdown, &mut SiteStats::default())
+}
+
+/// Adds images and code blocks of `markdown` to `stats`, and returns its
+/// word count (code excluded).
+fn count_markdown(markdown: &str, stats: &mut SiteStats) -> usize {
+    let mut words = 0;
+    let mut in_code = false;
+
+    for event in MarkdownParser::new_ext(markdown, Options::ENABLE_TABLES) {
+        match event {
+            Event::Start(Tag::CodeBlock(kind)) => {
+                in_code = true;
+                let lang = match kind {
+                    CodeBlockKind::Fenced(info) => fence_language(&info).to_string(),
+                    CodeBlockKind::Indented => "text".to_string(),
+                };
+                *stats.code_blocks.entry(lang).or_default() += 1;
+            }
+            Event::End(TagEnd::CodeBlock) => in_code = false,
+            Event::Start(Tag::Image { .. }) => stats.images += 1,
+            Event::Text(text) if !in_code => words += text.split_whitespace().count(),
+            _ => {}
+        }
+    }
+    words
+}
+
+impl fmt::Display for SiteStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Pages:  {}", self.pages.len())?;
+        writeln!(f, "Words:  {}", self.words)?;
+        writeln!(f, "Images: {}", self.images)?;
+
+        let longest = self.pages.iter().max_by_key(|p| p.words);
+        let shortest = self.pages.iter().min_by_key(|p| p.words);
+        if let (Some(longest), Some(shortest)) = (longest, shortest) {
+            writeln!(
+                f,
+                "Longest:  {} ({} words, {})",
+                longest.title, longest.words, longest.filename
+            )?;
+            writeln!(
+                f,
+                "Shortest: {} ({} words, {})",
+                shortest.title, shortest.words, shortest.filename
+            )?;
+        }
+
+        write_counts(f, "Code blocks", &self.code_blocks)?;
+        write_counts(f, "Tags", &self.tags)?;
+        write_counts(f, "Posts per month", &self.posts_per_month)
+    }
+}
+
+fn write_counts(
+    f: &mut fmt::Formatter<'_>,
+    title: &str,
+    counts: &BTreeMap<String, usize>,
+) -> fmt::Result {
+    if counts.is_empty() {
+        return Ok(());
+    }
+    writeln!(f, "\n{}:", title)?;
+    for (name, count) in counts {
+        writeln!(f, "  {:<20} {}", name, count)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,439 @@
+use async_trait::async_trait;
+use percent_encoding::percent_decode_str;
+use reqwest::{Method, Url};
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Where the markdown pages are read from. Pages are identified by their
+/// file name, `name.md`.
+#[async_trait]
+pub trait ContentStore: Send + Sync {
+    /// Names of every page, in no particular order
+    async fn list_pages(&self) -> anyhow::Result<Vec<String>>;
+
+    /// Raw markdown of the page `name`
+    async fn read_page(&self, name: &str) -> anyhow::Result<String>;
+
+    /// Reads any text file of the content, such as source files included in
+    /// pages. `path` is relative and may not leave the content's root.
+    async fn read_file(&self, path: &str) -> anyhow::Result<String>;
+
+    /// Same as `read_file`, for binary files such as images
+    async fn read_bytes(&self, path: &str) -> anyhow::Result<Vec<u8>>;
+
+    /// When the page `name` was last changed, if the store knows
+    async fn modified(&self, _name: &str) -> Option<SystemTime> {
+        None
+    }
+
+    /// Version of every page, changing whenever its content does, like an
+    /// ETag. Stores that can tell from their metadata do so without reading
+    /// the pages.
+    async fn page_versions(&self) -> anyhow::Result<HashMap<String, String>> {
+        let mut versions = HashMap::new();
+        for name in self.list_pages().await? {
+            if let Ok(content) = self.read_page(&name).await {
+                versions.insert(name, content_version(content.as_bytes()));
+            }
+        }
+        Ok(versions)
+    }
+
+    /// Same as `page_versions` for the file `path`, `None` when it can't be
+    /// read
+    async fn file_version(&self, path: &str) -> Option<String> {
+        let content = self.read_bytes(path).await.ok()?;
+        Some(content_version(&content))
+    }
+
+    /// When the page `name` was added and last changed, if the store knows
+    async fn history(&self, name: &str) -> Option<PageHistory> {
+        Some(PageHistory {
+            added: None,
+            updated: self.modified(name).await?,
+        })
+    }
+
+    /// Languages the page `name` is available in, when building one tree
+    /// per language
+    async fn translations(&self, _name: &str) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+/// When a page was added and last changed
+#[derive(Debug, Clone, Copy)]
+pub struct PageHistory {
+    pub added: Option<SystemTime>,
+    pub updated: SystemTime,
+}
+
+/// Version of a file with `content`, when the store knows nothing better
+fn content_version(content: &[u8]) -> String {
+    let mut hasher = DefaultHasher::new();
+    content.hash(&mut hasher);
+    format!("{:x}", hasher.finish())
+}
+
+/// Version of a local file, from its modification time and size
+fn metadata_version(metadata: &std::fs::Metadata) -> Option<String> {
+    let modified = metadata
+        .modified()
+        .ok()?
+        .duration_since(SystemTime::UNIX_EPOCH);
+    Some(format!("{}-{}", modified.ok()?.as_nanos(), metadata.len()))
+}
+
+fn is_page(name: &str) -> bool {
+    name.ends_with(".md") && !name.contains('/')
+}
+
+fn check_file_path(path: &str) -> anyhow::Result<()> {
+    let escapes = path.starts_with('/')
+        || path.contains('\\')
+        || path.split('/').any(|part| part == ".." || part.is_empty());
+    if escapes {
+        anyhow::bail!("Invalid file path '{}'", path);
+    }
+    Ok(())
+}
+
+/// Replaces `path` with `content` through a temporary file renamed over
+/// it, so a crash midway leaves the previous content intact. The file keeps
+/// its permissions, and new files get the umask's default ones.
+pub fn write_atomic(path: &Path, content: &[u8]) -> anyhow::Result<()> {
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let mut builder = tempfile::Builder::new();
+    // Temporary files are only readable by their owner otherwise
+    #[cfg(unix)]
+    builder.permissions(std::os::unix::fs::PermissionsExt::from_mode(0o666));
+    let mut file = builder.tempfile_in(dir)?;
+    if let Ok(metadata) = std::fs::metadata(path) {
+        file.as_file().set_permissions(metadata.permissions())?;
+    }
+    file.write_all(content)?;
+    file.as_file().sync_all()?;
+    file.persist(path)?;
+    Ok(())
+}
+
+/// Pages stored as files of a local directory
+pub struct LocalStore {
+    dir: PathBuf,
+}
+
+impl LocalStore {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+}
+
+#[async_trait]
+impl ContentStore for LocalStore {
+    async fn list_pages(&self) -> anyhow::Result<Vec<String>> {
+        let mut pages = Vec::new();
+        let mut entries = tokio::fs::read_dir(&self.dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            if let Some(name) = entry.file_name().to_str()
+                && is_page(name)
+            {
+                pages.push(name.to_string());
+            }
+        }
+        Ok(pages)
+    }
+
+    async fn read_page(&self, name: &str) -> anyhow::Result<String> {
+        if !is_page(name) {
+            anyhow::bail!("Invalid page name '{}'", name);
+        }
+        Ok(tokio::fs::read_to_string(self.dir.join(name)).await?)
+    }
+
+    async fn read_file(&self, path: &str) -> anyhow::Result<String> {
+        check_file_path(path)?;
+        Ok(tokio::fs::read_to_string(self.dir.join(path)).await?)
+    }
+
+    async fn read_bytes(&self, path: &str) -> anyhow::Result<Vec<u8>> {
+        check_file_path(path)?;
+        Ok(tokio::fs::read(self.dir.join(path)).await?)
+    }
+
+    async fn modified(&self, name: &str) -> Option<SystemTime> {
+        if !is_page(name) {
+            return None;
+        }
+        let metadata = tokio::fs::metadata(self.dir.join(name)).await.ok()?;
+        metadata.modified().ok()
+    }
+
+    async fn page_versions(&self) -> anyhow::Result<HashMap<String, String>> {
+        let mut versions = HashMap::new();
+        let mut entries = tokio::fs::read_dir(&self.dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            if let Some(name) = entry.file_name().to_str()
+                && is_page(name)
+                && let Ok(metadata) = entry.metadata().await
+                && let Some(version) = metadata_version(&metadata)
+            {
+                versions.insert(name.to_string(), version);
+            }
+        }
+        Ok(versions)
+    }
+
+    async fn file_version(&self, path: &str) -> Option<String> {
+        check_file_path(path).ok()?;
+        let metadata = tokio::fs::metadata(self.dir.join(path)).await.ok()?;
+        metadata_version(&metadata)
+    }
+
+    async fn history(&self, name: &str) -> Option<PageHistory> {
+        if !is_page(name) {
+            return None;
+        }
+        let metadata = tokio::fs::metadata(self.dir.join(name)).await.ok()?;
+        let modified = metadata.modified().ok()?;
+
+        // A checkout resets file times, so git knows better when it tracks the page
+        let Some(commits) = git_commit_times(&self.dir, name).await else {
+            return Some(PageHistory {
+                added: metadata.created().ok(),
+                updated: modified,
+            });
+        };
+        let edited = git_output(&self.dir, &["status", "--porcelain", "--", name])
+            .await
+            .is_some_and(|status| !status.is_empty());
+        Some(PageHistory {
+            added: commits.last().copied(),
+            updated: if edited { modified } else { commits[0] },
+        })
+    }
+}
+
+/// Times of the commits changing `name` in the git repository `dir` is in,
+/// newest first, `None` when git doesn't track it.
+async fn git_commit_times(dir: &Path, name: &str) -> Option<Vec<SystemTime>> {
+    let log = git_output(dir, &["log", "--follow", "--format=%ct", "--", name]).await?;
+    let times: Vec<SystemTime> = log
+        .lines()
+        .filter_map(|line| line.trim().parse().ok())
+        .map(|secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs))
+        .collect();
+    (!times.is_empty()).then_some(times)
+}
+
+async fn git_output(dir: &Path, args: &[&str]) -> Option<String> {
+    let output = tokio::process::Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .await
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Pages stored in a WebDAV collection, such as a Nextcloud folder or an
+/// object storage bucket exposed over WebDAV
+pub struct WebDavStore {
+    base_url: Url,
+    credentials: Option<(String, String)>,
+    client: reqwest::Client,
+}
+
+impl WebDavStore {
+    pub fn new(base_url: &str, credentials: Option<(String, String)>) -> anyhow::Result<Self> {
+        // Without a trailing slash, joining would replace the last segment
+        let base_url = if base_url.ends_with('/') {
+            Url::parse(base_url)?
+        } else {
+            Url::parse(&format!("{}/", base_url))?
+        };
+        Ok(Self {
+            base_url,
+            credentials,
+            client: reqwest::Client::new(),
+        })
+    }
+
+    fn request(&self, method: Method, url: Url) -> reqwest::RequestBuilder {
+        let request = self.client.request(method, url);
+        match &self.credentials {
+            Some((user, password)) => request.basic_auth(user, Some(password)),
+            None => request,
+        }
+    }
+}
+
+#[async_trait]
+impl ContentStore for WebDavStore {
+    async fn list_pages(&self) -> anyhow::Result<Vec<String>> {
+        let body = self
+            .request(Method::from_bytes(b"PROPFIND")?, self.base_url.clone())
+            .header("Depth", "1")
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+
+        Ok(hrefs(&body)
+            .filter_map(|href| {
+                let name = href.trim_end_matches('/').rsplit('/').next()?;
+                let name = percent_decode_str(name).decode_utf8().ok()?;
+                is_page(&name).then(|| name.into_owned())
+            })
+            .collect())
+    }
+
+    async fn read_page(&self, name: &str) -> anyhow::Result<String> {
+        if !is_page(name) {
+            anyhow::bail!("Invalid page name '{}'", name);
+        }
+        let url = self.base_url.join(name)?;
+        Ok(self
+            .request(Method::GET, url)
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?)
+    }
+
+    async fn read_file(&self, path: &str) -> anyhow::Result<String> {
+        check_file_path(path)?;
+        let url = self.base_url.join(path)?;
+        Ok(self
+            .request(Method::GET, url)
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?)
+    }
+
+    async fn read_bytes(&self, path: &str) -> anyhow::Result<Vec<u8>> {
+        check_file_path(path)?;
+        let url = self.base_url.join(path)?;
+        Ok(self
+            .request(Method::GET, url)
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?
+            .to_vec())
+    }
+
+    async fn modified(&self, name: &str) -> Option<SystemTime> {
+        if !is_page(name) {
+            return None;
+        }
+        let url = self.base_url.join(name).ok()?;
+        let response = self.request(Method::HEAD, url).send().await.ok()?;
+        let modified = response.headers().get(reqwest::header::LAST_MODIFIED)?;
+        let modified = chrono::DateTime::parse_from_rfc2822(modified.to_str().ok()?).ok()?;
+        Some(modified.into())
+    }
+
+    async fn page_versions(&self) -> anyhow::Result<HashMap<String, String>> {
+        let body = self
+            .request(Method::from_bytes(b"PROPFIND")?, self.base_url.clone())
+            .header("Depth", "1")
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+
+        let mut versions = HashMap::new();
+        for (href, version) in href_versions(&body) {
+            let Some(name) = href.trim_end_matches('/').rsplit('/').next() else {
+                continue;
+            };
+            if let Ok(name) = percent_decode_str(name).decode_utf8()
+                && is_page(&name)
+            {
+                versions.insert(name.into_owned(), version);
+            }
+        }
+        Ok(versions)
+    }
+
+    async fn file_version(&self, path: &str) -> Option<String> {
+        check_file_path(path).ok()?;
+        let url = self.base_url.join(path).ok()?;
+        let response = self.request(Method::HEAD, url).send().await.ok()?;
+        let headers = response.error_for_status().ok()?.headers().clone();
+        let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+        match header(reqwest::header::ETAG) {
+            Some(etag) => Some(etag.to_string()),
+            None => Some(format!(
+                "{}-{}",
+                header(reqwest::header::LAST_MODIFIED)?,
+                header(reqwest::header::CONTENT_LENGTH).unwrap_or_default()
+            )),
+        }
+    }
+}
+
+/// `<href>` of every `<response>` of a PROPFIND response along with its
+/// `<getetag>`, or else `<getlastmodified>` and `<getcontentlength>`.
+/// Responses with neither are left out.
+fn href_versions(xml: &str) -> Vec<(&str, String)> {
+    let mut versions = Vec::new();
+    let (mut href, mut etag, mut modified, mut length) = (None, None, None, None);
+    for chunk in xml.split('<') {
+        let Some((tag, text)) = chunk.split_once('>') else {
+            continue;
+        };
+        let local_name = tag
+            .rsplit(':')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        let text = Some(text.trim()).filter(|text| !text.is_empty());
+        match local_name.as_str() {
+            "href" => href = text,
+            "getetag" => etag = text,
+            "getlastmodified" => modified = text,
+            "getcontentlength" => length = text,
+            "response" if tag.starts_with('/') => {
+                let version = match (etag, modified) {
+                    (Some(etag), _) => Some(etag.to_string()),
+                    (None, Some(modified)) => {
+                        Some(format!("{}-{}", modified, length.unwrap_or_default()))
+                    }
+                    (None, None) => None,
+                };
+                if let (Some(href), Some(version)) = (href, version) {
+                    versions.push((href, version));
+                }
+                (href, etag, modified, length) = (None, None, None, None);
+            }
+            _ => {}
+        }
+    }
+    versions
+}
+
+/// Text of every `<href>` element of a PROPFIND response, whatever its
+/// namespace prefix.
+fn hrefs(xml: &str) -> impl Iterator<Item = &str> {
+    xml.split('<').filter_map(|chunk| {
+        let (tag, text) = chunk.split_once('>')?;
+        let local_name = tag.rsplit(':').next()?;
+        local_name
+            .eq_ignore_ascii_case("href")
+            .then_some(text.trim())
+    })
+}
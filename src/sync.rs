@@ -0,0 +1,232 @@
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::store::write_atomic;
+use crate::themes::copy_dir;
+
+/// Hashes of the synced files as of the last sync, in the docs directory
+pub const SYNC_STATE_FILE: &str = ".blog-sync.json";
+
+/// Where `blog sync` sends the docs directory
+#[derive(Debug)]
+pub enum Remote {
+    /// A remote (name or URL) of the git repository the docs directory is in
+    Git(String),
+    /// An rsync destination such as `host:/srv/blog`, over ssh
+    Rsync(String),
+    /// A directory, for instance a mounted share
+    Dir(PathBuf),
+}
+
+impl Remote {
+    /// Git when the docs directory is a repository, with `origin` as the
+    /// default remote. Otherwise `remote` is required.
+    pub fn new(docs_dir: &Path, remote: Option<String>) -> anyhow::Result<Self> {
+        if docs_dir.join(".git").exists() {
+            return Ok(Remote::Git(remote.unwrap_or_else(|| "origin".to_string())));
+        }
+        let Some(remote) = remote else {
+            anyhow::bail!("No sync remote, set `sync_remote` or pass --remote");
+        };
+        let path = PathBuf::from(&remote);
+        Ok(if remote.contains(':') && !path.exists() {
+            Remote::Rsync(remote)
+        } else {
+            Remote::Dir(path)
+        })
+    }
+}
+
+/// Brings `docs_dir` and `remote` to the same content. Changes made on one
+/// side only are copied to the other, and files changed on both sides since
+/// the last sync are reported as conflicts without touching either side.
+pub fn sync(docs_dir: &Path, remote: &Remote) -> anyhow::Result<()> {
+    match remote {
+        Remote::Git(remote) => sync_git(docs_dir, remote),
+        Remote::Rsync(_) | Remote::Dir(_) => sync_files(docs_dir, remote),
+    }
+}
+
+fn sync_git(docs_dir: &Path, remote: &str) -> anyhow::Result<()> {
+    let git = |args: &[&str]| {
+        Command::new("git")
+            .arg("-C")
+            .arg(docs_dir)
+            .args(args)
+            .output()
+            .map_err(|e| anyhow::anyhow!("Failed to run git: {}", e))
+    };
+    let run = |args: &[&str]| -> anyhow::Result<String> {
+        let output = git(args)?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "git {} failed: {}",
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    };
+
+    // Commit local changes
+    run(&["add", "-A"])?;
+    if !git(&["diff", "--cached", "--quiet"])?.status.success() {
+        run(&["commit", "--quiet", "-m", "Sync local changes"])?;
+        tracing::info!("Committed local changes");
+    }
+
+    // Merge remote changes
+    let branch = run(&["rev-parse", "--abbrev-ref", "HEAD"])?;
+    if git(&["fetch", "--quiet", remote, &branch])?
+        .status
+        .success()
+    {
+        let merge = git(&["merge", "--quiet", "--no-edit", "FETCH_HEAD"])?;
+        if !merge.status.success() {
+            let conflicts = run(&["diff", "--name-only", "--diff-filter=U"])?;
+            if conflicts.is_empty() {
+                anyhow::bail!(
+                    "git merge failed: {}",
+                    String::from_utf8_lossy(&merge.stderr).trim()
+                );
+            }
+            run(&["merge", "--abort"])?;
+            anyhow::bail!(
+                "Conflicting changes in {}, merge {}/{} with git and sync again",
+                conflicts.lines().collect::<Vec<_>>().join(", "),
+                remote,
+                branch
+            );
+        }
+    } else {
+        tracing::info!("No branch '{}' on {} yet", branch, remote);
+    }
+
+    run(&["push", "--quiet", remote, &format!("HEAD:{}", branch)])?;
+    tracing::info!("Synced with {}", remote);
+    Ok(())
+}
+
+fn sync_files(docs_dir: &Path, remote: &Remote) -> anyhow::Result<()> {
+    let state_path = docs_dir.join(SYNC_STATE_FILE);
+    let base: BTreeMap<String, String> = match std::fs::read_to_string(&state_path) {
+        Ok(content) => serde_json::from_str(&content)?,
+        Err(_) => BTreeMap::new(),
+    };
+
+    // Fetch remote content
+    let fetched = tempfile::tempdir()?;
+    match remote {
+        Remote::Rsync(remote) => rsync(&format!("{}/", remote), fetched.path(), false)?,
+        Remote::Dir(dir) if dir.exists() => copy_dir(dir, fetched.path())?,
+        _ => {}
+    }
+
+    let local = hash_tree(docs_dir)?;
+    let theirs = hash_tree(fetched.path())?;
+    let paths: BTreeSet<&String> = local.keys().chain(theirs.keys()).collect();
+
+    let mut pulled = Vec::new();
+    let mut conflicts = Vec::new();
+    for path in paths {
+        let (b, l, r) = (base.get(path), local.get(path), theirs.get(path));
+        if l == r || r == b {
+            continue;
+        }
+        if l == b {
+            pulled.push(path);
+        } else {
+            conflicts.push(path.as_str());
+        }
+    }
+    if !conflicts.is_empty() {
+        anyhow::bail!(
+            "Files changed both here and on the remote since the last sync: {}",
+            conflicts.join(", ")
+        );
+    }
+
+    // Apply remote changes
+    for path in pulled {
+        let target = docs_dir.join(path);
+        if theirs.contains_key(path) {
+            if let Some(parent) = target.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            write_atomic(&target, &std::fs::read(fetched.path().join(path))?)?;
+            tracing::info!("Pulled {}", path);
+        } else {
+            std::fs::remove_file(&target)?;
+            tracing::info!("Removed {}, deleted on the remote", path);
+        }
+    }
+
+    // Push local changes
+    match remote {
+        Remote::Rsync(remote) => rsync(&format!("{}/", docs_dir.display()), remote, true)?,
+        Remote::Dir(dir) => {
+            for path in theirs.keys() {
+                if !docs_dir.join(path).exists() {
+                    std::fs::remove_file(dir.join(path))?;
+                }
+            }
+            copy_dir(docs_dir, dir)?;
+        }
+        Remote::Git(_) => unreachable!(),
+    }
+
+    let synced = hash_tree(docs_dir)?;
+    std::fs::write(&state_path, serde_json::to_string_pretty(&synced)?)?;
+    tracing::info!("Synced {} files", synced.len());
+    Ok(())
+}
+
+/// Copies the contents of `from` into `to`, leaving out hidden files on
+/// both sides. With `delete`, files missing from `from` are removed.
+fn rsync(from: &str, to: impl AsRef<std::ffi::OsStr>, delete: bool) -> anyhow::Result<()> {
+    let mut command = Command::new("rsync");
+    command.args(["-a", "--exclude=.*"]);
+    if delete {
+        command.arg("--delete");
+    }
+    let status = command
+        .arg(from)
+        .arg(to)
+        .status()
+        .map_err(|e| anyhow::anyhow!("Failed to run rsync: {}", e))?;
+    if !status.success() {
+        anyhow::bail!("rsync from {} failed", from);
+    }
+    Ok(())
+}
+
+/// Content hash of every non-hidden file under `dir`, by relative path.
+pub fn hash_tree(dir: &Path) -> anyhow::Result<BTreeMap<String, String>> {
+    fn walk(root: &Path, dir: &Path, hashes: &mut BTreeMap<String, String>) -> anyhow::Result<()> {
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            let path = entry.path();
+            if path.is_dir() {
+                walk(root, &path, hashes)?;
+            } else {
+                let relative = path
+                    .strip_prefix(root)?
+                    .to_string_lossy()
+                    .replace('\\', "/");
+                let digest = Sha256::digest(std::fs::read(&path)?);
+                let hex = digest.iter().map(|b| format!("{:02x}", b)).collect();
+                hashes.insert(relative, hex);
+            }
+        }
+        Ok(())
+    }
+
+    let mut hashes = BTreeMap::new();
+    walk(dir, dir, &mut hashes)?;
+    Ok(hashes)
+}
@@ -0,0 +1,66 @@
+use lazy_static::lazy_static;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::RwLock;
+
+lazy_static! {
+    /// Canonical spelling of tags, keyed by lowercased alias or spelling,
+    /// see `[tag_aliases]`
+    static ref TAG_ALIASES: RwLock<HashMap<String, String>> = RwLock::new(HashMap::new());
+}
+
+/// Sets the aliases `frontmatter::parse` merges tags with: `alias =
+/// "canonical"`, like `rustlang = "rust"`. Tags are matched ignoring case,
+/// and spellings of canonical tags in another case are merged too.
+pub fn set_tag_aliases(aliases: &BTreeMap<String, String>) -> anyhow::Result<()> {
+    let mut table: HashMap<String, String> = HashMap::new();
+    for canonical in aliases.values() {
+        match table.insert(canonical.to_lowercase(), canonical.clone()) {
+            Some(other) if other != *canonical => anyhow::bail!(
+                "Tags \"{}\" and \"{}\" are both canonical, pick one spelling",
+                other,
+                canonical
+            ),
+            _ => {}
+        }
+    }
+    for (alias, canonical) in aliases {
+        let key = alias.to_lowercase();
+        if let Some(other) = table.get(&key)
+            && other != canonical
+        {
+            anyhow::bail!(
+                "Tag alias `{}` points to \"{}\", but \"{}\" is canonical itself",
+                alias,
+                canonical,
+                other
+            );
+        }
+        table.insert(key, canonical.clone());
+    }
+    *TAG_ALIASES.write().unwrap() = table;
+    Ok(())
+}
+
+/// The canonical spelling of `tag`
+pub fn canonical_tag(tag: &str) -> String {
+    let tag = tag.trim();
+    TAG_ALIASES
+        .read()
+        .unwrap()
+        .get(&tag.to_lowercase())
+        .cloned()
+        .unwrap_or_else(|| tag.to_string())
+}
+
+/// `tags` in their canonical spelling, without the duplicates merging them
+/// makes
+pub fn canonical_tags(tags: Vec<String>) -> Vec<String> {
+    let mut canonical: Vec<String> = Vec::with_capacity(tags.len());
+    for tag in tags {
+        let tag = canonical_tag(&tag);
+        if !canonical.contains(&tag) {
+            canonical.push(tag);
+        }
+    }
+    canonical
+}
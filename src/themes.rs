@@ -0,0 +1,130 @@
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::EMBEDDED_TEMPLATES;
+
+/// Where installed themes live, in the docs directory
+pub const THEMES_DIR: &str = "themes";
+pub const MANIFEST_FILE: &str = "theme.toml";
+/// Files of a theme copied to the output as is
+pub const THEME_ASSETS_DIR: &str = "assets";
+
+/// `theme.toml` at the root of a theme package. Next to it, a theme ships
+/// any of the embedded template files (`_base.html`, `style.css`, ...) and
+/// an optional `assets/` directory.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ThemeManifest {
+    pub name: String,
+    pub description: Option<String>,
+    pub version: Option<String>,
+}
+
+impl ThemeManifest {
+    pub fn load(theme_dir: &Path) -> anyhow::Result<Self> {
+        let path = theme_dir.join(MANIFEST_FILE);
+        let content = std::fs::read_to_string(&path)
+            .map_err(|e| anyhow::anyhow!("Missing theme manifest {:?}: {}", path, e))?;
+        let manifest: ThemeManifest = toml::from_str(&content)
+            .map_err(|e| anyhow::anyhow!("Invalid theme manifest {:?}: {}", path, e))?;
+
+        let valid = !manifest.name.is_empty()
+            && manifest
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            anyhow::bail!(
+                "Invalid theme name '{}', use letters, digits, '-' and '_'",
+                manifest.name
+            );
+        }
+        Ok(manifest)
+    }
+}
+
+/// Directory of the installed theme `name`.
+pub fn theme_dir(docs_dir: &Path, name: &str) -> anyhow::Result<PathBuf> {
+    let dir = docs_dir.join(THEMES_DIR).join(name);
+    if !dir.join(MANIFEST_FILE).exists() {
+        anyhow::bail!(
+            "Theme '{}' is not installed, try `blog theme install`",
+            name
+        );
+    }
+    Ok(dir)
+}
+
+/// Reads the templates a theme overrides.
+pub fn read_theme_templates(theme_dir: &Path) -> anyhow::Result<Vec<(&'static str, String)>> {
+    let mut templates = Vec::new();
+    for (name, _) in EMBEDDED_TEMPLATES {
+        let path = theme_dir.join(name);
+        if path.exists() {
+            templates.push((*name, std::fs::read_to_string(&path)?));
+        }
+    }
+    Ok(templates)
+}
+
+/// Installs the theme at `source`, a local directory or a git URL, into
+/// `docs_dir/themes/<name>` and returns its name. An installed theme of the
+/// same name is only replaced with `force`.
+pub fn install_theme(docs_dir: &Path, source: &str, force: bool) -> anyhow::Result<String> {
+    let clone_dir;
+    let source_dir = if is_git_url(source) {
+        clone_dir = tempfile::tempdir()?;
+        tracing::info!("Cloning {}", source);
+        let status = Command::new("git")
+            .args(["clone", "--depth", "1", "--quiet", source])
+            .arg(clone_dir.path())
+            .status()
+            .map_err(|e| anyhow::anyhow!("Failed to run git: {}", e))?;
+        if !status.success() {
+            anyhow::bail!("Failed to clone {}", source);
+        }
+        clone_dir.path().to_path_buf()
+    } else {
+        PathBuf::from(source)
+    };
+
+    let manifest = ThemeManifest::load(&source_dir)?;
+    let target = docs_dir.join(THEMES_DIR).join(&manifest.name);
+    if target.exists() {
+        if !force {
+            anyhow::bail!(
+                "Theme '{}' is already installed, use --force to replace it",
+                manifest.name
+            );
+        }
+        std::fs::remove_dir_all(&target)?;
+    }
+
+    copy_dir(&source_dir, &target)?;
+    tracing::info!("Installed theme '{}' in {:?}", manifest.name, target);
+    Ok(manifest.name)
+}
+
+fn is_git_url(source: &str) -> bool {
+    source.contains("://") || source.starts_with("git@") || source.ends_with(".git")
+}
+
+/// Copies `from` into `to` recursively, leaving out hidden entries such as
+/// `.git`.
+pub fn copy_dir(from: &Path, to: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(to)?;
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let path = entry.path();
+        if path.is_dir() {
+            copy_dir(&path, &to.join(entry.file_name()))?;
+        } else {
+            std::fs::copy(&path, to.join(entry.file_name()))?;
+        }
+    }
+    Ok(())
+}
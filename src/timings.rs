@@ -0,0 +1,110 @@
+use lazy_static::lazy_static;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Upper bounds of the histogram buckets, in seconds
+const BUCKETS: &[f64] = &[0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0];
+
+lazy_static! {
+    static ref HISTOGRAMS: Mutex<BTreeMap<&'static str, Histogram>> = Mutex::new(BTreeMap::new());
+}
+
+/// Durations of one phase of rendering, across every page rendered
+#[derive(Default)]
+struct Histogram {
+    /// Count of durations within each of `BUCKETS`, not cumulative
+    counts: [u64; BUCKETS.len()],
+    sum: Duration,
+    count: u64,
+}
+
+impl Histogram {
+    fn record(&mut self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        if let Some(bucket) = BUCKETS.iter().position(|bound| seconds <= *bound) {
+            self.counts[bucket] += 1;
+        }
+        self.sum += duration;
+        self.count += 1;
+    }
+}
+
+/// Time spent in each phase of rendering a page
+#[derive(Default, Debug)]
+pub struct PageTimings {
+    /// Reading the page from the content store
+    pub read: Duration,
+    /// Turning the markdown into HTML, minus highlighting
+    pub parse: Duration,
+    /// Highlighting code blocks
+    pub highlight: Duration,
+    /// Rendering the page template
+    pub template: Duration,
+}
+
+impl PageTimings {
+    /// Adds the timings to the histograms and logs them at debug level.
+    pub fn record(&self, page: &str) {
+        let phases = [
+            ("read", self.read),
+            ("parse", self.parse),
+            ("highlight", self.highlight),
+            ("template", self.template),
+        ];
+        let mut histograms = HISTOGRAMS.lock().unwrap();
+        for (phase, duration) in phases {
+            histograms.entry(phase).or_default().record(duration);
+        }
+        tracing::debug!(
+            page,
+            read_ms = ms(self.read),
+            parse_ms = ms(self.parse),
+            highlight_ms = ms(self.highlight),
+            template_ms = ms(self.template),
+            "Rendered page"
+        );
+    }
+}
+
+fn ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// The render phase histograms in the Prometheus text format, served at
+/// `/metrics`.
+pub fn render_metrics() -> String {
+    let mut text = String::from(
+        "# HELP blog_render_phase_seconds Time spent in each phase of rendering a page\n\
+         # TYPE blog_render_phase_seconds histogram\n",
+    );
+    for (phase, histogram) in HISTOGRAMS.lock().unwrap().iter() {
+        let mut cumulative = 0;
+        for (bound, count) in BUCKETS.iter().zip(histogram.counts) {
+            cumulative += count;
+            let _ = writeln!(
+                text,
+                "blog_render_phase_seconds_bucket{{phase=\"{}\",le=\"{}\"}} {}",
+                phase, bound, cumulative
+            );
+        }
+        let _ = writeln!(
+            text,
+            "blog_render_phase_seconds_bucket{{phase=\"{}\",le=\"+Inf\"}} {}",
+            phase, histogram.count
+        );
+        let _ = writeln!(
+            text,
+            "blog_render_phase_seconds_sum{{phase=\"{}\"}} {}",
+            phase,
+            histogram.sum.as_secs_f64()
+        );
+        let _ = writeln!(
+            text,
+            "blog_render_phase_seconds_count{{phase=\"{}\"}} {}",
+            phase, histogram.count
+        );
+    }
+    text
+}
@@ -0,0 +1,113 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::RenderOptions;
+
+/// Directory of the docs directory holding `<lang>.toml` files of UI strings
+pub const LOCALES_DIR: &str = "locales";
+
+/// Translations of the UI strings shipped with blog
+const EMBEDDED_LOCALES: &[(&str, &str)] = &[
+    ("de", include_str!("../locales/de.toml")),
+    ("es", include_str!("../locales/es.toml")),
+    ("fr", include_str!("../locales/fr.toml")),
+];
+
+/// The UI strings of the embedded templates, in English, by key. Templates
+/// find them under `strings`, like `{{ strings.home }}`, in the language of
+/// the page.
+pub const UI_STRINGS: &[(&str, &str)] = &[
+    ("home", "Home"),
+    ("toggle_theme", "Toggle color scheme"),
+    ("languages", "Languages"),
+    ("pages", "Pages"),
+    ("search", "Search..."),
+    ("no_results", "No results"),
+    ("view", "view"),
+    ("views", "views"),
+    ("previous", "Previous"),
+    ("next", "Next"),
+    ("related", "Related"),
+    ("age_warning_one", "This post is over a year old"),
+    ("age_warning", "This post is over {years} years old"),
+    ("mentions", "Mentions"),
+    ("series", "Series"),
+    ("series_part", "Part {position} of"),
+    ("whats_new", "What's new"),
+    ("follow_changes", "Follow changes"),
+    ("added", "Added"),
+    ("updated", "Updated"),
+    ("show_all_lines", "Show all {lines} lines"),
+    ("not_found", "Page not found"),
+    (
+        "not_found_text",
+        "There is no page at this address. Maybe one of these is what you were looking for:",
+    ),
+];
+
+/// Translations of the UI strings, by language then key, see `[translations]`
+pub type Translations = BTreeMap<String, BTreeMap<String, String>>;
+
+/// Every translation of the UI strings: those shipped with blog, overridden
+/// by the files of the site's `locales/`, overridden by `configured`, from
+/// `blog.toml`.
+pub fn load_translations(
+    docs_dir: &Path,
+    configured: Translations,
+) -> anyhow::Result<Translations> {
+    let mut translations = Translations::new();
+    for (language, source) in EMBEDDED_LOCALES {
+        translations.insert(language.to_string(), toml::from_str(source)?);
+    }
+
+    let dir = docs_dir.join(LOCALES_DIR);
+    if dir.is_dir() {
+        for entry in std::fs::read_dir(&dir)? {
+            let path = entry?.path();
+            let Some(language) = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .filter(|_| path.extension().is_some_and(|e| e == "toml"))
+            else {
+                continue;
+            };
+            let strings: BTreeMap<String, String> =
+                toml::from_str(&std::fs::read_to_string(&path)?)
+                    .map_err(|e| anyhow::anyhow!("Invalid locale file {:?}: {}", path, e))?;
+            translations
+                .entry(language.to_string())
+                .or_default()
+                .extend(strings);
+        }
+    }
+
+    for (language, strings) in configured {
+        translations.entry(language).or_default().extend(strings);
+    }
+    Ok(translations)
+}
+
+/// The UI strings in the language pages are rendered in: its translations,
+/// which may add strings for custom templates, and English for the keys it
+/// lacks.
+pub fn ui_strings(options: &RenderOptions) -> BTreeMap<&str, &str> {
+    let mut strings: BTreeMap<&str, &str> = UI_STRINGS.iter().copied().collect();
+    if let Some(translated) = options
+        .language
+        .as_ref()
+        .and_then(|language| options.translations.get(language))
+    {
+        strings.extend(translated.iter().map(|(k, v)| (k.as_str(), v.as_str())));
+    }
+    strings
+}
+
+/// The UI string `key` in the language pages are rendered in
+pub fn ui_string<'a>(options: &'a RenderOptions, key: &str) -> &'a str {
+    ui_strings(options).get(key).copied().unwrap_or_default()
+}
+
+/// Sets `strings` in the template context of a page of the site.
+pub fn insert_into(options: &RenderOptions, context: &mut tera::Context) {
+    context.insert("strings", &ui_strings(options));
+}
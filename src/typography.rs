@@ -0,0 +1,126 @@
+use std::collections::VecDeque;
+
+use pulldown_cmark::{CowStr, Event, Tag, TagEnd};
+use serde::Deserialize;
+
+const NBSP: char = '\u{a0}';
+const NARROW_NBSP: char = '\u{202f}';
+
+/// Locale rules used by the typography pass.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TypographyLocale {
+    En,
+    Fr,
+}
+
+/// Inserts non-breaking spaces into text events so that short words and
+/// numbers stay attached to what follows, and paragraphs never end on a
+/// lone word.
+pub struct TypographyRenderer<'a, I> {
+    inner: I,
+    locale: Option<TypographyLocale>,
+    queue: VecDeque<Event<'a>>,
+}
+
+impl<'a, I: Iterator<Item = Event<'a>>> TypographyRenderer<'a, I> {
+    /// A `None` locale leaves the stream untouched.
+    pub fn new(inner: I, locale: Option<TypographyLocale>) -> Self {
+        Self {
+            inner,
+            locale,
+            queue: VecDeque::new(),
+        }
+    }
+}
+
+impl<'a, I: Iterator<Item = Event<'a>>> Iterator for TypographyRenderer<'a, I> {
+    type Item = Event<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(event) = self.queue.pop_front() {
+            return Some(event);
+        }
+
+        let event = self.inner.next()?;
+        let Some(locale) = self.locale else {
+            return Some(event);
+        };
+
+        match event {
+            Event::Start(Tag::Paragraph) => {
+                let mut paragraph = vec![event];
+                for inner_event in self.inner.by_ref() {
+                    let end = matches!(inner_event, Event::End(TagEnd::Paragraph));
+                    paragraph.push(fix_text(inner_event, locale));
+                    if end {
+                        break;
+                    }
+                }
+                prevent_widow(&mut paragraph);
+                self.queue.extend(paragraph);
+                self.queue.pop_front()
+            }
+            event => Some(fix_text(event, locale)),
+        }
+    }
+}
+
+fn fix_text(event: Event<'_>, locale: TypographyLocale) -> Event<'_> {
+    match event {
+        Event::Text(text) => Event::Text(CowStr::from(apply_rules(&text, locale))),
+        event => event,
+    }
+}
+
+fn apply_rules(text: &str, locale: TypographyLocale) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut output = String::with_capacity(text.len());
+    let mut word_start = 0;
+
+    for (i, &c) in chars.iter().enumerate() {
+        if c != ' ' {
+            output.push(c);
+            continue;
+        }
+
+        let word = &chars[word_start..i];
+        let next = chars.get(i + 1).copied();
+        word_start = i + 1;
+
+        if locale == TypographyLocale::Fr && matches!(next, Some('?' | '!' | ';' | '»')) {
+            output.push(NARROW_NBSP);
+        } else if locale == TypographyLocale::Fr && next == Some(':') {
+            output.push(NBSP);
+        } else if locale == TypographyLocale::Fr && word == ['«'] {
+            output.push(NARROW_NBSP);
+        } else if next.is_some() && is_short_word(word) {
+            output.push(NBSP);
+        } else {
+            output.push(c);
+        }
+    }
+    output
+}
+
+/// One or two letter words, and numbers, should not end a line.
+fn is_short_word(word: &[char]) -> bool {
+    !word.is_empty()
+        && (word.len() <= 2 && word.iter().all(|c| c.is_alphabetic())
+            || word.iter().all(|c| c.is_ascii_digit()))
+}
+
+/// Glues the last two words of a paragraph together.
+fn prevent_widow(paragraph: &mut [Event<'_>]) {
+    for event in paragraph.iter_mut().rev() {
+        let Event::Text(text) = event else {
+            continue;
+        };
+        if let Some(pos) = text.trim_end().rfind(' ') {
+            let mut fixed = text.to_string();
+            fixed.replace_range(pos..pos + 1, &NBSP.to_string());
+            *event = Event::Text(CowStr::from(fixed));
+        }
+        return;
+    }
+}
@@ -0,0 +1,495 @@
+use axum::{
+    Extension, Form,
+    extract::State,
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use lazy_static::lazy_static;
+use percent_encoding::utf8_percent_encode;
+use pulldown_cmark::{Event, Options, Parser as MarkdownParser, Tag};
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Semaphore;
+
+use crate::check::local_target;
+use crate::languages::language_stores;
+use crate::pings::QUERY_ESCAPES;
+use crate::proxy::ClientInfo;
+use crate::store::{ContentStore, LocalStore};
+use crate::{AppState, RenderOptions, frontmatter, get_summary_data, page_url, request_options};
+
+/// File of the docs directory remembering which links of each page were
+/// mentioned, so builds only send webmentions for the links that changed
+pub const SENT_FILE: &str = ".webmentions.json";
+
+/// Route receiving webmentions, under the site root
+pub const ENDPOINT: &str = "webmention";
+
+/// Bytes of a source read when looking for the link to the target
+const MAX_SOURCE_BYTES: usize = 1024 * 1024;
+
+/// Received webmentions waiting to be verified, beyond which senders are
+/// told to retry later
+const MAX_PENDING: usize = 64;
+
+/// Sources fetched at once by verifications
+const MAX_FETCHES: usize = 4;
+
+lazy_static! {
+    /// (source, target) of the webmentions waiting to be verified
+    static ref PENDING: Mutex<HashSet<(String, String)>> = Mutex::new(HashSet::new());
+    static ref FETCHES: Semaphore = Semaphore::new(MAX_FETCHES);
+}
+
+fn client() -> reqwest::Result<reqwest::Client> {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+}
+
+/// Sends a webmention to every site a page of `docs_dir` links to, when
+/// the link is new since the last time, or was removed. Targets without a
+/// webmention endpoint count as done. Failures are logged and retried by
+/// the next build.
+pub async fn send_webmentions(docs_dir: &Path, options: &RenderOptions) -> anyhow::Result<()> {
+    let Some(base_url) = &options.base_url else {
+        tracing::warn!("Not sending webmentions without a base URL");
+        return Ok(());
+    };
+
+    // Page URLs and the web URLs they link to
+    let mut pages: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    let trees: Vec<(String, Box<dyn ContentStore>)> = if options.languages.is_empty() {
+        vec![(
+            base_url.clone(),
+            Box::new(LocalStore::new(docs_dir.to_path_buf())),
+        )]
+    } else {
+        language_stores(docs_dir, &options.languages)
+            .await?
+            .into_iter()
+            .map(|(language, store)| {
+                let root = format!("{}{}/", base_url, language);
+                (root, Box::new(store) as Box<dyn ContentStore>)
+            })
+            .collect()
+    };
+    for (root, store) in &trees {
+        for page in get_summary_data(store.as_ref()).await {
+            let content = store.read_page(&page.filename).await.unwrap_or_default();
+            let (_, body) = frontmatter::parse(&content);
+            let source = format!(
+                "{}{}",
+                root,
+                page_url(&page.filename, true, options.pretty_urls)
+            );
+            let targets = web_links(body)
+                .into_iter()
+                .filter(|target| !target.starts_with(base_url.as_str()))
+                .collect();
+            pages.insert(source, targets);
+        }
+    }
+
+    let sent_path = docs_dir.join(SENT_FILE);
+    let known: BTreeMap<String, BTreeSet<String>> = match std::fs::read(&sent_path) {
+        Ok(json) => serde_json::from_slice(&json).unwrap_or_else(|e| {
+            tracing::warn!("Ignoring invalid {}: {}", SENT_FILE, e);
+            BTreeMap::new()
+        }),
+        Err(_) => BTreeMap::new(),
+    };
+    let mut sent = known.clone();
+
+    let client = client()?;
+    let mut count = 0;
+    let sources: BTreeSet<String> = pages.keys().chain(sent.keys()).cloned().collect();
+    for source in sources {
+        let current = pages.get(&source).cloned().unwrap_or_default();
+        let previous = sent.get(&source).cloned().unwrap_or_default();
+        let mut done = previous.clone();
+        for target in current.symmetric_difference(&previous) {
+            match send_webmention(&client, &source, target).await {
+                Ok(sent) => {
+                    count += usize::from(sent);
+                    if !done.remove(target) {
+                        done.insert(target.clone());
+                    }
+                }
+                Err(e) => tracing::warn!("Failed to mention {} from {}: {}", target, source, e),
+            }
+        }
+        if done.is_empty() {
+            sent.remove(&source);
+        } else {
+            sent.insert(source, done);
+        }
+    }
+
+    if sent != known {
+        std::fs::write(&sent_path, serde_json::to_string_pretty(&sent)?)?;
+    }
+    if count > 0 {
+        tracing::info!("Sent {} webmentions", count);
+    }
+    Ok(())
+}
+
+/// The web URLs the links of a page point at
+fn web_links(markdown: &str) -> BTreeSet<String> {
+    MarkdownParser::new_ext(markdown, Options::ENABLE_TABLES | Options::ENABLE_FOOTNOTES)
+        .filter_map(|event| match event {
+            Event::Start(Tag::Link { dest_url, .. }) => Some(dest_url),
+            _ => None,
+        })
+        .filter(|url| url.starts_with("https://") || url.starts_with("http://"))
+        .map(|url| url.split('#').next().unwrap_or_default().to_string())
+        .collect()
+}
+
+/// Tells `target`'s webmention endpoint that `source` links to it. Returns
+/// whether it has an endpoint.
+async fn send_webmention(
+    client: &reqwest::Client,
+    source: &str,
+    target: &str,
+) -> anyhow::Result<bool> {
+    let Some(endpoint) = discover_endpoint(client, target).await? else {
+        tracing::debug!("No webmention endpoint for {}", target);
+        return Ok(false);
+    };
+    let encode = |url: &str| utf8_percent_encode(url, QUERY_ESCAPES).to_string();
+    let response = client
+        .post(endpoint.clone())
+        .header(
+            reqwest::header::CONTENT_TYPE,
+            "application/x-www-form-urlencoded",
+        )
+        .body(format!(
+            "source={}&target={}",
+            encode(source),
+            encode(target)
+        ))
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        anyhow::bail!("{} answered {}", endpoint, response.status());
+    }
+    tracing::info!("Mentioned {} from {}", target, source);
+    Ok(true)
+}
+
+/// The webmention endpoint of `target`, from its `Link` headers or else
+/// the first `<link>` or `<a>` of its HTML with `rel="webmention"`
+async fn discover_endpoint(
+    client: &reqwest::Client,
+    target: &str,
+) -> anyhow::Result<Option<reqwest::Url>> {
+    let response = client.get(target).send().await?;
+    let url = response.url().clone();
+    let from_header = response
+        .headers()
+        .get_all(reqwest::header::LINK)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .find_map(|link| {
+            let (href, params) = link.trim().strip_prefix('<')?.split_once('>')?;
+            params
+                .split(';')
+                .filter_map(|param| param.trim().strip_prefix("rel="))
+                .any(|rel| is_webmention_rel(rel.trim_matches('"')))
+                .then(|| href.to_string())
+        });
+    let href = match from_header {
+        Some(href) => Some(href),
+        None => {
+            let is_html = response
+                .headers()
+                .get(reqwest::header::CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .is_some_and(|value| value.contains("html"));
+            if is_html {
+                html_endpoint(&response.text().await?)
+            } else {
+                None
+            }
+        }
+    };
+    Ok(href.and_then(|href| url.join(&href).ok()))
+}
+
+fn is_webmention_rel(rel: &str) -> bool {
+    rel.split_whitespace()
+        .any(|rel| rel.eq_ignore_ascii_case("webmention"))
+}
+
+/// `href` of the first `<link>` or `<a>` of `html` with `rel="webmention"`
+fn html_endpoint(html: &str) -> Option<String> {
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+        if let Some(comment) = rest.strip_prefix("!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end..]);
+            continue;
+        }
+        let name_end = rest
+            .find(|c: char| c.is_whitespace() || c == '>' || c == '/')
+            .unwrap_or(rest.len());
+        let name = rest[..name_end].to_ascii_lowercase();
+        if name != "link" && name != "a" {
+            continue;
+        }
+        let tag = &rest[..rest.find('>').unwrap_or(rest.len())];
+        if attribute(tag, "rel").is_some_and(is_webmention_rel)
+            && let Some(href) = attribute(tag, "href")
+        {
+            return Some(href.replace("&amp;", "&"));
+        }
+    }
+    None
+}
+
+/// Value of the `name` attribute of `tag`, the text between `<` and `>`
+fn attribute<'t>(tag: &'t str, name: &str) -> Option<&'t str> {
+    // Past the element's name
+    let mut rest = tag.trim_start_matches(|c: char| !c.is_whitespace());
+    loop {
+        rest = rest.trim_start();
+        if rest.is_empty() {
+            return None;
+        }
+        let name_end = rest
+            .find(|c: char| c.is_whitespace() || c == '=')
+            .unwrap_or(rest.len());
+        let attribute_name = &rest[..name_end];
+        rest = rest[name_end..].trim_start();
+        let Some(value) = rest.strip_prefix('=') else {
+            if attribute_name.eq_ignore_ascii_case(name) {
+                return Some("");
+            }
+            continue;
+        };
+        let value = value.trim_start();
+        let (value, after) = match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => {
+                let end = value[1..].find(quote).map_or(value.len(), |end| end + 1);
+                (&value[1..end], value.get(end + 1..).unwrap_or_default())
+            }
+            _ => {
+                let end = value.find(char::is_whitespace).unwrap_or(value.len());
+                (&value[..end], &value[end..])
+            }
+        };
+        if attribute_name.eq_ignore_ascii_case(name) {
+            return Some(value);
+        }
+        rest = after;
+    }
+}
+
+#[derive(Deserialize)]
+pub struct Webmention {
+    source: String,
+    target: String,
+}
+
+/// `POST /webmention`: records that `source` mentions `target`, a page of
+/// the site, once it's checked that `source` does link to it. Mentions
+/// whose source stopped linking to the page, or is gone, are removed. The
+/// check happens after answering 202, as the spec recommends, so senders
+/// learn nothing of what fetching the source gave.
+pub async fn receive_handler(
+    State(state): State<Arc<AppState>>,
+    client: Option<Extension<ClientInfo>>,
+    Form(mention): Form<Webmention>,
+) -> Response {
+    if state.db.is_none() {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    let is_web_url = |url: &str| {
+        reqwest::Url::parse(url).is_ok_and(|url| matches!(url.scheme(), "http" | "https"))
+    };
+    if !is_web_url(&mention.source) || !is_web_url(&mention.target) {
+        return (
+            StatusCode::BAD_REQUEST,
+            "Source and target must be web URLs",
+        )
+            .into_response();
+    }
+    if mention.source == mention.target {
+        return (StatusCode::BAD_REQUEST, "Source and target are the same").into_response();
+    }
+
+    let options = request_options(&state, client);
+    let Some(filename) = target_page(&mention.target, &options) else {
+        return (StatusCode::BAD_REQUEST, "Target is not a page of this site").into_response();
+    };
+    match state.store.read_page(&filename).await {
+        Ok(content) if !frontmatter::parse(&content).0.is_draft() => {}
+        _ => {
+            return (StatusCode::BAD_REQUEST, "Target is not a page of this site").into_response();
+        }
+    }
+
+    // Each verification fetches the source: bound how many wait, and check
+    // a mention sent again while waiting only once
+    let key = (mention.source.clone(), mention.target.clone());
+    {
+        let mut pending = PENDING.lock().unwrap();
+        if pending.contains(&key) {
+            return (StatusCode::ACCEPTED, "Webmention accepted").into_response();
+        }
+        if pending.len() >= MAX_PENDING {
+            return (
+                StatusCode::SERVICE_UNAVAILABLE,
+                [(header::RETRY_AFTER, "60")],
+                "Too many webmentions waiting, retry later",
+            )
+                .into_response();
+        }
+        pending.insert(key.clone());
+    }
+    let state = state.clone();
+    tokio::spawn(async move {
+        if let Ok(_permit) = FETCHES.acquire().await {
+            verify(state, filename, mention).await;
+        }
+        PENDING.lock().unwrap().remove(&key);
+    });
+    (StatusCode::ACCEPTED, "Webmention accepted").into_response()
+}
+
+/// Records or removes the mention of `filename` by `mention.source`,
+/// depending on whether it still links to the page.
+async fn verify(state: Arc<AppState>, filename: String, mention: Webmention) {
+    let Some(db) = &state.db else {
+        return;
+    };
+    let links = match source_links(&mention.source, &mention.target).await {
+        Ok(links) => links,
+        Err(e) => {
+            tracing::warn!("Ignored the webmention of {}: {}", filename, e);
+            return;
+        }
+    };
+    if links {
+        match db.add_webmention(&filename, &mention.source) {
+            Ok(()) => tracing::info!(
+                "Received a webmention of {} from {}",
+                filename,
+                mention.source
+            ),
+            Err(e) => tracing::error!("Failed to record a webmention: {}", e),
+        }
+        return;
+    }
+    match db.remove_webmention(&filename, &mention.source) {
+        Ok(true) => tracing::info!(
+            "Removed the webmention of {} from {}",
+            filename,
+            mention.source
+        ),
+        Ok(false) => tracing::info!(
+            "Ignored the webmention of {}: {} does not link to it",
+            filename,
+            mention.source
+        ),
+        Err(e) => tracing::error!("Failed to remove a webmention: {}", e),
+    }
+}
+
+/// The page of `target`, a URL of the served site
+fn target_page(target: &str, options: &RenderOptions) -> Option<String> {
+    let path = match &options.base_url {
+        Some(base_url) => target.strip_prefix(base_url.as_str())?.to_string(),
+        None => {
+            let url = reqwest::Url::parse(target).ok()?;
+            let root = format!("{}/", options.base_path);
+            url.path().strip_prefix(&root)?.to_string()
+        }
+    };
+    let path = path.split(['#', '?']).next()?;
+    let path = percent_encoding::percent_decode_str(path)
+        .decode_utf8()
+        .ok()?;
+    match path.strip_suffix('/') {
+        Some("") => None,
+        Some(slug) => Some(format!("{}.md", slug)),
+        None => local_target(&path).filter(|file| file.ends_with(".md")),
+    }
+}
+
+/// Whether `source` still links to `target`: `false` when it is gone, an
+/// error when it can't be fetched. Only public addresses are requested, so
+/// that mentions can't make the server reach its own network, and only the
+/// first `MAX_SOURCE_BYTES` of the source are read.
+async fn source_links(source: &str, target: &str) -> anyhow::Result<bool> {
+    let url = reqwest::Url::parse(source)?;
+    let host = url
+        .host_str()
+        .ok_or_else(|| anyhow::anyhow!("{} has no host", source))?;
+    let port = url.port_or_known_default().unwrap_or(80);
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port)).await?.collect();
+    if addrs.is_empty() || !addrs.iter().all(|addr| is_public(addr.ip())) {
+        anyhow::bail!("{} is not a public address", host);
+    }
+
+    // Pinned to the checked addresses, and not following redirects, which
+    // could lead anywhere
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .redirect(reqwest::redirect::Policy::none())
+        .resolve_to_addrs(host, &addrs)
+        .build()?;
+    let mut response = client.get(url).send().await?;
+    match response.status() {
+        StatusCode::GONE | StatusCode::NOT_FOUND => return Ok(false),
+        status if !status.is_success() => anyhow::bail!("{} answered {}", source, status),
+        _ => {}
+    }
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        body.extend_from_slice(&chunk);
+        if body.len() >= MAX_SOURCE_BYTES {
+            body.truncate(MAX_SOURCE_BYTES);
+            break;
+        }
+    }
+    Ok(String::from_utf8_lossy(&body).contains(target))
+}
+
+/// Whether `ip` is reachable from the internet at large, rather than a
+/// loopback, private, link-local or otherwise reserved address
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                || a == 0
+                || (a == 100 && (64..128).contains(&b))
+                || a >= 240)
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(IpAddr::V4(ip)),
+            None => {
+                let first = ip.segments()[0];
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    || (first & 0xfe00) == 0xfc00
+                    || (first & 0xffc0) == 0xfe80)
+            }
+        },
+    }
+}